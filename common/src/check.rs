@@ -0,0 +1,21 @@
+//! Support for the `check` subcommands of the binaries
+
+use anyhow::{anyhow, Result};
+use std::future::Future;
+
+/// Runs one step of a check and prints its outcome
+///
+/// Returns an error that names the step if it fails. The error of the step itself is
+/// only printed.
+pub async fn step<T>(name: &str, f: impl Future<Output = Result<T>>) -> Result<T> {
+    match f.await {
+        Ok(v) => {
+            println!("{}: ok", name);
+            Ok(v)
+        }
+        Err(e) => {
+            println!("{}: failed: {:#}", name, e);
+            Err(anyhow!("the {} check failed", name))
+        }
+    }
+}
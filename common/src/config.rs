@@ -1,5 +1,10 @@
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use serde::{de::Error, Deserialize, Deserializer};
+use std::sync::{Arc, RwLock};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
 
 pub fn load<T: for<'a> Deserialize<'a>>() -> Result<T> {
     load_().context("cannot load config.toml")
@@ -9,3 +14,155 @@ fn load_<T: for<'a> Deserialize<'a>>() -> Result<T> {
     let content = std::fs::read_to_string("config.toml")?;
     Ok(toml::from_str(&content)?)
 }
+
+/// A config that is loaded again when the process receives SIGHUP
+///
+/// Settings that can be changed at runtime, e.g. poll intervals, are read through
+/// [LiveConfig::current] every time they are needed instead of once at startup.
+pub struct LiveConfig<T> {
+    current: RwLock<Arc<T>>,
+    /// The number of reloads so far
+    generation: watch::Sender<u64>,
+    generation_rx: watch::Receiver<u64>,
+}
+
+impl<T: for<'a> Deserialize<'a>> LiveConfig<T> {
+    pub fn new(config: T) -> Self {
+        let (generation, generation_rx) = watch::channel(0);
+        Self {
+            current: RwLock::new(Arc::new(config)),
+            generation,
+            generation_rx,
+        }
+    }
+
+    /// Returns the config as of the last reload
+    pub fn current(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Returns the number of reloads so far
+    pub fn generation(&self) -> u64 {
+        *self.generation_rx.borrow()
+    }
+
+    /// Returns once the config has been reloaded more often than `generation` times
+    pub async fn reloaded(&self, generation: u64) {
+        let mut rx = self.generation_rx.clone();
+        if *rx.borrow() != generation {
+            return;
+        }
+        while let Some(g) = rx.recv().await {
+            if g != generation {
+                return;
+            }
+        }
+        futures::future::pending().await
+    }
+
+    /// Loads config.toml whenever the process receives SIGHUP
+    ///
+    /// `reloaded` is called with the previous and the new config. If the file cannot be
+    /// loaded, the error is logged and the previous config stays in effect.
+    pub async fn reload_on_sighup(&self, reloaded: impl Fn(&T, &T)) {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            log::info!("received SIGHUP, reloading config.toml");
+            let new = match load() {
+                Ok(c) => Arc::new(c),
+                Err(e) => {
+                    log::error!("keeping the previous config: {:#}", e);
+                    continue;
+                }
+            };
+            let old = std::mem::replace(&mut *self.current.write().unwrap(), new.clone());
+            reloaded(&old, &new);
+            let _ = self.generation.broadcast(self.generation() + 1);
+        }
+    }
+}
+
+/// The `[user_agent]` section of the config
+///
+/// Upstream services require identifiable clients, so all outbound http requests carry
+/// a user agent that contains a way to contact the operator.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserAgent {
+    /// The name of the client, e.g. `magnets.moe`
+    pub product: String,
+    /// A URL or email address under which the operator can be reached
+    #[serde(deserialize_with = "deserialize_contact")]
+    pub contact: String,
+}
+
+impl UserAgent {
+    /// Returns the value of the `User-Agent` header
+    ///
+    /// Example: `magnets.moe (+https://magnets.moe/contact)`
+    pub fn header(&self) -> String {
+        format!("{} (+{})", self.product, self.contact)
+    }
+
+    /// Returns the contact as a link, i.e. email addresses are prefixed with `mailto:`
+    pub fn contact_link(&self) -> String {
+        match is_url(&self.contact) {
+            true => self.contact.clone(),
+            false => format!("mailto:{}", self.contact),
+        }
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+fn is_email(s: &str) -> bool {
+    match s.find('@') {
+        Some(p) => p > 0 && p + 1 < s.len() && !s.contains(char::is_whitespace),
+        _ => false,
+    }
+}
+
+fn deserialize_contact<'de, D>(d: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(d)?;
+    if is_url(&s) || is_email(&s) {
+        Ok(s)
+    } else {
+        Err(D::Error::custom(format!(
+            "contact `{}` is neither an http(s) URL nor an email address",
+            s
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_contact() {
+        let parse = |contact: &str| {
+            toml::from_str::<UserAgent>(&format!(
+                "product = \"magnets.moe\"\ncontact = \"{}\"",
+                contact
+            ))
+        };
+        let ua = parse("https://magnets.moe/contact").unwrap();
+        assert_eq!(ua.header(), "magnets.moe (+https://magnets.moe/contact)");
+        assert_eq!(ua.contact_link(), "https://magnets.moe/contact");
+        let ua = parse("admin@magnets.moe").unwrap();
+        assert_eq!(ua.contact_link(), "mailto:admin@magnets.moe");
+        assert!(parse("").is_err());
+        assert!(parse("magnets.moe").is_err());
+        assert!(parse("@magnets.moe").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::{Path, PathBuf};
+
+/// Size of a mirrored cover image
+///
+/// The processor generates one webp thumbnail per size. The site serves them under
+/// `/img/cover/{show_id}/{size}`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoverSize {
+    Small,
+    Medium,
+}
+
+pub const COVER_SIZES: &[CoverSize] = &[CoverSize::Small, CoverSize::Medium];
+
+impl CoverSize {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Medium => "medium",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        COVER_SIZES.iter().copied().find(|c| c.as_str() == s)
+    }
+
+    /// Returns the bounding box of the thumbnail
+    ///
+    /// The aspect ratio of the original image is preserved.
+    pub fn dimensions(self) -> (u32, u32) {
+        match self {
+            Self::Small => (100, 142),
+            Self::Medium => (230, 326),
+        }
+    }
+
+    /// Returns the path of the thumbnail of a show below the cover directory
+    pub fn path(self, dir: &Path, show_id: i64) -> PathBuf {
+        dir.join(show_id.to_string())
+            .join(format!("{}.webp", self.as_str()))
+    }
+}
@@ -50,10 +50,9 @@ fn formatter(f: &mut Formatter, r: &Record) -> io::Result<()> {
         Level::Trace => "<7>",
     };
     f.write_all(level.as_bytes())?;
-    if let Some(x) = r.module_path() {
-        f.write_all(&[b'['])?;
-        f.write_all(x.as_bytes())?;
-        f.write_all(b"] ")?;
-    }
+    // The target is the module path unless a dedicated target has been set
+    f.write_all(&[b'['])?;
+    f.write_all(r.target().as_bytes())?;
+    f.write_all(b"] ")?;
     writeln!(f, "{}", r.args())
 }
@@ -0,0 +1,133 @@
+//! Feature flags
+//!
+//! Flags gate risky new behavior so that it can be enabled per deployment without a
+//! rebuild. The value of a flag is taken from the `[flags]` section of the config
+//! unless it is overridden by the `flags` key in `magnets.state`, e.g.
+//!
+//! ```sql
+//! update magnets.state set value = '{"api": false}' where key = 'flags';
+//! ```
+//!
+//! Updating that key sends a `state_change` notification so that running processes
+//! pick up the change immediately.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+use tokio_postgres::{types::Json, GenericClient};
+
+/// The key in `magnets.state` that contains the overrides
+pub const FLAGS_STATE_KEY: &str = "flags";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Flag {
+    /// Serve the json api
+    Api,
+    /// Match new torrents with the processor's `nyaa.shadow_analyzer` instead of the
+    /// default analyzer, which then runs in the shadow
+    NewAnalyzer,
+}
+
+pub const FLAGS: &[Flag] = &[Flag::Api, Flag::NewAnalyzer];
+
+impl Flag {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Flag::Api => "api",
+            Flag::NewAnalyzer => "new_analyzer",
+        }
+    }
+
+    fn index(self) -> usize {
+        FLAGS.iter().position(|&f| f == self).unwrap()
+    }
+}
+
+/// The `[flags]` section of the config
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct FlagConfig {
+    pub api: bool,
+    pub new_analyzer: bool,
+}
+
+impl Default for FlagConfig {
+    fn default() -> Self {
+        Self {
+            api: true,
+            new_analyzer: false,
+        }
+    }
+}
+
+impl FlagConfig {
+    fn get(&self, flag: Flag) -> bool {
+        match flag {
+            Flag::Api => self.api,
+            Flag::NewAnalyzer => self.new_analyzer,
+        }
+    }
+}
+
+/// The current values of all flags
+pub struct Flags {
+    config: FlagConfig,
+    values: Vec<AtomicBool>,
+}
+
+impl Flags {
+    pub fn new(config: &FlagConfig) -> Self {
+        Self {
+            config: config.clone(),
+            values: FLAGS
+                .iter()
+                .map(|&f| AtomicBool::new(config.get(f)))
+                .collect(),
+        }
+    }
+
+    pub fn enabled(&self, flag: Flag) -> bool {
+        self.values[flag.index()].load(Relaxed)
+    }
+
+    /// Applies the overrides from `magnets.state`
+    ///
+    /// Flags that are not overridden fall back to the config.
+    pub fn apply_overrides(&self, overrides: &HashMap<String, bool>) {
+        for name in overrides.keys() {
+            if FLAGS.iter().all(|f| f.as_str() != name) {
+                log::warn!("ignoring override of unknown flag {}", name);
+            }
+        }
+        for &flag in FLAGS {
+            let value = match overrides.get(flag.as_str()) {
+                Some(&v) => v,
+                _ => self.config.get(flag),
+            };
+            if self.values[flag.index()].swap(value, Relaxed) != value {
+                log::info!("flag {} changed to {}", flag.as_str(), value);
+            }
+        }
+    }
+
+    /// Reloads the overrides from `magnets.state`
+    pub async fn refresh<C: GenericClient>(&self, client: &C) -> Result<()> {
+        // language=sql
+        let row = client
+            .query_opt(
+                "select value from magnets.state where key = $1",
+                &[&FLAGS_STATE_KEY],
+            )
+            .await
+            .context("cannot load the flag overrides")?;
+        let overrides = match row {
+            Some(row) => row.get::<_, Json<HashMap<String, bool>>>(0).0,
+            _ => HashMap::new(),
+        };
+        self.apply_overrides(&overrides);
+        Ok(())
+    }
+}
@@ -56,6 +56,18 @@ impl Format {
         Ok(v)
     }
 
+    /// Formats the format as a stable machine-readable string
+    pub fn as_api_str(self) -> &'static str {
+        match self {
+            Format::Tv => "tv",
+            Format::TvShort => "tv_short",
+            Format::Movie => "movie",
+            Format::Special => "special",
+            Format::Ova => "ova",
+            Format::Ona => "ona",
+        }
+    }
+
     /// Formats the format as a human-readable string
     pub fn as_str(self) -> &'static str {
         match self {
@@ -1,17 +1,44 @@
 #![allow(clippy::needless_lifetimes)]
 #![allow(clippy::new_without_default)]
 
+pub use cover::*;
+
 pub use format::*;
 
+pub use magnet::*;
+
+pub use media::*;
+
+pub use role::*;
+
+pub use script::*;
+
 pub use season::*;
 
+pub use source::*;
+
+pub mod check;
 pub mod config;
+mod cover;
 pub mod env;
+pub mod flags;
 mod format;
+mod magnet;
+mod media;
 pub mod pg;
+mod role;
+mod script;
 mod season;
+mod source;
+pub mod textnorm;
 pub mod time;
 
+/// The number of days for which a login on the site is valid
+///
+/// The site sets the lifetime of the session cookie to this and the processor the expiry
+/// of the session when it completes the login.
+pub const SESSION_DAYS: i64 = 30;
+
 pub struct ShowNameType;
 
 /// Corresponds to `magnets.show_name_type`
@@ -20,3 +47,10 @@ impl ShowNameType {
     pub const ENGLISH: i32 = 2;
     pub const ADDITIONAL: i32 = 3;
 }
+
+pub struct ExternalSite;
+
+/// Corresponds to `magnets.external_site`
+impl ExternalSite {
+    pub const MYANIMELIST: i32 = 1;
+}
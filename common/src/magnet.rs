@@ -0,0 +1,284 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, PercentEncode, CONTROLS};
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+    mem::MaybeUninit,
+};
+
+pub const TRACKERS: [&str; 5] = [
+    "http://nyaa.tracker.wf:7777/announce",
+    "udp://open.stealth.si:80/announce",
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://tracker.coppersurfer.tk:6969/announce",
+    "udp://exodus.desync.com:6969/announce",
+];
+
+/// Encodes a value of a query parameter
+///
+/// Besides the characters that are not allowed in a query, this encodes `&` and `=`,
+/// which would end the value, `+`, which many clients decode as a space, and `%`, which
+/// would otherwise start an escape sequence.
+pub fn query_encode(input: &str) -> PercentEncode {
+    const QUERY: AsciiSet = CONTROLS
+        .add(b' ')
+        .add(b'"')
+        .add(b'#')
+        .add(b'%')
+        .add(b'&')
+        .add(b'\'')
+        .add(b'+')
+        .add(b'<')
+        .add(b'=')
+        .add(b'>');
+    utf8_percent_encode(input, &QUERY)
+}
+
+/// Encodes a segment of a path
+///
+/// This encodes the same characters as [query_encode] plus `/` and `?`, which would end
+/// the segment.
+pub fn path_encode(input: &str) -> PercentEncode {
+    const SEGMENT: AsciiSet = CONTROLS
+        .add(b' ')
+        .add(b'"')
+        .add(b'#')
+        .add(b'%')
+        .add(b'&')
+        .add(b'\'')
+        .add(b'+')
+        .add(b'/')
+        .add(b'<')
+        .add(b'=')
+        .add(b'>')
+        .add(b'?');
+    utf8_percent_encode(input, &SEGMENT)
+}
+
+pub struct HexFormatter<'a>(pub &'a [u8]);
+
+impl<'a> Display for HexFormatter<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        const HEX_CHARS_LOWER: [u8; 16] = *b"0123456789abcdef";
+        unsafe {
+            #[allow(clippy::uninit_assumed_init)]
+            let mut buf: [u8; 40] = MaybeUninit::uninit().assume_init();
+            let mut buf_pos = 0;
+            for &byte in self.0 {
+                if buf_pos == buf.len() {
+                    f.write_str(std::str::from_utf8_unchecked(&buf))?;
+                    buf_pos = 0;
+                }
+                *buf.get_unchecked_mut(buf_pos) = HEX_CHARS_LOWER[(byte >> 4) as usize];
+                *buf.get_unchecked_mut(buf_pos + 1) =
+                    HEX_CHARS_LOWER[(byte & 0xf) as usize];
+                buf_pos += 2;
+            }
+            if buf_pos > 0 {
+                f.write_str(std::str::from_utf8_unchecked(&buf[..buf_pos]))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a magnet link from the title and the info hash of a torrent
+///
+/// The link contains the display name (`dn`) and the trackers (`tr`). The exact length
+/// (`xl`) and a shorter display name are opt-in.
+#[derive(Copy, Clone)]
+pub struct MagnetFormatter<'a> {
+    title: &'a str,
+    hash: &'a [u8],
+    size: Option<i64>,
+    max_name_chars: Option<usize>,
+    trackers: &'a [&'a str],
+}
+
+impl<'a> MagnetFormatter<'a> {
+    pub fn new(title: &'a str, hash: &'a [u8]) -> Self {
+        Self {
+            title,
+            hash,
+            size: None,
+            max_name_chars: None,
+            trackers: &TRACKERS,
+        }
+    }
+
+    /// Adds the size in bytes as `xl`. Sizes that are not positive are omitted since
+    /// they are not known.
+    pub fn with_size(self, size: i64) -> Self {
+        Self {
+            size: Some(size),
+            ..self
+        }
+    }
+
+    /// Truncates the display name to at most this many characters
+    pub fn with_max_name_chars(self, max: usize) -> Self {
+        Self {
+            max_name_chars: Some(max),
+            ..self
+        }
+    }
+
+    /// Replaces the default [TRACKERS]
+    pub fn with_trackers(self, trackers: &'a [&'a str]) -> Self {
+        Self { trackers, ..self }
+    }
+
+    fn name(&self) -> &'a str {
+        match self.max_name_chars {
+            Some(max) => match self.title.char_indices().nth(max) {
+                Some((pos, _)) => &self.title[..pos],
+                _ => self.title,
+            },
+            _ => self.title,
+        }
+    }
+}
+
+impl<'a> Display for MagnetFormatter<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "magnet:?xt=urn:btih:{}", HexFormatter(self.hash))?;
+        if let Some(size) = self.size.filter(|&s| s > 0) {
+            write!(f, "&xl={}", size)?;
+        }
+        write!(f, "&dn={}", query_encode(self.name()))?;
+        for tracker in self.trackers {
+            write!(f, "&tr={}", query_encode(tracker))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: [u8; 20] = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89,
+        0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67,
+    ];
+
+    const TRACKER: &[&str] = &["udp://tracker.example.org:1337/announce"];
+
+    fn magnet(title: &str) -> String {
+        MagnetFormatter::new(title, &HASH)
+            .with_trackers(TRACKER)
+            .to_string()
+    }
+
+    #[test]
+    fn formats_hashes() {
+        assert_eq!(
+            HexFormatter(&HASH).to_string(),
+            "0123456789abcdef0123456789abcdef01234567"
+        );
+        assert_eq!(HexFormatter(&[]).to_string(), "");
+        // longer than the internal buffer
+        let long: Vec<u8> = (0..=255).collect();
+        let hex = HexFormatter(&long).to_string();
+        assert_eq!(hex.len(), 512);
+        assert!(hex.starts_with("000102"));
+        assert!(hex.ends_with("fdfeff"));
+    }
+
+    #[test]
+    fn formats_reference_magnets() {
+        assert_eq!(
+            magnet("[SubsPlease] Sousou no Frieren - 05 (1080p) [8E3F2A1B].mkv"),
+            "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567\
+             &dn=[SubsPlease]%20Sousou%20no%20Frieren%20-%2005%20(1080p)%20[8E3F2A1B].mkv\
+             &tr=udp://tracker.example.org:1337/announce"
+        );
+        assert_eq!(
+            MagnetFormatter::new("a", &HASH).to_string(),
+            "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567&dn=a\
+             &tr=http://nyaa.tracker.wf:7777/announce\
+             &tr=udp://open.stealth.si:80/announce\
+             &tr=udp://tracker.opentrackr.org:1337/announce\
+             &tr=udp://tracker.coppersurfer.tk:6969/announce\
+             &tr=udp://exodus.desync.com:6969/announce"
+        );
+    }
+
+    #[test]
+    fn encodes_query_delimiters() {
+        let cases = [
+            ("Tom & Jerry", "Tom%20%26%20Jerry"),
+            ("A+B", "A%2BB"),
+            ("100% Pascal-sensei", "100%25%20Pascal-sensei"),
+            ("a=b", "a%3Db"),
+            ("#1", "%231"),
+            ("\"quoted\" 'single'", "%22quoted%22%20%27single%27"),
+            ("<tag>", "%3Ctag%3E"),
+            ("tab\tnewline\n", "tab%09newline%0A"),
+            ("Re:Zero?/", "Re:Zero?/"),
+            (
+                "進撃の巨人",
+                "%E9%80%B2%E6%92%83%E3%81%AE%E5%B7%A8%E4%BA%BA",
+            ),
+        ];
+        for (title, dn) in &cases {
+            assert_eq!(
+                magnet(title),
+                format!(
+                    "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567\
+                     &dn={}&tr=udp://tracker.example.org:1337/announce",
+                    dn
+                ),
+                "{}",
+                title
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_trackers() {
+        let trackers = &["http://tracker.example.org/announce?passkey=a&b"];
+        assert_eq!(
+            MagnetFormatter::new("a", &HASH)
+                .with_trackers(trackers)
+                .to_string(),
+            "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567&dn=a\
+             &tr=http://tracker.example.org/announce?passkey%3Da%26b"
+        );
+        assert_eq!(
+            MagnetFormatter::new("a", &HASH)
+                .with_trackers(&[])
+                .to_string(),
+            "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567&dn=a"
+        );
+    }
+
+    #[test]
+    fn adds_size() {
+        let base = MagnetFormatter::new("a", &HASH).with_trackers(&[]);
+        assert_eq!(
+            base.with_size(1_503_238_554).to_string(),
+            "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567\
+             &xl=1503238554&dn=a"
+        );
+        assert_eq!(base.with_size(0).to_string(), base.to_string());
+        assert_eq!(base.with_size(-1).to_string(), base.to_string());
+    }
+
+    #[test]
+    fn truncates_names() {
+        let dn = |title: &str, max: usize| {
+            let magnet = MagnetFormatter::new(title, &HASH)
+                .with_trackers(&[])
+                .with_max_name_chars(max)
+                .to_string();
+            magnet.split("&dn=").nth(1).unwrap().to_string()
+        };
+        assert_eq!(dn("Frieren", 4), "Frie");
+        assert_eq!(dn("Frieren", 7), "Frieren");
+        assert_eq!(dn("Frieren", 100), "Frieren");
+        assert_eq!(dn("Frieren", 0), "");
+        // truncated at character boundaries before encoding
+        assert_eq!(dn("進撃の巨人", 2), "%E9%80%B2%E6%92%83");
+        assert_eq!(dn("A & B", 3), "A%20%26");
+    }
+}
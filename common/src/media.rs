@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Result};
+
+/// The vertical resolution of the video of a torrent
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Resolution {
+    P480,
+    P720,
+    P1080,
+    P2160,
+}
+
+pub const RESOLUTIONS: &[Resolution] = &[
+    Resolution::P480,
+    Resolution::P720,
+    Resolution::P1080,
+    Resolution::P2160,
+];
+
+impl Resolution {
+    /// Returns the database constant of the resolution
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::P480 => 480,
+            Self::P720 => 720,
+            Self::P1080 => 1080,
+            Self::P2160 => 2160,
+        }
+    }
+
+    /// Parses a database resolution constant
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            480 => Self::P480,
+            720 => Self::P720,
+            1080 => Self::P1080,
+            2160 => Self::P2160,
+            _ => return Err(anyhow!("invalid resolution {}", n)),
+        };
+        Ok(v)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::P480 => "480p",
+            Self::P720 => "720p",
+            Self::P1080 => "1080p",
+            Self::P2160 => "2160p",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        RESOLUTIONS.iter().copied().find(|r| r.as_str() == s)
+    }
+
+    fn of(token: &str) -> Option<Self> {
+        let res = match token {
+            "480p" => Self::P480,
+            "720p" => Self::P720,
+            "1080p" => Self::P1080,
+            "2160p" | "4k" => Self::P2160,
+            // e.g. 1920x1080
+            _ => match token.find('x') {
+                Some(pos)
+                    if pos > 0 && token[..pos].bytes().all(|b| b.is_ascii_digit()) =>
+                {
+                    Self::parse(&format!("{}p", &token[pos + 1..]))?
+                }
+                _ => return None,
+            },
+        };
+        Some(res)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VideoCodec {
+    H264,
+    H265,
+    Av1,
+}
+
+pub const VIDEO_CODECS: &[VideoCodec] =
+    &[VideoCodec::H264, VideoCodec::H265, VideoCodec::Av1];
+
+impl VideoCodec {
+    /// Returns the database constant of the codec
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::H264 => 1,
+            Self::H265 => 2,
+            Self::Av1 => 3,
+        }
+    }
+
+    /// Parses a database codec constant
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            1 => Self::H264,
+            2 => Self::H265,
+            3 => Self::Av1,
+            _ => return Err(anyhow!("invalid video codec {}", n)),
+        };
+        Ok(v)
+    }
+
+    /// Formats the codec as a stable machine-readable string
+    pub fn as_api_str(self) -> &'static str {
+        match self {
+            Self::H264 => "h264",
+            Self::H265 => "h265",
+            Self::Av1 => "av1",
+        }
+    }
+
+    /// Formats the codec as a human-readable string
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::H264 => "H.264",
+            Self::H265 => "H.265",
+            Self::Av1 => "AV1",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        VIDEO_CODECS.iter().copied().find(|c| c.as_api_str() == s)
+    }
+
+    fn of(token: &str) -> Option<Self> {
+        let codec = match token {
+            "x264" | "h264" | "avc" => Self::H264,
+            "x265" | "h265" | "hevc" => Self::H265,
+            "av1" => Self::Av1,
+            _ => return None,
+        };
+        Some(codec)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AudioCodec {
+    Aac,
+    Flac,
+    Opus,
+    /// AC-3 and E-AC-3 (Dolby Digital)
+    Ac3,
+}
+
+pub const AUDIO_CODECS: &[AudioCodec] = &[
+    AudioCodec::Aac,
+    AudioCodec::Flac,
+    AudioCodec::Opus,
+    AudioCodec::Ac3,
+];
+
+impl AudioCodec {
+    /// Returns the database constant of the codec
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::Aac => 1,
+            Self::Flac => 2,
+            Self::Opus => 3,
+            Self::Ac3 => 4,
+        }
+    }
+
+    /// Parses a database codec constant
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            1 => Self::Aac,
+            2 => Self::Flac,
+            3 => Self::Opus,
+            4 => Self::Ac3,
+            _ => return Err(anyhow!("invalid audio codec {}", n)),
+        };
+        Ok(v)
+    }
+
+    /// Formats the codec as a stable machine-readable string
+    pub fn as_api_str(self) -> &'static str {
+        match self {
+            Self::Aac => "aac",
+            Self::Flac => "flac",
+            Self::Opus => "opus",
+            Self::Ac3 => "ac3",
+        }
+    }
+
+    /// Formats the codec as a human-readable string
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Aac => "AAC",
+            Self::Flac => "FLAC",
+            Self::Opus => "Opus",
+            Self::Ac3 => "Dolby Digital",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        AUDIO_CODECS.iter().copied().find(|c| c.as_api_str() == s)
+    }
+
+    fn of(token: &str) -> Option<Self> {
+        let codec = match token {
+            "aac" => Self::Aac,
+            "flac" => Self::Flac,
+            "opus" => Self::Opus,
+            "ac3" | "eac3" => Self::Ac3,
+            // e.g. DDP5.1 or DD2.0
+            _ => {
+                let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+                match (token.strip_prefix("ddp"), token.strip_prefix("dd")) {
+                    (Some(rest), _) if digits(rest) => Self::Ac3,
+                    (_, Some(rest)) if !rest.is_empty() && digits(rest) => Self::Ac3,
+                    _ => return None,
+                }
+            }
+        };
+        Some(codec)
+    }
+}
+
+/// The video and audio formats that a torrent title advertises
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MediaInfo {
+    pub resolution: Option<Resolution>,
+    pub video_codec: Option<VideoCodec>,
+    pub audio_codec: Option<AudioCodec>,
+    /// Whether the torrent contains both the original and a dubbed audio track
+    pub dual_audio: bool,
+}
+
+impl MediaInfo {
+    /// Detects the formats in a torrent title
+    ///
+    /// The title is split into words at every character that is not alphanumeric.
+    /// Notations that are split this way, e.g. `H.264` or `Dual-Audio`, are recognized by
+    /// also looking at each word joined with the next one. If a title lists several
+    /// values of a format, e.g. a batch in 720p and 1080p, the first one is used.
+    pub fn detect(title: &str) -> Self {
+        let lower = title.to_lowercase();
+        let words: Vec<_> = lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+        let mut info = Self::default();
+        for (i, &word) in words.iter().enumerate() {
+            let joined = match words.get(i + 1) {
+                Some(next) => format!("{}{}", word, next),
+                _ => String::new(),
+            };
+            info.resolution = info.resolution.or_else(|| Resolution::of(word));
+            info.video_codec = info
+                .video_codec
+                .or_else(|| VideoCodec::of(word))
+                .or_else(|| VideoCodec::of(&joined));
+            info.audio_codec = info
+                .audio_codec
+                .or_else(|| AudioCodec::of(word))
+                .or_else(|| AudioCodec::of(&joined));
+            info.dual_audio |= word == "dualaudio" || joined == "dualaudio";
+        }
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            MediaInfo::detect(
+                "[SubsPlease] Shingeki no Kyojin - 01 (1080p) [ABCD1234].mkv"
+            ),
+            MediaInfo {
+                resolution: Some(Resolution::P1080),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MediaInfo::detect(
+                "[Group] Show - 01 [BD 1920x1080 HEVC 10bit FLAC Dual-Audio]"
+            ),
+            MediaInfo {
+                resolution: Some(Resolution::P1080),
+                video_codec: Some(VideoCodec::H265),
+                audio_codec: Some(AudioCodec::Flac),
+                dual_audio: true,
+            }
+        );
+        assert_eq!(
+            MediaInfo::detect("Show S01E01 2160p WEB-DL DDP5.1 H.264-GROUP"),
+            MediaInfo {
+                resolution: Some(Resolution::P2160),
+                video_codec: Some(VideoCodec::H264),
+                audio_codec: Some(AudioCodec::Ac3),
+                dual_audio: false,
+            }
+        );
+    }
+}
@@ -1,16 +1,25 @@
 use crate::time::StdDuration;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use futures::future::poll_fn;
 use rustls::ClientConfig;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
+    collections::HashMap,
+    convert::TryInto,
+    io::ErrorKind,
     ops::Deref,
     str::FromStr,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicU32, Ordering::Relaxed},
+        Arc, Weak,
+    },
+    time::Instant,
 };
 use tokio::{sync::Mutex, task::JoinHandle};
 use tokio_postgres::{
-    AsyncMessage, Client, Connection, IsolationLevel, Socket, Transaction,
+    config::TargetSessionAttrs, error::SqlState, types::ToSql, AsyncMessage, Client,
+    Connection, IsolationLevel, Row, Socket, Statement, Transaction,
 };
 use tokio_postgres_rustls::{MakeRustlsConnect, RustlsStream};
 
@@ -28,19 +37,28 @@ impl FromClient for Dummy {
     }
 }
 
-/// Container for a single postgres connection
+/// Container for a postgres connection or a pool of them
 ///
-/// This container implements a kind of MVCC. Users who borrow the connection get access
+/// This container implements a kind of MVCC. Users who borrow a connection get access
 /// to the connection that was contained in the container at the time. If the contained
 /// connection is replaced, previous users will hold on to the version they borrowed.
 ///
-/// Multiple users can use the contained connection concurrently. Are queries are
-/// pipelined.
+/// Multiple users can use a contained connection concurrently. Their queries are
+/// pipelined. A pooled container (see [PgHolder::pooled]) hands out the connection with
+/// the fewest borrowers and opens another connection, up to the size of the pool, once
+/// all connections are borrowed. This keeps a slow query from delaying all other
+/// queries.
 ///
-/// If the connection fails, it will get replaced by a new connection the next time
-/// someone tries to borrow it. This operation is transparent.
+/// If a connection fails, it will get replaced by a new connection the next time
+/// someone tries to borrow it. This operation is transparent. If the new connection
+/// cannot be established because the server is failing over, establishing it is retried
+/// for up to [FAILOVER_RETRIES] times.
 pub struct PgHolder<T = Dummy, R = NoOpMessageHandler> {
-    con: Mutex<PgHolderCon<T>>,
+    /// Contains a single connection unless the container is pooled
+    slots: Box<[Mutex<PgHolderCon<T>>]>,
+    /// Connections that have been used more recently are not checked before they are
+    /// borrowed
+    health_check_after: StdDuration,
     message_handler: R,
     persistent: bool,
     connector: PgConnector,
@@ -50,6 +68,45 @@ struct PgHolderCon<T> {
     version: u64,
     pg: Option<Arc<Pg<T>>>,
     join_handle: Option<JoinHandle<()>>,
+    /// When the connection was last borrowed
+    last_used: Option<Instant>,
+}
+
+impl<T> PgHolderCon<T> {
+    fn new() -> Self {
+        Self {
+            version: 0,
+            pg: None,
+            join_handle: None,
+            last_used: None,
+        }
+    }
+
+    /// Returns the number of users that have borrowed the connection
+    fn borrowers(&self) -> Option<usize> {
+        // The container holds one reference itself
+        self.pg.as_ref().map(|pg| Arc::strong_count(pg) - 1)
+    }
+}
+
+/// Settings of the connection pool of a [PgHolder]
+#[derive(Clone, Debug, Deserialize)]
+pub struct PoolConfig {
+    /// The maximum number of connections
+    pub size: usize,
+    /// Connections other than the first are closed once they have not been borrowed for
+    /// this many seconds
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Connections are checked with an empty query before they are borrowed if they
+    /// have not been borrowed for this many seconds. They are checked every time if
+    /// this is 0.
+    #[serde(default)]
+    pub health_check_after_secs: u64,
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    300
 }
 
 pub type PgClient = Client;
@@ -62,9 +119,177 @@ pub type PgConnection = Connection<Socket, RustlsStream<Socket>>;
 /// is established. See [site::db::Statements].
 pub struct Pg<T> {
     client: PgClient,
+    statements: StatementCache,
+    explainer: Option<Arc<Explainer>>,
     pub t: T,
 }
 
+impl<T> Pg<T> {
+    /// Prepares an ad-hoc statement or returns it from the statement cache
+    ///
+    /// Use this for queries that are not worth a field in `T`.
+    pub async fn prepare_cached(&self, sql: &str) -> Result<Statement> {
+        self.statements.prepare(&self.client, sql).await
+    }
+
+    // The following methods shadow the methods of the client so that all queries
+    // through `Pg` are seen by the slow statement log (see [ExplainConfig]).
+
+    pub async fn query(
+        &self,
+        stmt: &Statement,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let start = Instant::now();
+        let res = self.client.query(stmt, params).await;
+        self.observe(stmt, params, start).await;
+        res
+    }
+
+    pub async fn query_opt(
+        &self,
+        stmt: &Statement,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, tokio_postgres::Error> {
+        let start = Instant::now();
+        let res = self.client.query_opt(stmt, params).await;
+        self.observe(stmt, params, start).await;
+        res
+    }
+
+    pub async fn query_one(
+        &self,
+        stmt: &Statement,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Row, tokio_postgres::Error> {
+        let start = Instant::now();
+        let res = self.client.query_one(stmt, params).await;
+        self.observe(stmt, params, start).await;
+        res
+    }
+
+    async fn observe(
+        &self,
+        stmt: &Statement,
+        params: &[&(dyn ToSql + Sync)],
+        start: Instant,
+    ) {
+        let explainer = match &self.explainer {
+            Some(e) => e,
+            _ => return,
+        };
+        let elapsed = start.elapsed();
+        if elapsed.as_millis() < explainer.config.threshold_ms as u128 {
+            return;
+        }
+        let slow = explainer.slow.fetch_add(1, Relaxed);
+        if slow % explainer.config.sample_every.max(1) != 0 {
+            return;
+        }
+        if let Err(e) = self.explain(stmt, params, elapsed.as_millis()).await {
+            log::warn!(
+                target: EXPLAIN_TARGET,
+                "could not explain statement {}: {:#}",
+                stmt.name(),
+                e
+            );
+        }
+    }
+
+    async fn explain(
+        &self,
+        stmt: &Statement,
+        params: &[&(dyn ToSql + Sync)],
+        millis: u128,
+    ) -> Result<()> {
+        // Statements prepared by the client are listed here with their SQL
+        // language=sql
+        let row = self
+            .client
+            .query_opt(
+                "select statement from pg_prepared_statements where name = $1",
+                &[&stmt.name()],
+            )
+            .await?;
+        let sql: String = match row {
+            Some(row) => row.get(0),
+            _ => return Ok(()),
+        };
+        // The statement is executed again
+        if !sql.trim_start().to_lowercase().starts_with("select") {
+            return Ok(());
+        }
+        let rows = self
+            .client
+            .query(&*format!("explain (analyze, buffers) {}", sql), params)
+            .await?;
+        let plan: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+        log::info!(
+            target: EXPLAIN_TARGET,
+            "statement took {} ms:\n{}\n{}",
+            millis,
+            sql.trim(),
+            plan.join("\n")
+        );
+        Ok(())
+    }
+}
+
+/// The log target of the slow statement log
+pub const EXPLAIN_TARGET: &str = "magnets::explain";
+
+/// Settings of the slow statement log
+///
+/// Queries through [Pg] that take longer than `threshold_ms` are executed a second time
+/// with `EXPLAIN (ANALYZE, BUFFERS)` and the plan is logged to [EXPLAIN_TARGET]. Only
+/// every `sample_every`-th slow query is explained so that a slow statement does not
+/// double the load on the database. Statements that are not a `select` are never
+/// explained.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExplainConfig {
+    pub threshold_ms: u64,
+    #[serde(default = "default_sample_every")]
+    pub sample_every: u32,
+}
+
+fn default_sample_every() -> u32 {
+    10
+}
+
+#[derive(Debug)]
+struct Explainer {
+    config: ExplainConfig,
+    /// The number of slow queries so far
+    slow: AtomicU32,
+}
+
+/// Prepared statements of a connection keyed by their SQL text
+///
+/// Statements are prepared when they are first used. Each connection has its own cache,
+/// so after [PgHolder] replaces a failed connection, the statements are prepared again
+/// on the new connection.
+#[derive(Default)]
+pub struct StatementCache {
+    statements: std::sync::Mutex<HashMap<String, Statement>>,
+}
+
+impl StatementCache {
+    pub async fn prepare(&self, client: &PgClient, sql: &str) -> Result<Statement> {
+        if let Some(stmt) = self.statements.lock().unwrap().get(sql) {
+            return Ok(stmt.clone());
+        }
+        let stmt = client
+            .prepare(sql)
+            .await
+            .with_context(|| format!("cannot prepare statement {}", sql.trim()))?;
+        self.statements
+            .lock()
+            .unwrap()
+            .insert(sql.to_string(), stmt.clone());
+        Ok(stmt)
+    }
+}
+
 impl<T> Deref for Pg<T> {
     type Target = PgClient;
 
@@ -91,18 +316,72 @@ lazy_static::lazy_static! {
     };
 }
 
+/// How often establishing a connection is retried if it fails during a failover
+pub const FAILOVER_RETRIES: u32 = 20;
+
+/// The delay between attempts to establish a connection during a failover
+const FAILOVER_RETRY_DELAY: StdDuration = StdDuration::from_millis(500);
+
+/// Returns whether the error is caused by the server shutting down or not accepting
+/// connections (yet), e.g. because the primary is switching
+///
+/// Such errors are expected to resolve themselves after a short time. Other io errors,
+/// e.g. TLS failures or a missing socket, are permanent and are not retried.
+pub fn is_failover_error(e: &anyhow::Error) -> bool {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<tokio_postgres::Error>() {
+            if e.is_closed() {
+                return true;
+            }
+            if let Some(code) = e.code() {
+                return *code == SqlState::ADMIN_SHUTDOWN
+                    || *code == SqlState::CRASH_SHUTDOWN
+                    || *code == SqlState::CANNOT_CONNECT_NOW
+                    || *code == SqlState::READ_ONLY_SQL_TRANSACTION;
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::UnexpectedEof
+            );
+        }
+    }
+    false
+}
+
+/// Connects to postgres
+///
+/// The connection string can contain multiple hosts, e.g. `host=a,b`. They are tried in
+/// order. If there are multiple hosts, only hosts that accept writes are used (as if
+/// `target_session_attrs=read-write` had been specified) so that the connection always
+/// goes to the current primary.
 #[derive(Clone, Debug)]
 pub struct PgConnector {
     connection_string: Arc<str>,
+    explainer: Option<Arc<Explainer>>,
 }
 
 impl PgConnector {
     pub fn new(connection_string: String) -> Self {
         Self {
             connection_string: connection_string.into_boxed_str().into(),
+            explainer: None,
         }
     }
 
+    /// Enables the slow statement log for the connections of [PgHolder]s
+    pub fn with_explain(mut self, config: ExplainConfig) -> Self {
+        self.explainer = Some(Arc::new(Explainer {
+            config,
+            slow: AtomicU32::new(0),
+        }));
+        self
+    }
+
     /// Creates a new postgres client
     pub async fn connect(&self) -> Result<PgClient> {
         self.connect_with_handler(&NoOpMessageHandler)
@@ -116,7 +395,11 @@ impl PgConnector {
         message_handler: &M,
     ) -> Result<(PgClient, JoinHandle<()>)> {
         let (client, con) = {
-            tokio_postgres::Config::from_str(&self.connection_string)?
+            let mut config = tokio_postgres::Config::from_str(&self.connection_string)?;
+            if config.get_hosts().len() > 1 {
+                config.target_session_attrs(TargetSessionAttrs::ReadWrite);
+            }
+            config
                 .connect(MAKE_RUSTLS_CONNECT.clone())
                 .await
                 .context("cannot connect to postgres")?
@@ -140,6 +423,8 @@ async fn client<T: FromClient, M: MessageHandler>(
     let (client, join_handle) = connector.connect_with_handler(message_handler).await?;
     let pg = Pg {
         t: T::from_client(&client).await?,
+        statements: StatementCache::default(),
+        explainer: connector.explainer.clone(),
         client,
     };
     Ok((pg, join_handle))
@@ -150,6 +435,26 @@ impl<T: FromClient> PgHolder<T> {
     pub fn new(connector: &PgConnector) -> Arc<Self> {
         Self::with_message_handler(NoOpMessageHandler, false, connector)
     }
+
+    /// Creates a new container with a pool of connections
+    ///
+    /// Connections are only opened when they are needed.
+    pub fn pooled(connector: &PgConnector, config: &PoolConfig) -> Arc<Self> {
+        let holder = Arc::new(Self {
+            slots: (0..config.size.max(1))
+                .map(|_| Mutex::new(PgHolderCon::new()))
+                .collect(),
+            health_check_after: StdDuration::from_secs(config.health_check_after_secs),
+            message_handler: NoOpMessageHandler,
+            persistent: false,
+            connector: connector.clone(),
+        });
+        if holder.slots.len() > 1 {
+            let idle_timeout = StdDuration::from_secs(config.idle_timeout_secs.max(1));
+            tokio::spawn(close_idle(Arc::downgrade(&holder), idle_timeout));
+        }
+        holder
+    }
 }
 
 impl<T: FromClient, M: MessageHandler> PgHolder<T, M> {
@@ -160,11 +465,8 @@ impl<T: FromClient, M: MessageHandler> PgHolder<T, M> {
         connector: &PgConnector,
     ) -> Arc<Self> {
         let holder = Arc::new(Self {
-            con: Mutex::new(PgHolderCon {
-                version: 0,
-                pg: None,
-                join_handle: None,
-            }),
+            slots: vec![Mutex::new(PgHolderCon::new())].into_boxed_slice(),
+            health_check_after: StdDuration::from_secs(0),
             message_handler,
             persistent,
             connector: connector.clone(),
@@ -175,24 +477,85 @@ impl<T: FromClient, M: MessageHandler> PgHolder<T, M> {
         holder
     }
 
-    /// Borrows the connection
+    /// Borrows a connection
     pub async fn borrow(&self) -> Result<Arc<Pg<T>>> {
+        let slot = &self.slots[self.pick_slot()];
         loop {
             let (ver, con) = {
-                let locked = self.con.lock().await;
+                let mut locked = slot.lock().await;
+                let fresh = locked
+                    .last_used
+                    .map(|t| t.elapsed() < self.health_check_after)
+                    .unwrap_or(false);
+                if fresh {
+                    if let Some(con) = &locked.pg {
+                        let con = con.clone();
+                        locked.last_used = Some(Instant::now());
+                        return Ok(con);
+                    }
+                }
                 (locked.version, locked.pg.clone())
             };
             if let Some(con) = con {
-                if con.simple_query("").await.is_ok() {
-                    return Ok(con);
+                match con.simple_query("").await {
+                    Ok(_) => {
+                        let mut locked = slot.lock().await;
+                        if locked.version == ver {
+                            locked.last_used = Some(Instant::now());
+                        }
+                        return Ok(con);
+                    }
+                    Err(e) => log::warn!("postgres connection failed: {:#}", e),
                 }
             }
-            self.connect(ver).await?;
+            self.connect_retrying(slot, ver).await?;
         }
     }
 
-    async fn connect(&self, ver: u64) -> Result<()> {
-        let mut locked = self.con.lock().await;
+    /// Returns the index of the slot whose connection should be borrowed
+    ///
+    /// This is the connection with the fewest borrowers unless all connections are
+    /// borrowed and the pool is not full. Slots that are currently connecting are
+    /// skipped.
+    fn pick_slot(&self) -> usize {
+        if self.slots.len() == 1 {
+            return 0;
+        }
+        choose_slot(self.slots.iter().map(|slot| match slot.try_lock() {
+            Ok(locked) => match locked.borrowers() {
+                Some(n) => SlotState::Open(n),
+                None => SlotState::Closed,
+            },
+            _ => SlotState::Connecting,
+        }))
+    }
+
+    /// Like [Self::connect] but retries failover errors
+    async fn connect_retrying(
+        &self,
+        slot: &Mutex<PgHolderCon<T>>,
+        ver: u64,
+    ) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.connect(slot, ver).await {
+                Err(e) if attempt < FAILOVER_RETRIES && is_failover_error(&e) => {
+                    attempt += 1;
+                    log::warn!(
+                        "could not connect to postgres (attempt {}/{}): {:#}",
+                        attempt,
+                        FAILOVER_RETRIES,
+                        e
+                    );
+                    tokio::time::delay_for(FAILOVER_RETRY_DELAY).await;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    async fn connect(&self, slot: &Mutex<PgHolderCon<T>>, ver: u64) -> Result<()> {
+        let mut locked = slot.lock().await;
         if ver == locked.version {
             log::info!(
                 "creating postgres connection for thread {}",
@@ -214,13 +577,13 @@ async fn keep_connected<T: FromClient, R: MessageHandler>(holder: Weak<PgHolder<
     while let Some(holder) = holder.upgrade() {
         let join_handle = {
             let (ver, join_handle) = {
-                let mut locked = holder.con.lock().await;
+                let mut locked = holder.slots[0].lock().await;
                 (locked.version, locked.join_handle.take())
             };
             match join_handle {
                 Some(h) => h,
                 _ => {
-                    if let Err(e) = holder.connect(ver).await {
+                    if let Err(e) = holder.connect_retrying(&holder.slots[0], ver).await {
                         log::error!("could not connect to postgres: {:#}", e);
                         log::info!("sleeping for 10 seconds");
                         drop(holder);
@@ -235,6 +598,89 @@ async fn keep_connected<T: FromClient, R: MessageHandler>(holder: Weak<PgHolder<
     }
 }
 
+/// The state of a slot of a pool as seen by [PgHolder::pick_slot]
+#[derive(Copy, Clone, Debug)]
+enum SlotState {
+    /// The slot is locked, usually because its connection is being established
+    Connecting,
+    Closed,
+    /// The connection is open and has this many borrowers
+    Open(usize),
+}
+
+/// Returns the index of the slot to borrow, see [PgHolder::pick_slot]
+fn choose_slot(states: impl IntoIterator<Item = SlotState>) -> usize {
+    let mut least_borrowed: Option<(usize, usize)> = None;
+    let mut closed = None;
+    for (idx, state) in states.into_iter().enumerate() {
+        match state {
+            SlotState::Connecting => {}
+            SlotState::Open(n) => {
+                if least_borrowed.map(|(m, _)| n < m).unwrap_or(true) {
+                    least_borrowed = Some((n, idx));
+                }
+            }
+            SlotState::Closed => {
+                if closed.is_none() {
+                    closed = Some(idx);
+                }
+            }
+        }
+    }
+    match (least_borrowed, closed) {
+        (Some((0, idx)), _) => idx,
+        (_, Some(idx)) => idx,
+        (Some((_, idx)), _) => idx,
+        _ => 0,
+    }
+}
+
+/// Returns whether an open connection has not been borrowed for `idle_timeout`
+fn is_idle(
+    borrowers: Option<usize>,
+    last_used: Option<Instant>,
+    idle_timeout: StdDuration,
+    now: Instant,
+) -> bool {
+    borrowers == Some(0)
+        && last_used
+            .map(|t| now.saturating_duration_since(t) >= idle_timeout)
+            .unwrap_or(true)
+}
+
+/// Closes the connections of a pool that have not been borrowed for `idle_timeout`
+///
+/// The first connection is kept open so that the first query after a quiet period
+/// does not have to wait for a new connection.
+async fn close_idle<T: FromClient, R: MessageHandler>(
+    holder: Weak<PgHolder<T, R>>,
+    idle_timeout: StdDuration,
+) {
+    loop {
+        tokio::time::delay_for(idle_timeout).await;
+        let holder = match holder.upgrade() {
+            Some(h) => h,
+            _ => return,
+        };
+        let mut closed = 0;
+        for slot in &holder.slots[1..] {
+            let mut locked = slot.lock().await;
+            let now = Instant::now();
+            if is_idle(locked.borrowers(), locked.last_used, idle_timeout, now) {
+                locked.pg = None;
+                closed += 1;
+            }
+        }
+        if closed > 0 {
+            log::info!(
+                "closed {} idle postgres connections of thread {}",
+                closed,
+                std::thread::current().name().unwrap_or("?")
+            );
+        }
+    }
+}
+
 async fn drive_connection<T: MessageHandler>(
     mut con: PgConnection,
     handler: T,
@@ -364,3 +810,119 @@ macro_rules! create_statement {
         }
     }
 }
+
+/// Blobs whose JSON is smaller than this are stored uncompressed
+const COMPRESSION_THRESHOLD: usize = 1024;
+/// Blobs larger than this are rejected when decompressing
+const MAX_BLOB_LEN: usize = 256 * 1024 * 1024;
+const CODEC_NONE: u8 = 0;
+const CODEC_ZSTD: u8 = 1;
+/// The codec followed by the length of the JSON as a little-endian u32
+const BLOB_HEADER_LEN: usize = 5;
+
+/// Serializes `value` as JSON for storage in a `bytea` column
+///
+/// Large values are compressed with zstd. The blob starts with a header containing the
+/// codec and the uncompressed length. See [decompress_json].
+pub fn compress_json<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(value)?;
+    if json.len() > MAX_BLOB_LEN {
+        return Err(anyhow!("blob of {} bytes is too large", json.len()));
+    }
+    let mut blob = Vec::with_capacity(BLOB_HEADER_LEN + json.len());
+    if json.len() < COMPRESSION_THRESHOLD {
+        blob.push(CODEC_NONE);
+        blob.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        blob.extend_from_slice(&json);
+    } else {
+        blob.push(CODEC_ZSTD);
+        blob.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        blob.extend_from_slice(&zstd::block::compress(&json, 3)?);
+    }
+    Ok(blob)
+}
+
+/// Deserializes a blob created by [compress_json]
+pub fn decompress_json<T: DeserializeOwned>(blob: &[u8]) -> Result<T> {
+    if blob.len() < BLOB_HEADER_LEN {
+        return Err(anyhow!("blob is too short to contain a header"));
+    }
+    let len = u32::from_le_bytes(blob[1..BLOB_HEADER_LEN].try_into().unwrap()) as usize;
+    if len > MAX_BLOB_LEN {
+        return Err(anyhow!("blob of {} bytes is too large", len));
+    }
+    let data = &blob[BLOB_HEADER_LEN..];
+    let json = match blob[0] {
+        CODEC_NONE => data.to_vec(),
+        CODEC_ZSTD => {
+            zstd::block::decompress(data, len).context("cannot decompress blob")?
+        }
+        codec => return Err(anyhow!("blob has unknown codec {}", codec)),
+    };
+    if json.len() != len {
+        return Err(anyhow!(
+            "blob contains {} bytes but its header says {}",
+            json.len(),
+            len
+        ));
+    }
+    Ok(serde_json::from_slice(&json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blobs_round_trip() {
+        let small = vec![(1i64, "Frieren".to_string())];
+        let blob = compress_json(&small).unwrap();
+        assert_eq!(blob[0], CODEC_NONE);
+        assert_eq!(decompress_json::<Vec<(i64, String)>>(&blob).unwrap(), small);
+
+        let large: Vec<_> = (0..1000i64).map(|i| (i, format!("show {}", i))).collect();
+        let blob = compress_json(&large).unwrap();
+        assert_eq!(blob[0], CODEC_ZSTD);
+        assert!(blob.len() < serde_json::to_vec(&large).unwrap().len());
+        assert_eq!(decompress_json::<Vec<(i64, String)>>(&blob).unwrap(), large);
+
+        assert!(decompress_json::<Vec<i64>>(&[]).is_err());
+        assert!(decompress_json::<Vec<i64>>(&[7, 2, 0, 0, 0, b'[', b']']).is_err());
+    }
+
+    #[test]
+    fn picks_slots() {
+        use SlotState::*;
+        assert_eq!(choose_slot(vec![Open(2), Open(0), Open(1)]), 1);
+        assert_eq!(choose_slot(vec![Open(1), Closed, Open(2), Closed]), 1);
+        assert_eq!(choose_slot(vec![Closed, Open(0)]), 1);
+        assert_eq!(choose_slot(vec![Open(3), Open(1)]), 1);
+        assert_eq!(choose_slot(vec![Connecting, Open(2)]), 1);
+        assert_eq!(choose_slot(vec![Connecting, Connecting]), 0);
+    }
+
+    #[test]
+    fn detects_idle_connections() {
+        let timeout = StdDuration::from_secs(300);
+        let used = Instant::now();
+        let later = used + StdDuration::from_secs(400);
+        assert!(is_idle(Some(0), Some(used), timeout, later));
+        assert!(is_idle(Some(0), None, timeout, later));
+        assert!(!is_idle(Some(0), Some(used), timeout, used));
+        assert!(!is_idle(Some(1), Some(used), timeout, later));
+        assert!(!is_idle(None, Some(used), timeout, later));
+    }
+
+    #[test]
+    fn detects_failover_errors() {
+        let io = |kind| anyhow::Error::new(std::io::Error::new(kind, "test"));
+        assert!(is_failover_error(&io(ErrorKind::ConnectionRefused)));
+        assert!(is_failover_error(&io(ErrorKind::ConnectionReset)));
+        assert!(is_failover_error(
+            &io(ErrorKind::UnexpectedEof).context("connecting")
+        ));
+        assert!(!is_failover_error(&io(ErrorKind::NotFound)));
+        assert!(!is_failover_error(&io(ErrorKind::InvalidData)));
+        assert!(!is_failover_error(&anyhow!("test")));
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{anyhow, Result};
+use std::fmt::{self, Display, Formatter};
+
+/// Role of a user of the admin interface
+///
+/// Roles are ordered by privilege: an admin can do everything a moderator can do and a
+/// moderator can do everything a readonly user can do.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Role {
+    Admin,
+    Moderator,
+    Readonly,
+}
+
+impl Role {
+    /// Returns the database constant of the role
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::Admin => 1,
+            Self::Moderator => 2,
+            Self::Readonly => 3,
+        }
+    }
+
+    /// Parses a database role constant
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            1 => Self::Admin,
+            2 => Self::Moderator,
+            3 => Self::Readonly,
+            _ => return Err(anyhow!("invalid role {}", n)),
+        };
+        Ok(v)
+    }
+
+    /// Parses the role name used on the command line
+    pub fn from_name(n: &str) -> Result<Self> {
+        let v = match n {
+            "admin" => Self::Admin,
+            "moderator" => Self::Moderator,
+            "readonly" => Self::Readonly,
+            _ => return Err(anyhow!("invalid role {}", n)),
+        };
+        Ok(v)
+    }
+
+    /// Returns the role name used on the command line
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Moderator => "moderator",
+            Self::Readonly => "readonly",
+        }
+    }
+
+    /// Returns whether this role has at least the privileges of `required`
+    pub fn allows(self, required: Role) -> bool {
+        self.to_db() <= required.to_db()
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
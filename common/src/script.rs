@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+
+/// A non-latin script in which a torrent title is written
+///
+/// This is a hint for the language of the torrent. Titles in latin script have no
+/// script.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Script {
+    Cyrillic,
+    /// Chinese, Japanese or Korean
+    Cjk,
+    Thai,
+}
+
+pub const SCRIPTS: &[Script] = &[Script::Cyrillic, Script::Cjk, Script::Thai];
+
+impl Script {
+    /// Returns the database constant of the script
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::Cyrillic => 1,
+            Self::Cjk => 2,
+            Self::Thai => 3,
+        }
+    }
+
+    /// Parses a database script constant
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            1 => Self::Cyrillic,
+            2 => Self::Cjk,
+            3 => Self::Thai,
+            _ => return Err(anyhow!("invalid script {}", n)),
+        };
+        Ok(v)
+    }
+
+    /// Formats the script as a stable machine-readable string
+    pub fn as_api_str(self) -> &'static str {
+        match self {
+            Self::Cyrillic => "cyrillic",
+            Self::Cjk => "cjk",
+            Self::Thai => "thai",
+        }
+    }
+
+    /// Formats the script as a human-readable string
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cyrillic => "Cyrillic",
+            Self::Cjk => "Chinese/Japanese/Korean",
+            Self::Thai => "Thai",
+        }
+    }
+
+    fn of(c: char) -> Option<Self> {
+        let script = match c as u32 {
+            0x0400..=0x052f => Self::Cyrillic,
+            0x0e00..=0x0e7f => Self::Thai,
+            0x1100..=0x11ff
+            | 0x3040..=0x30ff
+            | 0x3130..=0x318f
+            | 0x3400..=0x4dbf
+            | 0x4e00..=0x9fff
+            | 0xac00..=0xd7af
+            | 0xff66..=0xff9f => Self::Cjk,
+            _ => return None,
+        };
+        Some(script)
+    }
+
+    /// Detects the script of a torrent title
+    ///
+    /// Text in brackets and parentheses is ignored because it usually contains the
+    /// release group and the video format. Returns `None` if the remaining letters are
+    /// mostly latin. CJK characters count twice because a single character usually
+    /// corresponds to several latin letters.
+    pub fn detect(title: &str) -> Option<Self> {
+        let mut depth = 0u32;
+        let mut latin = 0;
+        let mut counts = [0; 3];
+        for c in title.chars() {
+            match c {
+                '[' | '(' | '【' | '「' => depth += 1,
+                ']' | ')' | '】' | '」' => depth = depth.saturating_sub(1),
+                _ if depth > 0 => {}
+                _ => match Self::of(c) {
+                    Some(Self::Cjk) => counts[1] += 2,
+                    Some(s) => counts[s.to_db() as usize - 1] += 1,
+                    _ if c.is_alphabetic() => latin += 1,
+                    _ => {}
+                },
+            }
+        }
+        let (script, count) = SCRIPTS
+            .iter()
+            .zip(counts.iter())
+            .max_by_key(|(_, &count)| count)
+            .unwrap();
+        match *count > latin {
+            true => Some(*script),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            Script::detect("[SubsPlease] Shingeki no Kyojin - 01 (1080p)"),
+            None
+        );
+        assert_eq!(
+            Script::detect("[SubsPlease] Shingeki no Kyojin (進撃の巨人) - 01"),
+            None
+        );
+        assert_eq!(
+            Script::detect("[Group] 進撃の巨人 - 01 [1080p][ABCD1234].mkv"),
+            Some(Script::Cjk)
+        );
+        assert_eq!(
+            Script::detect("[AniLibria] Атака титанов - 01 [WEBRip 1080p]"),
+            Some(Script::Cyrillic)
+        );
+        assert_eq!(Script::detect("ผ่าพิภพไททัน ตอนที่ 1"), Some(Script::Thai));
+    }
+}
@@ -61,6 +61,28 @@ impl Season {
         };
         Ok(s)
     }
+
+    /// Parses the lowercase season name used by the JSON API
+    pub fn from_api_str(s: &str) -> Result<Self> {
+        let s = match s {
+            "winter" => Self::Winter,
+            "spring" => Self::Spring,
+            "summer" => Self::Summer,
+            "fall" => Self::Fall,
+            _ => return Err(anyhow!("invalid season {}", s)),
+        };
+        Ok(s)
+    }
+
+    /// Returns the lowercase season name used by the JSON API
+    pub fn as_api_str(self) -> &'static str {
+        match self {
+            Self::Winter => "winter",
+            Self::Spring => "spring",
+            Self::Summer => "summer",
+            Self::Fall => "fall",
+        }
+    }
 }
 
 impl Display for Season {
@@ -95,9 +117,13 @@ impl YearSeason {
 
     /// Returns the season at the time of the function call
     pub fn current() -> YearSeason {
-        let today = chrono::Utc::today();
-        let year = today.year() as u16;
-        let season = match today.month() {
+        Self::of(&chrono::Utc::today())
+    }
+
+    /// Returns the season containing a date
+    pub fn of<D: Datelike>(date: &D) -> YearSeason {
+        let year = date.year() as u16;
+        let season = match date.month() {
             1..=3 => Season::Winter,
             4..=6 => Season::Spring,
             7..=9 => Season::Summer,
@@ -160,6 +186,27 @@ impl YearSeason {
         };
         Ok(YearSeason { year, season })
     }
+
+    /// Returns the identifier of this YearSeason used by the JSON API
+    ///
+    /// Example: Spring 2020: 2020-spring
+    pub fn to_api_str(&self) -> String {
+        format!("{}-{}", self.year, self.season.as_api_str())
+    }
+
+    /// Parses a YearSeason from its JSON API identifier
+    pub fn from_api_str(s: &str) -> Result<Self> {
+        let (l, r) = match s.find('-') {
+            Some(p) => (&s[..p], &s[p + 1..]),
+            _ => return Err(anyhow!("invalid year season {}", s)),
+        };
+        let year = match l.parse() {
+            Ok(y) => y,
+            _ => return Err(anyhow!("invalid year season {}", s)),
+        };
+        let season = Season::from_api_str(r)?;
+        Ok(YearSeason { year, season })
+    }
 }
 
 impl Debug for YearSeason {
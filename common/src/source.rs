@@ -0,0 +1,45 @@
+use anyhow::{anyhow, Result};
+
+/// A site from which we ingest torrents
+///
+/// The same torrent can be uploaded to several sources. Such torrents are stored once in
+/// `magnets.torrent` with one row per source in `magnets.torrent_source`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    Nyaa,
+    Anidex,
+    AnimeTosho,
+}
+
+impl Source {
+    pub const ALL: [Source; 3] = [Source::Nyaa, Source::Anidex, Source::AnimeTosho];
+
+    /// Returns the database constant of the source
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::Nyaa => 1,
+            Self::Anidex => 2,
+            Self::AnimeTosho => 3,
+        }
+    }
+
+    /// Parses a database source constant
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            1 => Self::Nyaa,
+            2 => Self::Anidex,
+            3 => Self::AnimeTosho,
+            _ => return Err(anyhow!("invalid source {}", n)),
+        };
+        Ok(v)
+    }
+
+    /// Returns the name of the source as displayed on the site
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Nyaa => "nyaa.si",
+            Self::Anidex => "anidex.info",
+            Self::AnimeTosho => "animetosho.org",
+        }
+    }
+}
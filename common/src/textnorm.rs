@@ -0,0 +1,264 @@
+//! Normalization of text
+//!
+//! All text normalization happens in this module. There are three levels:
+//!
+//! - storage: Text from upstream (nyaa.si titles, AniList names) is stored in NFC form.
+//!   See [storage].
+//! - search: Names are compared in the form returned by [search_fold]. The client-side
+//!   search of the site uses it directly and `static/show_list.js` contains a copy of
+//!   it that must be kept in sync. The matcher uses [matcher_key] which is the ascii
+//!   subset of the same folding. This ensures that the matcher and the site search
+//!   agree on which names are equal. Kana in torrent titles are transliterated by
+//!   [romanize_kana] before the title analyzer splits them.
+//! - display: Text is displayed as stored. Shows are grouped in the show list by
+//!   [display_letter].
+
+use std::borrow::Cow;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Romaji of the hiragana U+3041 to U+3096 (Hepburn)
+///
+/// Katakana are mapped to hiragana before looking them up. The entries of the small
+/// `ya`, `yu`, `yo`, and `tsu` are handled specially by [search_fold].
+const KANA: [&str; 86] = [
+    "a", "a", "i", "i", "u", "u", "e", "e", "o", "o", // ぁ - お
+    "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go", // か - ご
+    "sa", "za", "shi", "ji", "su", "zu", "se", "ze", "so", "zo", // さ - ぞ
+    "ta", "da", "chi", "ji", "", "tsu", "zu", "te", "de", "to", "do", // た - ど
+    "na", "ni", "nu", "ne", "no", // な - の
+    "ha", "ba", "pa", "hi", "bi", "pi", "fu", "bu", "pu", // は - ぷ
+    "he", "be", "pe", "ho", "bo", "po", // へ - ぽ
+    "ma", "mi", "mu", "me", "mo", // ま - も
+    "ya", "ya", "yu", "yu", "yo", "yo", // ゃ - よ
+    "ra", "ri", "ru", "re", "ro", // ら - ろ
+    "wa", "wa", "i", "e", "o", "n", "vu", "ka", "ke", // ゎ - ゖ
+];
+
+const HIRAGANA_START: u32 = 0x3041;
+const HIRAGANA_END: u32 = 0x3096;
+const KATAKANA_OFFSET: u32 = 0x60;
+const SMALL_TSU: char = 'っ';
+const SMALL_YA: char = 'ゃ';
+const SMALL_YU: char = 'ゅ';
+const SMALL_YO: char = 'ょ';
+const LONG_VOWEL: char = 'ー';
+
+/// Normalizes text before it is stored in the database
+pub fn storage(s: &str) -> String {
+    s.nfc().collect()
+}
+
+/// Returns the letter under which a name is listed in the show list
+///
+/// Diacritics are removed so that e.g. `É` is listed under `e`.
+pub fn display_letter(s: &str) -> Option<char> {
+    s.nfkd().next().map(|c| c.to_ascii_lowercase())
+}
+
+/// Returns the key under which the matcher looks up a name
+///
+/// This is [search_fold] restricted to `[a-z0-9]` because the matcher's heap only
+/// supports ascii.
+pub fn matcher_key(s: &str) -> String {
+    let mut key = search_fold(s);
+    key.retain(|c| matches!(c, 'a'..='z' | '0'..='9'));
+    key.shrink_to_fit();
+    key
+}
+
+/// Transliterates the runs of hiragana and katakana in a title to romaji
+///
+/// All other characters are preserved. This allows the title analyzer, which only sees
+/// ascii, to read titles that spell the name of a show in kana.
+pub fn romanize_kana(s: &str) -> Cow<str> {
+    let is_kana = |c: char| c == LONG_VOWEL || to_hiragana(c).is_some();
+    if !s.chars().any(is_kana) {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::new();
+    let mut run = String::new();
+    for c in s.chars() {
+        if is_kana(c) {
+            run.push(c);
+            continue;
+        }
+        res.push_str(&search_fold(&run));
+        run.clear();
+        res.push(c);
+    }
+    res.push_str(&search_fold(&run));
+    Cow::Owned(res)
+}
+
+fn to_hiragana(c: char) -> Option<char> {
+    let n = c as u32;
+    let katakana = HIRAGANA_START + KATAKANA_OFFSET..=HIRAGANA_END + KATAKANA_OFFSET;
+    let n = if katakana.contains(&n) {
+        n - KATAKANA_OFFSET
+    } else {
+        n
+    };
+    if (HIRAGANA_START..=HIRAGANA_END).contains(&n) {
+        std::char::from_u32(n)
+    } else {
+        None
+    }
+}
+
+/// Folds text for searching
+///
+/// - full-width and other compatibility characters are replaced by their canonical
+///   equivalents (NFKC)
+/// - hiragana and katakana are transliterated to romaji
+/// - the text is lowercased and diacritics are removed
+/// - everything that is not alphanumeric is removed
+///
+/// Letters of other scripts, e.g. cyrillic or kanji, are preserved.
+pub fn search_fold(s: &str) -> String {
+    let mut res = String::new();
+    // Set after a small tsu which doubles the next consonant
+    let mut double_next = false;
+    // Whether the last character that was pushed was the end of a kana
+    let mut last_was_kana = false;
+    for c in s.nfkc() {
+        if c == LONG_VOWEL {
+            continue;
+        }
+        let kana = match to_hiragana(c) {
+            Some(k) => k,
+            _ => {
+                double_next = false;
+                last_was_kana = false;
+                for l in c.to_lowercase().nfkd() {
+                    if !is_combining_mark(l) && l.is_alphanumeric() {
+                        res.push(l);
+                    }
+                }
+                continue;
+            }
+        };
+        if kana == SMALL_TSU {
+            double_next = true;
+            continue;
+        }
+        let romaji = KANA[(kana as u32 - HIRAGANA_START) as usize];
+        if matches!(kana, SMALL_YA | SMALL_YU | SMALL_YO)
+            && last_was_kana
+            && res.ends_with('i')
+        {
+            // e.g. きゃ -> kya, しゃ -> sha
+            res.pop();
+            if !(res.ends_with("sh") || res.ends_with("ch") || res.ends_with('j')) {
+                res.push('y');
+            }
+            res.push_str(&romaji[1..]);
+            continue;
+        }
+        if double_next {
+            double_next = false;
+            match romaji.as_bytes().first() {
+                Some(b'c') => res.push('t'),
+                Some(&b) if !matches!(b, b'a' | b'i' | b'u' | b'e' | b'o' | b'n') => {
+                    res.push(b as char)
+                }
+                _ => {}
+            }
+        }
+        res.push_str(romaji);
+        last_was_kana = true;
+    }
+    res
+}
+
+/// Folds the words of a text for full-text search
+///
+/// Unlike [search_fold], word boundaries are kept: The text is split at every character
+/// that is not alphanumeric and the folded words are joined by single spaces. The site
+/// applies this to queries and the processor to the indexed names and titles.
+pub fn search_words(s: &str) -> String {
+    s.split(|c: char| !c.is_alphanumeric())
+        .map(search_fold)
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_width_case_and_diacritics() {
+        assert_eq!(
+            search_fold("ＳＨＩＮＧＥＫＩ no Kyojin"),
+            "shingekinokyojin"
+        );
+        assert_eq!(search_fold("Pokémon: Journeys"), "pokemonjourneys");
+        assert_eq!(search_fold("Ｒｅ：ゼロ"), "rezero");
+    }
+
+    #[test]
+    fn transliterates_kana() {
+        assert_eq!(search_fold("カウボーイビバップ"), "kauboibibappu");
+        assert_eq!(search_fold("しんげきのきょじん"), "shingekinokyojin");
+        assert_eq!(search_fold("ちょっと"), "chotto");
+        assert_eq!(search_fold("ｼｬｰﾛｯﾄ"), "sharotto");
+    }
+
+    #[test]
+    fn romanizes_kana_in_titles() {
+        assert_eq!(
+            romanize_kana("[Group] ゆるキャン△ - 01 [1080p]"),
+            "[Group] yurukyan△ - 01 [1080p]"
+        );
+        assert!(matches!(romanize_kana("Yuru Camp - 01"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn preserves_other_scripts() {
+        assert_eq!(search_fold("Атака Титанов"), "атакатитанов");
+        assert_eq!(search_fold("進撃の巨人"), "進撃no巨人");
+    }
+
+    /// A show that the matcher considers equal to a title must also be found by the
+    /// site search and vice versa
+    #[test]
+    fn matcher_and_site_search_agree() {
+        let pairs = [
+            ("Kaguya-sama wa Kokurasetai", "KAGUYA SAMA WA KOKURASETAI"),
+            ("Shingeki no Kyojin", "しんげき の きょじん"),
+            ("Pokémon", "Pokemon"),
+            ("Ｄｒ．ＳＴＯＮＥ", "Dr. Stone"),
+        ];
+        for &(name, title) in &pairs {
+            assert_eq!(matcher_key(name), matcher_key(title));
+            assert_eq!(search_fold(name), search_fold(title));
+        }
+        for &s in &["Re:Zero − Starting Life", "Атака Титанов 2", "ＪｏＪｏ"]
+        {
+            let ascii: String = search_fold(s).chars().filter(|c| c.is_ascii()).collect();
+            assert_eq!(matcher_key(s), ascii);
+        }
+    }
+
+    #[test]
+    fn storage_is_nfc() {
+        assert_eq!(storage("e\u{301}"), "\u{e9}");
+    }
+
+    #[test]
+    fn display_letter_strips_diacritics() {
+        assert_eq!(display_letter("Élan"), Some('e'));
+        assert_eq!(display_letter(""), None);
+    }
+
+    #[test]
+    fn keeps_word_boundaries() {
+        assert_eq!(
+            search_words("[SubsPlease] Sousou no Frieren - 05 (1080p)"),
+            "subsplease sousou no frieren 05 1080p"
+        );
+        assert_eq!(search_words("ゆるキャン△ SEASON２"), "yurukyan season2");
+        assert_eq!(search_words(" - "), "");
+    }
+}
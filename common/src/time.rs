@@ -1,9 +1,12 @@
-use chrono::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 use std::{
     fmt,
     fmt::{Display, Formatter},
-    time::SystemTime,
+    sync::Mutex,
+    time::Instant,
 };
+use tokio::sync::watch;
 
 pub type StdDuration = std::time::Duration;
 
@@ -11,13 +14,6 @@ pub const MINUTE: StdDuration = StdDuration::from_secs(60);
 
 pub const HOUR: StdDuration = StdDuration::from_secs(60 * 60);
 
-/// Sleeps until `time`
-pub async fn sleep_until(time: SystemTime) {
-    if let Ok(t) = time.duration_since(SystemTime::now()) {
-        tokio::time::delay_for(t).await;
-    }
-}
-
 /// Formats a stdlib Duration like a chrono Duration
 pub struct DurationFmt(pub StdDuration);
 
@@ -29,3 +25,128 @@ impl Display for DurationFmt {
         }
     }
 }
+
+/// A source of time
+///
+/// Production code uses [SystemClock]. Tests use [MockClock] to control the passage of
+/// time.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    /// Returns the current monotonic time
+    fn instant(&self) -> Instant;
+
+    /// Returns the current wall-clock time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Sleeps for `duration`
+    async fn sleep(&self, duration: StdDuration);
+
+    /// Sleeps until the monotonic time `deadline`
+    async fn sleep_until(&self, deadline: Instant) {
+        let now = self.instant();
+        if deadline > now {
+            self.sleep(deadline - now).await;
+        }
+    }
+
+    /// Sleeps until the wall-clock time `time`
+    async fn sleep_until_time(&self, time: DateTime<Utc>) {
+        if let Ok(d) = (time - self.now()).to_std() {
+            self.sleep(d).await;
+        }
+    }
+}
+
+/// The real clock
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn sleep(&self, duration: StdDuration) {
+        tokio::time::delay_for(duration).await;
+    }
+}
+
+/// A clock that only advances when [MockClock::advance] is called
+///
+/// Sleeping tasks wake up once the clock has been advanced past their deadline.
+pub struct MockClock {
+    start_instant: Instant,
+    start_time: DateTime<Utc>,
+    elapsed: Mutex<StdDuration>,
+    tx: watch::Sender<StdDuration>,
+    rx: watch::Receiver<StdDuration>,
+}
+
+impl MockClock {
+    /// Creates a clock whose wall-clock time starts at `start`
+    pub fn new(start: DateTime<Utc>) -> Self {
+        let (tx, rx) = watch::channel(StdDuration::from_secs(0));
+        Self {
+            start_instant: Instant::now(),
+            start_time: start,
+            elapsed: Mutex::new(StdDuration::from_secs(0)),
+            tx,
+            rx,
+        }
+    }
+
+    /// Advances the clock by `duration`
+    pub fn advance(&self, duration: StdDuration) {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        *elapsed += duration;
+        let _ = self.tx.broadcast(*elapsed);
+    }
+
+    fn elapsed(&self) -> StdDuration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl Clock for MockClock {
+    fn instant(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.start_time + Duration::from_std(self.elapsed()).unwrap()
+    }
+
+    async fn sleep(&self, duration: StdDuration) {
+        let deadline = self.elapsed() + duration;
+        let mut rx = self.rx.clone();
+        while self.elapsed() < deadline {
+            if rx.recv().await.is_none() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use futures::poll;
+
+    #[tokio::test]
+    async fn mock_sleep() {
+        let clock = MockClock::new(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0));
+        let mut sleep = clock.sleep(StdDuration::from_secs(10));
+        assert!(poll!(&mut sleep).is_pending());
+        clock.advance(StdDuration::from_secs(9));
+        assert!(poll!(&mut sleep).is_pending());
+        clock.advance(StdDuration::from_secs(1));
+        assert!(poll!(&mut sleep).is_ready());
+        assert_eq!(clock.now(), Utc.ymd(2021, 1, 1).and_hms(0, 0, 10));
+    }
+}
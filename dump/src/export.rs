@@ -0,0 +1,159 @@
+use crate::schema::{get_schema, Table};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{pin_mut, StreamExt};
+use postgres_types::Type;
+use rusqlite::{types::Value, Connection};
+use std::path::Path;
+use tokio_postgres::{
+    binary_copy::{BinaryCopyOutRow, BinaryCopyOutStream},
+    Transaction,
+};
+
+/// The tables of the public export
+///
+/// New tables are private until they are added here. The export must contain the tables
+/// that the site reads in SQLite mode (see `site/src/repo/sqlite.rs`).
+const PUBLIC_TABLES: &[&str] = &[
+    "expected_release",
+    "external_site",
+    "hash_type",
+    "recommended_torrent",
+    "rel_torrent_show",
+    "release_group",
+    "schedule",
+    "show",
+    "show_external_id",
+    "show_format",
+    "show_name",
+    "show_name_type",
+    "source",
+    "torrent",
+    "torrent_source",
+];
+
+/// Indexes for the queries of the site (see `site/src/repo/sqlite.rs`)
+// language=sql
+const INDEXES: &str = "
+    create index show_show_id on show (show_id);
+    create index show_name_show_id on show_name (show_id);
+    create index torrent_torrent_id on torrent (torrent_id);
+    create index torrent_nyaa_id on torrent (nyaa_id);
+    create index torrent_release_group_id on torrent (release_group_id, nyaa_id);
+    create index rel_torrent_show_show_id on rel_torrent_show (show_id, nyaa_id);
+    create index rel_torrent_show_torrent_id on rel_torrent_show (torrent_id);
+    create index torrent_source_torrent_id on torrent_source (torrent_id);
+    create index recommended_torrent_show_id on recommended_torrent (show_id, episode);
+    create index recommended_torrent_torrent_id on recommended_torrent (torrent_id);
+    create index schedule_airs_at on schedule (airs_at);
+";
+
+/// Exports the public tables into a single SQLite file
+///
+/// The tables and columns are the same as in postgres. Types are mapped as follows:
+///
+/// - `bool`, `int4`, `int8`: integer
+/// - `timestamptz`: integer (unix timestamp)
+/// - `text`, `jsonb`: text
+/// - `date`: text (`YYYY-MM-DD`)
+/// - `bytea`: blob
+///
+/// The site can serve its pages from this file, see the `db.sqlite` option of the site.
+pub async fn export_sqlite(location: &str, tran: &Transaction<'_>) -> Result<()> {
+    let path = Path::new(location);
+    if path.exists() {
+        return Err(anyhow!("error: {} already exists", location));
+    }
+    let schema = get_schema(tran).await?;
+    let mut con = Connection::open(path)
+        .with_context(|| anyhow!("cannot create sqlite database {}", location))?;
+    let sqlite = con.transaction()?;
+    for table in &schema.tables {
+        if !PUBLIC_TABLES.contains(&&*table.name) {
+            continue;
+        }
+        export_table(table, tran, &sqlite)
+            .await
+            .with_context(|| anyhow!("cannot export table {}", table.name))?;
+    }
+    sqlite
+        .execute_batch(INDEXES)
+        .context("cannot create indexes")?;
+    sqlite.commit()?;
+    Ok(())
+}
+
+async fn export_table(
+    table: &Table,
+    tran: &Transaction<'_>,
+    sqlite: &Connection,
+) -> Result<()> {
+    let mut columns = vec![];
+    for column in &table.columns {
+        columns.push(format!("{} {}", column.name, sqlite_type(&column.ty)?));
+    }
+    sqlite.execute_batch(&format!(
+        "create table {} ({});",
+        table.name,
+        columns.join(", ")
+    ))?;
+
+    let placeholders = vec!["?"; table.columns.len()].join(", ");
+    let mut insert = sqlite.prepare(&format!(
+        "insert into {} values ({})",
+        table.name, placeholders
+    ))?;
+
+    let stmt = format!("copy magnets.{} to stdout binary", table.name);
+    let stream = tran.copy_out(&*stmt).await?;
+    let types: Vec<_> = table.columns.iter().map(|c| c.ty.clone()).collect();
+    let reader = BinaryCopyOutStream::new(stream, &types);
+    pin_mut!(reader);
+    while let Some(row) = reader.next().await {
+        let row = row?;
+        let values: Vec<_> = types
+            .iter()
+            .enumerate()
+            .map(|(idx, ty)| value(&row, idx, ty))
+            .collect();
+        insert.execute(values)?;
+    }
+    Ok(())
+}
+
+fn sqlite_type(ty: &Type) -> Result<&'static str> {
+    let res = match *ty {
+        Type::BOOL | Type::INT4 | Type::INT8 | Type::TIMESTAMPTZ => "integer",
+        Type::TEXT | Type::JSONB | Type::DATE => "text",
+        Type::FLOAT8 => "real",
+        Type::BYTEA => "blob",
+        ref t => return Err(anyhow!("cannot export type {}", t)),
+    };
+    Ok(res)
+}
+
+fn value(row: &BinaryCopyOutRow, idx: usize, ty: &Type) -> Value {
+    let res = match *ty {
+        Type::BOOL => row
+            .get::<Option<bool>>(idx)
+            .map(|v| Value::Integer(v as i64)),
+        Type::INT4 => row
+            .get::<Option<i32>>(idx)
+            .map(|v| Value::Integer(v as i64)),
+        Type::INT8 => row.get::<Option<i64>>(idx).map(Value::Integer),
+        Type::FLOAT8 => row.get::<Option<f64>>(idx).map(Value::Real),
+        Type::TIMESTAMPTZ => row
+            .get::<Option<DateTime<Utc>>>(idx)
+            .map(|v| Value::Integer(v.timestamp())),
+        Type::TEXT => row.get::<Option<String>>(idx).map(Value::Text),
+        Type::JSONB => row
+            .get::<Option<serde_json::Value>>(idx)
+            .map(|v| Value::Text(v.to_string())),
+        Type::DATE => row
+            .get::<Option<NaiveDate>>(idx)
+            .map(|v| Value::Text(v.to_string())),
+        Type::BYTEA => row.get::<Option<Vec<u8>>>(idx).map(Value::Blob),
+        ref t => unreachable!("cannot export type {}", t),
+    };
+    res.unwrap_or(Value::Null)
+}
@@ -1,6 +1,7 @@
 #![deny(unused_must_use)]
 
 mod dump;
+mod export;
 mod load;
 mod pg;
 mod schema;
@@ -34,6 +35,19 @@ async fn main() -> Result<()> {
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .subcommand(SubCommand::with_name("dump").about("Dumps the database"))
         .subcommand(SubCommand::with_name("load").about("Loads the database"))
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports the public tables to a single file")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Sets the format of the export")
+                        .takes_value(true)
+                        .possible_values(&["sqlite"])
+                        .required(true),
+                ),
+        )
         .get_matches();
     let location = matches.value_of("location").unwrap();
     let connection_string = matches.value_of("connection_string").unwrap();
@@ -43,6 +57,10 @@ async fn main() -> Result<()> {
     match matches.subcommand() {
         ("dump", _) => dump::dump(location, &tran).await?,
         ("load", _) => load::load(location, &tran).await?,
+        ("export", Some(m)) => match m.value_of("format").unwrap() {
+            "sqlite" => export::export_sqlite(location, &tran).await?,
+            _ => unreachable!(),
+        },
         _ => unreachable!(),
     }
     tran.commit().await?;
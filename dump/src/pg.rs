@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use bytes::BufMut;
+use chrono::NaiveDate;
 use common::time::StdDuration;
 use memchr::memchr3;
 use postgres_types::{accepts, to_sql_checked};
@@ -69,7 +70,9 @@ map! {
     TEXT => Text,
     INT4 => Int4,
     INT8 => Int8,
+    FLOAT8 => Float8,
     TIMESTAMPTZ => Timestamptz,
+    DATE => Date,
     JSONB => Json,
     BYTEA => Bytea,
     BOOL => Bool,
@@ -172,6 +175,23 @@ macro_rules! int {
 int!(Int4, i32);
 int!(Int8, i64);
 
+pub struct Float8;
+
+impl<W: Write, G: GenericRow> Serializer<W, G> for Float8 {
+    fn serialize(&self, w: &mut W, row: &G, idx: usize) -> Result<()> {
+        // The Display implementation of f64 round-trips through FromStr
+        plain::<_, f64, G>(w, row, idx)
+    }
+}
+
+impl Deserializer for Float8 {
+    fn read(&self, line: &str) -> Result<Box<dyn ToSql + Sync>> {
+        null_check!(line);
+        let f: f64 = line.parse()?;
+        Ok(Box::new(f))
+    }
+}
+
 #[derive(Debug)]
 pub struct Null;
 
@@ -311,6 +331,21 @@ impl Deserializer for Timestamptz {
     }
 }
 
+pub struct Date;
+
+impl<W: Write, G: GenericRow> Serializer<W, G> for Date {
+    fn serialize(&self, w: &mut W, row: &G, idx: usize) -> Result<()> {
+        plain::<_, NaiveDate, G>(w, row, idx)
+    }
+}
+
+impl Deserializer for Date {
+    fn read(&self, line: &str) -> Result<Box<dyn ToSql + Sync>> {
+        null_check!(line);
+        Ok(Box::new(NaiveDate::from_str(line)?))
+    }
+}
+
 pub struct Json;
 
 impl<W: Write, G: GenericRow> Serializer<W, G> for Json {
@@ -0,0 +1,36 @@
+use crate::state::State;
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// Reports a problem that requires the attention of an operator
+///
+/// The alert is always logged. If `alerts.webhook_url` is set, it is also posted as
+/// `{"text": "..."}`, the format of Slack and Mattermost incoming webhooks. Failures to
+/// deliver the alert are logged and otherwise ignored.
+pub async fn send(state: &State<'_>, text: &str) {
+    log::warn!("alert: {}", text);
+    let url = match &state.config.alerts.webhook_url {
+        Some(url) => url,
+        _ => return,
+    };
+    if let Err(e) = post(state, url, text).await {
+        log::error!("could not deliver alert to the webhook: {:#}", e);
+    }
+}
+
+#[derive(Serialize)]
+struct Body<'a> {
+    text: &'a str,
+}
+
+async fn post(state: &State<'_>, url: &str, text: &str) -> Result<()> {
+    state
+        .web_client
+        .post(url)
+        .json(&Body { text })
+        .send()
+        .await
+        .context("cannot send the request")?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use crate::{
+    db_state,
+    db_state::{ALIAS_SUGGESTIONS, REMATCH_UNMATCHED},
+    job_lock,
+    job_lock::Job,
+    show_db::ShowDb,
+    state::State,
+    title_analyzer,
+    title_analyzer::{Candidate, MatchError, Reason},
+};
+use anyhow::Result;
+use common::{pg, textnorm, ShowNameType};
+use isnt::std_1::vec::IsntVecExt;
+use std::collections::HashMap;
+use tokio_postgres::GenericClient;
+
+/// Clusters with fewer unmatched torrents are not worth reviewing
+const MIN_TORRENTS: i32 = 2;
+/// Near misses with more candidates are too vague to suggest an alias
+const MAX_CANDIDATES: usize = 3;
+
+/// Computes or applies the alias suggestions when `alias_suggestions` is set
+///
+/// 1 replaces the pending suggestions with suggestions for the current unmatched
+/// torrents. 2 adds the approved aliases as additional names of their shows and
+/// rematches the unmatched torrents. See /admin/alias-suggestions on the site.
+pub async fn watch_alias_suggestions(state: &State<'_>) {
+    loop {
+        state.db_watcher.alias_suggestions.notified().await;
+        let mode: i32 = match get_mode(state).await {
+            Ok(m) => m,
+            Err(e) => {
+                log::error!("could not get alias_suggestions, assuming 0: {:#}", e);
+                0
+            }
+        };
+        let res = match mode {
+            0 => continue,
+            1 => store(state).await,
+            2 => apply(state).await,
+            _ => {
+                log::error!("database contains unknown alias_suggestions mode {}", mode);
+                continue;
+            }
+        };
+        if let Err(e) = res {
+            log::error!("could not handle alias_suggestions mode {}: {:#}", mode, e);
+        }
+    }
+}
+
+async fn get_mode(state: &State<'_>) -> Result<i32> {
+    let con = state.pg.borrow().await?;
+    db_state::get(&**con, ALIAS_SUGGESTIONS).await
+}
+
+/// Unmatched torrents whose names have the same matcher key
+struct Cluster {
+    /// The name extracted from the first title in the cluster
+    alias: String,
+    key: String,
+    example_title: String,
+    torrents: i32,
+    /// The shows of the prefix search that are near misses of the key
+    candidates: Vec<Candidate>,
+}
+
+/// Clusters the unmatched titles that are near misses of the prefix search
+///
+/// Titles whose name matches multiple shows exactly or no show at all are ignored. An
+/// alias would not help the former and there is nothing to suggest for the latter.
+fn cluster(show_db: &ShowDb, titles: &[String]) -> Vec<Cluster> {
+    let mut clusters: HashMap<String, Cluster> = HashMap::new();
+    for title in titles {
+        let (name, key, mut candidates) = match title_analyzer::find_show(show_db, title)
+        {
+            Err(MatchError {
+                reason:
+                    Reason::NoMatch {
+                        name,
+                        key,
+                        candidates,
+                    },
+                ..
+            }) => (name, key, candidates),
+            _ => continue,
+        };
+        if let Some(cluster) = clusters.get_mut(&key) {
+            cluster.torrents += 1;
+            continue;
+        }
+        candidates.retain(|c| is_near_miss(&key, c));
+        clusters.insert(
+            key.clone(),
+            Cluster {
+                alias: name,
+                key,
+                example_title: title.clone(),
+                torrents: 1,
+                candidates,
+            },
+        );
+    }
+    clusters
+        .into_iter()
+        .map(|(_, c)| c)
+        .filter(|c| {
+            c.torrents >= MIN_TORRENTS
+                && c.candidates.is_not_empty()
+                && c.candidates.len() <= MAX_CANDIDATES
+        })
+        .collect()
+}
+
+/// Returns whether one of the names of the candidate shares at least half of the key
+///
+/// The prefix search returns the shows below the longest prefix of the key in the
+/// heap. If that prefix is short, the candidates are unrelated to the alias.
+fn is_near_miss(key: &str, candidate: &Candidate) -> bool {
+    candidate.names.iter().any(|name| {
+        let name = textnorm::matcher_key(name);
+        let common = key
+            .bytes()
+            .zip(name.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        2 * common >= key.len()
+    })
+}
+
+/// Replaces the pending suggestions
+///
+/// Rejected and applied suggestions are kept so that they are not suggested again.
+async fn store(state: &State<'_>) -> Result<()> {
+    log::info!("computing the alias suggestions");
+    let show_db = state.show_db.get().await?;
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    job_lock::lock(&tran, Job::AliasSuggestions).await?;
+    let titles = load_unmatched(&tran).await?;
+    let clusters = cluster(&show_db, &titles);
+    let mut aliases = vec![];
+    let mut keys = vec![];
+    let mut show_ids = vec![];
+    let mut torrents = vec![];
+    let mut example_titles = vec![];
+    for cluster in &clusters {
+        for candidate in &cluster.candidates {
+            aliases.push(&*cluster.alias);
+            keys.push(&*cluster.key);
+            show_ids.push(candidate.show_id);
+            torrents.push(cluster.torrents);
+            example_titles.push(&*cluster.example_title);
+        }
+    }
+    // language=sql
+    tran.execute("delete from magnets.alias_suggestion where status = 0", &[])
+        .await?;
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.alias_suggestion
+            (alias, alias_key, show_id, torrents, example_title)
+        select *
+        from unnest($1::text[], $2::text[], $3::bigint[], $4::int[], $5::text[])
+        on conflict (alias_key, show_id) do nothing",
+        &[&aliases, &keys, &show_ids, &torrents, &example_titles],
+    )
+    .await?;
+    db_state::set(&tran, ALIAS_SUGGESTIONS, 0).await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    log::info!(
+        "suggested {} aliases for {} clusters of {} unmatched torrents",
+        aliases.len(),
+        clusters.len(),
+        titles.len()
+    );
+    Ok(())
+}
+
+async fn load_unmatched(pg: &impl GenericClient) -> Result<Vec<String>> {
+    // language=sql
+    let rows = pg
+        .query(
+            "select title from magnets.torrent where not matched order by nyaa_id",
+            &[],
+        )
+        .await?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// Adds the approved aliases as additional names of their shows
+async fn apply(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    job_lock::lock(&tran, Job::AliasSuggestions).await?;
+    // Suggestions of shows that have been removed since the suggestions were computed
+    // are applied without effect.
+    // language=sql
+    let rows = tran
+        .query(
+            "
+            update magnets.alias_suggestion a
+            set status = 3
+            where a.status = 1
+            returning
+                a.show_id,
+                a.alias,
+                exists (
+                    select *
+                    from magnets.show s
+                    where s.show_id = a.show_id and s.removal is null
+                )",
+            &[],
+        )
+        .await?;
+    let mut added = 0;
+    for row in rows.iter().filter(|r| r.get::<_, bool>(2)) {
+        let name = textnorm::storage(row.get(1));
+        // language=sql
+        added += tran
+            .execute(
+                "
+                insert into magnets.show_name (show_id, show_name_type, name)
+                select $1, $2, $3
+                where not exists (
+                    select *
+                    from magnets.show_name
+                    where show_id = $1 and name = $3
+                )",
+                &[&row.get::<_, i64>(0), &ShowNameType::ADDITIONAL, &name],
+            )
+            .await?;
+    }
+    if added > 0 {
+        // Delivered on commit
+        tran.batch_execute("notify show_change").await?;
+        // The matcher reloads the show db before rematching
+        // language=sql
+        tran.execute(
+            "update magnets.state set value = '1' where key = $1 and value = '0'",
+            &[&REMATCH_UNMATCHED],
+        )
+        .await?;
+    }
+    db_state::set(&tran, ALIAS_SUGGESTIONS, 0).await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    log::info!("added {} of {} approved aliases", added, rows.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::show_db::test_db;
+
+    #[test]
+    fn clusters_near_misses() {
+        let db = test_db(
+            &[
+                (1, &["Kimetsu no Yaiba"], false),
+                (2, &["Kimetsu no Yaiba Yuukaku Hen"], false),
+            ],
+            &[],
+        );
+        let titles: Vec<_> = [
+            "[A] Kimetsu no Yaiba Mugen - 01",
+            "[B] Kimetsu no Yaiba Mugen - 02",
+            // Only one torrent
+            "[A] Kimetsu no Yaiba Katana - 01",
+            // The candidates only share "kim" with the key
+            "[A] Kimi ni Todoke - 01",
+            "[B] Kimi ni Todoke - 02",
+            // Matched
+            "[A] Kimetsu no Yaiba - 01",
+        ]
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+        let clusters = cluster(&db, &titles);
+        assert_eq!(clusters.len(), 1);
+        let cluster = &clusters[0];
+        assert!(cluster.key.starts_with("kimetsunoyaibamugen"));
+        assert_eq!(cluster.torrents, 2);
+        assert_eq!(cluster.example_title, "[A] Kimetsu no Yaiba Mugen - 01");
+        let mut show_ids: Vec<_> = cluster.candidates.iter().map(|c| c.show_id).collect();
+        show_ids.sort_unstable();
+        assert_eq!(show_ids, [1, 2]);
+    }
+
+    #[test]
+    fn detects_near_misses() {
+        let candidate = Candidate {
+            show_id: 1,
+            names: vec!["Other".to_string(), "Kimetsu no Yaiba".to_string()],
+        };
+        assert!(is_near_miss("kimetsunoyaibamugen", &candidate));
+        // Exactly half of the key
+        assert!(is_near_miss("kimetsunoyaibaxxxxxxxxxxxxxx", &candidate));
+        assert!(!is_near_miss("kimetsunoyaibaxxxxxxxxxxxxxxx", &candidate));
+        assert!(!is_near_miss("kiminitodoke", &candidate));
+    }
+}
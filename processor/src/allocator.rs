@@ -2,6 +2,7 @@ use isnt::std_1::primitive::IsntMutPtrExt;
 use std::{
     alloc::{GlobalAlloc, Layout, System},
     ptr,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
 };
 
 /// Very space-efficient allocator for linux
@@ -18,16 +19,41 @@ static GLOBAL: Allocator = Allocator;
 
 const PAGE_SIZE: usize = 4096;
 
+/// The number of bytes currently allocated through [GLOBAL]
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of bytes currently allocated on the heap
+pub fn allocated() -> usize {
+    ALLOCATED.load(Relaxed)
+}
+
+/// Returns free memory at the top of the malloc heap to the system
+pub fn trim() {
+    unsafe {
+        libc::malloc_trim(0);
+    }
+}
+
+fn track(old_size: usize, new_size: usize, ptr: *mut u8) -> *mut u8 {
+    if ptr.is_not_null() {
+        ALLOCATED.fetch_add(new_size, Relaxed);
+        ALLOCATED.fetch_sub(old_size, Relaxed);
+    }
+    ptr
+}
+
 unsafe impl GlobalAlloc for Allocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if layout.size() >= PAGE_SIZE {
+        let ptr = if layout.size() >= PAGE_SIZE {
             mmap(layout.size())
         } else {
             System.alloc(layout)
-        }
+        };
+        track(0, layout.size(), ptr)
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Relaxed);
         if layout.size() >= PAGE_SIZE {
             libc::munmap(ptr as *mut _, layout.size());
         } else {
@@ -36,14 +62,22 @@ unsafe impl GlobalAlloc for Allocator {
     }
 
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        if layout.size() >= PAGE_SIZE {
+        let ptr = if layout.size() >= PAGE_SIZE {
             mmap(layout.size())
         } else {
             System.alloc_zeroed(layout)
-        }
+        };
+        track(0, layout.size(), ptr)
     }
 
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = self.realloc_(ptr, layout, new_size);
+        track(layout.size(), new_size, new)
+    }
+}
+
+impl Allocator {
+    unsafe fn realloc_(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
         #[allow(clippy::collapsible_if)]
         if layout.size() >= PAGE_SIZE {
             if new_size >= PAGE_SIZE {
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use serde_json::Value;
+use tokio_postgres::{types::Json, Transaction};
+
+/// Recorded as the actor of the changes that are made on the command line
+pub const ACTOR: &str = "processor";
+
+/// Records a change that was made on the command line in `magnets.audit_log`
+///
+/// Must be called in the transaction that makes the change so that the entry is
+/// committed with it. `action` is named like the actions of the admin site, e.g.
+/// `assign-match`.
+pub async fn record(
+    tran: &Transaction<'_>,
+    action: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<()> {
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.audit_log (actor, action, before, after)
+        values ($1, $2, $3, $4)",
+        &[&ACTOR, &action, &before.map(Json), &after.map(Json)],
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::{config::Config, matcher, metadata, show_db};
+use anyhow::Result;
+use common::{check::step, pg::PgConnector};
+
+/// Verifies that the processor can start
+///
+/// Loads the config, connects to postgres, and prepares all statements. The outcome of
+/// each step is printed and the exit code is non-zero if a step fails. Meant to run
+/// before a new deployment takes over.
+///
+/// Usage: `processor check`
+pub fn check() -> Result<()> {
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(check_())
+}
+
+async fn check_() -> Result<()> {
+    let config: Config = step("config", async { common::config::load() }).await?;
+    let connector = PgConnector::new(config.db.connection_string.clone());
+    let con = step("postgres", connector.connect()).await?;
+    step("statements", async {
+        matcher::check_statements(&con).await?;
+        show_db::check_statements(&con).await?;
+        metadata::schedule::check_statements(&con).await?;
+        metadata::shows::check_statements(&con).await
+    })
+    .await?;
+    Ok(())
+}
@@ -1,5 +1,7 @@
-use common::time::StdDuration;
+use crate::title_analyzer::Analyzer;
+use common::{config::UserAgent, flags::FlagConfig, time::StdDuration, Resolution};
 use serde::{de::Error, Deserialize, Deserializer};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -7,6 +9,37 @@ pub struct Config {
     pub anilist: Anilist,
     pub nyaa: Nyaa,
     pub http: Http,
+    pub user_agent: UserAgent,
+    #[serde(default)]
+    pub covers: Covers,
+    #[serde(default)]
+    pub releases: Releases,
+    #[serde(default)]
+    pub metrics: Metrics,
+    #[serde(default)]
+    pub standby: Standby,
+    #[serde(default)]
+    pub memory: Memory,
+    #[serde(default)]
+    pub export: Export,
+    #[serde(default)]
+    pub search: Search,
+    #[serde(default)]
+    pub matcher: Matcher,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub alerts: Alerts,
+    #[serde(default)]
+    pub heartbeat: Heartbeat,
+    #[serde(default)]
+    pub recommend: Recommend,
+    #[serde(default)]
+    pub webhooks: Webhooks,
+    #[serde(default)]
+    pub watchlists: Watchlists,
+    #[serde(default)]
+    pub flags: FlagConfig,
 }
 
 #[derive(Debug, Deserialize)]
@@ -16,11 +49,21 @@ pub struct Db {
 
 #[derive(Debug, Deserialize)]
 pub struct Http {
-    pub user_agent: String,
+    #[serde(
+        default = "default_http_cache_ttl",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub cache_ttl: StdDuration,
+}
+
+fn default_http_cache_ttl() -> StdDuration {
+    StdDuration::from_secs(24 * 60 * 60)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Anilist {
+    #[serde(default = "default_anilist_url")]
+    pub url: String,
     #[serde(deserialize_with = "deserialize_duration")]
     pub startup_grace_period: StdDuration,
     #[serde(deserialize_with = "deserialize_duration")]
@@ -29,10 +72,535 @@ pub struct Anilist {
     pub shows_poll_interval: StdDuration,
 }
 
+fn default_anilist_url() -> String {
+    "https://graphql.anilist.co".to_string()
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Nyaa {
+    #[serde(default = "default_nyaa_url")]
+    pub url: String,
+    #[serde(default)]
+    pub format: ListingFormat,
     #[serde(deserialize_with = "deserialize_duration")]
     pub scrape_interval: StdDuration,
+    #[serde(default)]
+    pub shadow_analyzer: Option<Analyzer>,
+    #[serde(
+        default = "default_trusted_refresh_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub trusted_refresh_interval: StdDuration,
+    #[serde(default = "default_trusted_refresh_batch")]
+    pub trusted_refresh_batch: i64,
+    #[serde(
+        default = "default_size_reparse_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub size_reparse_interval: StdDuration,
+    #[serde(
+        default = "default_swarm_refresh_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub swarm_refresh_interval: StdDuration,
+    #[serde(default = "default_swarm_refresh_pages")]
+    pub swarm_refresh_pages: u32,
+    #[serde(default)]
+    pub mirrors: Vec<Mirror>,
+}
+
+/// How the listing of a source is read
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingFormat {
+    /// The HTML table
+    Html,
+    /// The RSS feed. It only contains the newest torrents but does not change when the
+    /// layout of the site changes.
+    Rss,
+}
+
+impl Default for ListingFormat {
+    fn default() -> Self {
+        ListingFormat::Html
+    }
+}
+
+fn default_trusted_refresh_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+fn default_trusted_refresh_batch() -> i64 {
+    50
+}
+
+fn default_size_reparse_interval() -> StdDuration {
+    StdDuration::from_secs(10 * 60)
+}
+
+fn default_swarm_refresh_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+fn default_swarm_refresh_pages() -> u32 {
+    10
+}
+
+fn default_nyaa_url() -> String {
+    "https://nyaa.si".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Mirror {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub format: ListingFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Covers {
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+    #[serde(
+        default = "default_covers_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+}
+
+impl Default for Covers {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            poll_interval: default_covers_poll_interval(),
+        }
+    }
+}
+
+fn default_covers_poll_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Releases {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(
+        default = "default_releases_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+}
+
+impl Default for Releases {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval: default_releases_poll_interval(),
+        }
+    }
+}
+
+fn default_releases_poll_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Standby {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(
+        default = "default_standby_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+}
+
+impl Default for Standby {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval: default_standby_poll_interval(),
+        }
+    }
+}
+
+fn default_standby_poll_interval() -> StdDuration {
+    StdDuration::from_secs(10)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Memory {
+    #[serde(default)]
+    pub budget_mib: Option<usize>,
+    #[serde(
+        default = "default_memory_check_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub check_interval: StdDuration,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self {
+            budget_mib: None,
+            check_interval: default_memory_check_interval(),
+        }
+    }
+}
+
+fn default_memory_check_interval() -> StdDuration {
+    StdDuration::from_secs(60)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Export {
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+    #[serde(
+        default = "default_export_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            poll_interval: default_export_poll_interval(),
+        }
+    }
+}
+
+fn default_export_poll_interval() -> StdDuration {
+    StdDuration::from_secs(60)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Search {
+    #[serde(
+        default = "default_search_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            poll_interval: default_search_poll_interval(),
+        }
+    }
+}
+
+fn default_search_poll_interval() -> StdDuration {
+    StdDuration::from_secs(60)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Matcher {
+    /// The number of seasons whose shows are tried before all other shows
+    pub recent_seasons: Option<u32>,
+    /// Alerting on a low match rate. Disabled if not set.
+    pub anomaly: Option<Anomaly>,
+}
+
+impl Matcher {
+    /// The time between checks of the match rate
+    pub fn anomaly_interval(&self) -> StdDuration {
+        self.anomaly
+            .as_ref()
+            .map(|a| a.interval)
+            .unwrap_or_else(default_anomaly_interval)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Anomaly {
+    /// The share of matched torrents below which an alert is sent
+    pub threshold: f64,
+    /// The number of newest torrents over which the share is computed
+    #[serde(default = "default_anomaly_window")]
+    pub window: i64,
+    /// The time between checks
+    #[serde(
+        default = "default_anomaly_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: StdDuration,
+}
+
+fn default_anomaly_window() -> i64 {
+    500
+}
+
+fn default_anomaly_interval() -> StdDuration {
+    StdDuration::from_secs(10 * 60)
+}
+
+/// The providers that keep the current season up to date while the anilist sync is stale
+#[derive(Debug, Deserialize)]
+pub struct Metadata {
+    /// In order of priority. No provider is used if this is empty.
+    #[serde(default)]
+    pub providers: Vec<ProviderName>,
+    /// The age of the last complete anilist sync after which the providers are used
+    #[serde(
+        default = "default_stale_after",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub stale_after: StdDuration,
+    #[serde(
+        default = "default_metadata_check_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub check_interval: StdDuration,
+    #[serde(default = "default_kitsu_url")]
+    pub kitsu_url: String,
+    #[serde(default = "default_anidb_titles_url")]
+    pub anidb_titles_url: String,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            providers: vec![],
+            stale_after: default_stale_after(),
+            check_interval: default_metadata_check_interval(),
+            kitsu_url: default_kitsu_url(),
+            anidb_titles_url: default_anidb_titles_url(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderName {
+    Anilist,
+    Kitsu,
+    Anidb,
+}
+
+fn default_stale_after() -> StdDuration {
+    StdDuration::from_secs(2 * 24 * 60 * 60)
+}
+
+fn default_metadata_check_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+fn default_kitsu_url() -> String {
+    "https://kitsu.io/api/edge".to_string()
+}
+
+fn default_anidb_titles_url() -> String {
+    "https://anidb.net/api/anime-titles.dat.gz".to_string()
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Alerts {
+    pub webhook_url: Option<String>,
+}
+
+/// See [crate::heartbeat]
+#[derive(Debug, Deserialize)]
+pub struct Heartbeat {
+    #[serde(
+        default = "default_heartbeat_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: StdDuration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: default_heartbeat_interval(),
+        }
+    }
+}
+
+fn default_heartbeat_interval() -> StdDuration {
+    StdDuration::from_secs(30)
+}
+
+/// The weights of the score by which the recommended torrent of an episode is chosen.
+/// See [crate::recommend].
+#[derive(Debug, Deserialize)]
+pub struct Recommend {
+    #[serde(
+        default = "default_recommend_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+    /// Added if the uploader is trusted
+    #[serde(default = "default_recommend_trusted")]
+    pub trusted: f64,
+    /// Added depending on the resolution in the title
+    #[serde(
+        default = "default_recommend_resolutions",
+        deserialize_with = "deserialize_resolutions"
+    )]
+    pub resolutions: Vec<(Resolution, f64)>,
+    /// The release groups whose torrents get `group` added
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default = "default_recommend_group")]
+    pub group: f64,
+    /// Multiplied with `ln(1 + seeders)`
+    #[serde(default = "default_recommend_seeders")]
+    pub seeders: f64,
+}
+
+impl Default for Recommend {
+    fn default() -> Self {
+        Self {
+            poll_interval: default_recommend_poll_interval(),
+            trusted: default_recommend_trusted(),
+            resolutions: default_recommend_resolutions(),
+            groups: vec![],
+            group: default_recommend_group(),
+            seeders: default_recommend_seeders(),
+        }
+    }
+}
+
+fn default_recommend_poll_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+fn default_recommend_trusted() -> f64 {
+    4.0
+}
+
+fn default_recommend_resolutions() -> Vec<(Resolution, f64)> {
+    vec![(Resolution::P1080, 3.0), (Resolution::P720, 2.0)]
+}
+
+fn default_recommend_group() -> f64 {
+    2.0
+}
+
+fn default_recommend_seeders() -> f64 {
+    1.0
+}
+
+/// See [crate::webhooks]
+#[derive(Debug, Deserialize)]
+pub struct Webhooks {
+    #[serde(
+        default = "default_webhooks_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub poll_interval: StdDuration,
+    /// The number of attempts after which a notification is dropped
+    #[serde(default = "default_webhooks_max_attempts")]
+    pub max_attempts: i32,
+    /// See [crate::notifiers::render_message]
+    #[serde(default = "default_webhooks_message")]
+    pub message: String,
+    pub telegram_bot_token: Option<String>,
+    #[serde(default = "default_telegram_api_url")]
+    pub telegram_api_url: String,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self {
+            poll_interval: default_webhooks_poll_interval(),
+            max_attempts: default_webhooks_max_attempts(),
+            message: default_webhooks_message(),
+            telegram_bot_token: None,
+            telegram_api_url: default_telegram_api_url(),
+        }
+    }
+}
+
+fn default_webhooks_message() -> String {
+    "{show} {episode}\n{title}\n{magnet}".to_string()
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+fn default_webhooks_poll_interval() -> StdDuration {
+    StdDuration::from_secs(10)
+}
+
+fn default_webhooks_max_attempts() -> i32 {
+    10
+}
+
+/// See [crate::watchlists]
+#[derive(Debug, Deserialize)]
+pub struct Watchlists {
+    /// The AniList API client. Logins are not processed if the secret is not set.
+    pub anilist_client_id: Option<i64>,
+    pub anilist_client_secret: Option<String>,
+    /// Must be the redirect URL of the API client
+    #[serde(default = "default_watchlists_redirect_url")]
+    pub redirect_url: String,
+    #[serde(default = "default_anilist_oauth_url")]
+    pub oauth_url: String,
+    #[serde(
+        default = "default_watchlists_login_poll_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub login_poll_interval: StdDuration,
+    #[serde(
+        default = "default_watchlists_sync_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub sync_interval: StdDuration,
+    /// The minimum time between syncing two users so that the shows sync keeps most of
+    /// the rate limit of AniList
+    #[serde(
+        default = "default_watchlists_sync_user_delay",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub sync_user_delay: StdDuration,
+}
+
+impl Default for Watchlists {
+    fn default() -> Self {
+        Self {
+            anilist_client_id: None,
+            anilist_client_secret: None,
+            redirect_url: default_watchlists_redirect_url(),
+            oauth_url: default_anilist_oauth_url(),
+            login_poll_interval: default_watchlists_login_poll_interval(),
+            sync_interval: default_watchlists_sync_interval(),
+            sync_user_delay: default_watchlists_sync_user_delay(),
+        }
+    }
+}
+
+fn default_watchlists_redirect_url() -> String {
+    "https://magnets.moe/login/anilist".to_string()
+}
+
+fn default_anilist_oauth_url() -> String {
+    "https://anilist.co/api/v2/oauth".to_string()
+}
+
+fn default_watchlists_login_poll_interval() -> StdDuration {
+    StdDuration::from_secs(2)
+}
+
+fn default_watchlists_sync_interval() -> StdDuration {
+    StdDuration::from_secs(60 * 60)
+}
+
+fn default_watchlists_sync_user_delay() -> StdDuration {
+    StdDuration::from_secs(5)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Metrics {
+    pub listen_addr: Option<SocketAddr>,
 }
 
 fn deserialize_duration<'de, D>(d: D) -> Result<StdDuration, D::Error>
@@ -48,3 +616,18 @@ where
         ))),
     }
 }
+
+fn deserialize_resolutions<'de, D>(d: D) -> Result<Vec<(Resolution, f64)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let weights: HashMap<String, f64> = Deserialize::deserialize(d)?;
+    let mut res = vec![];
+    for (name, weight) in weights {
+        match Resolution::parse(&name) {
+            Some(r) => res.push((r, weight)),
+            _ => return Err(D::Error::custom(format!("unknown resolution `{}`", name))),
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,102 @@
+use crate::state::State;
+use anyhow::{anyhow, Context, Result};
+use common::{time::MINUTE, COVER_SIZES};
+use image::DynamicImage;
+use std::{fs, path::Path, sync::Arc};
+
+/// Mirrors the AniList cover images of all shows
+///
+/// The covers are downloaded and converted to webp thumbnails which are stored in the
+/// `covers.directory` and served by the site. This way the site does not depend on
+/// hotlinking the AniList CDN. A cover is mirrored again whenever its url changes,
+/// which happens when the shows are refreshed. Covers are not mirrored if the directory
+/// is not set.
+pub async fn mirror_covers(state: &State<'_>) {
+    let dir = match &state.config.covers.directory {
+        Some(d) => d,
+        _ => return,
+    };
+    loop {
+        if state.memory.shedding() {
+            log::info!("not mirroring covers because the memory budget is exceeded");
+        } else if let Err(e) = mirror_covers_now(state, dir).await {
+            log::error!("could not mirror covers: {:#}", e);
+        }
+        state.sleep(|c| c.covers.poll_interval).await;
+    }
+}
+
+async fn mirror_covers_now(state: &State<'_>, dir: &Path) -> Result<()> {
+    let con = state.pg_connector.connect().await?;
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select show_id, cover_url
+            from magnets.show
+            where cover_url is distinct from cover_mirrored_url and cover_url is not null
+                and removal is null",
+            &[],
+        )
+        .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+    log::info!("mirroring {} covers", rows.len());
+    for row in rows {
+        let show_id: i64 = row.get("show_id");
+        let cover_url: String = row.get("cover_url");
+        if let Err(e) = mirror_cover(state, dir, show_id, &cover_url).await {
+            log::warn!("could not mirror cover of show {}: {:#}", show_id, e);
+            // Don't hammer the CDN if it is unavailable
+            tokio::time::delay_for(MINUTE).await;
+            continue;
+        }
+        state.leader.ensure().await?;
+        // language=sql
+        con.execute(
+            "update magnets.show set cover_mirrored_url = $1 where show_id = $2",
+            &[&cover_url, &show_id],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn mirror_cover(
+    state: &State<'_>,
+    dir: &Path,
+    show_id: i64,
+    url: &str,
+) -> Result<()> {
+    let original = state.http_cache.get(url).await?;
+    let dir = dir.to_path_buf();
+    // Decoding and encoding images is expensive. Don't block the runtime.
+    tokio::task::spawn_blocking(move || write_thumbnails(&dir, show_id, &original))
+        .await
+        .map_err(|e| anyhow!("thumbnail task panicked: {}", e))?
+}
+
+fn write_thumbnails(dir: &Path, show_id: i64, original: &Arc<Vec<u8>>) -> Result<()> {
+    let image = image::load_from_memory(original).context("cannot decode cover")?;
+    for &size in COVER_SIZES {
+        let (width, height) = size.dimensions();
+        let thumbnail =
+            DynamicImage::ImageRgba8(image.thumbnail(width, height).to_rgba8());
+        let webp = webp::Encoder::from_image(&thumbnail).encode(80.0);
+        write_atomic(&size.path(dir, show_id), &webp)
+            .with_context(|| format!("cannot write {} thumbnail", size.as_str()))?;
+    }
+    Ok(())
+}
+
+/// Writes a file such that the site never serves a partially written thumbnail
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -1,11 +1,18 @@
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use common::pg::{MessageHandler, PgClient};
+use common::{
+    pg,
+    pg::{MessageHandler, PgClient},
+};
 use paste::paste;
-use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, sync::Arc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
 use tokio::sync::Notify;
-use tokio_postgres::{types::Json, GenericClient};
+use tokio_postgres::{types::Json, GenericClient, SimpleQueryMessage};
 
 macro_rules! states {
     ($($id:ident,)*) => {
@@ -19,29 +26,33 @@ macro_rules! w {
     ($($id:ident,)*) => {
         pub struct DbWatcher {
             $(pub $id: Notify,)*
+            /// The `max_<source>_id` keys of the torrent sources, see [DbWatcher::source]
+            sources: Mutex<HashMap<String, Arc<Notify>>>,
+            /// The values of `magnets.state` as of the last (re)connect
+            known: Mutex<HashMap<String, String>>,
         }
 
         impl DbWatcher {
             pub fn new() -> Arc<Self> {
                 Arc::new(Self {
                     $($id: Notify::new(),)*
+                    sources: Default::default(),
+                    known: Default::default(),
                 })
             }
 
             pub fn notify_all(&self) {
                 $(self.$id.notify();)*
+                for n in self.sources.lock().unwrap().values() {
+                    n.notify();
+                }
             }
 
-            pub fn handle_str(&self, s: &str) {
-                let n = match s {
-                    $(paste!([<$id:upper>]) => &self.$id,)*
-                    _ => {
-                        log::warn!("received unknown state change: {}", s);
-                        return;
-                    }
-                };
-                log::info!("received state change of row {}", s);
-                n.notify();
+            fn get(&self, s: &str) -> Option<&Notify> {
+                match s {
+                    $(paste!([<$id:upper>]) => Some(&self.$id),)*
+                    _ => None,
+                }
             }
         }
     }
@@ -50,19 +61,83 @@ macro_rules! w {
 states! {
     max_nyaa_si_id,
     rematch_unmatched,
+    match_diff,
+    alias_suggestions,
     last_shows_update,
     last_schedule_update,
     initial_setup,
+    flags,
+    matcher_anomaly,
+    processor_heartbeat,
 }
 
+/// The names of all shows for the /shows page as `[show_id, name, show_name_type]`
+/// arrays. Stored in `magnets.state_blob`.
+pub const SHOW_LIST: &str = "show_list";
+
 w! {
-    max_nyaa_si_id,
     rematch_unmatched,
+    match_diff,
+    alias_suggestions,
     last_shows_update,
     last_schedule_update,
+    flags,
 }
 
 impl DbWatcher {
+    /// Returns the notifier of the `max_<source>_id` key of a torrent source
+    ///
+    /// It is notified when the key is lowered, e.g. by hand to scrape the source again.
+    pub fn source(&self, max_id_key: &str) -> Arc<Notify> {
+        self.sources
+            .lock()
+            .unwrap()
+            .entry(max_id_key.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Notifies the waiters of a row. Returns false if nobody watches the row.
+    fn notify(&self, s: &str) -> bool {
+        if let Some(n) = self.get(s) {
+            n.notify();
+            return true;
+        }
+        match self.sources.lock().unwrap().get(s) {
+            Some(n) => {
+                n.notify();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn handle_str(&self, s: &str) {
+        if self.notify(s) {
+            log::info!("received state change of row {}", s);
+        } else {
+            log::warn!("received unknown state change: {}", s);
+        }
+    }
+
+    /// Notifies the rows whose values differ from the values seen at the last call
+    ///
+    /// Called after (re)connecting since notifications sent while the connection was
+    /// down are lost. Notifies all rows on the first call.
+    fn reconcile(&self, values: HashMap<String, String>) {
+        let mut known = self.known.lock().unwrap();
+        if known.is_empty() {
+            self.notify_all();
+        } else {
+            for (key, value) in &values {
+                if known.get(key) != Some(value) && self.notify(key) {
+                    log::info!("state of row {} changed while disconnected", key);
+                }
+            }
+        }
+        *known = values;
+    }
+
     pub fn message_handler(self: &Arc<Self>) -> WatchMessageHandler {
         WatchMessageHandler {
             watcher: self.clone(),
@@ -100,6 +175,50 @@ pub async fn get<P: GenericClient, T: for<'a> Deserialize<'a>>(
     Ok(res.0)
 }
 
+/// Stores a large value compressed in `magnets.state_blob`
+///
+/// Unlike the keys of `magnets.state`, blobs are created on the first write and changes
+/// are not notified.
+pub async fn set_blob<P: GenericClient, T: Serialize + ?Sized>(
+    p: &P,
+    key: &str,
+    value: &T,
+) -> Result<()> {
+    let blob = pg::compress_json(value)?;
+    p.execute(
+        // language=sql
+        "
+        insert into magnets.state_blob (key, value) values ($1, $2)
+        on conflict (key) do update set value = excluded.value, updated = now()",
+        &[&key, &blob],
+    )
+    .await
+    .with_context(|| anyhow!("cannot set database blob {}", key))?;
+    Ok(())
+}
+
+/// Retrieves a value stored with [set_blob]. Returns `None` if it was never stored.
+pub async fn get_blob<P: GenericClient, T: DeserializeOwned>(
+    p: &P,
+    key: &str,
+) -> Result<Option<T>> {
+    let row = p
+        // language=sql
+        .query_opt(
+            "select value from magnets.state_blob where key = $1",
+            &[&key],
+        )
+        .await
+        .with_context(|| anyhow!("cannot retrieve database blob {}", key))?;
+    let blob: Vec<u8> = match row {
+        Some(r) => r.get(0),
+        _ => return Ok(None),
+    };
+    let value = pg::decompress_json(&blob)
+        .with_context(|| anyhow!("cannot decode blob {}", key))?;
+    Ok(Some(value))
+}
+
 #[derive(Clone)]
 pub struct WatchMessageHandler {
     watcher: Arc<DbWatcher>,
@@ -108,11 +227,24 @@ pub struct WatchMessageHandler {
 #[async_trait]
 impl MessageHandler for WatchMessageHandler {
     async fn listen(&self, client: &PgClient) -> Result<()> {
-        client
-            .simple_query("listen state_change")
+        // Listen before reading the values so that no change is missed in between.
+        // Both are sent in a single round trip.
+        let messages = client
+            .simple_query(
+                // language=sql
+                "listen state_change; select key, value::text from magnets.state",
+            )
             .await
             .context("could not execute `listen state_change`")?;
-        self.watcher.notify_all();
+        let mut values = HashMap::new();
+        for message in messages {
+            if let SimpleQueryMessage::Row(row) = message {
+                if let (Some(key), Some(value)) = (row.get(0), row.get(1)) {
+                    values.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        self.watcher.reconcile(values);
         Ok(())
     }
 
@@ -1,8 +1,19 @@
-use crate::config::Config;
+use crate::{
+    config::Config,
+    db_state,
+    db_state::MATCH_DIFF,
+    job_lock,
+    job_lock::Job,
+    matcher::{insert_match, Overrides},
+    show_db::ShowDb,
+    state::State,
+    title_analyzer,
+};
 use anyhow::Result;
-use common::pg::PgConnector;
+use common::{pg, pg::PgConnector};
 use isnt::std_1::vec::IsntVecExt;
 use std::collections::HashMap;
+use tokio_postgres::GenericClient;
 
 /// Calculates the diff between the current state of `magnets.rel_torrent_show` and the
 /// result of rematching all torrents.
@@ -18,85 +29,238 @@ pub fn diff() -> Result<()> {
     Ok(())
 }
 
-enum Diff {
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Diff {
     Add,
     Sub,
 }
 
+/// The changes that a rematch would make to the matches of a torrent
+pub struct TorrentDiff {
+    pub torrent_id: i64,
+    pub nyaa_id: i64,
+    pub title: String,
+    pub changes: Vec<(Diff, i64)>,
+}
+
 async fn async_diff() -> Result<()> {
     let config: Config = common::config::load()?;
     let pg_connector = PgConnector::new(config.db.connection_string);
-    let current = load_current(&pg_connector).await?;
     let show_names = load_show_names(&pg_connector).await?;
-    let show_db = crate::show_db::ShowDbHolder::new(&pg_connector)
-        .get()
-        .await?;
+    let show_db =
+        crate::show_db::ShowDbHolder::new(&pg_connector, config.matcher.recent_seasons)
+            .get()
+            .await?;
     let pg = pg_connector.connect().await?;
+    for torrent in compute(&pg, &show_db).await? {
+        println!("{}", torrent.title);
+        for (diff, show_id) in &torrent.changes {
+            match diff {
+                Diff::Sub => println!("- {} -> {}", torrent.nyaa_id, show_id),
+                Diff::Add => println!("+ {} -> {}", torrent.nyaa_id, show_id),
+            }
+            for name in show_names.get(show_id).unwrap() {
+                println!("    {}", name);
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Calculates the changes that rematching all torrents would make
+pub async fn compute(
+    pg: &impl GenericClient,
+    show_db: &ShowDb,
+) -> Result<Vec<TorrentDiff>> {
+    let current = load_current(pg).await?;
+    let overrides = Overrides::load(pg, i64::MIN, i64::MAX).await?;
     // language=sql
     let torrents = pg
-        .query("select title, nyaa_id from magnets.torrent", &[])
+        .query(
+            "select torrent_id, nyaa_id, title from magnets.torrent",
+            &[],
+        )
         .await?;
+    let mut res = vec![];
     for torrent in torrents {
-        let title = torrent.get("title");
-        let nyaa_id = torrent.get("nyaa_id");
-        let current = current.get(&nyaa_id).map(|v| &**v).unwrap_or(&[]);
+        let torrent_id = torrent.get("torrent_id");
+        let title: String = torrent.get("title");
+        let current = current.get(&torrent_id).map(|v| &**v).unwrap_or(&[]);
+        let found = title_analyzer::find_show(show_db, &title).ok();
+        let new = overrides.apply(torrent_id, &title, found.map(|s| s.show_id));
         let mut diff = vec![];
-        match crate::title_analyzer::find_show(&show_db, title) {
-            Ok(show) => {
-                if current.is_empty() {
-                    diff.push((Diff::Add, show.show_id));
-                } else if current.len() == 1 {
-                    let old_show = current[0];
-                    if show.show_id != old_show {
-                        diff.push((Diff::Sub, old_show));
-                        diff.push((Diff::Add, show.show_id));
-                    }
-                } else {
-                    let mut have_new = false;
-                    for &old_show in current {
-                        if old_show == show.show_id {
-                            have_new = true;
-                        } else {
-                            diff.push((Diff::Sub, old_show));
-                        }
-                    }
-                    if !have_new {
-                        diff.push((Diff::Add, show.show_id));
-                    }
-                }
+        for &old_show in current {
+            if !new.contains(&old_show) {
+                diff.push((Diff::Sub, old_show));
             }
-            _ => {
-                for &old_show in current {
-                    diff.push((Diff::Sub, old_show));
-                }
+        }
+        for &new_show in &new {
+            if !current.contains(&new_show) {
+                diff.push((Diff::Add, new_show));
             }
         }
         if diff.is_not_empty() {
-            println!("{}", title);
-            for (diff, show_id) in &diff {
-                match diff {
-                    Diff::Sub => println!("- {} -> {}", nyaa_id, show_id),
-                    Diff::Add => println!("+ {} -> {}", nyaa_id, show_id),
-                }
-                for name in show_names.get(show_id).unwrap() {
-                    println!("    {}", name);
-                }
+            res.push(TorrentDiff {
+                torrent_id,
+                nyaa_id: torrent.get("nyaa_id"),
+                title,
+                changes: diff,
+            });
+        }
+    }
+    Ok(res)
+}
+
+/// Computes or applies the rematch preview when `match_diff` is set
+///
+/// 1 replaces the contents of `magnets.match_diff` with the current diff. 2 applies the
+/// approved changes. See /admin/rematch-preview on the site.
+pub async fn watch_match_diff(state: &State<'_>) {
+    loop {
+        state.db_watcher.match_diff.notified().await;
+        let mode: i32 = match get_mode(state).await {
+            Ok(m) => m,
+            Err(e) => {
+                log::error!("could not get match_diff, assuming 0: {:#}", e);
+                0
+            }
+        };
+        let res = match mode {
+            0 => continue,
+            1 => store(state).await,
+            2 => apply(state).await,
+            _ => {
+                log::error!("database contains unknown match_diff mode {}", mode);
+                continue;
             }
-            println!();
+        };
+        if let Err(e) = res {
+            log::error!("could not handle match_diff mode {}: {:#}", mode, e);
+        }
+    }
+}
+
+async fn get_mode(state: &State<'_>) -> Result<i32> {
+    let con = state.pg.borrow().await?;
+    db_state::get(&**con, MATCH_DIFF).await
+}
+
+/// Replaces the contents of `magnets.match_diff` with the current diff
+async fn store(state: &State<'_>) -> Result<()> {
+    log::info!("computing the rematch preview");
+    let show_db = state.show_db.get().await?;
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    job_lock::lock(&tran, Job::Rematch).await?;
+    let diffs = compute(&tran, &show_db).await?;
+    let mut torrent_ids = vec![];
+    let mut show_ids = vec![];
+    let mut added = vec![];
+    for torrent in &diffs {
+        for &(diff, show_id) in &torrent.changes {
+            torrent_ids.push(torrent.torrent_id);
+            show_ids.push(show_id);
+            added.push(diff == Diff::Add);
         }
     }
+    // language=sql
+    tran.execute("delete from magnets.match_diff", &[]).await?;
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.match_diff (torrent_id, show_id, added)
+        select * from unnest($1::bigint[], $2::bigint[], $3::bool[])",
+        &[&torrent_ids, &show_ids, &added],
+    )
+    .await?;
+    db_state::set(&tran, MATCH_DIFF, 0).await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    log::info!(
+        "the rematch preview contains {} changes of {} torrents",
+        torrent_ids.len(),
+        diffs.len()
+    );
     Ok(())
 }
 
-async fn load_current(pg_connector: &PgConnector) -> Result<HashMap<i64, Vec<i64>>> {
-    let pg = pg_connector.connect().await?;
+/// Applies the approved changes in `magnets.match_diff` and removes them from the table
+async fn apply(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    job_lock::lock(&tran, Job::Rematch).await?;
+    // language=sql
+    let rows = tran
+        .query(
+            "
+            delete from magnets.match_diff d
+            using magnets.torrent t
+            where d.status = 1 and t.torrent_id = d.torrent_id
+            returning
+                d.torrent_id,
+                d.show_id,
+                d.added,
+                t.title,
+                exists (
+                    select *
+                    from magnets.rel_torrent_show rts
+                    where rts.torrent_id = d.torrent_id and rts.show_id = d.show_id
+                )",
+            &[],
+        )
+        .await?;
+    // Removals first so that a torrent that moves between shows never has an outdated
+    // `matched` flag.
+    let mut removed = vec![];
+    for row in rows.iter().filter(|r| !r.get::<_, bool>(2)) {
+        let torrent_id: i64 = row.get(0);
+        // language=sql
+        tran.execute(
+            "delete from magnets.rel_torrent_show where torrent_id = $1 and show_id = $2",
+            &[&torrent_id, &row.get::<_, i64>(1)],
+        )
+        .await?;
+        removed.push(torrent_id);
+    }
+    // language=sql
+    tran.execute(
+        "
+        update magnets.torrent t
+        set matched = exists (
+            select *
+            from magnets.rel_torrent_show rts
+            where rts.torrent_id = t.torrent_id
+        )
+        where t.torrent_id = any($1)",
+        &[&removed],
+    )
+    .await?;
+    // The torrent might have been matched with the show since the preview was computed.
+    for row in rows
+        .iter()
+        .filter(|r| r.get::<_, bool>(2) && !r.get::<_, bool>(4))
+    {
+        insert_match(&tran, row.get(0), row.get(1), row.get(3)).await?;
+    }
+    db_state::set(&tran, MATCH_DIFF, 0).await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    log::info!("applied {} approved rematch changes", rows.len());
+    Ok(())
+}
+
+async fn load_current(pg: &impl GenericClient) -> Result<HashMap<i64, Vec<i64>>> {
     // language=sql
     let rows = pg
-        .query("select nyaa_id, show_id from magnets.rel_torrent_show", &[])
+        .query(
+            "select torrent_id, show_id from magnets.rel_torrent_show",
+            &[],
+        )
         .await?;
     let mut res = HashMap::new();
     for row in rows {
-        res.entry(row.get("nyaa_id"))
+        res.entry(row.get("torrent_id"))
             .or_insert(vec![])
             .push(row.get("show_id"));
     }
@@ -0,0 +1,266 @@
+use crate::{
+    audit, config::Config, db_state::REMATCH_UNMATCHED, show_lifecycle,
+    show_lifecycle::Removal,
+};
+use anyhow::{anyhow, Result};
+use common::{pg, pg::PgConnector, textnorm, Format, ShowNameType, YearSeason};
+use serde_json::json;
+use tokio_postgres::{types::ToSql, Row, Transaction};
+
+const USAGE: &str = "usage:
+    processor show set <show_id> [--season <year>-<season>] [--format <format>]
+    processor show unset <show_id> [--season] [--format]
+    processor show add-name <show_id> --type additional <name>
+    processor show delete <show_id>
+    processor show merge <show_id> --into <show_id>";
+
+enum Command {
+    /// An edit of a listed show
+    Edit(Edit),
+    Remove(Removal),
+}
+
+enum Edit {
+    Set {
+        season: Option<YearSeason>,
+        format: Option<Format>,
+    },
+    /// Releases the season or format to the AniList sync
+    Unset {
+        season: bool,
+        format: bool,
+    },
+    AddName(String),
+}
+
+/// Corrects the metadata of a show
+///
+/// Invoked as
+///
+/// - `processor show set <show_id> --season 2024-Spring --format TV` to override the
+///   season or format. The AniList sync no longer changes the fields edited this way.
+/// - `processor show unset <show_id> --season --format` to let the AniList sync change
+///   the season or format again. The next sync restores the values from AniList.
+/// - `processor show add-name <show_id> --type additional <name>` to add a name that
+///   the matcher should recognize.
+/// - `processor show delete <show_id>` to remove a show. Its torrents are rematched.
+/// - `processor show merge <show_id> --into <show_id>` to remove a duplicate show. Its
+///   torrents and additional names are moved to the other show and its page redirects
+///   there.
+///
+/// Afterwards the site is notified and unmatched torrents are rematched. All edits are
+/// recorded in `magnets.audit_log`.
+pub fn show(args: &[String]) -> Result<()> {
+    let (show_id, command) = parse(args)?;
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async_edit(show_id, &command))?;
+    println!("updated show {}", show_id);
+    Ok(())
+}
+
+fn parse(args: &[String]) -> Result<(i64, Command)> {
+    let (command, show_id, mut rest) = match args {
+        [command, show_id, rest @ ..] => match show_id.parse() {
+            Ok(id) => (command, id, rest),
+            _ => return Err(anyhow!("invalid show id {}", show_id)),
+        },
+        _ => return Err(anyhow!(USAGE)),
+    };
+    let command = match &**command {
+        "set" => {
+            let mut season = None;
+            let mut format = None;
+            while let [flag, value, tail @ ..] = rest {
+                match &**flag {
+                    "--season" => {
+                        season = Some(YearSeason::from_api_str(&value.to_lowercase())?)
+                    }
+                    "--format" => {
+                        format = Some(Format::from_anilist(
+                            &value.to_uppercase().replace('-', "_"),
+                        )?)
+                    }
+                    _ => return Err(anyhow!(USAGE)),
+                }
+                rest = tail;
+            }
+            if !rest.is_empty() || (season.is_none() && format.is_none()) {
+                return Err(anyhow!(USAGE));
+            }
+            Command::Edit(Edit::Set { season, format })
+        }
+        "unset" => {
+            let mut season = false;
+            let mut format = false;
+            for flag in rest {
+                match &**flag {
+                    "--season" => season = true,
+                    "--format" => format = true,
+                    _ => return Err(anyhow!(USAGE)),
+                }
+            }
+            if !season && !format {
+                return Err(anyhow!(USAGE));
+            }
+            Command::Edit(Edit::Unset { season, format })
+        }
+        "add-name" => match rest {
+            [flag, ty, name] if flag == "--type" => {
+                if ty != "additional" {
+                    return Err(anyhow!(
+                        "only additional names can be added. romaji and english names \
+                         are synced from AniList"
+                    ));
+                }
+                Command::Edit(Edit::AddName(textnorm::storage(name)))
+            }
+            _ => return Err(anyhow!(USAGE)),
+        },
+        "delete" if rest.is_empty() => Command::Remove(Removal::Deleted),
+        "merge" => match rest {
+            [flag, into] if flag == "--into" => match into.parse() {
+                Ok(into) => Command::Remove(Removal::Merged(into)),
+                _ => return Err(anyhow!("invalid show id {}", into)),
+            },
+            _ => return Err(anyhow!(USAGE)),
+        },
+        _ => return Err(anyhow!(USAGE)),
+    };
+    Ok((show_id, command))
+}
+
+async fn async_edit(show_id: i64, command: &Command) -> Result<()> {
+    let config: Config = common::config::load()?;
+    let mut con = PgConnector::new(config.db.connection_string)
+        .connect()
+        .await?;
+    let tran = pg::transaction(&mut con).await?;
+    match *command {
+        Command::Edit(ref edit) => {
+            show_lifecycle::lock_listed(&tran, show_id).await?;
+            edit_listed(&tran, show_id, edit).await?;
+            // Torrents that did not match before might match now
+            // language=sql
+            tran.execute(
+                "update magnets.state set value = '1' where key = $1 and value = '0'",
+                &[&REMATCH_UNMATCHED],
+            )
+            .await?;
+        }
+        Command::Remove(removal) => {
+            show_lifecycle::remove(&tran, show_id, removal).await?;
+            let (action, after) = match removal {
+                Removal::Merged(into) => (
+                    "merge-show",
+                    json!({"show_id": show_id, "merged_into": into}),
+                ),
+                _ => ("delete-show", json!({"show_id": show_id})),
+            };
+            audit::record(&tran, action, None, Some(after)).await?;
+        }
+    }
+    tran.commit().await?;
+    Ok(())
+}
+
+/// Applies an edit to a show that has been locked by `show_lifecycle::lock_listed`
+async fn edit_listed(tran: &Transaction<'_>, show_id: i64, edit: &Edit) -> Result<()> {
+    match edit {
+        Edit::Set { season, format } => {
+            // language=sql
+            let sql = "
+                update magnets.show
+                set season = coalesce($2, season),
+                    show_format = coalesce($3, show_format),
+                    season_curated = season_curated or $2 is not null,
+                    format_curated = format_curated or $3 is not null
+                where show_id = $1
+                returning season, show_format, season_curated, format_curated";
+            let season = season.map(|s| s.to_db());
+            let format = format.map(|f| f.to_db());
+            edit_metadata(tran, show_id, sql, &season, &format).await?;
+            // Delivered on commit
+            tran.batch_execute("notify schedule_change").await?;
+        }
+        Edit::Unset { season, format } => {
+            // language=sql
+            let sql = "
+                update magnets.show
+                set season_curated = season_curated and not $2,
+                    format_curated = format_curated and not $3
+                where show_id = $1
+                returning season, show_format, season_curated, format_curated";
+            edit_metadata(tran, show_id, sql, season, format).await?;
+        }
+        Edit::AddName(name) => {
+            // language=sql
+            let duplicate = tran
+                .query_opt(
+                    "select 1 from magnets.show_name where show_id = $1 and name = $2",
+                    &[&show_id, name],
+                )
+                .await?;
+            if duplicate.is_some() {
+                return Err(anyhow!("show {} already has the name {}", show_id, name));
+            }
+            // language=sql
+            tran.execute(
+                "insert into magnets.show_name (show_id, show_name_type, name) values ($1, $2, $3)",
+                &[&show_id, &ShowNameType::ADDITIONAL, name],
+            )
+            .await?;
+            audit::record(
+                tran,
+                "add-show-name",
+                None,
+                Some(json!({"show_id": show_id, "name": name})),
+            )
+            .await?;
+            // Delivered on commit
+            tran.batch_execute("notify show_change").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs an update of the season and format that returns the new values and records it
+/// in the audit log
+async fn edit_metadata(
+    tran: &Transaction<'_>,
+    show_id: i64,
+    sql: &str,
+    season: &(dyn ToSql + Sync),
+    format: &(dyn ToSql + Sync),
+) -> Result<()> {
+    // language=sql
+    let before = tran
+        .query_one(
+            "
+            select season, show_format, season_curated, format_curated
+            from magnets.show
+            where show_id = $1",
+            &[&show_id],
+        )
+        .await?;
+    let after = tran.query_one(sql, &[&show_id, season, format]).await?;
+    let metadata = |row: &Row| {
+        json!({
+            "show_id": show_id,
+            "season": row.get::<_, Option<i32>>(0),
+            "show_format": row.get::<_, i32>(1),
+            "season_curated": row.get::<_, bool>(2),
+            "format_curated": row.get::<_, bool>(3),
+        })
+    };
+    audit::record(
+        tran,
+        "edit-show",
+        Some(metadata(&before)),
+        Some(metadata(&after)),
+    )
+    .await
+}
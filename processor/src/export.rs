@@ -0,0 +1,178 @@
+use crate::{covers::write_atomic, state::State};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use common::{HexFormatter, MagnetFormatter, Script};
+use serde::Serialize;
+use std::{collections::HashMap, fs, io, path::Path};
+use tokio_postgres::Client;
+
+/// Writes the torrents of every show as json files for static mirrors
+///
+/// The files are written to `export.directory`:
+///
+/// - `shows.json` lists the exported shows and their number of torrents
+/// - `shows/{show_id}.json` contains the torrents of a show, newest first
+///
+/// A show is only exported again when its torrents have changed. Since the state is
+/// kept in memory, all shows are exported once after a restart.
+pub async fn export_shows(state: &State<'_>) {
+    let directory = match &state.config.export.directory {
+        Some(d) => d,
+        _ => return,
+    };
+    let mut exported = HashMap::new();
+    loop {
+        if let Err(e) = export_shows_now(state, directory, &mut exported).await {
+            log::error!("could not export the shows: {:#}", e);
+        }
+        state.sleep(|c| c.export.poll_interval).await;
+    }
+}
+
+/// The number of torrents of a show and its newest match
+///
+/// Changes whenever a torrent is added to or removed from the show.
+type Fingerprint = (i64, i64);
+
+#[derive(Serialize)]
+struct IndexEntry {
+    show_id: i64,
+    torrents: i64,
+}
+
+#[derive(Serialize)]
+struct Show {
+    show_id: i64,
+    torrents: Vec<Torrent>,
+}
+
+#[derive(Serialize)]
+struct Torrent {
+    nyaa_id: i64,
+    title: String,
+    hash: String,
+    magnet: String,
+    size: i64,
+    trusted: bool,
+    batch: bool,
+    episode: Option<i32>,
+    /// The last episode of a batch
+    episode_end: Option<i32>,
+    uploaded_at: DateTime<Utc>,
+    /// The non-latin script of the title if there is one
+    script: Option<&'static str>,
+}
+
+async fn export_shows_now(
+    state: &State<'_>,
+    directory: &Path,
+    exported: &mut HashMap<i64, Fingerprint>,
+) -> Result<()> {
+    let con = state.pg_connector.connect().await?;
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select show_id, count(*), max(rel_torrent_show_id)
+            from magnets.rel_torrent_show
+            group by show_id
+            order by show_id",
+            &[],
+        )
+        .await?;
+    let mut current = HashMap::new();
+    let mut index = vec![];
+    for row in &rows {
+        let show_id: i64 = row.get(0);
+        current.insert(show_id, (row.get(1), row.get(2)));
+        index.push(IndexEntry {
+            show_id,
+            torrents: row.get(1),
+        });
+    }
+    let changed: Vec<_> = current
+        .iter()
+        .filter(|(show_id, fp)| exported.get(show_id) != Some(fp))
+        .map(|(&show_id, _)| show_id)
+        .collect();
+    let removed: Vec<_> = exported
+        .keys()
+        .filter(|show_id| !current.contains_key(show_id))
+        .copied()
+        .collect();
+    if changed.is_empty() && removed.is_empty() {
+        return Ok(());
+    }
+    log::info!(
+        "exporting {} changed and removing {} shows",
+        changed.len(),
+        removed.len()
+    );
+    for show_id in changed {
+        let show = load_show(&con, show_id).await?;
+        let json = serde_json::to_vec(&show)?;
+        write_atomic(&show_path(directory, show_id), &json)
+            .with_context(|| format!("cannot write the export of show {}", show_id))?;
+        exported.insert(show_id, current[&show_id]);
+    }
+    for show_id in removed {
+        match fs::remove_file(show_path(directory, show_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).context("cannot remove an exported show");
+            }
+            _ => {}
+        }
+        exported.remove(&show_id);
+    }
+    write_atomic(&directory.join("shows.json"), &serde_json::to_vec(&index)?)
+        .context("cannot write the index of exported shows")?;
+    Ok(())
+}
+
+fn show_path(directory: &Path, show_id: i64) -> std::path::PathBuf {
+    directory.join("shows").join(format!("{}.json", show_id))
+}
+
+async fn load_show(con: &Client, show_id: i64) -> Result<Show> {
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select t.nyaa_id, t.title, t.hash, t.size, t.trusted, t.batch, t.uploaded_at,
+                t.script, rts.episode, rts.episode_end
+            from magnets.rel_torrent_show rts
+            join magnets.torrent t using (torrent_id)
+            where rts.show_id = $1
+            order by rts.nyaa_id desc",
+            &[&show_id],
+        )
+        .await?;
+    let torrents = rows
+        .iter()
+        .map(|row| {
+            let title: String = row.get("title");
+            let hash: Vec<u8> = row.get("hash");
+            let size: i64 = row.get("size");
+            let script = match row.get::<_, Option<i32>>("script") {
+                Some(s) => Some(Script::from_db(s)?.as_api_str()),
+                _ => None,
+            };
+            Ok(Torrent {
+                nyaa_id: row.get("nyaa_id"),
+                hash: HexFormatter(&hash).to_string(),
+                magnet: MagnetFormatter::new(&title, &hash)
+                    .with_size(size)
+                    .to_string(),
+                title,
+                size,
+                trusted: row.get("trusted"),
+                batch: row.get("batch"),
+                episode: row.get("episode"),
+                episode_end: row.get("episode_end"),
+                uploaded_at: row.get("uploaded_at"),
+                script,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(Show { show_id, torrents })
+}
@@ -0,0 +1,18 @@
+use crate::state::State;
+
+/// Keeps the feature flags in sync with their overrides in `magnets.state`
+///
+/// The overrides are reloaded whenever the `flags` key changes and after every
+/// reconnect.
+pub async fn watch_flags(state: &State<'_>) {
+    loop {
+        state.db_watcher.flags.notified().await;
+        let res = match state.pg.borrow().await {
+            Ok(pg) => state.flags.refresh(&**pg).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            log::error!("could not refresh the flags: {:#}", e);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{audit, config::Config};
+use anyhow::{anyhow, Result};
+use common::{pg, pg::PgConnector, Role};
+use serde_json::json;
+
+/// Assigns a role to a user of the admin interface
+///
+/// Invoked as `processor grant <user> <role>`. The credentials of the user must still be
+/// added to the `admin.users` section of the site config.
+pub fn grant(args: &[String]) -> Result<()> {
+    let (name, role) = match args {
+        [name, role] => (name, Role::from_name(role)?),
+        _ => return Err(anyhow!("usage: processor grant <user> <role>")),
+    };
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async_grant(name, role))?;
+    println!("granted {} to {}", role, name);
+    Ok(())
+}
+
+async fn async_grant(name: &str, role: Role) -> Result<()> {
+    let config: Config = common::config::load()?;
+    let mut con = PgConnector::new(config.db.connection_string)
+        .connect()
+        .await?;
+    let tran = pg::transaction(&mut con).await?;
+    // language=sql
+    let old = tran
+        .query_opt(
+            "select role from magnets.admin_user where name = $1 for update",
+            &[&name],
+        )
+        .await?;
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.admin_user (name, role) values ($1, $2)
+        on conflict (name) do update set role = excluded.role",
+        &[&name, &role.to_db()],
+    )
+    .await?;
+    audit::record(
+        &tran,
+        "grant-role",
+        old.map(|row| json!({"name": name, "role": row.get::<_, i32>(0)})),
+        Some(json!({"name": name, "role": role.to_db()})),
+    )
+    .await?;
+    tran.commit().await?;
+    Ok(())
+}
@@ -1,7 +1,6 @@
 use crate::show_db::LARGE_NUMBER;
 use isnt::std_1::ops::IsntRangeExt;
-use num_traits::ToPrimitive;
-use std::{cell::Cell, ops::Range};
+use std::{cell::Cell, mem::size_of, ops::Range};
 
 /// A data structure for efficient prefix search
 ///
@@ -48,6 +47,87 @@ impl Node {
 }
 
 impl<T> AsciiHeap<T> {
+    /// Returns the approximate number of bytes allocated by this object
+    pub fn heap_size(&self) -> usize {
+        self.payloads.len() * size_of::<T>() + self.nodes.len() * size_of::<Node>()
+    }
+
+    /// Finds the position of the longest prefix in the heap
+    ///
+    /// Note that the string should only contain `[a-z0-9]` letters. All other letters
+    /// will be ignored, including `[A-Z]`.
+    pub fn find(&self, s: &str) -> usize {
+        let mut idx = 0;
+        let mut children = self.nodes[idx].children();
+        for &c in s.as_bytes() {
+            if matches!(c, b'a'..=b'z' | b'0'..=b'9') {
+                match children
+                    .into_iter()
+                    .find(|&idx| self.nodes[idx].letter == c)
+                {
+                    Some(child) => {
+                        idx = child;
+                        children = self.nodes[idx].children();
+                    }
+                    _ => break,
+                }
+            }
+        }
+        idx
+    }
+
+    /// Creates an iterator over all payloads below the node at the index in the heap
+    pub fn iter(&self, idx: usize) -> Iter<T> {
+        Iter {
+            heap: self,
+            cur: idx..idx + 1,
+            todo: vec![],
+            payloads: [].iter(),
+        }
+    }
+}
+
+/// A prefix of a key
+///
+/// The prefix is `lc[start..end]` in [HeapBuilder]. There is one of these per letter of
+/// every key, so it is kept as small as possible.
+struct PreData {
+    start: u32,
+    end: u32,
+    /// Index of the payload in the payloads of the keys or `NO_PAYLOAD` if this is not
+    /// a full key
+    payload: u32,
+}
+
+const NO_PAYLOAD: u32 = u32::MAX;
+const NO_PARENT: u32 = u32::MAX;
+
+/// A unique prefix that will become a node
+struct Data {
+    letter: u8,
+    num_children: u8,
+    payload_range: Range<u32>,
+    /// Index of the parent in the array or `NO_PARENT`
+    parent: u32,
+    heap_pos: Cell<u32>,
+    children_heap_pos: Cell<u32>,
+    next_child_pos: Cell<u32>,
+}
+
+/// The temporary buffers used to build an [AsciiHeap]
+///
+/// Their size is proportional to the total length of the keys. Reusing the builder
+/// across refreshes of the show database avoids allocating them again every time.
+#[derive(Default)]
+pub struct HeapBuilder {
+    /// The concatenation of all keys (after ascii reduction)
+    lc: Vec<u8>,
+    pre_datas: Vec<PreData>,
+    datas: Vec<Data>,
+    stack: Vec<u32>,
+}
+
+impl HeapBuilder {
     /// Creates a new heap from the given iterator
     ///
     /// The first component of the iterator will first be converted to ascii lowercase and
@@ -58,21 +138,26 @@ impl<T> AsciiHeap<T> {
     ///
     /// Note that if the resulting string is empty, the payload will not be contained in
     /// the heap.
-    pub fn new<'a, I: IntoIterator<Item = (&'a str, T)>>(strs: I) -> Self {
+    pub fn build<'a, T, I: IntoIterator<Item = (&'a str, T)>>(
+        &mut self,
+        strs: I,
+    ) -> AsciiHeap<T> {
+        let HeapBuilder {
+            lc,
+            pre_datas,
+            datas,
+            stack,
+        } = self;
+        lc.clear();
+        pre_datas.clear();
+        datas.clear();
+        stack.clear();
+
         // Step 1: For each prefix of each element in the iterator, create a PreData
         // object. The PreData object of the full string of an element has the payload
         // associated with it. Most of these (except duplicates) will later be turned
         // into nodes.
-        struct PreData<T> {
-            substring_range: Range<usize>,
-            payload: Option<T>,
-        }
-
-        // lc contains the concatenation of all strings in the iterator
-        // (after ascii reduction).
-        let mut lc = Vec::with_capacity(10 * LARGE_NUMBER);
-        let mut pre_datas = Vec::with_capacity(10 * LARGE_NUMBER);
-        let mut num_payloads = 0;
+        let mut key_payloads = Vec::with_capacity(LARGE_NUMBER);
 
         for (s, payload) in strs {
             let start = lc.len();
@@ -81,19 +166,20 @@ impl<T> AsciiHeap<T> {
                 if matches!(c, b'a'..=b'z' | b'0'..=b'9') {
                     lc.push(c);
                     pre_datas.push(PreData {
-                        substring_range: start..lc.len(),
-                        payload: None,
+                        start: start as u32,
+                        end: lc.len() as u32,
+                        payload: NO_PAYLOAD,
                     });
                 }
             }
             if start < lc.len() {
-                pre_datas.last_mut().unwrap().payload = Some(payload);
-                num_payloads += 1;
+                pre_datas.last_mut().unwrap().payload = key_payloads.len() as u32;
+                key_payloads.push(Some(payload));
             }
         }
 
         assert!(
-            pre_datas.len().to_u32().is_some(),
+            lc.len() < u32::MAX as usize && key_payloads.len() < NO_PAYLOAD as usize,
             "AsciiHeap supports at most u32::MAX nodes"
         );
 
@@ -101,7 +187,10 @@ impl<T> AsciiHeap<T> {
         // ensures that the array has the following property: All of the descendants of a
         // node occur in an array immediately after the node. This allows us to find the
         // children of a node using a simple stack algorithm.
-        pre_datas.sort_by_key(|data| &lc[data.substring_range.clone()]);
+        {
+            let lc = &*lc;
+            pre_datas.sort_by_key(|data| &lc[data.start as usize..data.end as usize]);
+        }
 
         // Step 3: For each PreData object that is not a duplicate (duplicate meaning that
         // is has the same associated key) create a Data object. After this, each data
@@ -109,28 +198,16 @@ impl<T> AsciiHeap<T> {
         // - how many children it has
         // - the position of its parent in the array
         // - the position of its payloads in the payload array
-        struct Data {
-            letter: u8,
-            payload_range: Range<u32>,
-            num_children: u8,
-            parent: Option<u32>,
-            heap_pos: Cell<u32>,
-            children_heap_pos: Cell<u32>,
-            next_child_pos: Cell<u32>,
-        }
-
-        let mut datas: Vec<Data> = Vec::with_capacity(LARGE_NUMBER);
-        let mut payloads = Vec::with_capacity(num_payloads);
+        let mut payloads = Vec::with_capacity(key_payloads.len());
         let mut num_single_letter = 0;
 
-        let mut stack: Vec<u32> = vec![];
         let mut prev_letter = 0;
         let mut prev_len = 0;
 
-        for pre_data in pre_datas {
+        for pre_data in pre_datas.iter() {
             // This is the associated letter of the node
-            let letter = lc[pre_data.substring_range.end - 1];
-            let len = pre_data.substring_range.len();
+            let letter = lc[pre_data.end as usize - 1];
+            let len = (pre_data.end - pre_data.start) as usize;
             // If the length is the same and the last letter is the same as the previous
             // element of the array, then the whole associated string must be the same.
             // This is because the array is prefix-complete. (Meaning that every prefix
@@ -143,8 +220,8 @@ impl<T> AsciiHeap<T> {
             let is_duplicate = (letter, len) == (prev_letter, prev_len);
             // The payload range is empty unless the element has a payload.
             let mut payload_range = (payloads.len() as u32)..(payloads.len() as u32);
-            if let Some(payload) = pre_data.payload {
-                payloads.push(payload);
+            if pre_data.payload != NO_PAYLOAD {
+                payloads.push(key_payloads[pre_data.payload as usize].take().unwrap());
                 if is_duplicate {
                     // If it's a duplicate, the payload will be associated with the
                     // previous element.
@@ -172,11 +249,11 @@ impl<T> AsciiHeap<T> {
                 Some(&idx) => {
                     // Tell our parent that it has one more child.
                     datas[idx as usize].num_children += 1;
-                    Some(idx)
+                    idx
                 }
                 _ => {
                     num_single_letter += 1;
-                    None
+                    NO_PARENT
                 }
             };
             prev_letter = letter;
@@ -185,8 +262,8 @@ impl<T> AsciiHeap<T> {
             stack.push(datas.len() as u32);
             datas.push(Data {
                 letter,
-                payload_range,
                 num_children: 0,
+                payload_range,
                 parent,
                 heap_pos: Cell::new(0),
                 children_heap_pos: Cell::new(0),
@@ -200,22 +277,19 @@ impl<T> AsciiHeap<T> {
         let mut next_free_node_position = num_single_letter as u32 + 1;
         let mut next_one_letter_pos = 1;
 
-        for data in &datas {
-            match data.parent {
-                Some(parent_idx) => {
-                    // If we have a parent, our position is the next position reserved
-                    // for its children.
-                    let parent = &datas[parent_idx as usize];
-                    let next_child_pos = parent.next_child_pos.get();
-                    data.heap_pos.set(next_child_pos);
-                    parent.next_child_pos.set(next_child_pos + 1);
-                }
-                _ => {
-                    // If we don't have a parent, our position is at the start of the
-                    // array in the single-letter area.
-                    data.heap_pos.set(next_one_letter_pos);
-                    next_one_letter_pos += 1;
-                }
+        for data in datas.iter() {
+            if data.parent != NO_PARENT {
+                // If we have a parent, our position is the next position reserved
+                // for its children.
+                let parent = &datas[data.parent as usize];
+                let next_child_pos = parent.next_child_pos.get();
+                data.heap_pos.set(next_child_pos);
+                parent.next_child_pos.set(next_child_pos + 1);
+            } else {
+                // If we don't have a parent, our position is at the start of the
+                // array in the single-letter area.
+                data.heap_pos.set(next_one_letter_pos);
+                next_one_letter_pos += 1;
             }
             // Reserve space for our children at the end of the array.
             data.children_heap_pos.set(next_free_node_position);
@@ -225,7 +299,7 @@ impl<T> AsciiHeap<T> {
 
         // Step 5: Sort the array by the heap position and transform the entries into
         // nodes.
-        datas.sort_by_key(|d| d.heap_pos.get());
+        datas.sort_unstable_by_key(|d| d.heap_pos.get());
 
         let mut nodes = Vec::with_capacity(datas.len() + 1);
         // This is the root node
@@ -236,13 +310,12 @@ impl<T> AsciiHeap<T> {
             payloads: 0..0,
         });
 
-        for data in datas {
-            let children_heap_pos = data.children_heap_pos.get();
+        for data in datas.iter() {
             nodes.push(Node {
                 letter: data.letter,
                 num_children: data.num_children,
-                pos_children: children_heap_pos,
-                payloads: data.payload_range,
+                pos_children: data.children_heap_pos.get(),
+                payloads: data.payload_range.clone(),
             });
         }
 
@@ -252,38 +325,12 @@ impl<T> AsciiHeap<T> {
         }
     }
 
-    /// Finds the position of the longest prefix in the heap
-    ///
-    /// Note that the string should only contain `[a-z0-9]` letters. All other letters
-    /// will be ignored, including `[A-Z]`.
-    pub fn find(&self, s: &str) -> usize {
-        let mut idx = 0;
-        let mut children = self.nodes[idx].children();
-        for &c in s.as_bytes() {
-            if matches!(c, b'a'..=b'z' | b'0'..=b'9') {
-                match children
-                    .into_iter()
-                    .find(|&idx| self.nodes[idx].letter == c)
-                {
-                    Some(child) => {
-                        idx = child;
-                        children = self.nodes[idx].children();
-                    }
-                    _ => break,
-                }
-            }
-        }
-        idx
-    }
-
-    /// Creates an iterator over all payloads below the node at the index in the heap
-    pub fn iter(&self, idx: usize) -> Iter<T> {
-        Iter {
-            heap: self,
-            cur: idx..idx + 1,
-            todo: vec![],
-            payloads: [].iter(),
-        }
+    /// Returns the approximate number of bytes held by the temporary buffers
+    pub fn heap_size(&self) -> usize {
+        self.lc.capacity()
+            + self.pre_datas.capacity() * size_of::<PreData>()
+            + self.datas.capacity() * size_of::<Data>()
+            + self.stack.capacity() * size_of::<u32>()
     }
 }
 
@@ -322,3 +369,28 @@ impl<'a, T> Iterator for Iter<'a, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_search() {
+        let keys = [
+            ("Shingeki", 1),
+            ("shingeki no kyojin", 2),
+            ("Shin-chan", 3),
+            ("k", 4),
+        ];
+        let mut builder = HeapBuilder::default();
+        for _ in 0..2 {
+            let heap = builder.build(keys.iter().map(|&(s, p)| (s, p)));
+            let mut all: Vec<_> = heap.iter(heap.find("shin")).copied().collect();
+            all.sort_unstable();
+            assert_eq!(all, [1, 2, 3]);
+            let idx = heap.find("shingekinokyojin2");
+            assert_eq!(heap.iter(idx).copied().collect::<Vec<_>>(), [2]);
+            assert_eq!(heap.iter(heap.find("k")).copied().collect::<Vec<_>>(), [4]);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{db_state, db_state::PROCESSOR_HEARTBEAT, state::State};
+use anyhow::Result;
+
+/// Records the current time as `processor_heartbeat` in `magnets.state`
+///
+/// The site reports itself as not ready on /readyz once the heartbeat is older than
+/// `health.processor_max_age_minutes`. All tasks of the processor share one thread, so a
+/// task that blocks the thread also stops the heartbeat.
+pub async fn record_heartbeat(state: &State<'_>) {
+    loop {
+        if let Err(e) = beat_now(state).await {
+            log::error!("could not record the heartbeat: {:#}", e);
+        }
+        state.sleep(|c| c.heartbeat.interval).await;
+    }
+}
+
+async fn beat_now(state: &State<'_>) -> Result<()> {
+    // A processor that lost the leadership must not pretend that the work is being done
+    state.leader.ensure().await?;
+    let con = state.pg.borrow().await?;
+    db_state::set(&**con, PROCESSOR_HEARTBEAT, state.clock.now()).await
+}
@@ -1,8 +1,123 @@
-use reqwest::Client;
+use anyhow::{Context, Result};
+use common::{config::UserAgent, time::StdDuration};
+use reqwest::{Client, StatusCode};
+use std::{collections::HashMap, fmt, sync::Arc, time::Instant};
+use tokio::sync::Mutex;
 
 /// Constructor for our global http client
 ///
-/// We set our user agent so that upstream can contact us if necessary.
-pub fn reqwest_client(user_agent: &str) -> Client {
-    Client::builder().user_agent(user_agent).build().unwrap()
+/// We set our user agent so that upstream can contact us if necessary. All outbound
+/// requests, including cover downloads and feeds, must go through this client.
+pub fn reqwest_client(user_agent: &UserAgent) -> Client {
+    Client::builder()
+        .user_agent(user_agent.header())
+        .build()
+        .unwrap()
+}
+
+/// The error of a request that was answered with an unsuccessful status code
+#[derive(Debug)]
+pub struct StatusError {
+    pub url: String,
+    pub status: StatusCode,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} status code is {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Expired entries are removed once the cache contains this many urls
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// An in-memory cache for GET requests keyed by URL
+///
+/// This is meant for resources that rarely change, such as detail pages and cover
+/// images, so that re-running a backfill does not download them again. Only successful
+/// responses are cached.
+///
+/// Concurrent requests for the same URL are coalesced: the first caller performs the
+/// request while the others wait for its result.
+pub struct HttpCache {
+    client: Client,
+    ttl: StdDuration,
+    entries: std::sync::Mutex<HashMap<String, Arc<Mutex<Option<Entry>>>>>,
+}
+
+struct Entry {
+    expires: Instant,
+    body: Arc<Vec<u8>>,
+}
+
+impl HttpCache {
+    pub fn new(client: &Client, ttl: StdDuration) -> Self {
+        Self {
+            client: client.clone(),
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    /// Returns the body of `url`, downloading it if it is not cached
+    pub async fn get(&self, url: &str) -> Result<Arc<Vec<u8>>> {
+        let slot = {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() >= PRUNE_THRESHOLD {
+                prune(&mut entries);
+            }
+            entries.entry(url.to_string()).or_default().clone()
+        };
+        let mut slot = slot.lock().await;
+        if let Some(entry) = &*slot {
+            if entry.expires > Instant::now() {
+                return Ok(entry.body.clone());
+            }
+        }
+        let body = Arc::new(self.fetch(url).await?);
+        *slot = Some(Entry {
+            expires: Instant::now() + self.ttl,
+            body: body.clone(),
+        });
+        Ok(body)
+    }
+
+    /// Drops all cached responses
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .with_context(|| format!("cannot request {}", url))?;
+        if !response.status().is_success() {
+            return Err(StatusError {
+                url: url.to_string(),
+                status: response.status(),
+            }
+            .into());
+        }
+        let body = response
+            .bytes()
+            .await
+            .with_context(|| format!("cannot read response of {}", url))?;
+        Ok(body.to_vec())
+    }
+}
+
+/// Removes all entries that have expired or never completed
+///
+/// Entries that are currently being fetched are kept.
+fn prune(entries: &mut HashMap<String, Arc<Mutex<Option<Entry>>>>) {
+    let now = Instant::now();
+    entries.retain(|_, slot| match slot.try_lock() {
+        Ok(entry) => matches!(&*entry, Some(e) if e.expires > now),
+        Err(_) => true,
+    });
 }
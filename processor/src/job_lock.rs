@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use common::pg::PgClient;
+use tokio_postgres::Transaction;
+
+/// First key of the advisory locks of the jobs
+const JOB_LOCK_CLASS: i32 = 0x6d61_676e;
+
+/// Jobs that must not interleave with another run of themselves
+///
+/// A run can be started concurrently by a second processor instance or by an operator.
+#[derive(Copy, Clone, Debug)]
+pub enum Job {
+    /// Rematching torrents which can truncate `magnets.rel_torrent_show`
+    Rematch = 1,
+    /// Refreshing the shows from AniList
+    Shows = 2,
+    /// Refreshing the schedule from AniList
+    Schedule = 3,
+    /// Computing or applying the alias suggestions
+    AliasSuggestions = 4,
+}
+
+/// Waits until no other transaction holds the lock of `job`
+///
+/// The lock is released when the transaction ends.
+pub async fn lock(tran: &Transaction<'_>, job: Job) -> Result<()> {
+    // language=sql
+    tran.execute(
+        "select pg_advisory_xact_lock($1, $2)",
+        &[&JOB_LOCK_CLASS, &(job as i32)],
+    )
+    .await
+    .with_context(|| format!("cannot lock job {:?}", job))?;
+    Ok(())
+}
+
+/// Like [lock] but for jobs that span multiple transactions
+///
+/// The lock is released when the connection is closed.
+pub async fn lock_session(con: &PgClient, job: Job) -> Result<()> {
+    // language=sql
+    con.execute(
+        "select pg_advisory_lock($1, $2)",
+        &[&JOB_LOCK_CLASS, &(job as i32)],
+    )
+    .await
+    .with_context(|| format!("cannot lock job {:?}", job))?;
+    Ok(())
+}
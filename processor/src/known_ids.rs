@@ -0,0 +1,79 @@
+use anyhow::Result;
+use common::{pg::PgClient, Source};
+use std::sync::Mutex;
+
+/// The set of nyaa ids that exist in `magnets.torrent_source`
+///
+/// Scraping overlaps with the previous scrape and backfills revisit many pages, so most
+/// scraped torrents already exist. This set allows us to skip the database lookup for
+/// them. Nyaa ids are dense, so a plain bitmap is both exact and small (one bit per
+/// id).
+///
+/// The set is loaded on first use and updated after new torrents have been committed.
+/// It can therefore only contain ids that exist in the database. Ids that are not in
+/// the set must still be checked against the database.
+pub struct KnownIds {
+    bits: Mutex<Option<Vec<u64>>>,
+}
+
+impl KnownIds {
+    pub fn new() -> Self {
+        Self {
+            bits: Mutex::new(None),
+        }
+    }
+
+    /// Loads the ids from the database unless this has already happened
+    pub async fn load(&self, con: &PgClient) -> Result<()> {
+        if self.bits.lock().unwrap().is_some() {
+            return Ok(());
+        }
+        log::info!("loading known nyaa ids");
+        // language=sql
+        let rows = con
+            .query(
+                "select source_id from magnets.torrent_source where source = $1",
+                &[&Source::Nyaa.to_db()],
+            )
+            .await?;
+        let mut bits = vec![];
+        for row in rows {
+            set(&mut bits, row.get(0));
+        }
+        *self.bits.lock().unwrap() = Some(bits);
+        Ok(())
+    }
+
+    /// Returns whether the torrent is known to exist
+    pub fn contains(&self, nyaa_id: i64) -> bool {
+        let bits = self.bits.lock().unwrap();
+        let bits = match &*bits {
+            Some(b) => b,
+            _ => return false,
+        };
+        let (word, bit) = position(nyaa_id);
+        matches!(bits.get(word), Some(w) if w & bit != 0)
+    }
+
+    /// Records torrents that have been committed to the database
+    pub fn insert(&self, nyaa_ids: impl IntoIterator<Item = i64>) {
+        if let Some(bits) = &mut *self.bits.lock().unwrap() {
+            for nyaa_id in nyaa_ids {
+                set(bits, nyaa_id);
+            }
+        }
+    }
+}
+
+fn position(nyaa_id: i64) -> (usize, u64) {
+    let nyaa_id = nyaa_id.max(0) as usize;
+    (nyaa_id / 64, 1 << (nyaa_id % 64))
+}
+
+fn set(bits: &mut Vec<u64>, nyaa_id: i64) {
+    let (word, bit) = position(nyaa_id);
+    if bits.len() <= word {
+        bits.resize(word + 1, 0);
+    }
+    bits[word] |= bit;
+}
@@ -0,0 +1,93 @@
+use crate::config::Standby;
+use anyhow::{anyhow, Result};
+use common::pg::{PgClient, PgConnector};
+use tokio::sync::Mutex;
+
+/// Key of the session-level advisory lock held by the active processor
+const LEADER_LOCK: i64 = 0x6d61_676e_6574_0001;
+
+/// Leader election between processor instances
+///
+/// If `standby.enabled` is set, a processor only starts its tasks once it holds an
+/// advisory lock. A second instance waits for the lock and takes over once the
+/// connection of the first instance is gone, e.g. because its host is in maintenance.
+///
+/// The lock is held by a dedicated connection. All write loops must call
+/// [Leader::ensure] before committing so that an instance that has lost this connection
+/// does not write concurrently with its successor.
+pub struct Leader {
+    enabled: bool,
+    con: Mutex<Option<PgClient>>,
+}
+
+impl Leader {
+    pub fn new(config: &Standby) -> Self {
+        Self {
+            enabled: config.enabled,
+            con: Mutex::new(None),
+        }
+    }
+
+    /// Waits until this instance holds the leader lock
+    pub async fn acquire(&self, connector: &PgConnector, config: &Standby) {
+        if !self.enabled {
+            return;
+        }
+        let mut logged = false;
+        loop {
+            match try_acquire(connector).await {
+                Ok(Some(con)) => {
+                    log::info!("this processor is now the leader");
+                    *self.con.lock().await = Some(con);
+                    return;
+                }
+                Ok(None) if !logged => {
+                    log::info!("another processor is the leader. waiting in standby");
+                    logged = true;
+                }
+                Ok(None) => {}
+                Err(e) => log::error!("could not acquire the leader lock: {:#}", e),
+            }
+            tokio::time::delay_for(config.poll_interval).await;
+        }
+    }
+
+    /// Returns an error if this instance is no longer the leader
+    pub async fn ensure(&self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let con = self.con.lock().await;
+        match &*con {
+            Some(con) if con.simple_query("").await.is_ok() => Ok(()),
+            _ => Err(anyhow!("this processor is no longer the leader")),
+        }
+    }
+
+    /// Exits the process once leadership has been lost
+    ///
+    /// The lock cannot be re-acquired safely while tasks are running. The process
+    /// supervisor restarts us in standby.
+    pub async fn watch(&self, config: &Standby) {
+        if !self.enabled {
+            return;
+        }
+        loop {
+            tokio::time::delay_for(config.poll_interval).await;
+            if let Err(e) = self.ensure().await {
+                log::error!("{:#}. exiting", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn try_acquire(connector: &PgConnector) -> Result<Option<PgClient>> {
+    let con = connector.connect().await?;
+    // language=sql
+    let acquired: bool = con
+        .query_one("select pg_try_advisory_lock($1)", &[&LEADER_LOCK])
+        .await?
+        .get(0);
+    Ok(if acquired { Some(con) } else { None })
+}
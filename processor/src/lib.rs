@@ -1,44 +1,129 @@
 #![allow(clippy::eval_order_dependence)] // https://github.com/rust-lang/rust-clippy/issues/5684
 
+mod alerts;
+mod alias_suggestions;
 #[cfg(target_os = "linux")]
 mod allocator;
-mod anilist;
+mod audit;
+mod check;
 mod config;
+mod covers;
 mod db_state;
 mod diff;
+mod edit_show;
+mod export;
+mod flags;
+mod grant;
 mod heap;
+mod heartbeat;
 mod http;
+mod job_lock;
+mod known_ids;
+mod leader;
+mod match_override;
+mod match_rate;
 mod matcher;
-mod nyaa;
+mod memory;
+mod metadata;
+mod metrics;
+mod notifiers;
+mod recommend;
+mod releases;
+mod reload;
+mod robots;
 mod scheduled;
+mod search;
+mod seasons;
+mod shadow;
 mod show_db;
+mod show_lifecycle;
+mod show_list;
+mod sizes;
 mod sleeper;
+mod sources;
 mod state;
+mod state_backup;
 mod strings;
 mod title_analyzer;
+mod title_info;
 mod trie;
+mod trusted;
+mod unmatched;
+mod watchlists;
+mod webhooks;
 
 use crate::{
-    anilist::{
+    alias_suggestions::watch_alias_suggestions,
+    config::Config,
+    covers::mirror_covers,
+    db_state::{DbWatcher, INITIAL_SETUP, LAST_SHOWS_UPDATE},
+    diff::watch_match_diff,
+    export::export_shows,
+    flags::watch_flags,
+    heartbeat::record_heartbeat,
+    http::HttpCache,
+    known_ids::KnownIds,
+    leader::Leader,
+    match_rate::watch_match_rate,
+    matcher::match_unmatched,
+    memory::{watch_memory, Memory},
+    metadata::{
         client::AnilistClient,
+        fallback::refresh_stale_season,
         schedule::load_schedule,
         shows::{load_shows, load_shows_now},
     },
-    config::Config,
-    db_state::{DbWatcher, INITIAL_SETUP, LAST_SHOWS_UPDATE},
-    matcher::match_unmatched,
-    nyaa::load_torrents,
+    metrics::{serve_metrics, Metrics},
+    recommend::recommend_torrents,
+    releases::load_releases,
+    reload::reload_config,
+    search::index_search,
     show_db::ShowDbHolder,
+    sizes::reparse_sizes,
+    sources::nyaa::{load_torrents, refresh_swarms},
     state::State,
+    title_info::backfill_title_info,
+    trusted::refresh_trusted,
+    watchlists::{complete_logins, sync_watchlists},
+    webhooks::deliver_webhooks,
 };
 use anyhow::Result;
 use chrono::Utc;
-use common::pg::{PgConnector, PgHolder};
+use common::{
+    config::LiveConfig,
+    flags::Flags,
+    pg::{PgConnector, PgHolder},
+    time::{Clock, SystemClock},
+};
+use std::sync::Arc;
 use tokio::time::Instant;
 
 pub fn processor() -> Result<()> {
     common::env::configure_logger();
 
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.first().map(|a| &**a) == Some("check") {
+        return check::check();
+    }
+    if args.first().map(|a| &**a) == Some("grant") {
+        return grant::grant(&args[1..]);
+    }
+    if args.first().map(|a| &**a) == Some("override") {
+        return match_override::match_override(&args[1..]);
+    }
+    if args.first().map(|a| &**a) == Some("show") {
+        return edit_show::show(&args[1..]);
+    }
+    if args.first().map(|a| &**a) == Some("state") {
+        return state_backup::state(&args[1..]);
+    }
+    if args.first().map(|a| &**a) == Some("unmatched") {
+        return unmatched::unmatched(&args[1..]);
+    }
+    if args.first().map(|a| &**a) == Some("webhook") {
+        return webhooks::webhook(&args[1..]);
+    }
+
     // Running our application in a thread reduces memory usage (glibc)
     std::thread::spawn(processor_in_thread).join().unwrap()?;
     Ok(())
@@ -59,30 +144,98 @@ fn processor_in_thread() -> Result<()> {
 }
 
 async fn process() -> Result<()> {
-    let config: Config = common::config::load()?;
+    let live_config = LiveConfig::<Config>::new(common::config::load()?);
+    let config = live_config.current();
+    let sources = sources::all(&config.nyaa)?;
     let db_watcher = DbWatcher::new();
-    let web_client = http::reqwest_client(&config.http.user_agent);
+    let web_client = http::reqwest_client(&config.user_agent);
     let pg_connector = PgConnector::new(config.db.connection_string.clone());
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
     let state = State {
         pg: PgHolder::with_message_handler(
             db_watcher.message_handler(),
             true,
             &pg_connector,
         ),
-        show_db: ShowDbHolder::new(&pg_connector),
+        show_db: ShowDbHolder::new(&pg_connector, config.matcher.recent_seasons),
         web_client: &web_client,
-        anilist_client: AnilistClient::new(&web_client),
+        http_cache: HttpCache::new(&web_client, config.http.cache_ttl),
+        known_nyaa_ids: KnownIds::new(),
+        anilist_client: AnilistClient::new(
+            &web_client,
+            &config.anilist.url,
+            clock.clone(),
+        ),
         db_watcher,
         startup_time: Instant::now(),
         pg_connector,
         config: &config,
+        live_config,
+        flags: Flags::new(&config.flags),
+        metrics: Metrics::new(),
+        leader: Leader::new(&config.standby),
+        memory: Memory::new(&config.memory),
+        clock,
     };
+    state
+        .leader
+        .acquire(&state.pg_connector, &config.standby)
+        .await;
     initial_setup(&state).await?;
     let analyze_unmatched = match_unmatched(&state);
     let load_schedule = load_schedule(&state);
-    let load_torrents = load_torrents(&state);
+    let load_torrents = load_torrents(&state, &sources);
     let load_shows = load_shows(&state);
-    futures::join!(analyze_unmatched, load_schedule, load_torrents, load_shows,);
+    let mirror_covers = mirror_covers(&state);
+    let load_releases = load_releases(&state);
+    let recommend_torrents = recommend_torrents(&state);
+    let watch_flags = watch_flags(&state);
+    let serve_metrics = serve_metrics(&state);
+    let watch_leader = state.leader.watch(&config.standby);
+    let watch_memory = watch_memory(&state);
+    let watch_match_diff = watch_match_diff(&state);
+    let watch_alias_suggestions = watch_alias_suggestions(&state);
+    let watch_match_rate = watch_match_rate(&state);
+    let refresh_trusted = refresh_trusted(&state);
+    let refresh_swarms = refresh_swarms(&state, &sources);
+    let reparse_sizes = reparse_sizes(&state);
+    let export_shows = export_shows(&state);
+    let index_search = index_search(&state);
+    let backfill_title_info = backfill_title_info(&state);
+    let refresh_stale_season = refresh_stale_season(&state);
+    let record_heartbeat = record_heartbeat(&state);
+    let reload_config = reload_config(&state);
+    let deliver_webhooks = deliver_webhooks(&state);
+    let complete_logins = complete_logins(&state);
+    let sync_watchlists = sync_watchlists(&state);
+    futures::join!(
+        analyze_unmatched,
+        load_schedule,
+        load_torrents,
+        load_shows,
+        mirror_covers,
+        load_releases,
+        recommend_torrents,
+        watch_flags,
+        serve_metrics,
+        watch_leader,
+        watch_memory,
+        watch_match_diff,
+        watch_alias_suggestions,
+        watch_match_rate,
+        refresh_trusted,
+        refresh_swarms,
+        reparse_sizes,
+        export_shows,
+        index_search,
+        backfill_title_info,
+        refresh_stale_season,
+        record_heartbeat,
+        reload_config,
+        deliver_webhooks,
+        complete_logins,
+        sync_watchlists,
+    );
     Ok(())
 }
 
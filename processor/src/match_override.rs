@@ -0,0 +1,418 @@
+use crate::{
+    audit, config::Config, db_state, db_state::REMATCH_UNMATCHED, matcher, show_lifecycle,
+};
+use anyhow::{anyhow, Result};
+use common::{pg, pg::PgConnector, textnorm};
+use serde_json::json;
+use tokio_postgres::Transaction;
+
+const USAGE: &str = "usage:
+    processor override title <show_id> <pattern>
+    processor override remove-title <title_override_id>
+    processor override group <show_id> <release_group> <name>
+    processor override remove-group <group_rule_id>
+    processor override torrent <nyaa_id> <show_id>
+    processor override reject <nyaa_id> <show_id>
+    processor override list";
+
+enum Command {
+    Title {
+        show_id: i64,
+        pattern: String,
+    },
+    RemoveTitle(i64),
+    Group {
+        show_id: i64,
+        release_group: String,
+        name: String,
+    },
+    RemoveGroup(i64),
+    Torrent {
+        nyaa_id: i64,
+        show_id: i64,
+        assigned: bool,
+    },
+    List,
+}
+
+/// Corrects the matches that the title analyzer finds
+///
+/// Invoked as
+///
+/// - `processor override title <show_id> <pattern>` to match all torrents whose title
+///   contains the case-insensitive regular expression with the show. Patterns take
+///   precedence over the title analyzer. All torrents are rematched.
+/// - `processor override remove-title <title_override_id>` to remove a pattern. All
+///   torrents are rematched.
+/// - `processor override group <show_id> <release_group> <name>` to match the titles of
+///   the release group whose name before the episode number is `name` with the show,
+///   e.g. `processor override group 123 SubsPlease "Oshi no Ko"`. All torrents are
+///   rematched.
+/// - `processor override remove-group <group_rule_id>` to remove a group rule. All
+///   torrents are rematched.
+/// - `processor override torrent <nyaa_id> <show_id>` to match a single torrent with a
+///   show.
+/// - `processor override reject <nyaa_id> <show_id>` to remove the match of a single
+///   torrent with a show and keep it from being matched again.
+/// - `processor override list` to print the patterns, group rules, and torrent
+///   overrides.
+///
+/// All changes are recorded in `magnets.audit_log`.
+pub fn match_override(args: &[String]) -> Result<()> {
+    let command = parse(args)?;
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async_override(&command))
+}
+
+fn parse(args: &[String]) -> Result<Command> {
+    let command = match args {
+        [command, show_id, pattern] if command == "title" => {
+            matcher::title_pattern(pattern)?;
+            Command::Title {
+                show_id: parse_id("show", show_id)?,
+                pattern: pattern.clone(),
+            }
+        }
+        [command, id] if command == "remove-title" => {
+            Command::RemoveTitle(parse_id("title override", id)?)
+        }
+        [command, show_id, release_group, name] if command == "group" => Command::Group {
+            show_id: parse_id("show", show_id)?,
+            release_group: release_group.clone(),
+            name: name.clone(),
+        },
+        [command, id] if command == "remove-group" => {
+            Command::RemoveGroup(parse_id("group rule", id)?)
+        }
+        [command, nyaa_id, show_id] if command == "torrent" || command == "reject" => {
+            Command::Torrent {
+                nyaa_id: parse_id("nyaa", nyaa_id)?,
+                show_id: parse_id("show", show_id)?,
+                assigned: command == "torrent",
+            }
+        }
+        [command] if command == "list" => Command::List,
+        _ => return Err(anyhow!(USAGE)),
+    };
+    Ok(command)
+}
+
+fn parse_id(kind: &str, id: &str) -> Result<i64> {
+    id.parse()
+        .map_err(|_| anyhow!("invalid {} id {}", kind, id))
+}
+
+async fn async_override(command: &Command) -> Result<()> {
+    let config: Config = common::config::load()?;
+    let mut con = PgConnector::new(config.db.connection_string)
+        .connect()
+        .await?;
+    let tran = pg::transaction(&mut con).await?;
+    match *command {
+        Command::Title {
+            show_id,
+            ref pattern,
+        } => {
+            show_lifecycle::lock_listed(&tran, show_id).await?;
+            // language=sql
+            let row = tran
+                .query_opt(
+                    "
+                    insert into magnets.title_override (pattern, show_id) values ($1, $2)
+                    on conflict (pattern) do nothing
+                    returning title_override_id",
+                    &[pattern, &show_id],
+                )
+                .await?;
+            let id: i64 = match row {
+                Some(row) => row.get(0),
+                _ => return Err(anyhow!("the pattern {} already exists", pattern)),
+            };
+            let after = json!({
+                "title_override_id": id,
+                "show_id": show_id,
+                "pattern": pattern,
+            });
+            audit::record(&tran, "add-title-override", None, Some(after)).await?;
+            rematch_all(&tran).await?;
+            println!("added title override {}", id);
+        }
+        Command::RemoveTitle(id) => {
+            // language=sql
+            let row = tran
+                .query_opt(
+                    "
+                    delete from magnets.title_override
+                    where title_override_id = $1
+                    returning show_id, pattern",
+                    &[&id],
+                )
+                .await?;
+            let row = match row {
+                Some(row) => row,
+                _ => return Err(anyhow!("there is no title override {}", id)),
+            };
+            let before = json!({
+                "title_override_id": id,
+                "show_id": row.get::<_, i64>(0),
+                "pattern": row.get::<_, String>(1),
+            });
+            audit::record(&tran, "remove-title-override", Some(before), None).await?;
+            rematch_all(&tran).await?;
+            println!("removed title override {}", id);
+        }
+        Command::Group {
+            show_id,
+            ref release_group,
+            ref name,
+        } => {
+            // Titles are compared by these keys (see `title_analyzer::find_group_rule`)
+            let release_group_key = textnorm::matcher_key(release_group);
+            let name_key = textnorm::matcher_key(name);
+            if release_group_key.is_empty() || name_key.is_empty() {
+                return Err(anyhow!(
+                    "the release group and the name must contain latin letters or digits"
+                ));
+            }
+            show_lifecycle::lock_listed(&tran, show_id).await?;
+            // language=sql
+            let row = tran
+                .query_opt(
+                    "
+                    insert into magnets.group_rule
+                    (release_group, name, release_group_key, name_key, show_id)
+                    values ($1, $2, $3, $4, $5)
+                    on conflict (release_group_key, name_key) do nothing
+                    returning group_rule_id",
+                    &[release_group, name, &release_group_key, &name_key, &show_id],
+                )
+                .await?;
+            let id: i64 = match row {
+                Some(row) => row.get(0),
+                _ => {
+                    return Err(anyhow!(
+                        "there already is a rule for {} of {}",
+                        name,
+                        release_group
+                    ))
+                }
+            };
+            let after = json!({
+                "group_rule_id": id,
+                "show_id": show_id,
+                "release_group": release_group,
+                "name": name,
+            });
+            audit::record(&tran, "add-group-rule", None, Some(after)).await?;
+            rematch_all(&tran).await?;
+            println!("added group rule {}", id);
+        }
+        Command::RemoveGroup(id) => {
+            // language=sql
+            let row = tran
+                .query_opt(
+                    "
+                    delete from magnets.group_rule
+                    where group_rule_id = $1
+                    returning show_id, release_group, name",
+                    &[&id],
+                )
+                .await?;
+            let row = match row {
+                Some(row) => row,
+                _ => return Err(anyhow!("there is no group rule {}", id)),
+            };
+            let before = json!({
+                "group_rule_id": id,
+                "show_id": row.get::<_, i64>(0),
+                "release_group": row.get::<_, String>(1),
+                "name": row.get::<_, String>(2),
+            });
+            audit::record(&tran, "remove-group-rule", Some(before), None).await?;
+            rematch_all(&tran).await?;
+            println!("removed group rule {}", id);
+        }
+        Command::Torrent {
+            nyaa_id,
+            show_id,
+            assigned,
+        } => {
+            override_torrent(&tran, nyaa_id, show_id, assigned).await?;
+            println!(
+                "{} torrent {} and show {}",
+                match assigned {
+                    true => "assigned",
+                    false => "unmatched",
+                },
+                nyaa_id,
+                show_id
+            );
+        }
+        Command::List => list(&tran).await?,
+    }
+    tran.commit().await?;
+    Ok(())
+}
+
+async fn override_torrent(
+    tran: &Transaction<'_>,
+    nyaa_id: i64,
+    show_id: i64,
+    assigned: bool,
+) -> Result<()> {
+    show_lifecycle::lock_listed(tran, show_id).await?;
+    // language=sql
+    let row = tran
+        .query_opt(
+            "select torrent_id, title from magnets.torrent where nyaa_id = $1 for update",
+            &[&nyaa_id],
+        )
+        .await?;
+    let (torrent_id, title): (i64, String) = match row {
+        Some(row) => (row.get(0), row.get(1)),
+        _ => return Err(anyhow!("there is no torrent with nyaa id {}", nyaa_id)),
+    };
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.match_override (torrent_id, show_id, assigned, actor)
+        values ($1, $2, $3, $4)
+        on conflict (torrent_id, show_id)
+        do update set assigned = $3, actor = $4, created = now()",
+        &[&torrent_id, &show_id, &assigned, &audit::ACTOR],
+    )
+    .await?;
+    // Named like the corrections on the admin site
+    let (action, before, after) = match assigned {
+        true => (
+            "assign-match",
+            None,
+            Some(json!({"torrent_id": torrent_id, "show_id": show_id})),
+        ),
+        false => (
+            "remove-match",
+            Some(json!({"torrent_id": torrent_id, "show_id": show_id})),
+            None,
+        ),
+    };
+    audit::record(tran, action, before, after).await?;
+    if assigned {
+        // language=sql
+        let matched = tran
+            .query_opt(
+                "select 1 from magnets.rel_torrent_show where torrent_id = $1 and show_id = $2",
+                &[&torrent_id, &show_id],
+            )
+            .await?
+            .is_some();
+        if !matched {
+            matcher::insert_match(tran, torrent_id, show_id, &title).await?;
+        }
+    } else {
+        // language=sql
+        tran.execute(
+            "delete from magnets.rel_torrent_show where torrent_id = $1 and show_id = $2",
+            &[&torrent_id, &show_id],
+        )
+        .await?;
+        // language=sql
+        tran.execute(
+            "
+            update magnets.torrent t
+            set matched = exists (
+                select * from magnets.rel_torrent_show rts where rts.torrent_id = t.torrent_id
+            )
+            where torrent_id = $1",
+            &[&torrent_id],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn list(tran: &Transaction<'_>) -> Result<()> {
+    // language=sql
+    let patterns = tran
+        .query(
+            "
+            select o.title_override_id, o.show_id, o.pattern, s.removal is not null as removed
+            from magnets.title_override o
+            join magnets.show s using (show_id)
+            order by o.title_override_id",
+            &[],
+        )
+        .await?;
+    println!("title overrides:");
+    for row in &patterns {
+        let removed: bool = row.get("removed");
+        println!(
+            "    {}: show {}: {}{}",
+            row.get::<_, i64>("title_override_id"),
+            row.get::<_, i64>("show_id"),
+            row.get::<_, String>("pattern"),
+            if removed { " (show removed)" } else { "" },
+        );
+    }
+    // language=sql
+    let rules = tran
+        .query(
+            "
+            select r.group_rule_id, r.show_id, r.release_group, r.name,
+                s.removal is not null as removed
+            from magnets.group_rule r
+            join magnets.show s using (show_id)
+            order by r.group_rule_id",
+            &[],
+        )
+        .await?;
+    println!("group rules:");
+    for row in &rules {
+        let removed: bool = row.get("removed");
+        println!(
+            "    {}: show {}: [{}] {}{}",
+            row.get::<_, i64>("group_rule_id"),
+            row.get::<_, i64>("show_id"),
+            row.get::<_, String>("release_group"),
+            row.get::<_, String>("name"),
+            if removed { " (show removed)" } else { "" },
+        );
+    }
+    // language=sql
+    let torrents = tran
+        .query(
+            "
+            select t.nyaa_id, o.show_id, o.assigned, o.actor
+            from magnets.match_override o
+            join magnets.torrent t using (torrent_id)
+            order by o.created",
+            &[],
+        )
+        .await?;
+    println!("torrent overrides:");
+    for row in &torrents {
+        let assigned: bool = row.get("assigned");
+        println!(
+            "    torrent {} {} show {} by {}",
+            row.get::<_, i64>("nyaa_id"),
+            if assigned {
+                "assigned to"
+            } else {
+                "rejected for"
+            },
+            row.get::<_, i64>("show_id"),
+            row.get::<_, String>("actor"),
+        );
+    }
+    Ok(())
+}
+
+/// Torrents that are already matched might match another show now
+///
+/// The matcher reloads the show db and with it the group rules before rematching.
+async fn rematch_all(tran: &Transaction<'_>) -> Result<()> {
+    db_state::set(tran, REMATCH_UNMATCHED, 2).await
+}
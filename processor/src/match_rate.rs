@@ -0,0 +1,77 @@
+use crate::{alerts, db_state, db_state::MATCHER_ANOMALY, state::State};
+use anyhow::Result;
+use common::pg;
+
+/// Alerts when the share of matched torrents among the newest torrents drops
+///
+/// Checks the last `matcher.anomaly.window` torrents every `matcher.anomaly.interval`
+/// (10 minutes by default). If fewer than `matcher.anomaly.threshold` of them are
+/// matched, e.g. after nyaa.si changed the format of its titles or the show db is
+/// broken, `matcher_anomaly` is set to true in `magnets.state` and an alert is sent. Another alert is sent once the rate has
+/// recovered.
+pub async fn watch_match_rate(state: &State<'_>) {
+    let config = match &state.config.matcher.anomaly {
+        Some(c) => c,
+        _ => return,
+    };
+    loop {
+        if let Err(e) = check(state, config.window, config.threshold).await {
+            log::error!("could not check the match rate: {:#}", e);
+        }
+        state.sleep(|c| c.matcher.anomaly_interval()).await;
+    }
+}
+
+async fn check(state: &State<'_>, window: i64, threshold: f64) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    // language=sql
+    let row = tran
+        .query_one(
+            "
+            select count(*) filter (where matched), count(*)
+            from (
+                select matched
+                from magnets.torrent
+                order by nyaa_id desc
+                limit $1
+            ) t",
+            &[&window],
+        )
+        .await?;
+    let matched: i64 = row.get(0);
+    let total: i64 = row.get(1);
+    // Don't raise an alarm while the database is still being filled
+    if total < window {
+        return Ok(());
+    }
+    let rate = matched as f64 / total as f64;
+    let anomaly = rate < threshold;
+    let was_anomaly: bool = db_state::get(&tran, MATCHER_ANOMALY).await?;
+    if anomaly == was_anomaly {
+        return Ok(());
+    }
+    db_state::set(&tran, MATCHER_ANOMALY, anomaly).await?;
+    // Only the leader sends the alert
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    let text = if anomaly {
+        format!(
+            "only {} of the last {} torrents have been matched ({:.0}%, threshold {:.0}%)",
+            matched,
+            total,
+            100.0 * rate,
+            100.0 * threshold
+        )
+    } else {
+        format!(
+            "the match rate has recovered: {} of the last {} torrents have been matched \
+             ({:.0}%)",
+            matched,
+            total,
+            100.0 * rate
+        )
+    };
+    alerts::send(state, &text).await;
+    Ok(())
+}
@@ -1,9 +1,12 @@
 use crate::{
-    db_state, db_state::REMATCH_UNMATCHED, show_db::Show, state::State, title_analyzer,
+    db_state, db_state::REMATCH_UNMATCHED, job_lock, job_lock::Job, show_db::ShowDb,
+    state::State, title_analyzer,
 };
 use anyhow::Result;
 use common::pg;
-use tokio_postgres::Transaction;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::{Client, GenericClient, Transaction};
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum RematchMode {
@@ -24,6 +27,10 @@ pub async fn match_unmatched(state: &State<'_>) {
         };
         if rematch != RematchMode::None {
             log::info!("rematching torrents");
+            // Rematches are usually requested after the shows have been edited
+            if let Err(e) = state.show_db.refresh().await {
+                log::error!("refreshing shows db failed: {:#}", e);
+            }
             if let Err(e) = match_unmatched_(state, rematch).await {
                 log::error!("matching unmatched torrents failed: {:#}", e);
             }
@@ -47,17 +54,25 @@ async fn get_rematch_unmatched(state: &State<'_>) -> Result<RematchMode> {
 
 // language=sql
 common::create_statement!(LoadAllUnmatchedTorrents, torrent_id, title;
-                          "select * from magnets.torrent where not matched");
+                          "select torrent_id, title from magnets.torrent where not matched");
+
+/// Prepares the statements of this module
+pub async fn check_statements(con: &Client) -> Result<()> {
+    LoadAllUnmatchedTorrents::new(con).await?;
+    Ok(())
+}
+
+/// The number of unmatched torrents that are held in memory at once
+const UNMATCHED_BATCH_SIZE: i32 = 1000;
 
 async fn match_unmatched_(state: &State<'_>, mode: RematchMode) -> Result<()> {
+    if mode == RematchMode::All {
+        return rematch_all(state).await;
+    }
     let show_db = state.show_db.get().await?;
     let mut con = state.pg_connector.connect().await?;
     let tran = pg::transaction(&mut con).await?;
-    if mode == RematchMode::All {
-        // language=sql
-        tran.simple_query("truncate magnets.rel_torrent_show")
-            .await?;
-    }
+    job_lock::lock(&tran, Job::Rematch).await?;
     // language=sql
     tran.simple_query(
         "
@@ -69,53 +84,371 @@ async fn match_unmatched_(state: &State<'_>, mode: RematchMode) -> Result<()> {
         )",
     )
     .await?;
+    let overrides = Overrides::load(&tran, i64::MIN, i64::MAX).await?;
     let load = LoadAllUnmatchedTorrents::new(&tran).await?;
-    let rows = tran.query(&load.stmt, &[]).await?;
+    // The backlog of unmatched torrents can be large. Read it through a portal in
+    // batches instead of loading it at once.
+    let portal = tran.bind(&load.stmt, &[]).await?;
     let mut matched = 0;
-    for row in &rows {
-        let title = row.get(load.title);
-        let torrent_id: i64 = row.get(load.torrent_id);
-        if let Ok(s) = title_analyzer::find_show(&show_db, title) {
-            insert_match(&tran, torrent_id, &s).await?;
-            if mode != RematchMode::All {
+    let mut total = 0;
+    loop {
+        let rows = tran.query_portal(&portal, UNMATCHED_BATCH_SIZE).await?;
+        if rows.is_empty() {
+            break;
+        }
+        total += rows.len();
+        for row in &rows {
+            let title = row.get(load.title);
+            let torrent_id: i64 = row.get(load.torrent_id);
+            let found = title_analyzer::find_show(&show_db, title).ok();
+            let shows = overrides.apply(torrent_id, title, found.map(|s| s.show_id));
+            for &show_id in &shows {
+                insert_match(&tran, torrent_id, show_id, title).await?;
                 log::info!(
                     "matched previously unmatched torrent {} with show {}: {}",
                     torrent_id,
-                    s.show_id,
+                    show_id,
                     title
                 );
             }
-            matched += 1;
+            if !shows.is_empty() {
+                matched += 1;
+            }
         }
     }
     log::info!(
         "matched {} out of {} previously unmatched torrents",
         matched,
-        rows.len()
+        total
     );
     db_state::set(&tran, REMATCH_UNMATCHED, 0).await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    Ok(())
+}
+
+/// The number of torrents that are rematched per transaction
+const REMATCH_CHUNK_SIZE: i64 = 10_000;
+
+/// Rematches all torrents
+///
+/// The new matches are collected in `magnets.rel_torrent_show_next` in one transaction
+/// per chunk of torrents. At the end they replace the contents of
+/// `magnets.rel_torrent_show` in a single short transaction. Until then the site keeps
+/// serving the old matches.
+async fn rematch_all(state: &State<'_>) -> Result<()> {
+    let show_db = state.show_db.get().await?;
+    let mut con = state.pg_connector.connect().await?;
+    // Held until `con` is dropped
+    job_lock::lock_session(&con, Job::Rematch).await?;
+    // language=sql
+    con.simple_query("truncate magnets.rel_torrent_show_next")
+        .await?;
+    let mut after = 0;
+    let mut total = 0;
+    loop {
+        let tran = pg::transaction(&mut con).await?;
+        let (torrents, last) = rematch_chunk(&tran, &show_db, after).await?;
+        tran.commit().await?;
+        if torrents == 0 {
+            break;
+        }
+        total += torrents;
+        after = last;
+        log::info!("rematched {} torrents", total);
+    }
+
+    let tran = pg::transaction(&mut con).await?;
+    // Block new torrents until the swap is done so that none of them is missing from the
+    // new matches. The torrents inserted since the last chunk are matched below.
+    // language=sql
+    tran.simple_query("lock table magnets.torrent in share mode")
+        .await?;
+    loop {
+        let (torrents, last) = rematch_chunk(&tran, &show_db, after).await?;
+        if torrents == 0 {
+            break;
+        }
+        after = last;
+    }
+    // language=sql
+    tran.simple_query(
+        "
+        delete from magnets.rel_torrent_show;
+
+        insert into magnets.rel_torrent_show
+            (show_id, torrent_id, nyaa_id, episode, episode_end)
+        select show_id, torrent_id, nyaa_id, episode, episode_end
+        from magnets.rel_torrent_show_next;
+
+        update magnets.torrent t
+        set
+            matched = exists (
+                select *
+                from magnets.rel_torrent_show_next n
+                where n.torrent_id = t.torrent_id
+            ),
+            batch = exists (
+                select *
+                from magnets.rel_torrent_show_next n
+                where n.torrent_id = t.torrent_id and n.batch
+            );
+
+        truncate magnets.rel_torrent_show_next;",
+    )
+    .await?;
+    db_state::set(&tran, REMATCH_UNMATCHED, 0).await?;
+    state.leader.ensure().await?;
     tran.commit().await?;
+    log::info!("rematched all torrents");
     Ok(())
 }
 
+/// Matches the next chunk of torrents with `torrent_id > after`
+///
+/// Returns the number of torrents in the chunk and the largest torrent id.
+async fn rematch_chunk(
+    tran: &Transaction<'_>,
+    show_db: &ShowDb,
+    after: i64,
+) -> Result<(usize, i64)> {
+    // language=sql
+    let rows = tran
+        .query(
+            "
+            select torrent_id, nyaa_id, title
+            from magnets.torrent
+            where torrent_id > $1
+            order by torrent_id
+            limit $2",
+            &[&after, &REMATCH_CHUNK_SIZE],
+        )
+        .await?;
+    let last = rows.last().map(|r| r.get(0)).unwrap_or(after);
+    let overrides = Overrides::load(tran, after + 1, last).await?;
+    let mut show_ids = vec![];
+    let mut torrent_ids = vec![];
+    let mut nyaa_ids = vec![];
+    let mut episodes = vec![];
+    let mut episode_ends = vec![];
+    let mut batches = vec![];
+    for row in &rows {
+        let torrent_id: i64 = row.get(0);
+        let title: &str = row.get(2);
+        let found = title_analyzer::find_show(show_db, title).ok();
+        for show_id in overrides.apply(torrent_id, title, found.map(|s| s.show_id)) {
+            show_ids.push(show_id);
+            torrent_ids.push(torrent_id);
+            nyaa_ids.push(row.get::<_, i64>(1));
+            let (episode, episode_end) = title_analyzer::find_episodes(title);
+            episodes.push(episode);
+            episode_ends.push(episode_end);
+            batches.push(title_analyzer::is_batch(title));
+        }
+    }
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.rel_torrent_show_next
+            (show_id, torrent_id, nyaa_id, episode, episode_end, batch)
+        select *
+        from unnest($1::bigint[], $2::bigint[], $3::bigint[], $4::int[], $5::int[], $6::bool[])",
+        &[&show_ids, &torrent_ids, &nyaa_ids, &episodes, &episode_ends, &batches],
+    )
+    .await?;
+    Ok((rows.len(), last))
+}
+
+/// The manual corrections of matches
+///
+/// Moderators assign torrents to shows and reject matches on /admin/torrent or with
+/// `processor override torrent` (`magnets.match_override`). Titles can be matched to a
+/// show by a pattern with `processor override title` (`magnets.title_override`).
+///
+/// A matching pattern wins over the title analyzer. The matcher never matches a torrent
+/// to a rejected show and always matches it to its assigned shows, also when all
+/// torrents are rematched.
+#[derive(Default)]
+pub struct Overrides {
+    assigned: HashMap<i64, Vec<i64>>,
+    rejected: HashSet<(i64, i64)>,
+    /// In the order in which they were added
+    patterns: Vec<(Regex, i64)>,
+}
+
+impl Overrides {
+    /// Loads the title patterns and the overrides of the torrents with ids between
+    /// `from` and `to` inclusive
+    pub async fn load(con: &impl GenericClient, from: i64, to: i64) -> Result<Self> {
+        let mut res = Self::load_patterns(con).await?;
+        // language=sql
+        let rows = con
+            .query(
+                "
+                select torrent_id, show_id, assigned
+                from magnets.match_override
+                where torrent_id between $1 and $2",
+                &[&from, &to],
+            )
+            .await?;
+        for row in rows {
+            let torrent_id: i64 = row.get("torrent_id");
+            let show_id: i64 = row.get("show_id");
+            match row.get("assigned") {
+                true => res.assigned.entry(torrent_id).or_default().push(show_id),
+                false => {
+                    res.rejected.insert((torrent_id, show_id));
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// Loads only the title patterns, e.g. for new torrents
+    pub async fn load_patterns(con: &impl GenericClient) -> Result<Self> {
+        // language=sql
+        let rows = con
+            .query(
+                "
+                select o.pattern, o.show_id
+                from magnets.title_override o
+                join magnets.show s using (show_id)
+                where s.removal is null
+                order by o.title_override_id",
+                &[],
+            )
+            .await?;
+        let mut res = Self::default();
+        for row in rows {
+            let pattern: &str = row.get("pattern");
+            match title_pattern(pattern) {
+                Ok(regex) => res.patterns.push((regex, row.get("show_id"))),
+                Err(e) => log::error!("invalid title override {}: {:#}", pattern, e),
+            }
+        }
+        Ok(res)
+    }
+
+    /// Returns the show of the first pattern that matches the title
+    pub fn pattern_show(&self, title: &str) -> Option<i64> {
+        self.patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(title))
+            .map(|(_, show_id)| *show_id)
+    }
+
+    /// Returns the shows of a torrent given the show found by the title analyzer
+    pub fn apply(&self, torrent_id: i64, title: &str, found: Option<i64>) -> Vec<i64> {
+        let mut shows = self.assigned.get(&torrent_id).cloned().unwrap_or_default();
+        if let Some(show_id) = self.pattern_show(title).or(found) {
+            if !shows.contains(&show_id)
+                && !self.rejected.contains(&(torrent_id, show_id))
+            {
+                shows.push(show_id);
+            }
+        }
+        shows
+    }
+}
+
+/// Records that a torrent has been matched to a show
+///
+/// The episodes and whether the torrent is a batch are extracted from the title.
+/// Nothing is recorded if a moderator has rejected the match. Returns whether the match
+/// has been recorded.
 pub async fn insert_match(
     tran: &Transaction<'_>,
     torrent_id: i64,
-    s: &Show,
-) -> Result<()> {
+    show_id: i64,
+    title: &str,
+) -> Result<bool> {
+    let (episode, episode_end) = title_analyzer::find_episodes(title);
+    let batch = title_analyzer::is_batch(title);
+    // language=sql
+    let inserted = tran
+        .execute(
+            "
+            insert into magnets.rel_torrent_show
+                (show_id, torrent_id, nyaa_id, episode, episode_end)
+            select $1, $2, nyaa_id, $3, $4
+            from magnets.torrent
+            where torrent_id = $2
+                and not exists (
+                    select 1
+                    from magnets.match_override o
+                    where o.torrent_id = $2 and o.show_id = $1 and not o.assigned
+                )",
+            &[&show_id, &torrent_id, &episode, &episode_end],
+        )
+        .await?;
+    if inserted == 0 {
+        log::info!(
+            "not matching torrent {} with show {} because the match has been rejected",
+            torrent_id,
+            show_id
+        );
+        return Ok(false);
+    }
     // language=sql
     tran.execute(
-        "insert into magnets.rel_torrent_show (show_id, torrent_id, nyaa_id)
-        select $1, $2, nyaa_id
-        from magnets.torrent where torrent_id = $2",
-        &[&s.show_id, &torrent_id],
+        "update magnets.torrent set matched = true, batch = $2 where torrent_id = $1",
+        &[&torrent_id, &batch],
     )
     .await?;
+    // See `search::update_last_torrents`
     // language=sql
     tran.execute(
-        "update magnets.torrent set matched = true where torrent_id = $1",
-        &[&torrent_id],
+        "
+        update magnets.show s
+        set last_torrent_at = t.uploaded_at
+        from magnets.torrent t
+        where s.show_id = $1 and t.torrent_id = $2
+            and (s.last_torrent_at is null or s.last_torrent_at < t.uploaded_at)",
+        &[&show_id, &torrent_id],
     )
     .await?;
-    Ok(())
+    Ok(true)
+}
+
+/// Compiles the pattern of a title override
+///
+/// Patterns are regular expressions that are matched case-insensitively anywhere in the
+/// title.
+pub fn title_pattern(pattern: &str) -> Result<Regex> {
+    Ok(Regex::new(&format!("(?i){}", pattern))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_overrides() {
+        let mut overrides = Overrides::default();
+        overrides.assigned.insert(1, vec![10]);
+        overrides.rejected.insert((1, 11));
+        overrides.rejected.insert((2, 11));
+        assert_eq!(overrides.apply(1, "", Some(11)), vec![10]);
+        assert_eq!(overrides.apply(1, "", Some(10)), vec![10]);
+        assert_eq!(overrides.apply(1, "", Some(12)), vec![10, 12]);
+        assert_eq!(overrides.apply(2, "", Some(11)), Vec::<i64>::new());
+        assert_eq!(overrides.apply(3, "", Some(11)), vec![11]);
+        assert_eq!(overrides.apply(3, "", None), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn patterns_win_over_the_analyzer() {
+        let mut overrides = Overrides::default();
+        overrides
+            .patterns
+            .push((title_pattern(r"\bfrieren\b").unwrap(), 20));
+        overrides
+            .patterns
+            .push((title_pattern("sousou").unwrap(), 21));
+        overrides.rejected.insert((2, 20));
+        let title = "[SubsPlease] Sousou no Frieren - 05 (1080p)";
+        assert_eq!(overrides.apply(1, title, Some(11)), vec![20]);
+        assert_eq!(overrides.apply(2, title, Some(11)), Vec::<i64>::new());
+        assert_eq!(overrides.apply(1, "Other - 01", Some(11)), vec![11]);
+    }
 }
@@ -0,0 +1,75 @@
+use crate::{config, state::State};
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+const MIB: usize = 1024 * 1024;
+
+/// Load-shedding when the processor exceeds its memory budget
+///
+/// The processor runs with a small memory budget. If the heap grows above
+/// `memory.budget_mib`, non-essential work is deferred until the heap shrinks again.
+/// Essential work (scraping, matching, syncing AniList) always runs.
+pub struct Memory {
+    budget: Option<usize>,
+    shedding: AtomicBool,
+}
+
+impl Memory {
+    pub fn new(config: &config::Memory) -> Self {
+        Self {
+            budget: config.budget_mib.map(|b| b * MIB),
+            shedding: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns whether non-essential work should be deferred
+    pub fn shedding(&self) -> bool {
+        self.shedding.load(Relaxed)
+    }
+}
+
+/// Returns the number of bytes allocated on the heap or 0 if this is unknown
+pub fn heap_usage() -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        crate::allocator::allocated()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        0
+    }
+}
+
+fn trim() {
+    #[cfg(target_os = "linux")]
+    crate::allocator::trim();
+}
+
+/// Periodically compares the heap usage with the budget
+pub async fn watch_memory(state: &State<'_>) {
+    let budget = match state.memory.budget {
+        Some(b) => b,
+        _ => return,
+    };
+    loop {
+        let usage = heap_usage();
+        let over = usage > budget;
+        if over {
+            log::warn!(
+                "heap usage of {} MiB exceeds the budget of {} MiB (show db: {} MiB). \
+                 deferring non-essential work",
+                usage / MIB,
+                budget / MIB,
+                state.show_db.heap_size().await / MIB,
+            );
+            state.http_cache.clear();
+            trim();
+        } else if state.memory.shedding() {
+            log::info!(
+                "heap usage of {} MiB is within the budget again",
+                usage / MIB
+            );
+        }
+        state.memory.shedding.store(over, Relaxed);
+        state.sleep(|c| c.memory.check_interval).await;
+    }
+}
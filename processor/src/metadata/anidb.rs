@@ -0,0 +1,185 @@
+use crate::{
+    http::HttpCache,
+    metadata::provider::{MetadataProvider, ProviderShow},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use common::{textnorm, YearSeason};
+use flate2::read::GzDecoder;
+use std::{collections::HashMap, io::Read};
+
+/// The titles dump of anidb.net
+///
+/// The dump contains the titles of all shows but neither their seasons nor their anilist
+/// ids. This provider can therefore not list a season. Instead it looks up the shows of
+/// the providers with a higher priority and returns their synonyms, short titles and
+/// official titles so that the matcher recognizes more spellings.
+///
+/// AniDB bans clients that download the dump more than once per day. It is cached by the
+/// [HttpCache].
+pub struct Anidb<'a> {
+    pub http_cache: &'a HttpCache,
+    pub url: &'a str,
+}
+
+/// The type of official titles in the dump. The other types are the primary title (1),
+/// synonyms (2) and short titles (3).
+const OFFICIAL: u8 = 4;
+
+#[derive(Default)]
+struct Entry {
+    titles: Vec<Title>,
+}
+
+struct Title {
+    ty: u8,
+    lang: String,
+    title: String,
+}
+
+/// Parses the dump
+///
+/// Each line has the format `aid|type|language|title`. Lines starting with `#` are
+/// comments.
+fn parse_dump(dump: &str) -> HashMap<i64, Entry> {
+    let mut entries: HashMap<i64, Entry> = HashMap::new();
+    for line in dump.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.splitn(4, '|');
+        let (aid, ty, lang, title) =
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(aid), Some(ty), Some(lang), Some(title)) => (aid, ty, lang, title),
+                _ => continue,
+            };
+        let (aid, ty) = match (aid.parse(), ty.parse()) {
+            (Ok(aid), Ok(ty)) => (aid, ty),
+            _ => continue,
+        };
+        entries.entry(aid).or_default().titles.push(Title {
+            ty,
+            lang: lang.to_string(),
+            title: textnorm::storage(title),
+        });
+    }
+    entries
+}
+
+/// Returns the titles of the known shows
+///
+/// A show is found if one of its titles folds to a title of exactly one entry.
+fn lookup(entries: &HashMap<i64, Entry>, known: &[ProviderShow]) -> Vec<ProviderShow> {
+    let mut by_title: HashMap<String, Vec<i64>> = HashMap::new();
+    for (&aid, entry) in entries {
+        for title in &entry.titles {
+            let aids = by_title
+                .entry(textnorm::search_fold(&title.title))
+                .or_default();
+            if !aids.contains(&aid) {
+                aids.push(aid);
+            }
+        }
+    }
+    let mut shows = vec![];
+    for show in known {
+        let aid = std::iter::once(&show.romaji)
+            .chain(show.english.as_ref())
+            .filter_map(|t| match by_title.get(&textnorm::search_fold(t)) {
+                Some(aids) if aids.len() == 1 => Some(aids[0]),
+                _ => None,
+            })
+            .next();
+        let entry = match aid {
+            Some(aid) => &entries[&aid],
+            _ => continue,
+        };
+        let mut found = ProviderShow {
+            anilist_id: show.anilist_id,
+            mal_id: show.mal_id,
+            romaji: show.romaji.clone(),
+            ..Default::default()
+        };
+        for title in &entry.titles {
+            if !matches!(&*title.lang, "x-jat" | "en" | "ja") {
+                continue;
+            }
+            if title.ty == OFFICIAL && title.lang == "en" && found.english.is_none() {
+                found.english = Some(title.title.clone());
+            } else {
+                found.additional.push(title.title.clone());
+            }
+        }
+        shows.push(found);
+    }
+    shows
+}
+
+#[async_trait]
+impl<'a> MetadataProvider for Anidb<'a> {
+    fn name(&self) -> &'static str {
+        "anidb"
+    }
+
+    async fn season(
+        &self,
+        _season: YearSeason,
+        known: &[ProviderShow],
+    ) -> Result<Vec<ProviderShow>> {
+        if known.is_empty() {
+            return Ok(vec![]);
+        }
+        let compressed = self.http_cache.get(self.url).await?;
+        let mut dump = String::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut dump)
+            .context("cannot decompress the titles dump")?;
+        Ok(lookup(&parse_dump(&dump), known))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+# created: Sat Oct 18 02:00:01 2026
+# <aid>|<type>|<language>|<title>
+17617|1|x-jat|Sousou no Frieren
+17617|4|en|Frieren: Beyond Journey's End
+17617|4|ja|葬送のフリーレン
+17617|3|x-jat|Frieren
+17617|2|de|Frieren - Nach dem Ende der Reise
+9541|1|x-jat|Shingeki no Kyojin
+";
+
+    #[test]
+    fn returns_titles_of_known_shows() {
+        let known = [
+            ProviderShow {
+                anilist_id: Some(154587),
+                romaji: "SOUSOU NO FRIEREN".to_string(),
+                ..Default::default()
+            },
+            ProviderShow {
+                romaji: "Unknown".to_string(),
+                ..Default::default()
+            },
+        ];
+        let shows = lookup(&parse_dump(DUMP), &known);
+        assert_eq!(
+            shows,
+            vec![ProviderShow {
+                anilist_id: Some(154587),
+                romaji: "SOUSOU NO FRIEREN".to_string(),
+                english: Some("Frieren: Beyond Journey's End".to_string()),
+                additional: vec![
+                    "Sousou no Frieren".to_string(),
+                    "葬送のフリーレン".to_string(),
+                    "Frieren".to_string(),
+                ],
+                ..Default::default()
+            }]
+        );
+    }
+}
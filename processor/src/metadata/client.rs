@@ -0,0 +1,199 @@
+use crate::sleeper::Sleeper;
+use anyhow::{anyhow, Result};
+use common::time::{Clock, StdDuration, MINUTE};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Instant};
+use tokio::sync::Mutex;
+
+/// A client for the anilist graphql API
+///
+/// This client has several nice properties:
+///
+/// - It can be used concurrently but enforces a timeout between the starts of
+///   requests
+/// - It automatically handles rate-limit errors by sleeping and retrying. While one
+///   request is backing off, no other request is started.
+pub struct AnilistClient<'a> {
+    client: &'a Client,
+    url: &'a str,
+    clock: Arc<dyn Clock>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    sleeper: Sleeper,
+    /// No request is started before this instant
+    paused_until: Option<Instant>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response<T> {
+    data: Option<T>,
+    errors: Option<Vec<Error>>,
+}
+
+#[derive(Serialize, Debug)]
+struct Body<'a, T> {
+    query: &'a str,
+    variables: &'a T,
+}
+
+#[derive(Deserialize, Debug)]
+struct Error {
+    message: String,
+    status: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PageInfo {
+    pub total: i32,
+    pub per_page: i32,
+    pub current_page: i32,
+    pub last_page: i32,
+    pub has_next_page: bool,
+}
+
+impl<'a> AnilistClient<'a> {
+    pub fn new(client: &'a Client, url: &'a str, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            client,
+            url,
+            inner: Mutex::new(Inner {
+                sleeper: Sleeper::new(clock.clone()),
+                paused_until: None,
+            }),
+            clock,
+        }
+    }
+
+    pub async fn request<V: Serialize, T: for<'b> Deserialize<'b>>(
+        &self,
+        query: &str,
+        variables: &V,
+    ) -> T {
+        loop {
+            match self.try_request(query, variables).await {
+                Ok(d) => return d,
+                Err(e) => log::error!("could perform request: {:#}", e),
+            }
+        }
+    }
+
+    /// Like [Self::request] but returns the error instead of retrying
+    ///
+    /// The pause after a failed request still applies to all other requests. This is
+    /// used by the fallback providers which must not block during an anilist outage.
+    pub async fn try_request<V: Serialize, T: for<'b> Deserialize<'b>>(
+        &self,
+        query: &str,
+        variables: &V,
+    ) -> Result<T> {
+        self.try_request_(None, query, variables).await
+    }
+
+    /// Like [Self::try_request] but on behalf of the user to whom the access token
+    /// belongs
+    pub async fn try_request_as<V: Serialize, T: for<'b> Deserialize<'b>>(
+        &self,
+        access_token: &str,
+        query: &str,
+        variables: &V,
+    ) -> Result<T> {
+        self.try_request_(Some(access_token), query, variables)
+            .await
+    }
+
+    async fn try_request_<V: Serialize, T: for<'b> Deserialize<'b>>(
+        &self,
+        access_token: Option<&str>,
+        query: &str,
+        variables: &V,
+    ) -> Result<T> {
+        self.wait_for_turn().await;
+        let mut retry_after = None;
+        let res = self
+            .request_(&mut retry_after, access_token, query, variables)
+            .await;
+        if res.is_err() {
+            let delay = match retry_after {
+                Some(retry_after) => StdDuration::from_secs(retry_after),
+                _ => {
+                    // Some error has occurred that is not related to rate
+                    // limiting.
+                    MINUTE
+                }
+            };
+            log::info!("sleeping for {} seconds", delay.as_secs());
+            // Other requests that are in flight might fail as well. Only ever
+            // extend the pause.
+            let until = self.clock.instant() + delay;
+            let mut inner = self.inner.lock().await;
+            inner.paused_until = Some(match inner.paused_until {
+                Some(p) if p > until => p,
+                _ => until,
+            });
+        }
+        res
+    }
+
+    /// Waits until the next request may be started
+    ///
+    /// The lock is held while sleeping so that concurrent users are started one after
+    /// another.
+    async fn wait_for_turn(&self) {
+        let mut inner = self.inner.lock().await;
+        if let Some(until) = inner.paused_until.take() {
+            self.clock.sleep_until(until).await;
+            // Mark the start of the next try in the sleeper so that the next user
+            // gets delayed appropriately.
+            inner.sleeper.set_now();
+        }
+        // If everything were working properly, this one second timeout should ensure that
+        // we never go over the 90 requests/minute limit imposed by the anilist API.
+        // However: https://github.com/AniList/ApiV2-GraphQL-Docs/issues/103
+        inner.sleeper.sleep(StdDuration::from_secs(1)).await;
+    }
+
+    async fn request_<V: Serialize, T: for<'b> Deserialize<'b>>(
+        &self,
+        retry_after: &mut Option<u64>,
+        access_token: Option<&str>,
+        query: &str,
+        variables: &V,
+    ) -> Result<T> {
+        let body = Body { query, variables };
+        let mut request = self
+            .client
+            .post(self.url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json");
+        if let Some(token) = access_token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.json(&body).send().await?;
+        if let Some(limit) = response.headers().get("Retry-After") {
+            if let Ok(limit) = limit.to_str() {
+                if let Ok(num) = limit.parse::<u64>() {
+                    *retry_after = Some(num + 10);
+                    return Err(anyhow!("Retry-After header is set: {}", num));
+                }
+            }
+            return Err(anyhow!(
+                "Retry-After header is set but the value is invalid"
+            ));
+        }
+        let text = response.text().await?;
+        let response: Response<T> = match serde_json::from_str(&text) {
+            Ok(p) => p,
+            Err(_) => return Err(anyhow!("cannot parse response {}", text)),
+        };
+        if let Some(d) = response.data {
+            return Ok(d);
+        }
+        Err(anyhow!(
+            "response data is null, errors: {:?}",
+            response.errors
+        ))
+    }
+}
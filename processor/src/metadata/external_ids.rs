@@ -0,0 +1,75 @@
+//! The ids of shows on other sites
+//!
+//! Anilist knows the MyAnimeList id of most shows. The ids are synced together with the
+//! shows and stored in `magnets.show_external_id` so that the site can link to MAL.
+
+use anyhow::Result;
+use common::ExternalSite;
+use std::collections::HashMap;
+use tokio_postgres::{Client, Transaction};
+
+// language=sql
+common::create_statement!(LoadExternalIds, show_id, external_id;
+                          "select show_id, external_id from magnets.show_external_id where external_site = $1");
+
+/// Prepares the statements of this module
+pub async fn check_statements(con: &Client) -> Result<()> {
+    LoadExternalIds::new(con).await?;
+    Ok(())
+}
+
+/// Returns the MAL ids of all shows that have one, keyed by show id
+pub async fn load_mal_ids(tran: &Transaction<'_>) -> Result<HashMap<i64, i64>> {
+    let load = LoadExternalIds::new(tran).await?;
+    let rows = tran
+        .query(&load.stmt, &[&ExternalSite::MYANIMELIST])
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| (row.get(load.show_id), row.get(load.external_id)))
+        .collect())
+}
+
+/// Stores the MAL id of a show if it differs from the stored one
+///
+/// The id is removed if anilist no longer knows it.
+pub async fn store_mal_id(
+    tran: &Transaction<'_>,
+    show_id: i64,
+    old: Option<i64>,
+    new: Option<i64>,
+) -> Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    log::info!(
+        "updating mal id of show {} from {:?} to {:?}",
+        show_id,
+        old,
+        new
+    );
+    match new {
+        Some(mal_id) => {
+            // language=sql
+            tran.execute(
+                "
+                insert into magnets.show_external_id (show_id, external_site, external_id)
+                values ($1, $2, $3)
+                on conflict (show_id, external_site) do update set external_id = $3",
+                &[&show_id, &ExternalSite::MYANIMELIST, &mal_id],
+            )
+            .await?;
+        }
+        _ => {
+            // language=sql
+            tran.execute(
+                "
+                delete from magnets.show_external_id
+                where show_id = $1 and external_site = $2",
+                &[&show_id, &ExternalSite::MYANIMELIST],
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use crate::{
+    db_state,
+    db_state::LAST_SHOWS_UPDATE,
+    job_lock,
+    job_lock::Job,
+    metadata::{
+        external_ids,
+        provider::{merge, providers, ProviderShow},
+    },
+    show_list,
+    state::State,
+};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use common::{pg, textnorm, ExternalSite, ShowNameType, YearSeason};
+use std::collections::HashMap;
+use tokio_postgres::Transaction;
+
+/// Loads the current season from the metadata providers while the anilist sync is stale
+///
+/// The anilist sync refreshes all shows and fails as a whole during an outage. New shows
+/// of the current season would then never be matched. Once the last complete sync is
+/// older than `metadata.stale_after`, the providers are asked for the current season,
+/// highest priority first, and their shows are merged.
+///
+/// Shows are only matched by their anilist or myanimelist id. Titles are not unique
+/// enough: a wrong match would attach the titles of one show to another for good. Shows
+/// that exist are only given the titles that they lack as additional names, which the
+/// anilist sync keeps. New shows are only added if a provider knows their anilist id.
+/// The next anilist sync takes them over.
+pub async fn refresh_stale_season(state: &State<'_>) {
+    if state.config.metadata.providers.is_empty() {
+        return;
+    }
+    loop {
+        state.sleep(|c| c.metadata.check_interval).await;
+        if let Err(e) = refresh_stale_season_now(state).await {
+            log::error!(
+                "could not load the season from the fallback providers: {:#}",
+                e
+            );
+        }
+    }
+}
+
+async fn refresh_stale_season_now(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    let last_update: DateTime<Utc> = db_state::get(&con, LAST_SHOWS_UPDATE).await?;
+    let stale_after = Duration::from_std(state.config.metadata.stale_after)?;
+    let now = state.clock.now();
+    if now - last_update < stale_after {
+        return Ok(());
+    }
+    let season = YearSeason::of(&now);
+    log::warn!(
+        "the last anilist sync was at {}. loading {} from the fallback providers.",
+        last_update,
+        season.display_name()
+    );
+    let mut shows = vec![];
+    for provider in providers(state) {
+        match provider.season(season, &shows).await {
+            Ok(s) => {
+                log::info!("{} returned {} shows", provider.name(), s.len());
+                merge(&mut shows, s);
+            }
+            Err(e) => log::warn!("{} failed: {:#}", provider.name(), e),
+        }
+    }
+    if shows.is_empty() {
+        return Ok(());
+    }
+    let tran = pg::transaction(&mut con).await?;
+    job_lock::lock(&tran, Job::Shows).await?;
+    let names_changed = store(&tran, season, &shows).await?;
+    if names_changed {
+        // See load_shows
+        tran.batch_execute("notify show_change").await?;
+    }
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    if names_changed {
+        show_list::store(&con).await?;
+        state.show_db.refresh().await?;
+    }
+    Ok(())
+}
+
+/// Stores the shows of the providers
+///
+/// Returns whether names have been added.
+async fn store(
+    tran: &Transaction<'_>,
+    season: YearSeason,
+    shows: &[ProviderShow],
+) -> Result<bool> {
+    // language=sql
+    let rows = tran
+        .query(
+            "
+            select s.show_id, s.anilist_id, e.external_id as mal_id, n.name
+            from magnets.show s
+            join magnets.show_name n using (show_id)
+            left join magnets.show_external_id e
+                on e.show_id = s.show_id and e.external_site = $1
+            where s.removal is null",
+            &[&ExternalSite::MYANIMELIST],
+        )
+        .await?;
+    let mut by_anilist_id = HashMap::new();
+    let mut by_mal_id = HashMap::new();
+    let mut names: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in &rows {
+        let show_id: i64 = row.get("show_id");
+        let name: String = row.get("name");
+        by_anilist_id.insert(row.get::<_, i64>("anilist_id"), show_id);
+        if let Some(mal_id) = row.get::<_, Option<i64>>("mal_id") {
+            by_mal_id.insert(mal_id, show_id);
+        }
+        names
+            .entry(show_id)
+            .or_default()
+            .push(textnorm::search_fold(&name));
+    }
+    let mut names_changed = false;
+    for show in shows {
+        let show_id = show
+            .anilist_id
+            .and_then(|id| by_anilist_id.get(&id))
+            .or_else(|| show.mal_id.and_then(|id| by_mal_id.get(&id)))
+            .copied();
+        match show_id {
+            Some(show_id) => {
+                let known = names.entry(show_id).or_default();
+                let titles = std::iter::once(&show.romaji)
+                    .chain(show.english.as_ref())
+                    .chain(show.additional.iter());
+                for title in titles {
+                    let folded = textnorm::search_fold(title);
+                    if folded.is_empty() || known.contains(&folded) {
+                        continue;
+                    }
+                    log::info!("adding name to show {}: {}", show_id, title);
+                    insert_name(tran, show_id, ShowNameType::ADDITIONAL, title).await?;
+                    known.push(folded);
+                    names_changed = true;
+                }
+            }
+            _ => {
+                let (anilist_id, format) = match (show.anilist_id, show.format) {
+                    (Some(id), Some(format)) => (id, format),
+                    _ => {
+                        log::info!(
+                            "not adding show {} because its anilist id or format is unknown",
+                            show.romaji
+                        );
+                        continue;
+                    }
+                };
+                // Removed shows keep their anilist id. Leave them removed.
+                // language=sql
+                let row = tran
+                    .query_opt(
+                        "insert into magnets.show (anilist_id, show_format, season, episodes) values ($1, $2, $3, $4) on conflict (anilist_id) do nothing returning show_id",
+                        &[&anilist_id, &format.to_db(), &season.to_db(), &show.episodes],
+                    )
+                    .await?;
+                let show_id: i64 = match row {
+                    Some(row) => row.get("show_id"),
+                    _ => continue,
+                };
+                log::info!("added new show {}", show.romaji);
+                insert_name(tran, show_id, ShowNameType::ROMAJI, &show.romaji).await?;
+                if let Some(english) = &show.english {
+                    insert_name(tran, show_id, ShowNameType::ENGLISH, english).await?;
+                }
+                for name in &show.additional {
+                    insert_name(tran, show_id, ShowNameType::ADDITIONAL, name).await?;
+                }
+                external_ids::store_mal_id(tran, show_id, None, show.mal_id).await?;
+                by_anilist_id.insert(anilist_id, show_id);
+                names_changed = true;
+            }
+        }
+    }
+    Ok(names_changed)
+}
+
+async fn insert_name(
+    tran: &Transaction<'_>,
+    show_id: i64,
+    show_name_type: i32,
+    name: &str,
+) -> Result<()> {
+    // language=sql
+    tran.execute("insert into magnets.show_name (show_id, show_name_type, name) values ($1, $2, $3) on conflict (show_id, show_name_type, name) do nothing",
+                 &[&show_id, &show_name_type, &textnorm::storage(name)]).await?;
+    Ok(())
+}
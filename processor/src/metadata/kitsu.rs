@@ -0,0 +1,245 @@
+use crate::{
+    metadata::provider::{MetadataProvider, ProviderShow},
+    sleeper::Sleeper,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use common::{time::Clock, Format, YearSeason};
+use reqwest::Client;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+/// The maximum number of pages of a season
+///
+/// Kitsu returns at most 20 shows per page. Seasons have fewer than 300 shows.
+const MAX_PAGES: usize = 30;
+
+/// The JSON:API of kitsu.io
+///
+/// Kitsu maps most shows to their anilist and MAL ids, so shows that anilist has not
+/// synced yet can still be added under their anilist id.
+pub struct Kitsu<'a> {
+    client: &'a Client,
+    url: &'a str,
+    sleeper: Mutex<Sleeper>,
+}
+
+impl<'a> Kitsu<'a> {
+    pub fn new(client: &'a Client, url: &'a str, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            client,
+            url,
+            sleeper: Mutex::new(Sleeper::new(clock)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Vec<Anime>,
+    #[serde(default)]
+    included: Vec<Mapping>,
+    links: Links,
+}
+
+#[derive(Deserialize)]
+struct Links {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Anime {
+    attributes: AnimeAttributes,
+    relationships: Relationships,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnimeAttributes {
+    canonical_title: String,
+    #[serde(default)]
+    titles: HashMap<String, Option<String>>,
+    #[serde(default)]
+    abbreviated_titles: Option<Vec<String>>,
+    subtype: Option<String>,
+    episode_count: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct Relationships {
+    mappings: Relationship,
+}
+
+#[derive(Deserialize)]
+struct Relationship {
+    #[serde(default)]
+    data: Vec<Reference>,
+}
+
+#[derive(Deserialize)]
+struct Reference {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Mapping {
+    id: String,
+    #[serde(rename = "type")]
+    ty: String,
+    attributes: MappingAttributes,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MappingAttributes {
+    external_site: String,
+    external_id: String,
+}
+
+fn format(subtype: &str) -> Option<Format> {
+    let format = match subtype {
+        "TV" => Format::Tv,
+        "movie" => Format::Movie,
+        "special" => Format::Special,
+        "OVA" => Format::Ova,
+        "ONA" => Format::Ona,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Converts a page of the anime listing
+fn parse_page(response: Response) -> Vec<ProviderShow> {
+    let mappings: HashMap<_, _> = response
+        .included
+        .iter()
+        .filter(|m| m.ty == "mappings")
+        .map(|m| (&*m.id, &m.attributes))
+        .collect();
+    let mut shows = vec![];
+    for anime in response.data {
+        let mut show = ProviderShow::default();
+        for reference in &anime.relationships.mappings.data {
+            let mapping = match mappings.get(&*reference.id) {
+                Some(m) => m,
+                _ => continue,
+            };
+            let id = mapping.external_id.parse().ok();
+            match &*mapping.external_site {
+                "anilist/anime" => show.anilist_id = id,
+                "myanimelist/anime" => show.mal_id = id,
+                _ => {}
+            }
+        }
+        let a = anime.attributes;
+        let mut titles = a.titles;
+        let mut title = |lang: &str| titles.remove(lang).flatten();
+        show.romaji = title("en_jp").unwrap_or_else(|| a.canonical_title.clone());
+        show.english = title("en");
+        show.additional.extend(title("ja_jp"));
+        if show.romaji != a.canonical_title {
+            show.additional.push(a.canonical_title);
+        }
+        show.additional
+            .extend(a.abbreviated_titles.unwrap_or_default());
+        show.format = a.subtype.as_deref().and_then(format);
+        show.episodes = a.episode_count;
+        shows.push(show);
+    }
+    shows
+}
+
+#[async_trait]
+impl<'a> MetadataProvider for Kitsu<'a> {
+    fn name(&self) -> &'static str {
+        "kitsu"
+    }
+
+    async fn season(
+        &self,
+        season: YearSeason,
+        _known: &[ProviderShow],
+    ) -> Result<Vec<ProviderShow>> {
+        let mut url = Some(format!(
+            "{}/anime?filter[season]={}&filter[seasonYear]={}&include=mappings&page[limit]=20",
+            self.url,
+            season.season.as_api_str(),
+            season.year
+        ));
+        let mut shows = vec![];
+        let mut pages = 0;
+        while let Some(u) = url.take() {
+            pages += 1;
+            if pages > MAX_PAGES {
+                log::warn!("kitsu returned more than {} pages", MAX_PAGES);
+                break;
+            }
+            self.sleeper
+                .lock()
+                .await
+                .sleep(Duration::from_secs(1))
+                .await;
+            let response: Response = self
+                .client
+                .get(&u)
+                .header("Accept", "application/vnd.api+json")
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+                .with_context(|| format!("cannot parse {}", u))?;
+            url = response.links.next.clone();
+            shows.extend(parse_page(response));
+        }
+        Ok(shows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_anime_with_mappings() {
+        let json = r#"{
+            "data": [{
+                "id": "46474",
+                "type": "anime",
+                "attributes": {
+                    "canonicalTitle": "Sousou no Frieren",
+                    "titles": {
+                        "en": "Frieren: Beyond Journey's End",
+                        "en_jp": "Sousou no Frieren",
+                        "ja_jp": "葬送のフリーレン"
+                    },
+                    "abbreviatedTitles": ["Frieren"],
+                    "subtype": "TV",
+                    "episodeCount": 28
+                },
+                "relationships": {
+                    "mappings": {"data": [{"type": "mappings", "id": "1"}, {"type": "mappings", "id": "2"}]}
+                }
+            }],
+            "included": [
+                {"id": "1", "type": "mappings", "attributes": {"externalSite": "myanimelist/anime", "externalId": "52991"}},
+                {"id": "2", "type": "mappings", "attributes": {"externalSite": "anilist/anime", "externalId": "154587"}}
+            ],
+            "links": {}
+        }"#;
+        let shows = parse_page(serde_json::from_str(json).unwrap());
+        assert_eq!(
+            shows,
+            vec![ProviderShow {
+                anilist_id: Some(154587),
+                mal_id: Some(52991),
+                romaji: "Sousou no Frieren".to_string(),
+                english: Some("Frieren: Beyond Journey's End".to_string()),
+                additional: vec!["葬送のフリーレン".to_string(), "Frieren".to_string()],
+                format: Some(Format::Tv),
+                episodes: Some(28),
+            }]
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use crate::state::State;
 
+pub mod anidb;
 pub mod client;
+pub mod external_ids;
+pub mod fallback;
+pub mod kitsu;
+pub mod provider;
 pub mod schedule;
 pub mod shows;
 
@@ -0,0 +1,273 @@
+use crate::{
+    config::ProviderName,
+    metadata::{anidb::Anidb, client::AnilistClient, kitsu::Kitsu},
+    state::State,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use common::{textnorm, Format, YearSeason};
+use serde::{Deserialize, Serialize};
+
+/// A show as known by a metadata provider
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProviderShow {
+    pub anilist_id: Option<i64>,
+    pub mal_id: Option<i64>,
+    pub romaji: String,
+    pub english: Option<String>,
+    /// Synonyms, abbreviations and titles in other languages
+    pub additional: Vec<String>,
+    pub format: Option<Format>,
+    pub episodes: Option<i32>,
+}
+
+impl ProviderShow {
+    fn titles(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(&*self.romaji)
+            .chain(self.english.as_deref())
+            .chain(self.additional.iter().map(|a| &**a))
+    }
+
+    /// Whether both shows are the same show
+    ///
+    /// Shows are identified by their anilist id, their MAL id or their folded romaji
+    /// title, in this order.
+    fn same(&self, other: &ProviderShow) -> bool {
+        if let (Some(a), Some(b)) = (self.anilist_id, other.anilist_id) {
+            return a == b;
+        }
+        if let (Some(a), Some(b)) = (self.mal_id, other.mal_id) {
+            return a == b;
+        }
+        textnorm::search_fold(&self.romaji) == textnorm::search_fold(&other.romaji)
+    }
+}
+
+/// A source of the shows of a season
+///
+/// The anilist shows sync refreshes all shows at once and is the source of truth. While
+/// it is stale, the providers keep the current season up to date (see
+/// [super::fallback]).
+#[async_trait]
+pub trait MetadataProvider: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the shows of a season
+    ///
+    /// `known` contains the merged shows of the providers with a higher priority.
+    /// Providers that cannot list seasons return additional titles of these shows.
+    async fn season(
+        &self,
+        season: YearSeason,
+        known: &[ProviderShow],
+    ) -> Result<Vec<ProviderShow>>;
+}
+
+/// Returns the configured providers in order of priority
+pub fn providers<'a>(state: &'a State<'a>) -> Vec<Box<dyn MetadataProvider + 'a>> {
+    let config = &state.config.metadata;
+    config
+        .providers
+        .iter()
+        .map(|name| -> Box<dyn MetadataProvider> {
+            match name {
+                ProviderName::Anilist => Box::new(Anilist {
+                    client: &state.anilist_client,
+                }),
+                ProviderName::Kitsu => Box::new(Kitsu::new(
+                    state.web_client,
+                    &config.kitsu_url,
+                    state.clock.clone(),
+                )),
+                ProviderName::Anidb => Box::new(Anidb {
+                    http_cache: &state.http_cache,
+                    url: &config.anidb_titles_url,
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Merges the shows of a provider into the shows of the providers with a higher priority
+///
+/// The romaji and english titles of the show that was known first are kept. All other
+/// titles are added as additional titles unless they fold to a title that is already
+/// known. Ids, the format and the number of episodes are only taken if they are not
+/// known yet.
+pub fn merge(merged: &mut Vec<ProviderShow>, shows: Vec<ProviderShow>) {
+    for show in shows {
+        let existing = match merged.iter_mut().find(|m| m.same(&show)) {
+            Some(e) => e,
+            _ => {
+                merged.push(show);
+                continue;
+            }
+        };
+        existing.anilist_id = existing.anilist_id.or(show.anilist_id);
+        existing.mal_id = existing.mal_id.or(show.mal_id);
+        existing.format = existing.format.or(show.format);
+        existing.episodes = existing.episodes.or(show.episodes);
+        let mut folded: Vec<_> = existing.titles().map(textnorm::search_fold).collect();
+        for title in show.titles() {
+            let f = textnorm::search_fold(title);
+            if !f.is_empty() && !folded.contains(&f) {
+                folded.push(f);
+                existing.additional.push(title.to_string());
+            }
+        }
+    }
+}
+
+/// The season query of anilist
+///
+/// Unlike the shows sync, this does not retry failed requests.
+struct Anilist<'a> {
+    client: &'a AnilistClient<'a>,
+}
+
+const SEASON_QUERY: &str = r#"
+query ($page: Int, $season: MediaSeason, $year: Int) {
+  page: Page(perPage: 50, page: $page) {
+    page_info: pageInfo {
+      has_next_page: hasNextPage
+    }
+    media(season: $season, seasonYear: $year, sort: ID, format_in: [TV, TV_SHORT, MOVIE, SPECIAL, OVA, ONA]) {
+      id
+      id_mal: idMal
+      title {
+        romaji
+        english
+      }
+      synonyms
+      format
+      episodes
+    }
+  }
+}"#;
+
+#[derive(Serialize)]
+struct Variables {
+    page: i32,
+    season: String,
+    year: u16,
+}
+
+#[derive(Deserialize)]
+struct PageInfo {
+    has_next_page: bool,
+}
+
+#[derive(Deserialize)]
+struct Title {
+    romaji: String,
+    english: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    id: i64,
+    id_mal: Option<i64>,
+    title: Title,
+    #[serde(default)]
+    synonyms: Vec<String>,
+    format: String,
+    episodes: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    page_info: PageInfo,
+    media: Vec<Media>,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    page: Page,
+}
+
+#[async_trait]
+impl<'a> MetadataProvider for Anilist<'a> {
+    fn name(&self) -> &'static str {
+        "anilist"
+    }
+
+    async fn season(
+        &self,
+        season: YearSeason,
+        _known: &[ProviderShow],
+    ) -> Result<Vec<ProviderShow>> {
+        let mut shows = vec![];
+        for page in 1.. {
+            let variables = Variables {
+                page,
+                season: season.season.as_api_str().to_ascii_uppercase(),
+                year: season.year,
+            };
+            let data: Data = self.client.try_request(SEASON_QUERY, &variables).await?;
+            for m in data.page.media {
+                shows.push(ProviderShow {
+                    anilist_id: Some(m.id),
+                    mal_id: m.id_mal,
+                    romaji: m.title.romaji,
+                    english: m.title.english,
+                    additional: m.synonyms,
+                    format: Format::from_anilist(&m.format).ok(),
+                    episodes: m.episodes,
+                });
+            }
+            if !data.page.page_info.has_next_page {
+                break;
+            }
+        }
+        Ok(shows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(anilist_id: Option<i64>, romaji: &str) -> ProviderShow {
+        ProviderShow {
+            anilist_id,
+            romaji: romaji.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_titles_of_higher_priority() {
+        let mut merged = vec![ProviderShow {
+            english: Some("Frieren: Beyond Journey's End".to_string()),
+            ..show(Some(154587), "Sousou no Frieren")
+        }];
+        merge(
+            &mut merged,
+            vec![ProviderShow {
+                mal_id: Some(52991),
+                english: Some("Frieren".to_string()),
+                additional: vec!["葬送のフリーレン".to_string()],
+                format: Some(Format::Tv),
+                ..show(Some(154587), "SOUSOU NO FRIEREN")
+            }],
+        );
+        assert_eq!(merged.len(), 1);
+        let m = &merged[0];
+        assert_eq!(m.romaji, "Sousou no Frieren");
+        assert_eq!(m.english.as_deref(), Some("Frieren: Beyond Journey's End"));
+        assert_eq!(m.additional, vec!["Frieren", "葬送のフリーレン"]);
+        assert_eq!(m.mal_id, Some(52991));
+        assert_eq!(m.format, Some(Format::Tv));
+    }
+
+    #[test]
+    fn identifies_shows_by_ids_before_titles() {
+        let mut merged = vec![show(Some(1), "Show"), show(None, "Other Show")];
+        merge(
+            &mut merged,
+            vec![show(Some(2), "Show"), show(None, "other show")],
+        );
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[2].anilist_id, Some(2));
+    }
+}
@@ -1,24 +1,25 @@
 use crate::{
-    anilist::{client::PageInfo, wait_for_grace_period},
     db_state::LAST_SCHEDULE_UPDATE,
+    job_lock,
+    job_lock::Job,
+    metadata::{client::PageInfo, wait_for_grace_period},
     scheduled::Scheduled,
     state::State,
 };
 use anyhow::Result;
 use chrono::{DateTime, Duration, TimeZone, Utc};
 use common::{pg, time::MINUTE};
+use isnt::std_1::vec::IsntVecExt;
 use serde::{Deserialize, Serialize};
 use std::{cmp::Ordering, ops::Not};
-use tokio_postgres::Transaction;
+use tokio_postgres::{Client, Transaction};
 
 /// Loads the schedule once per hour
 pub async fn load_schedule(state: &State<'_>) {
     wait_for_grace_period(state).await;
-    let scheduled = Scheduled::new(
-        state,
-        LAST_SCHEDULE_UPDATE,
-        state.config.anilist.schedule_poll_interval,
-    );
+    let scheduled = Scheduled::new(state, LAST_SCHEDULE_UPDATE, |c| {
+        c.anilist.schedule_poll_interval
+    });
     loop {
         scheduled.wait(&state.db_watcher.last_schedule_update).await;
         log::info!("loading the schedule");
@@ -52,39 +53,63 @@ enum Diff {
 pub async fn load_schedule_(state: &State<'_>) -> Result<()> {
     let mut pg = state.pg_connector.connect().await?;
     let tran = pg::transaction(&mut pg).await?;
+    job_lock::lock(&tran, Job::Schedule).await?;
 
-    let existing = load_existing_items(&tran).await?;
-    let new = load_new_items(state).await?;
+    let (start, stop) = window();
+    let existing = load_existing_items(&tran, start).await?;
+    let new = load_new_items(state, start, stop).await?;
 
     let diff = compute_diff(existing, new);
 
     log::info!("found {} schedule changes", diff.len());
+    let changed = diff.is_not_empty();
 
+    let mut deleted = vec![];
+    let mut anilist_ids = vec![];
+    let mut episodes = vec![];
+    let mut airs_at = vec![];
     for diff in diff {
         match diff {
-            Diff::Del(e) => {
-                // language=sql
-                tran.execute(
-                    "delete from magnets.schedule where schedule_id = $1",
-                    &[&e.schedule_id],
-                )
-                .await?;
-            }
+            Diff::Del(e) => deleted.push(e.schedule_id),
             Diff::Add(n) => {
-                // language=sql
-                tran.execute(
-                    "
-                    insert into magnets.schedule (show_id, episode, airs_at)
-                    select show_id, $2, $3
-                    from magnets.show
-                    where anilist_id = $1",
-                    &[&n.anilist_id, &n.episode, &n.airs_at],
-                )
-                .await?;
+                anilist_ids.push(n.anilist_id);
+                episodes.push(n.episode);
+                airs_at.push(n.airs_at);
             }
         }
     }
 
+    // The diff contains hundreds of rows whenever the schedule window moves by a day.
+    // Apply it in at most two round trips.
+    if deleted.is_not_empty() {
+        // language=sql
+        tran.execute(
+            "delete from magnets.schedule where schedule_id = any($1)",
+            &[&deleted],
+        )
+        .await?;
+    }
+    if anilist_ids.is_not_empty() {
+        // language=sql
+        tran.execute(
+            "
+            insert into magnets.schedule (show_id, episode, airs_at)
+            select s.show_id, n.episode, n.airs_at
+            from unnest($1::bigint[], $2::int[], $3::timestamptz[])
+                as n(anilist_id, episode, airs_at)
+            join magnets.show s using (anilist_id)
+            on conflict (show_id, episode, airs_at) do nothing",
+            &[&anilist_ids, &episodes, &airs_at],
+        )
+        .await?;
+    }
+
+    if changed {
+        // Delivered on commit. The site drops its cached schedule when it receives this.
+        tran.batch_execute("notify schedule_change").await?;
+    }
+
+    state.leader.ensure().await?;
     Ok(tran.commit().await?)
 }
 
@@ -120,15 +145,39 @@ fn compute_diff(mut existing: Vec<ExistingItem>, mut new: Vec<Item>) -> Vec<Diff
     res
 }
 
+/// Returns the time range of the schedule retrieved from anilist
+///
+/// On magnets.moe, we only display the schedule from yesterday to six days from now
+/// (7 days total). Therefore it makes sense to only retrieve a similar number of
+/// days from anilist. Note however that we load one more day into the future to cover
+/// the time between midnight and the next reload of the schedule.
+///
+/// Entries before the start of the window are never modified. They are kept for the
+/// calendar.
+fn window() -> (DateTime<Utc>, DateTime<Utc>) {
+    let today = Utc::today().and_hms(0, 0, 0);
+    (today - Duration::days(1), today + Duration::days(7))
+}
+
 // language=sql
 common::create_statement!(LoadScheduleItems, schedule_id, show_id, episode, airs_at, anilist_id; "
     select sch.schedule_id, sch.show_id, sch.episode, sch.airs_at, sho.anilist_id
     from magnets.schedule sch
-    join magnets.show sho using (show_id)");
+    join magnets.show sho using (show_id)
+    where sch.airs_at > $1");
+
+/// Prepares the statements of this module
+pub async fn check_statements(con: &Client) -> Result<()> {
+    LoadScheduleItems::new(con).await?;
+    Ok(())
+}
 
-async fn load_existing_items(tran: &Transaction<'_>) -> Result<Vec<ExistingItem>> {
+async fn load_existing_items(
+    tran: &Transaction<'_>,
+    start: DateTime<Utc>,
+) -> Result<Vec<ExistingItem>> {
     let stmt = LoadScheduleItems::new(tran).await?;
-    let rows = tran.query(&stmt.stmt, &[]).await?;
+    let rows = tran.query(&stmt.stmt, &[&start]).await?;
     let mut res = vec![];
     for row in rows {
         res.push(ExistingItem {
@@ -144,7 +193,11 @@ async fn load_existing_items(tran: &Transaction<'_>) -> Result<Vec<ExistingItem>
 }
 
 /// Loads the schedule
-async fn load_new_items(state: &State<'_>) -> Result<Vec<Item>> {
+async fn load_new_items(
+    state: &State<'_>,
+    start: DateTime<Utc>,
+    stop: DateTime<Utc>,
+) -> Result<Vec<Item>> {
     const QUERY: &str = r#"
 query ($start: Int, $stop: Int, $page: Int) {
   page: Page(perPage: 50, page: $page) {
@@ -198,20 +251,12 @@ query ($start: Int, $stop: Int, $page: Int) {
         }
     }
 
-    // On magnets.moe, we only display the schedule from yesterday to six days from now
-    // (7 days total). Therefore it makes sense to only retrieve a similar number of
-    // days from anilist. Note however that we load one more day into the future to cover
-    // the time between midnight and the next reload of the schedule.
-    let today = Utc::today().and_hms(0, 0, 0);
-    let yesterday = (today - Duration::days(1)).timestamp();
-    let next_week = (today + Duration::days(7)).timestamp();
-
     let mut scheds = vec![];
     for page in 1.. {
         log::info!("loading schedule page {}", page);
         let variables = Variables {
-            start: yesterday,
-            stop: next_week,
+            start: start.timestamp(),
+            stop: stop.timestamp(),
             page,
         };
         let data: Data = state.anilist_client.request(QUERY, &variables).await;
@@ -0,0 +1,506 @@
+use crate::{
+    db_state::LAST_SHOWS_UPDATE,
+    job_lock,
+    job_lock::Job,
+    leader::Leader,
+    metadata::{
+        client::{AnilistClient, PageInfo},
+        external_ids, wait_for_grace_period,
+    },
+    scheduled::Scheduled,
+    search, seasons, show_lifecycle,
+    show_lifecycle::Removal,
+    show_list,
+    state::State,
+};
+use anyhow::Result;
+use common::{
+    pg, pg::PgClient, textnorm, time::MINUTE, Format, Season, ShowNameType, YearSeason,
+};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::Client;
+
+/// The maximum number of anilist pages that are fetched at the same time
+const PAGE_CONCURRENCY: usize = 3;
+
+/// The maximum number of shows that are removed because they are missing from anilist
+///
+/// If more shows are missing, the traversal of the pages probably went wrong and no
+/// shows are removed.
+const MAX_MISSING_SHOWS: usize = 50;
+
+/// Refreshes our copy of the anilist shows database once a day
+pub async fn load_shows(state: &State<'_>) {
+    wait_for_grace_period(state).await;
+    let scheduled =
+        Scheduled::new(state, LAST_SHOWS_UPDATE, |c| c.anilist.shows_poll_interval);
+    loop {
+        scheduled.wait(&state.db_watcher.last_shows_update).await;
+        log::info!("loading the shows");
+        if let Err(e) = load_shows_now(state).await {
+            log::error!("loading the shows failed: {:#}", e);
+            tokio::time::delay_for(5 * MINUTE).await;
+        } else {
+            scheduled.update().await;
+            // Refresh the show db so that the analyzer has access to the new data.
+            if let Err(e) = state.show_db.refresh().await {
+                log::error!("refreshing shows db failed: {:#}", e);
+            }
+        }
+    }
+}
+
+/// Refreshes our copy of the anilist shows database
+pub async fn load_shows_now(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    // The shows are written in one transaction per page. Hold the lock until `con` is
+    // dropped.
+    job_lock::lock_session(&con, Job::Shows).await?;
+    let shows = load_shows_from_db(&mut con).await?;
+    log::info!("loaded {} existing shows", shows.len());
+    // Note that we load the pages in increasing order of anilist's ids. This means
+    // that we should not miss any shows unless an older show gets deleted while
+    // we are traversing the pages.
+    //
+    // Up to PAGE_CONCURRENCY pages are fetched at the same time but they are stored in
+    // order. The client spaces out the requests. Once we've reached the last page, the
+    // pages still in flight are dropped.
+    let mut pages = stream::iter(1..)
+        .map(|i| fetch_shows_page(&state.anilist_client, i))
+        .buffered(PAGE_CONCURRENCY);
+    let mut seen = HashSet::new();
+    while let Some(page) = pages.next().await {
+        seen.extend(page.media.iter().map(|m| m.id));
+        let has_next = store_shows_page(&mut con, &shows, &state.leader, page).await?;
+        if !has_next {
+            break;
+        }
+    }
+    sync_removals(&mut con, &shows, &seen, &state.leader).await?;
+    seasons::infer_seasons(&con, &state.leader, None).await?;
+    state.leader.ensure().await?;
+    search::update_last_torrents(&con).await?;
+    show_list::store(&con).await?;
+    Ok(())
+}
+
+// language=sql
+common::create_statement!(LoadAllShows, show_id, show_format, season, anilist_id, episodes, cover_url, season_curated, format_curated, removal, merged_into;
+                          "select show_id, show_format, season, anilist_id, episodes, cover_url, season_curated, format_curated, removal, merged_into from magnets.show");
+
+// language=sql
+common::create_statement!(LoadAllShowNames, show_name_id, show_id, name, show_name_type;
+                          "select show_name_id, show_id, name, show_name_type from magnets.show_name");
+
+/// Prepares the statements of this module
+pub async fn check_statements(con: &Client) -> Result<()> {
+    LoadAllShows::new(con).await?;
+    LoadAllShowNames::new(con).await?;
+    external_ids::check_statements(con).await?;
+    Ok(())
+}
+
+struct Show {
+    show_id: i64,
+    anilist_id: i64,
+    format: Format,
+    season: Option<YearSeason>,
+    episodes: Option<i32>,
+    cover_url: Option<String>,
+    /// Whether the season has been set by hand and must not be synced
+    season_curated: bool,
+    /// Whether the format has been set by hand and must not be synced
+    format_curated: bool,
+    removal: Option<Removal>,
+    names: Vec<Name>,
+    mal_id: Option<i64>,
+}
+
+struct Name {
+    show_name_id: i64,
+    name: String,
+    show_name_type: i32,
+}
+
+/// Loads our copy of the anilist shows database
+async fn load_shows_from_db(con: &mut PgClient) -> Result<HashMap<i64, Show>> {
+    let tran = pg::transaction(con).await?;
+    let load = LoadAllShows::new(&tran).await?;
+    let mut shows = HashMap::new();
+    let rows = tran.query(&load.stmt, &[]).await?;
+    for row in rows {
+        let season = match row.get(load.season) {
+            Some(s) => Some(YearSeason::from_db(s)?),
+            _ => None,
+        };
+        let removal = match row.get(load.removal) {
+            Some(r) => Some(Removal::from_db(r, row.get(load.merged_into))?),
+            _ => None,
+        };
+        let show = Show {
+            show_id: row.get(load.show_id),
+            anilist_id: row.get(load.anilist_id),
+            format: Format::from_db(row.get(load.show_format))?,
+            season,
+            episodes: row.get(load.episodes),
+            cover_url: row.get(load.cover_url),
+            season_curated: row.get(load.season_curated),
+            format_curated: row.get(load.format_curated),
+            removal,
+            names: vec![],
+            mal_id: None,
+        };
+        shows.insert(show.show_id, show);
+    }
+    let load = LoadAllShowNames::new(&tran).await?;
+    let rows = tran.query(&load.stmt, &[]).await?;
+    for row in rows {
+        let name = Name {
+            show_name_id: row.get(load.show_name_id),
+            name: row.get(load.name),
+            show_name_type: row.get(load.show_name_type),
+        };
+        shows
+            .get_mut(&row.get(load.show_id))
+            .unwrap()
+            .names
+            .push(name);
+    }
+    for (show_id, mal_id) in external_ids::load_mal_ids(&tran).await? {
+        if let Some(show) = shows.get_mut(&show_id) {
+            show.mal_id = Some(mal_id);
+        }
+    }
+    Ok(shows.into_iter().map(|(_, v)| (v.anilist_id, v)).collect())
+}
+
+/// Removes the shows that anilist no longer lists and restores those that reappeared
+///
+/// `seen` contains the anilist ids of all shows of the current traversal.
+async fn sync_removals(
+    con: &mut PgClient,
+    existing: &HashMap<i64, Show>,
+    seen: &HashSet<i64>,
+    leader: &Leader,
+) -> Result<()> {
+    let mut missing = vec![];
+    let mut reappeared = vec![];
+    for show in existing.values() {
+        match (show.removal, seen.contains(&show.anilist_id)) {
+            (None, false) => missing.push(show.show_id),
+            (Some(Removal::RemovedFromAnilist), true) => reappeared.push(show.show_id),
+            _ => {}
+        }
+    }
+    if missing.len() > MAX_MISSING_SHOWS {
+        log::error!(
+            "{} shows are missing from anilist. not removing them.",
+            missing.len()
+        );
+        missing.clear();
+    }
+    if missing.is_empty() && reappeared.is_empty() {
+        return Ok(());
+    }
+    let tran = pg::transaction(con).await?;
+    for show_id in missing {
+        log::info!(
+            "removing show {} because anilist no longer lists it",
+            show_id
+        );
+        show_lifecycle::remove(&tran, show_id, Removal::RemovedFromAnilist).await?;
+    }
+    for show_id in reappeared {
+        log::info!("restoring show {} because anilist lists it again", show_id);
+        show_lifecycle::restore(&tran, show_id).await?;
+    }
+    leader.ensure().await?;
+    tran.commit().await?;
+    Ok(())
+}
+
+const QUERY: &str = r#"
+query ($page: Int) {
+  page: Page(perPage: 50, page: $page) {
+    page_info: pageInfo {
+      total
+      per_page: perPage
+      current_page: currentPage
+      last_page: lastPage
+      has_next_page: hasNextPage
+    }
+    media(sort: ID, format_in: [TV, TV_SHORT, MOVIE, SPECIAL, OVA, ONA]) {
+      id
+      id_mal: idMal
+      title {
+        romaji
+        english
+      }
+      season_year: seasonYear
+      season
+      format
+      episodes
+      cover_image: coverImage {
+        large
+      }
+      popularity
+    }
+  }
+}"#;
+
+#[derive(Serialize)]
+struct Variables {
+    page: i32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Title {
+    // We assume that the romaji name is always set. This holds true as of this
+    // comment. Both the frontend and the backend rely on having a romaji name.
+    romaji: String,
+    english: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CoverImage {
+    large: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Media {
+    id: i64,
+    /// The MyAnimeList id
+    id_mal: Option<i64>,
+    title: Title,
+    season_year: Option<u16>,
+    season: Option<String>,
+    format: String,
+    episodes: Option<i32>,
+    cover_image: Option<CoverImage>,
+    /// The number of users who have the show on their list
+    popularity: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Page {
+    page_info: PageInfo,
+    media: Vec<Media>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Data {
+    page: Page,
+}
+
+/// Fetches one page of the anilist shows database
+async fn fetch_shows_page(client: &AnilistClient<'_>, page: i32) -> Page {
+    log::info!("loading anilist shows page {}", page);
+    let data: Data = client.request(QUERY, &Variables { page }).await;
+    data.page
+}
+
+/// Stores one page of the anilist shows database
+async fn store_shows_page(
+    con: &mut PgClient,
+    existing: &HashMap<i64, Show>,
+    leader: &Leader,
+    page: Page,
+) -> Result<bool> {
+    // We are transactional on a per-page basis. Note that we HAVE to calculate a diff to
+    // preserve the foreign key constraints. This is also more efficient because the
+    // upstream database changes very little.
+    let tran = pg::transaction(con).await?;
+
+    let mut names_changed = false;
+    for x in &page.media {
+        let format = match Format::from_anilist(&x.format) {
+            Ok(f) => f,
+            Err(_) => {
+                log::warn!("cannot parse format of anilist show: {}", x.format);
+                // Note that we do not abort the operation if we cannot deal with the
+                // response. I assume that any parsing problem will require manual
+                // intervention. No point in aborting and retrying later. Instead skip
+                // to the next result.
+                continue;
+            }
+        };
+        let season = match (x.season_year, &x.season) {
+            (Some(season_year), Some(season)) => {
+                let season = match Season::from_anilist_str(season) {
+                    Ok(s) => s,
+                    Err(_) => {
+                        log::warn!("cannot parse anilist season: {}", season);
+                        continue;
+                    }
+                };
+                Some(YearSeason {
+                    year: season_year,
+                    season,
+                })
+            }
+            _ => None,
+        };
+        let cover_url = x.cover_image.as_ref().and_then(|c| c.large.clone());
+        // We store everything in NFC form
+        let romaji = textnorm::storage(&x.title.romaji);
+        let mut names = vec![];
+        if let Some(n) = &x.title.english {
+            let name = textnorm::storage(n);
+            if name != romaji {
+                names.push(Name {
+                    show_name_id: -1,
+                    name,
+                    show_name_type: ShowNameType::ENGLISH,
+                });
+            }
+        }
+        names.push(Name {
+            show_name_id: -1,
+            name: romaji,
+            show_name_type: ShowNameType::ROMAJI,
+        });
+        if let Some(existing) = existing.get(&x.id) {
+            if !existing.format_curated && existing.format != format {
+                log::info!(
+                    "updating format of show {} from {} to {}",
+                    existing.show_id,
+                    existing.format.as_str(),
+                    format
+                );
+                // language=sql
+                tran.execute(
+                    "update magnets.show set show_format = $1 where show_id = $2",
+                    &[&format.to_db(), &existing.show_id],
+                )
+                .await?;
+            }
+            if !existing.season_curated && existing.season != season {
+                log::info!(
+                    "updating season of show {} from {:?} to {:?}",
+                    existing.show_id,
+                    existing.season,
+                    season
+                );
+                // language=sql
+                tran.execute(
+                    "update magnets.show set season = $1 where show_id = $2",
+                    &[&season.map(|s| s.to_db()), &existing.show_id],
+                )
+                .await?;
+            }
+            if existing.episodes != x.episodes {
+                log::info!(
+                    "updating episodes of show {} from {:?} to {:?}",
+                    existing.show_id,
+                    existing.episodes,
+                    x.episodes
+                );
+                // language=sql
+                tran.execute(
+                    "update magnets.show set episodes = $1 where show_id = $2",
+                    &[&x.episodes, &existing.show_id],
+                )
+                .await?;
+            }
+            if existing.cover_url != cover_url {
+                log::info!(
+                    "updating cover of show {} from {:?} to {:?}",
+                    existing.show_id,
+                    existing.cover_url,
+                    cover_url
+                );
+                // language=sql
+                tran.execute(
+                    "update magnets.show set cover_url = $1 where show_id = $2",
+                    &[&cover_url, &existing.show_id],
+                )
+                .await?;
+            }
+            external_ids::store_mal_id(
+                &tran,
+                existing.show_id,
+                existing.mal_id,
+                x.id_mal,
+            )
+            .await?;
+            for name in names {
+                match existing
+                    .names
+                    .iter()
+                    .find(|e| e.show_name_type == name.show_name_type)
+                {
+                    Some(old) => {
+                        if name.name != old.name {
+                            log::info!(
+                                "updating name ({}) of show {} from {} to {}",
+                                old.show_name_type,
+                                existing.show_id,
+                                old.name,
+                                name.name
+                            );
+                            names_changed = true;
+                            // language=sql
+                            tran.execute("update magnets.show_name set name = $1 where show_name_id = $2",
+                                         &[&name.name, &old.show_name_id]).await?;
+                        }
+                    }
+                    _ => {
+                        log::info!(
+                            "adding new name ({}) to show {}: {}",
+                            name.show_name_type,
+                            existing.show_id,
+                            name.name
+                        );
+                        names_changed = true;
+                        // language=sql
+                        tran.execute("insert into magnets.show_name (show_id, show_name_type, name) values ($1, $2, $3) on conflict (show_id, show_name_type, name) do nothing",
+                                     &[&existing.show_id, &name.show_name_type, &name.name]).await?;
+                    }
+                }
+            }
+            continue;
+        }
+        log::info!("adding new show {}", x.title.romaji);
+        // language=sql
+        let row = tran
+            .query_one(
+                "insert into magnets.show (anilist_id, show_format, season, episodes, cover_url) values ($1, $2, $3, $4, $5) returning show_id",
+                &[&x.id, &format.to_db(), &season.map(|s| s.to_db()), &x.episodes, &cover_url],
+            )
+            .await?;
+        let show_id: i64 = row.get("show_id");
+        for name in names {
+            // language=sql
+            tran.execute("insert into magnets.show_name (show_id, show_name_type, name) values ($1, $2, $3) on conflict (show_id, show_name_type, name) do nothing",
+                         &[&show_id, &name.show_name_type, &name.name]).await?;
+        }
+        external_ids::store_mal_id(&tran, show_id, None, x.id_mal).await?;
+    }
+
+    // The popularity changes all the time. Store it for the whole page at once instead
+    // of logging every change.
+    let anilist_ids: Vec<_> = page.media.iter().map(|m| m.id).collect();
+    let popularities: Vec<_> = page.media.iter().map(|m| m.popularity).collect();
+    // language=sql
+    tran.execute(
+        "
+        update magnets.show s
+        set popularity = x.popularity
+        from unnest($1::bigint[], $2::int[]) x (anilist_id, popularity)
+        where s.anilist_id = x.anilist_id and s.popularity is distinct from x.popularity",
+        &[&anilist_ids, &popularities],
+    )
+    .await?;
+
+    if names_changed {
+        // Delivered on commit. The site drops its cached show names when it receives this.
+        tran.batch_execute("notify show_change").await?;
+    }
+
+    leader.ensure().await?;
+    tran.commit().await?;
+
+    Ok(page.page_info.has_next_page)
+}
@@ -0,0 +1,245 @@
+use crate::{
+    db_state,
+    db_state::{LAST_SCHEDULE_UPDATE, LAST_SHOWS_UPDATE},
+    memory,
+    state::State,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use common::{pg::PgClient, time::StdDuration, Source};
+use futures::{future, future::select, pin_mut};
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::atomic::{AtomicI64, Ordering::Relaxed},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+/// Metrics that are only known to the running processor
+pub struct Metrics {
+    last_successful_scrape: AtomicI64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            last_successful_scrape: AtomicI64::new(0),
+        }
+    }
+
+    /// Records that nyaa.si or one of its mirrors has been scraped successfully
+    pub fn scrape_succeeded(&self) {
+        self.last_successful_scrape
+            .store(Utc::now().timestamp(), Relaxed);
+    }
+}
+
+/// Serves freshness gauges in the prometheus text format
+///
+/// The gauges are meant for alerting rules such as
+/// `time() - magnets_last_successful_scrape_timestamp > 600`. Metrics are only served if
+/// `metrics.listen_addr` is set. The address is bound again when it changes on reload.
+/// This is a minimal http server that answers every request with the metrics. Don't
+/// expose it to the internet.
+pub async fn serve_metrics(state: &State<'_>) {
+    loop {
+        let addr = state.live_config.current().metrics.listen_addr;
+        let serve = async {
+            if let Some(addr) = addr {
+                serve_metrics_on(state, addr).await;
+            }
+            // Binding failed. Wait for another address.
+            future::pending::<()>().await
+        };
+        let changed = listen_addr_changed(state, addr);
+        pin_mut!(serve, changed);
+        select(serve, changed).await;
+    }
+}
+
+async fn listen_addr_changed(state: &State<'_>, addr: Option<SocketAddr>) {
+    loop {
+        let generation = state.live_config.generation();
+        if state.live_config.current().metrics.listen_addr != addr {
+            return;
+        }
+        state.live_config.reloaded(generation).await;
+    }
+}
+
+async fn serve_metrics_on(state: &State<'_>, addr: SocketAddr) {
+    let mut listener = match TcpListener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            log::error!("cannot bind the metrics endpoint to {}: {}", addr, e);
+            return;
+        }
+    };
+    log::info!("serving metrics on {}", addr);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((s, _)) => s,
+            Err(e) => {
+                log::error!("cannot accept metrics connection: {}", e);
+                continue;
+            }
+        };
+        // Requests are handled one at a time. Don't let a slow client block the others
+        // for long.
+        match timeout(StdDuration::from_secs(5), respond(state, stream)).await {
+            Ok(Err(e)) => log::warn!("could not serve metrics: {:#}", e),
+            Err(_) => log::warn!("metrics request timed out"),
+            _ => {}
+        }
+    }
+}
+
+async fn respond(state: &State<'_>, mut stream: TcpStream) -> Result<()> {
+    // Read the request head. Its content is irrelevant.
+    let mut buf = vec![0; 4096];
+    let mut len = 0;
+    while len < buf.len() && !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf[len..]).await?;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+    let (status, body) = match render(state).await {
+        Ok(body) => ("200 OK", body),
+        Err(e) => {
+            log::error!("could not collect metrics: {:#}", e);
+            ("500 Internal Server Error", String::new())
+        }
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Writes the median and maximum time between the upload and the ingestion of the
+/// torrents of the last hour per source
+async fn write_ingestion_lag(body: &mut String, pg: &PgClient) -> Result<()> {
+    // language=sql
+    let rows = pg
+        .query(
+            "
+            select
+                source,
+                percentile_cont(0.5) within group (
+                    order by extract(epoch from created - uploaded_at)
+                )::float8 as median,
+                max(extract(epoch from created - uploaded_at))::float8 as max
+            from magnets.torrent_source
+            where created > now() - interval '1 hour' and uploaded_at is not null
+            group by source",
+            &[],
+        )
+        .await
+        .context("cannot compute the ingestion lag")?;
+    for (name, column, help) in &[
+        (
+            "magnets_ingestion_lag_median_seconds",
+            "median",
+            "Median seconds between the upload and the ingestion of the torrents of the \
+             last hour",
+        ),
+        (
+            "magnets_ingestion_lag_max_seconds",
+            "max",
+            "Maximum seconds between the upload and the ingestion of the torrents of the \
+             last hour",
+        ),
+    ] {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} gauge", name);
+        for row in &rows {
+            let source = Source::from_db(row.get("source"))?;
+            let value: f64 = row.get(column);
+            let _ = writeln!(
+                body,
+                "{}{{source=\"{}\"}} {}",
+                name,
+                source.as_str(),
+                value
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn render(state: &State<'_>) -> Result<String> {
+    let pg = state.pg.borrow().await?;
+    let shows: DateTime<Utc> = db_state::get(&**pg, LAST_SHOWS_UPDATE).await?;
+    let schedule: DateTime<Utc> = db_state::get(&**pg, LAST_SCHEDULE_UPDATE).await?;
+    // language=sql
+    let stmt = pg
+        .prepare_cached(
+            "
+            select coalesce(avg((not matched)::int), 0)::float8
+            from magnets.torrent
+            where uploaded_at > now() - interval '24 hours'",
+        )
+        .await?;
+    let unmatched_ratio: f64 = pg
+        .query_one(&stmt, &[])
+        .await
+        .context("cannot compute the unmatched ratio")?
+        .get(0);
+    let scrape = state.metrics.last_successful_scrape.load(Relaxed);
+    let show_db_size = state.show_db.heap_size().await;
+    let mut body = String::new();
+    let gauges: &[(&str, &str, f64)] = &[
+        (
+            "magnets_last_successful_scrape_timestamp",
+            "Unix time of the last successful scrape of nyaa.si",
+            scrape as f64,
+        ),
+        (
+            "magnets_last_shows_sync_timestamp",
+            "Unix time of the last successful sync of the AniList shows",
+            shows.timestamp() as f64,
+        ),
+        (
+            "magnets_last_schedule_sync_timestamp",
+            "Unix time of the last successful sync of the AniList schedule",
+            schedule.timestamp() as f64,
+        ),
+        (
+            "magnets_unmatched_ratio_24h",
+            "Fraction of the torrents uploaded in the last 24 hours that are unmatched",
+            unmatched_ratio,
+        ),
+        (
+            "magnets_processor_heap_bytes",
+            "Bytes allocated on the heap of the processor",
+            memory::heap_usage() as f64,
+        ),
+        (
+            "magnets_processor_show_db_bytes",
+            "Approximate size of the in-memory show database",
+            show_db_size as f64,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} gauge", name);
+        let _ = writeln!(body, "{} {}", name, value);
+    }
+    write_ingestion_lag(&mut body, &**pg).await?;
+    Ok(body)
+}
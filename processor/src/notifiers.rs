@@ -0,0 +1,239 @@
+//! The formats in which notifications about new torrents are posted
+//!
+//! Generic webhooks receive a json object with all fields of a [Notification]. Discord
+//! webhooks and Telegram chats receive the message rendered from `webhooks.message`.
+
+use crate::{config::Webhooks, state::State};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::error::Error;
+
+/// The kind of a webhook as stored in `magnets.webhook.kind`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WebhookKind {
+    /// The target is a url that receives the notification as json
+    Json,
+    /// The target is the url of a Discord webhook
+    Discord,
+    /// The target is the id of a chat of the bot in `webhooks.telegram_bot_token`
+    Telegram,
+}
+
+impl WebhookKind {
+    pub fn to_db(self) -> i32 {
+        match self {
+            Self::Json => 1,
+            Self::Discord => 2,
+            Self::Telegram => 3,
+        }
+    }
+
+    pub fn from_db(n: i32) -> Result<Self> {
+        let v = match n {
+            1 => Self::Json,
+            2 => Self::Discord,
+            3 => Self::Telegram,
+            _ => return Err(anyhow!("invalid webhook kind {}", n)),
+        };
+        Ok(v)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Discord => "discord",
+            Self::Telegram => "telegram",
+        }
+    }
+}
+
+/// A torrent that has been matched to a show
+#[derive(Serialize)]
+pub struct Notification<'a> {
+    pub show_id: i64,
+    pub anilist_id: i64,
+    /// The romaji name of the show
+    pub show_name: &'a str,
+    /// The first episode of the torrent if its title contains one
+    pub episode: Option<i32>,
+    /// The last episode if the torrent contains multiple episodes
+    pub episode_end: Option<i32>,
+    pub torrent_id: i64,
+    pub nyaa_id: i64,
+    pub title: &'a str,
+    pub uploaded_at: DateTime<Utc>,
+    pub magnet: String,
+    /// The rendered message. Slack and Mattermost show `text`, Discord shows `content`.
+    pub text: String,
+    pub content: String,
+}
+
+/// Renders the message of a notification
+///
+/// The placeholders `{show}`, `{episode}`, `{title}`, `{magnet}`, and `{nyaa_id}` are
+/// replaced. `{episode}` is the episode number, a range such as `1-12` for batches, or
+/// empty if the title contains no episode number. Other text is copied verbatim.
+pub fn render_message(
+    template: &str,
+    show_name: &str,
+    episode: Option<i32>,
+    episode_end: Option<i32>,
+    title: &str,
+    magnet: &str,
+    nyaa_id: i64,
+) -> String {
+    let episode = match (episode, episode_end) {
+        (Some(first), Some(last)) => format!("{}-{}", first, last),
+        (Some(first), None) => first.to_string(),
+        _ => String::new(),
+    };
+    let nyaa_id = nyaa_id.to_string();
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('}').map_or(rest.len(), |e| e + 1);
+        let value = match &rest[..end] {
+            "{show}" => show_name,
+            "{episode}" => &episode,
+            "{title}" => title,
+            "{magnet}" => magnet,
+            "{nyaa_id}" => &nyaa_id,
+            _ => {
+                message.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        message.push_str(value);
+        rest = &rest[end..];
+    }
+    message.push_str(rest);
+    message
+}
+
+/// Discord rejects messages that are longer
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// Telegram rejects messages that are longer
+const TELEGRAM_MAX_CHARS: usize = 4096;
+
+#[derive(Serialize)]
+struct DiscordMessage<'a> {
+    content: &'a str,
+    /// Keeps titles from mentioning `@everyone`
+    allowed_mentions: AllowedMentions,
+}
+
+#[derive(Serialize)]
+struct AllowedMentions {
+    parse: [&'static str; 0],
+}
+
+#[derive(Serialize)]
+struct TelegramMessage<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+    disable_web_page_preview: bool,
+}
+
+/// Posts a notification to the target of a webhook
+pub async fn send(
+    state: &State<'_>,
+    config: &Webhooks,
+    kind: WebhookKind,
+    target: &str,
+    notification: &Notification<'_>,
+) -> Result<()> {
+    match kind {
+        WebhookKind::Json => post(state, target, notification).await,
+        WebhookKind::Discord => {
+            let message = DiscordMessage {
+                content: truncate(&notification.text, DISCORD_MAX_CHARS),
+                allowed_mentions: AllowedMentions { parse: [] },
+            };
+            post(state, target, &message).await
+        }
+        WebhookKind::Telegram => {
+            let token = match &config.telegram_bot_token {
+                Some(t) => t,
+                _ => return Err(anyhow!("webhooks.telegram_bot_token is not set")),
+            };
+            let message = TelegramMessage {
+                chat_id: target,
+                text: truncate(&notification.text, TELEGRAM_MAX_CHARS),
+                disable_web_page_preview: true,
+            };
+            let url = format!(
+                "{}/bot{}/sendMessage",
+                config.telegram_api_url.trim_end_matches('/'),
+                token
+            );
+            post(state, &url, &message).await
+        }
+    }
+}
+
+fn truncate(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((pos, _)) => &s[..pos],
+        _ => s,
+    }
+}
+
+/// Posts `body` as json to `url`
+///
+/// The errors do not contain the url since the url of a Telegram chat contains the bot
+/// token and the errors are logged.
+async fn post(state: &State<'_>, url: &str, body: &impl Serialize) -> Result<()> {
+    let response =
+        state
+            .web_client
+            .post(url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| match e.source() {
+                Some(source) => anyhow!("cannot send the request: {}", source),
+                _ => anyhow!("cannot send the request"),
+            })?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("the server responded with status {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_messages() {
+        let template = "{show} {episode}: {title} ({nyaa_id})";
+        assert_eq!(
+            render_message(
+                template,
+                "Oshi no Ko",
+                Some(3),
+                None,
+                "[G] Oshi - 03",
+                "",
+                7
+            ),
+            "Oshi no Ko 3: [G] Oshi - 03 (7)"
+        );
+        assert_eq!(
+            render_message(template, "Oshi no Ko", Some(1), Some(11), "{show}", "", 7),
+            "Oshi no Ko 1-11: {show} (7)"
+        );
+        assert_eq!(
+            render_message("{{magnet} {x}", "", None, None, "", "magnet:?", 1),
+            "{magnet:? {x}"
+        );
+        assert_eq!(truncate("äöü", 2), "äö");
+        assert_eq!(truncate("äöü", 5), "äöü");
+    }
+}
@@ -0,0 +1,81 @@
+use crate::state::State;
+use anyhow::Result;
+use common::{pg, Resolution};
+
+/// Chooses the recommended torrent of every episode
+///
+/// Among the torrents of an episode that contain no other episodes, the one with the
+/// highest score is stored in `magnets.recommended_torrent`. The score is the sum of
+/// the weights in `recommend` that apply to the torrent: `trusted` if the uploader is
+/// trusted, the weight of its resolution, `group` if its release group is one of
+/// `groups`, and `seeders` times `ln(1 + seeders)`. Ties go to the newer torrent.
+///
+/// The weights are read before every run so that they can be changed with a reload.
+pub async fn recommend_torrents(state: &State<'_>) {
+    loop {
+        log::info!("updating the recommended torrents");
+        if let Err(e) = recommend_torrents_now(state).await {
+            log::error!("could not update the recommended torrents: {:#}", e);
+        }
+        state.sleep(|c| c.recommend.poll_interval).await;
+    }
+}
+
+async fn recommend_torrents_now(state: &State<'_>) -> Result<()> {
+    let config = state.live_config.current();
+    let weights = &config.recommend;
+    let resolutions: Vec<_> = weights
+        .resolutions
+        .iter()
+        .map(|&(r, _)| Resolution::to_db(r))
+        .collect();
+    let resolution_weights: Vec<_> =
+        weights.resolutions.iter().map(|&(_, w)| w).collect();
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    // language=sql
+    tran.execute("delete from magnets.recommended_torrent", &[])
+        .await?;
+    // language=sql
+    let n = tran
+        .execute(
+            "
+            insert into magnets.recommended_torrent (show_id, episode, torrent_id, score)
+            select distinct on (show_id, episode) show_id, episode, torrent_id, score
+            from (
+                select
+                    rts.show_id,
+                    rts.episode,
+                    rts.torrent_id,
+                    rts.nyaa_id,
+                    case when t.trusted then $1::float8 else 0 end
+                        + coalesce((
+                            select r.weight
+                            from unnest($2::int[], $3::float8[]) r (resolution, weight)
+                            where r.resolution = t.resolution
+                        ), 0)
+                        + case when g.name = any($4::text[]) then $5::float8 else 0 end
+                        + $6::float8 * ln(1 + greatest(coalesce(t.seeders, 0), 0)) as score
+                from magnets.rel_torrent_show rts
+                join magnets.torrent t using (torrent_id)
+                left join magnets.release_group g using (release_group_id)
+                where rts.episode is not null
+                    and rts.episode_end is null
+                    and not t.batch
+            ) x
+            order by show_id, episode, score desc, nyaa_id desc",
+            &[
+                &weights.trusted,
+                &resolutions,
+                &resolution_weights,
+                &weights.groups,
+                &weights.group,
+                &weights.seeders,
+            ],
+        )
+        .await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    log::info!("recommended torrents for {} episodes", n);
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use crate::{robots, state::State, title_analyzer};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use common::{pg, pg::PgClient};
+use rss::Channel;
+
+/// A release group that publishes an RSS feed of its releases
+struct ReleaseGroup {
+    name: &'static str,
+    feed: &'static str,
+}
+
+const RELEASE_GROUPS: &[ReleaseGroup] = &[
+    ReleaseGroup {
+        name: "SubsPlease",
+        feed: "https://subsplease.org/rss/?r=1080",
+    },
+    ReleaseGroup {
+        name: "Erai-raws",
+        feed: "https://www.erai-raws.info/feed/?res=1080p",
+    },
+];
+
+/// Learns how long after airing the release groups usually publish an episode
+///
+/// The public RSS feeds of the release groups are polled and their releases are matched
+/// against the schedule. The observed delays are stored in
+/// `magnets.release_observation` and summarized in `magnets.expected_release`. This
+/// task is disabled unless `releases.enabled` is set.
+pub async fn load_releases(state: &State<'_>) {
+    if !state.config.releases.enabled {
+        return;
+    }
+    loop {
+        if state.memory.shedding() {
+            log::info!("not loading releases because the memory budget is exceeded");
+            state.sleep(|c| c.releases.poll_interval).await;
+            continue;
+        }
+        for group in RELEASE_GROUPS {
+            log::info!("loading the releases of {}", group.name);
+            if let Err(e) = load_group_releases(state, group).await {
+                log::error!("could not load the releases of {}: {:#}", group.name, e);
+            }
+        }
+        if let Err(e) = update_expected_releases(state).await {
+            log::error!("could not update the expected releases: {:#}", e);
+        }
+        state.sleep(|c| c.releases.poll_interval).await;
+    }
+}
+
+struct Release {
+    title: String,
+    published: DateTime<Utc>,
+}
+
+async fn load_group_releases(state: &State<'_>, group: &ReleaseGroup) -> Result<()> {
+    let user_agent = &state.config.user_agent.product;
+    if !robots::is_allowed(&state.http_cache, user_agent, group.feed).await? {
+        log::warn!("robots.txt does not allow fetching {}", group.feed);
+        return Ok(());
+    }
+    let releases = fetch_feed(&state.web_client, group.feed).await?;
+    let show_db = state.show_db.get().await?;
+    let con = state.pg_connector.connect().await?;
+    for release in releases {
+        let show = match title_analyzer::find_show(&show_db, &release.title) {
+            Ok(s) => s,
+            Err(e) => {
+                log::debug!("could not match release {}: {:#}", release.title, e);
+                continue;
+            }
+        };
+        let episode = match title_analyzer::find_episode_number(&release.title) {
+            Some(e) => e,
+            _ => continue,
+        };
+        state.leader.ensure().await?;
+        // Record how long after the scheduled airing time the episode was released.
+        // Releases of episodes that are not in the schedule are ignored.
+        // language=sql
+        con.execute(
+            "
+            insert into magnets.release_observation
+                (show_id, release_group, episode, delay_seconds)
+            select show_id, $2, episode, extract(epoch from $4 - airs_at)::int
+            from magnets.schedule
+            where show_id = $1 and episode = $3 and airs_at <= $4
+            on conflict (show_id, release_group, episode) do nothing",
+            &[&show.show_id, &group.name, &episode, &release.published],
+        )
+        .await?;
+        cross_check(&con, &release.title, show.show_id).await?;
+    }
+    Ok(())
+}
+
+/// Warns if the torrent of a release has been matched to a different show
+///
+/// The release groups upload the same titles to nyaa.si. A mismatch means that the
+/// analyzer treats the same title differently in both places which should never
+/// happen.
+async fn cross_check(con: &PgClient, title: &str, show_id: i64) -> Result<()> {
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select rts.show_id
+            from magnets.torrent t
+            join magnets.rel_torrent_show rts using (torrent_id)
+            where t.title = $1",
+            &[&title],
+        )
+        .await?;
+    for row in rows {
+        let torrent_show_id: i64 = row.get(0);
+        if torrent_show_id != show_id {
+            log::warn!(
+                "release {} matched show {} but the torrent is matched to show {}",
+                title,
+                show_id,
+                torrent_show_id
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_feed(client: &reqwest::Client, url: &str) -> Result<Vec<Release>> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("cannot request {}", url))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{} status code is {}", url, response.status()));
+    }
+    let body = response
+        .bytes()
+        .await
+        .with_context(|| format!("cannot read response of {}", url))?;
+    let channel = Channel::read_from(&body[..]).context("cannot parse feed")?;
+    let mut releases = vec![];
+    for item in channel.items() {
+        let (title, published) = match (item.title(), item.pub_date()) {
+            (Some(t), Some(p)) => (t, p),
+            _ => continue,
+        };
+        let published = match DateTime::parse_from_rfc2822(published) {
+            Ok(p) => p.with_timezone(&Utc),
+            Err(e) => {
+                log::warn!("cannot parse publication date {}: {}", published, e);
+                continue;
+            }
+        };
+        releases.push(Release {
+            title: title.to_string(),
+            published,
+        });
+    }
+    Ok(releases)
+}
+
+/// Summarizes the observations of the last 90 days
+///
+/// We use the 90th percentile so that "within Y hours" holds for most episodes. Groups
+/// without observations in that window no longer release the show and are removed.
+async fn update_expected_releases(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.expected_release (show_id, release_group, delay_seconds, samples)
+        select
+            show_id,
+            release_group,
+            percentile_disc(0.9) within group (order by delay_seconds),
+            count(*)
+        from magnets.release_observation
+        where created > now() - interval '90 days'
+        group by show_id, release_group
+        on conflict (show_id, release_group) do update
+        set delay_seconds = excluded.delay_seconds, samples = excluded.samples",
+        &[],
+    )
+    .await?;
+    // language=sql
+    tran.execute(
+        "
+        delete from magnets.expected_release e
+        where not exists (
+            select *
+            from magnets.release_observation o
+            where o.show_id = e.show_id and o.release_group = e.release_group
+                and o.created > now() - interval '90 days'
+        )",
+        &[],
+    )
+    .await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    Ok(())
+}
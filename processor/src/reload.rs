@@ -0,0 +1,69 @@
+use crate::{config::Config, state::State};
+use common::time::{DurationFmt, StdDuration};
+
+/// The settings that take effect without a restart
+///
+/// The weights in `recommend`, the other settings in `webhooks`, and
+/// `metrics.listen_addr` are also applied on reload. All other settings are only read at
+/// startup.
+const INTERVALS: &[(&str, fn(&Config) -> StdDuration)] = &[
+    ("anilist.schedule_poll_interval", |c| {
+        c.anilist.schedule_poll_interval
+    }),
+    ("anilist.shows_poll_interval", |c| {
+        c.anilist.shows_poll_interval
+    }),
+    ("covers.poll_interval", |c| c.covers.poll_interval),
+    ("export.poll_interval", |c| c.export.poll_interval),
+    ("heartbeat.interval", |c| c.heartbeat.interval),
+    ("matcher.anomaly.interval", |c| c.matcher.anomaly_interval()),
+    ("memory.check_interval", |c| c.memory.check_interval),
+    ("metadata.check_interval", |c| c.metadata.check_interval),
+    ("nyaa.scrape_interval", |c| c.nyaa.scrape_interval),
+    ("nyaa.size_reparse_interval", |c| {
+        c.nyaa.size_reparse_interval
+    }),
+    ("nyaa.swarm_refresh_interval", |c| {
+        c.nyaa.swarm_refresh_interval
+    }),
+    ("nyaa.trusted_refresh_interval", |c| {
+        c.nyaa.trusted_refresh_interval
+    }),
+    ("recommend.poll_interval", |c| c.recommend.poll_interval),
+    ("releases.poll_interval", |c| c.releases.poll_interval),
+    ("search.poll_interval", |c| c.search.poll_interval),
+    ("watchlists.login_poll_interval", |c| {
+        c.watchlists.login_poll_interval
+    }),
+    ("watchlists.sync_interval", |c| c.watchlists.sync_interval),
+    ("watchlists.sync_user_delay", |c| {
+        c.watchlists.sync_user_delay
+    }),
+    ("webhooks.poll_interval", |c| c.webhooks.poll_interval),
+];
+
+/// Reloads config.toml on SIGHUP
+///
+/// Tasks that are waiting for their next run recompute the wait with the new intervals.
+pub async fn reload_config(state: &State<'_>) {
+    state
+        .live_config
+        .reload_on_sighup(|old, new| {
+            for (name, interval) in INTERVALS {
+                let (old, new) = (interval(old), interval(new));
+                if old != new {
+                    log::info!(
+                        "{} changed from {} to {}",
+                        name,
+                        DurationFmt(old),
+                        DurationFmt(new)
+                    );
+                }
+            }
+            if old.metrics.listen_addr != new.metrics.listen_addr {
+                log::info!("metrics.listen_addr changed");
+            }
+            state.db_watcher.notify_all();
+        })
+        .await
+}
@@ -0,0 +1,140 @@
+use crate::http::{HttpCache, StatusError};
+use anyhow::{anyhow, Result};
+use url::Url;
+
+/// Checks whether the robots.txt of the host of `url` allows us to fetch `url`
+///
+/// Hosts that answer with a client error such as 404 have no robots.txt and allow
+/// everything. If the robots.txt cannot be retrieved otherwise, an error is returned and
+/// the caller must not crawl the host.
+pub async fn is_allowed(cache: &HttpCache, user_agent: &str, url: &str) -> Result<bool> {
+    let url = Url::parse(url)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("url {} has no host", url))?;
+    let robots_url = format!("{}://{}/robots.txt", url.scheme(), host);
+    let robots = match cache.get(&robots_url).await {
+        Ok(r) => r,
+        Err(e) if is_unavailable(&e) => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    let robots = String::from_utf8_lossy(&robots);
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    Ok(allows(&robots, user_agent, &path))
+}
+
+/// Returns whether an error means that the host has no robots.txt
+fn is_unavailable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<StatusError>()
+        .map(|e| e.status.is_client_error())
+        .unwrap_or(false)
+}
+
+/// Evaluates a robots.txt for a path
+///
+/// The rules of the group matching `user_agent` are used, falling back to the `*`
+/// group. The longest matching rule wins and `Allow` wins ties.
+fn allows(robots: &str, user_agent: &str, path: &str) -> bool {
+    let user_agent = user_agent.to_lowercase();
+    let mut specific = vec![];
+    let mut wildcard = vec![];
+    let mut agents: Vec<String> = vec![];
+    let mut in_rules = false;
+    for line in robots.lines() {
+        let line = line.split('#').next().unwrap().trim();
+        let (key, value) = match line.find(':') {
+            Some(pos) => (line[..pos].trim().to_lowercase(), line[pos + 1..].trim()),
+            _ => continue,
+        };
+        match &*key {
+            "user-agent" => {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_lowercase());
+            }
+            "allow" | "disallow" => {
+                in_rules = true;
+                let rule = (key == "allow", value.to_string());
+                for agent in &agents {
+                    if agent == "*" {
+                        wildcard.push(rule.clone());
+                    } else if user_agent.contains(&**agent) {
+                        specific.push(rule.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let rules = if specific.is_empty() {
+        wildcard
+    } else {
+        specific
+    };
+    let mut best: Option<(usize, bool)> = None;
+    for (allow, prefix) in rules {
+        // An empty disallow rule allows everything
+        if prefix.is_empty() || !path.starts_with(&*prefix) {
+            continue;
+        }
+        let better = match best {
+            Some((len, best_allow)) => {
+                prefix.len() > len || (prefix.len() == len && allow && !best_allow)
+            }
+            _ => true,
+        };
+        if better {
+            best = Some((prefix.len(), allow));
+        }
+    }
+    best.map(|(_, allow)| allow).unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    const ROBOTS: &str = "
+User-agent: *
+Disallow: /private/
+Allow: /private/feed
+
+User-agent: magnets
+User-agent: other
+Disallow: /rss # no feeds for us
+Allow: /rss/?r=1080
+";
+
+    #[test]
+    fn evaluates_rules() {
+        assert!(allows(ROBOTS, "curl", "/rss"));
+        assert!(!allows(ROBOTS, "curl", "/private/page"));
+        assert!(allows(ROBOTS, "curl", "/private/feed"));
+        assert!(!allows(ROBOTS, "Magnets.moe/1.0", "/rss/?r=720"));
+        assert!(allows(ROBOTS, "Magnets.moe/1.0", "/rss/?r=1080"));
+        assert!(allows(ROBOTS, "Magnets.moe/1.0", "/private/page"));
+        assert!(allows("", "curl", "/"));
+        assert!(allows("User-agent: *\nDisallow:", "curl", "/"));
+    }
+
+    #[test]
+    fn missing_robots_allow_everything() {
+        let error = |status| {
+            anyhow::Error::from(StatusError {
+                url: "https://example.org/robots.txt".to_string(),
+                status,
+            })
+        };
+        assert!(is_unavailable(&error(StatusCode::NOT_FOUND)));
+        assert!(is_unavailable(&error(StatusCode::GONE)));
+        assert!(!is_unavailable(&error(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(!is_unavailable(&anyhow!("cannot connect")));
+    }
+}
@@ -1,26 +1,35 @@
-use crate::state::State;
+use crate::{config::Config, state::State};
 use anyhow::Result;
-use chrono::{DateTime, Utc};
-use common::time::{sleep_until, DurationFmt, StdDuration, MINUTE};
+use chrono::{DateTime, Duration, Utc};
+use common::time::{DurationFmt, StdDuration, MINUTE};
 use futures::{
     future::{select, Either},
     pin_mut,
 };
-use std::time::SystemTime;
 use tokio::sync::Notify;
 use tokio_postgres::types::Json;
 
 pub struct Scheduled<'a> {
     state: &'a State<'a>,
     key: &'static str,
-    period: StdDuration,
+    /// Selects the period from the config. It is read before every wait so that a
+    /// reloaded config takes effect immediately.
+    period: fn(&Config) -> StdDuration,
 }
 
 impl<'a> Scheduled<'a> {
-    pub fn new(state: &'a State, key: &'static str, period: StdDuration) -> Self {
+    pub fn new(
+        state: &'a State,
+        key: &'static str,
+        period: fn(&Config) -> StdDuration,
+    ) -> Self {
         Self { state, key, period }
     }
 
+    fn period(&self) -> StdDuration {
+        (self.period)(&self.state.live_config.current())
+    }
+
     pub async fn wait(&self, n: &Notify) {
         while let Err(e) = self.wait_(n).await {
             log::error!(
@@ -29,7 +38,7 @@ impl<'a> Scheduled<'a> {
                 e
             );
             log::info!("sleeping for 5 minutes");
-            tokio::time::delay_for(5 * MINUTE).await;
+            self.state.clock.sleep(5 * MINUTE).await;
         }
     }
 
@@ -38,17 +47,16 @@ impl<'a> Scheduled<'a> {
             let con = self.state.pg.borrow().await?;
             let last = {
                 // language=sql
-                let row = con
-                    .query_one(
-                        "select value from magnets.state where key = $1",
-                        &[&self.key],
-                    )
+                let stmt = con
+                    .prepare_cached("select value from magnets.state where key = $1")
                     .await?;
+                let row = con.query_one(&stmt, &[&self.key]).await?;
                 let last: Json<DateTime<Utc>> = row.get(0);
-                SystemTime::from(last.0)
+                last.0
             };
             let notified = n.notified();
-            let sleep = sleep_until(last + self.period);
+            let next = last + Duration::from_std(self.period())?;
+            let sleep = self.state.clock.sleep_until_time(next);
             pin_mut!(notified, sleep);
             if let Either::Right(_) = select(notified, sleep).await {
                 break;
@@ -60,20 +68,20 @@ impl<'a> Scheduled<'a> {
     pub async fn update(&self) {
         if let Err(e) = self.update_().await {
             log::error!("cannot update schedule of {}: {:#}", self.key, e);
-            log::info!("manually sleeping for {}", DurationFmt(self.period));
-            tokio::time::delay_for(self.period).await;
+            let period = self.period();
+            log::info!("manually sleeping for {}", DurationFmt(period));
+            self.state.clock.sleep(period).await;
         }
     }
 
     async fn update_(&self) -> Result<()> {
-        let now = Utc::now();
+        let now = self.state.clock.now();
         let pg = self.state.pg.borrow().await?;
         // language=sql
-        pg.execute(
-            "update magnets.state set value = $1 where key = $2",
-            &[&Json(now), &self.key],
-        )
-        .await?;
+        let stmt = pg
+            .prepare_cached("update magnets.state set value = $1 where key = $2")
+            .await?;
+        pg.execute(&stmt, &[&Json(now), &self.key]).await?;
         Ok(())
     }
 }
@@ -0,0 +1,183 @@
+use crate::state::State;
+use anyhow::Result;
+use common::textnorm;
+use tokio_postgres::{Client, GenericClient};
+
+/// The number of torrents whose titles are folded per query
+const TORRENT_BATCH: i64 = 1000;
+
+/// Maintains the columns that back the search of the site
+///
+/// `magnets.show.search_names` and `magnets.torrent.search_title` contain the names and
+/// titles folded with `textnorm::search_words`. The folding cannot be done in SQL, so
+/// postgres only builds the tsvector and trigram indexes over these columns.
+///
+/// Torrents are indexed once their `search_title` is null, i.e. after they have been
+/// inserted or their title has been edited. The names of all shows are recomputed in
+/// every round since they are few and names are added by several tasks.
+pub async fn index_search(state: &State<'_>) {
+    loop {
+        if let Err(e) = index_search_now(state).await {
+            log::error!("could not update the search index: {:#}", e);
+        }
+        state.sleep(|c| c.search.poll_interval).await;
+    }
+}
+
+async fn index_search_now(state: &State<'_>) -> Result<()> {
+    let con = state.pg_connector.connect().await?;
+    index_shows(state, &con).await?;
+    index_torrents(state, &con).await?;
+    Ok(())
+}
+
+/// Folds the names of a show. Names that are equal after folding are only kept once.
+fn search_names(names: &[String]) -> String {
+    let mut folded: Vec<String> = vec![];
+    for name in names {
+        let name = textnorm::search_words(name);
+        if !name.is_empty() && !folded.contains(&name) {
+            folded.push(name);
+        }
+    }
+    folded.join("\n")
+}
+
+async fn index_shows(state: &State<'_>, con: &Client) -> Result<()> {
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select s.show_id, s.search_names, array_agg(n.name order by n.show_name_id)
+            from magnets.show s
+            join magnets.show_name n using (show_id)
+            group by s.show_id",
+            &[],
+        )
+        .await?;
+    let mut show_ids = vec![];
+    let mut names = vec![];
+    for row in &rows {
+        let current: Option<String> = row.get(1);
+        let folded = search_names(&row.get::<_, Vec<String>>(2));
+        if current.as_deref() != Some(&*folded) {
+            show_ids.push(row.get::<_, i64>(0));
+            names.push(folded);
+        }
+    }
+    if show_ids.is_empty() {
+        return Ok(());
+    }
+    log::info!("updating the search names of {} shows", show_ids.len());
+    state.leader.ensure().await?;
+    // language=sql
+    con.execute(
+        "
+        update magnets.show s
+        set search_names = x.search_names
+        from unnest($1::bigint[], $2::text[]) x (show_id, search_names)
+        where s.show_id = x.show_id",
+        &[&show_ids, &names],
+    )
+    .await?;
+    Ok(())
+}
+
+async fn index_torrents(state: &State<'_>, con: &Client) -> Result<()> {
+    let mut total = 0;
+    loop {
+        // language=sql
+        let rows = con
+            .query(
+                "
+                select torrent_id, title
+                from magnets.torrent
+                where search_title is null
+                order by torrent_id
+                limit $1",
+                &[&TORRENT_BATCH],
+            )
+            .await?;
+        if rows.is_empty() {
+            break;
+        }
+        let mut torrent_ids = vec![];
+        let mut titles = vec![];
+        let mut folded = vec![];
+        for row in &rows {
+            let title: String = row.get(1);
+            torrent_ids.push(row.get::<_, i64>(0));
+            folded.push(textnorm::search_words(&title));
+            titles.push(title);
+        }
+        state.leader.ensure().await?;
+        // The title is compared so that a title that was edited in the meantime is
+        // folded again in the next batch.
+        // language=sql
+        con.execute(
+            "
+            update magnets.torrent t
+            set search_title = x.search_title
+            from unnest($1::bigint[], $2::text[], $3::text[]) x (torrent_id, title, search_title)
+            where t.torrent_id = x.torrent_id and t.title = x.title",
+            &[&torrent_ids, &titles, &folded],
+        )
+        .await?;
+        total += rows.len();
+        if (rows.len() as i64) < TORRENT_BATCH {
+            break;
+        }
+    }
+    if total > 0 {
+        log::info!("indexed the titles of {} torrents", total);
+    }
+    Ok(())
+}
+
+/// Recomputes `magnets.show.last_torrent_at` of all shows
+///
+/// The column ranks the results of the show search on the site. Matching a torrent
+/// moves it forward right away. This catches up with the matches that have been removed
+/// or replaced by a rematch and is run after the daily shows sync.
+pub async fn update_last_torrents(con: &impl GenericClient) -> Result<()> {
+    // language=sql
+    let updated = con
+        .execute(
+            "
+            update magnets.show s
+            set last_torrent_at = x.last_torrent_at
+            from (
+                select s.show_id, (
+                    select max(t.uploaded_at)
+                    from magnets.rel_torrent_show rts
+                    join magnets.torrent t using (torrent_id)
+                    where rts.show_id = s.show_id
+                ) as last_torrent_at
+                from magnets.show s
+            ) x
+            where s.show_id = x.show_id
+                and s.last_torrent_at is distinct from x.last_torrent_at",
+            &[],
+        )
+        .await?;
+    if updated > 0 {
+        log::info!("updated the last torrent of {} shows", updated);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deduplicates_folded_names() {
+        let names = [
+            "Shingeki no Kyojin".to_string(),
+            "SHINGEKI NO KYOJIN".to_string(),
+            "Attack on Titan".to_string(),
+            "!!".to_string(),
+        ];
+        assert_eq!(search_names(&names), "shingeki no kyojin\nattack on titan");
+    }
+}
@@ -0,0 +1,68 @@
+use crate::leader::Leader;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use common::{pg::PgClient, YearSeason};
+
+/// Infers the seasons of shows for which AniList does not provide one
+///
+/// Many OVAs and ONAs have no season on AniList and would otherwise never appear on a
+/// season page. We use the season of the earliest scheduled episode or matched torrent
+/// instead and store it in `magnets.show.inferred_season`.
+///
+/// Only the seasons of `show_ids` are inferred if given. The scraper passes the shows
+/// of new matches so that it does not have to scan all shows after every scrape.
+pub async fn infer_seasons(
+    con: &PgClient,
+    leader: &Leader,
+    show_ids: Option<&[i64]>,
+) -> Result<()> {
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select s.show_id, s.inferred_season, least(
+                (
+                    select min(sch.airs_at)
+                    from magnets.schedule sch
+                    where sch.show_id = s.show_id
+                ),
+                (
+                    select min(t.uploaded_at)
+                    from magnets.rel_torrent_show rts
+                    join magnets.torrent t using (torrent_id)
+                    where rts.show_id = s.show_id
+                )
+            ) as earliest
+            from magnets.show s
+            where s.season is null and ($1::bigint[] is null or s.show_id = any($1))",
+            &[&show_ids],
+        )
+        .await?;
+    let mut changed = vec![];
+    let mut seasons = vec![];
+    for row in rows {
+        let earliest: Option<DateTime<Utc>> = row.get("earliest");
+        let inferred: Option<i32> = row.get("inferred_season");
+        let season = earliest.map(|e| YearSeason::of(&e).to_db());
+        if season != inferred {
+            changed.push(row.get::<_, i64>("show_id"));
+            seasons.push(season);
+        }
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+    log::info!("inferred the seasons of {} shows", changed.len());
+    leader.ensure().await?;
+    // language=sql
+    con.execute(
+        "
+        update magnets.show s
+        set inferred_season = n.season
+        from unnest($1::bigint[], $2::int[]) as n(show_id, season)
+        where s.show_id = n.show_id",
+        &[&changed, &seasons],
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::{
+    leader::Leader,
+    show_db::{Show, ShowDb},
+    title_analyzer::Analyzer,
+};
+use anyhow::Result;
+use common::pg::PgClient;
+
+/// A torrent that a candidate analyzer matched differently than the default analyzer
+pub struct Disagreement {
+    torrent_id: i64,
+    show_id: Option<i64>,
+    candidate_show_id: Option<i64>,
+}
+
+/// Runs the candidate analyzer on a title and compares the result with the real match
+pub fn compare(
+    candidate: Analyzer,
+    db: &ShowDb,
+    torrent_id: i64,
+    title: &str,
+    show: Option<&Show>,
+) -> Option<Disagreement> {
+    let show_id = show.map(|s| s.show_id);
+    let candidate_show_id = candidate.find_new_show(db, title).ok().map(|s| s.show_id);
+    if show_id == candidate_show_id {
+        return None;
+    }
+    log::debug!(
+        "{} analyzer matched torrent {} to {:?} instead of {:?}: {}",
+        candidate.as_str(),
+        torrent_id,
+        candidate_show_id,
+        show_id,
+        title
+    );
+    Some(Disagreement {
+        torrent_id,
+        show_id,
+        candidate_show_id,
+    })
+}
+
+/// Stores disagreements in `magnets.analyzer_shadow`
+///
+/// This must not run in the transaction that inserts the real matches so that a failure
+/// here never affects them.
+pub async fn record(
+    con: &PgClient,
+    leader: &Leader,
+    candidate: Analyzer,
+    disagreements: &[Disagreement],
+) -> Result<()> {
+    if disagreements.is_empty() {
+        return Ok(());
+    }
+    let torrent_ids: Vec<_> = disagreements.iter().map(|d| d.torrent_id).collect();
+    let show_ids: Vec<_> = disagreements.iter().map(|d| d.show_id).collect();
+    let candidate_show_ids: Vec<_> =
+        disagreements.iter().map(|d| d.candidate_show_id).collect();
+    leader.ensure().await?;
+    // language=sql
+    con.execute(
+        "
+        insert into magnets.analyzer_shadow
+            (torrent_id, analyzer, show_id, candidate_show_id)
+        select d.torrent_id, $1, d.show_id, d.candidate_show_id
+        from unnest($2::bigint[], $3::bigint[], $4::bigint[])
+            as d(torrent_id, show_id, candidate_show_id)
+        on conflict (torrent_id, analyzer) do update
+        set show_id = excluded.show_id,
+            candidate_show_id = excluded.candidate_show_id,
+            created = now()",
+        &[
+            &candidate.as_str(),
+            &torrent_ids,
+            &show_ids,
+            &candidate_show_ids,
+        ],
+    )
+    .await?;
+    Ok(())
+}
@@ -1,18 +1,19 @@
 use crate::{
-    heap::AsciiHeap,
+    heap::{AsciiHeap, HeapBuilder},
     strings::{ArcString, StringLists},
 };
 use anyhow::Result;
-use common::{pg, pg::PgConnector, Format, YearSeason};
+use common::{pg, pg::PgConnector, textnorm, Format, YearSeason};
 use smallvec::{smallvec, SmallVec};
 use std::{
     collections::HashMap,
     hash::{Hash, Hasher},
+    mem::size_of,
     ops::Range,
     sync::Arc,
 };
 use tokio::sync::Mutex;
-use tokio_postgres::Transaction;
+use tokio_postgres::{Client, Transaction};
 
 /// A large number suitable for ensuring that allocations occur via mmap
 pub const LARGE_NUMBER: usize = 10_000;
@@ -23,6 +24,9 @@ pub struct Show {
     pub seasons: SmallVec<[u32; 1]>,
     pub years: SmallVec<[u32; 1]>,
     pub formats: SmallVec<[Format; 1]>,
+    /// Whether the show is from one of the last `matcher.recent_seasons` seasons or
+    /// is currently airing
+    pub recent: bool,
 }
 
 impl Eq for Show {
@@ -40,26 +44,105 @@ impl Hash for Show {
     }
 }
 
+/// Maps the search names of a set of shows to their indices in `ShowDb::shows`
+pub struct NameIndex {
+    pub map: HashMap<ArcString, SmallVec<[usize; 1]>>,
+    pub heap: AsciiHeap<usize>,
+}
+
+impl NameIndex {
+    fn new(
+        map: HashMap<ArcString, SmallVec<[usize; 1]>>,
+        builder: &mut HeapBuilder,
+    ) -> Self {
+        let heap = builder.build(map.iter().flat_map(|(search_name, show_idxs)| {
+            show_idxs
+                .iter()
+                .map(move |&show_idx| (&**search_name, show_idx))
+        }));
+        Self { map, heap }
+    }
+
+    /// Returns the approximate number of bytes allocated by the index
+    ///
+    /// The search names are shared between indices and are not included.
+    fn heap_size(&self) -> usize {
+        self.map.capacity() * size_of::<(ArcString, SmallVec<[usize; 1]>)>()
+            + self.heap.heap_size()
+    }
+}
+
 pub struct ShowDb {
     pub shows: Box<[Show]>,
     pub names: StringLists,
-    pub map: HashMap<ArcString, SmallVec<[usize; 1]>>,
-    pub heap: AsciiHeap<usize>,
+    /// The names of all shows
+    pub all: NameIndex,
+    /// The names of the recent shows if `matcher.recent_seasons` is set
+    ///
+    /// This index is tried first for new torrents so that new releases are not matched
+    /// to older shows with the same name (see `title_analyzer::find_new_show`).
+    pub recent: Option<NameIndex>,
+    /// Maps the matcher keys of a release group and a name to the index of a show
+    ///
+    /// These rules from `magnets.group_rule` are consulted before the names so that
+    /// releases of groups with unusual naming can be matched.
+    pub group_rules: HashMap<(String, String), usize>,
+}
+
+impl ShowDb {
+    /// Returns the approximate number of bytes allocated by the database
+    pub fn heap_size(&self) -> usize {
+        let search_names: usize = self.all.map.keys().map(|k| k.len()).sum();
+        self.shows.len() * size_of::<Show>()
+            + self.names.heap_size()
+            + search_names
+            + self.all.heap_size()
+            + self.recent.as_ref().map(|r| r.heap_size()).unwrap_or(0)
+            + self
+                .group_rules
+                .keys()
+                .map(|(group, name)| {
+                    size_of::<((String, String), usize)>() + group.len() + name.len()
+                })
+                .sum::<usize>()
+    }
 }
 
 // language=sql
-common::create_statement!(LoadAllShows, show_id, show_format, season;
-                          "select show_id, show_format, season from magnets.show");
+common::create_statement!(LoadAllShows, show_id, show_format, season, recent; "
+    select
+        s.show_id,
+        s.show_format,
+        s.season,
+        coalesce(s.season, s.inferred_season) >= $1 or exists (
+            select *
+            from magnets.schedule sc
+            where sc.show_id = s.show_id and sc.airs_at > now()
+        ) as recent
+    from magnets.show s
+    where s.removal is null");
 
 async fn load_shows(
     tran: &Transaction<'_>,
+    recent_seasons: Option<u32>,
 ) -> Result<(HashMap<i64, usize>, Box<[Show]>)> {
     let load_all_shows = LoadAllShows::new(tran).await?;
 
     let mut shows = Vec::with_capacity(LARGE_NUMBER);
     let mut shows_map = HashMap::with_capacity(LARGE_NUMBER);
 
-    let rows = tran.query(&load_all_shows.stmt, &[]).await?;
+    // The oldest season that counts as recent
+    let cutoff = match recent_seasons {
+        Some(n) => {
+            let mut season = YearSeason::current();
+            for _ in 1..n {
+                season = season.prev();
+            }
+            season.to_db()
+        }
+        _ => i32::MAX,
+    };
+    let rows = tran.query(&load_all_shows.stmt, &[&cutoff]).await?;
     for row in rows {
         let mut show = Show {
             show_id: row.get(load_all_shows.show_id),
@@ -67,6 +150,7 @@ async fn load_shows(
             seasons: smallvec![],
             years: smallvec![],
             formats: smallvec![Format::from_db(row.get(load_all_shows.show_format),)?],
+            recent: row.get(load_all_shows.recent),
         };
         if let Some(season) = row.get::<_, Option<i32>>(load_all_shows.season) {
             show.years.push(YearSeason::from_db(season)?.year as u32);
@@ -78,8 +162,45 @@ async fn load_shows(
 }
 
 // language=sql
-common::create_statement!(LoadAllShowNames, show_id, name;
-                          "select show_id, name from magnets.show_name order by show_id");
+common::create_statement!(LoadAllShowNames, show_id, name; "
+    select sn.show_id, sn.name
+    from magnets.show_name sn
+    join magnets.show s using (show_id)
+    where s.removal is null
+    order by sn.show_id");
+
+// language=sql
+common::create_statement!(LoadAllGroupRules, release_group_key, name_key, show_id; "
+    select r.release_group_key, r.name_key, r.show_id
+    from magnets.group_rule r
+    join magnets.show s using (show_id)
+    where s.removal is null");
+
+/// Prepares the statements of this module
+pub async fn check_statements(con: &Client) -> Result<()> {
+    LoadAllShows::new(con).await?;
+    LoadAllShowNames::new(con).await?;
+    LoadAllGroupRules::new(con).await?;
+    Ok(())
+}
+
+/// Loads the group rules keyed by the matcher keys of the release group and the name
+async fn load_group_rules(
+    tran: &Transaction<'_>,
+) -> Result<Vec<((String, String), i64)>> {
+    let s = LoadAllGroupRules::new(tran).await?;
+    let rows = tran.query(&s.stmt, &[]).await?;
+    let rules = rows
+        .iter()
+        .map(|row| {
+            (
+                (row.get(s.release_group_key), row.get(s.name_key)),
+                row.get(s.show_id),
+            )
+        })
+        .collect();
+    Ok(rules)
+}
 
 async fn load_names(
     tran: &Transaction<'_>,
@@ -111,6 +232,9 @@ async fn load_names(
 fn build_db(
     shows: (HashMap<i64, usize>, Box<[Show]>),
     names: (StringLists, Vec<(i64, usize)>, usize),
+    group_rules: Vec<((String, String), i64)>,
+    recent_seasons: Option<u32>,
+    builder: &mut HeapBuilder,
 ) -> ShowDb {
     let (shows_map, mut shows) = shows;
     let (show_names, names, total_names) = names;
@@ -141,7 +265,7 @@ fn build_db(
             }
             {
                 let start = search_name_buf.len();
-                search_name_buf.push_str(&search_name(&name));
+                search_name_buf.push_str(&textnorm::matcher_key(&name));
                 let end = search_name_buf.len();
                 search_name_ranges.push((start..end, show_idx));
             }
@@ -154,18 +278,34 @@ fn build_db(
             .or_insert(smallvec![])
             .push(show_idx);
     }
-    let heap = AsciiHeap::new(names_map.iter().flat_map(|(search_name, show_idxs)| {
-        show_idxs
-            .iter()
-            .map(move |&show_idx| (&**search_name, show_idx))
-    }));
     log::info!("total show/name combos: {}", total_names);
     names_map.shrink_to_fit();
+    let recent = recent_seasons.map(|_| {
+        let mut recent_map: HashMap<_, SmallVec<_>> = names_map
+            .iter()
+            .filter_map(|(search_name, show_idxs)| {
+                let idxs: SmallVec<_> = show_idxs
+                    .iter()
+                    .copied()
+                    .filter(|&idx| shows[idx].recent)
+                    .collect();
+                (!idxs.is_empty()).then(|| (search_name.clone(), idxs))
+            })
+            .collect();
+        recent_map.shrink_to_fit();
+        log::info!("recent search names: {}", recent_map.len());
+        NameIndex::new(recent_map, builder)
+    });
+    let group_rules = group_rules
+        .into_iter()
+        .filter_map(|(key, show_id)| Some((key, *shows_map.get(&show_id)?)))
+        .collect();
     ShowDb {
         names: show_names,
         shows,
-        map: names_map,
-        heap,
+        all: NameIndex::new(names_map, builder),
+        recent,
+        group_rules,
     }
 }
 
@@ -238,50 +378,118 @@ pub fn find_season(s: &str) -> Option<(Range<usize>, u32)> {
     None
 }
 
-pub fn search_name(s: &str) -> String {
-    let mut search_name = String::new();
-    for &b in s.as_bytes() {
-        if matches!(b, b'a'..=b'z' | b'0'..=b'9') {
-            search_name.push(b as char);
-        }
-    }
-    search_name.shrink_to_fit();
-    search_name
-}
-
-async fn load_db(connector: &PgConnector) -> Result<ShowDb> {
-    log::info!("reloading the database");
-    let mut con = connector.connect().await?;
-    let tran = pg::transaction(&mut con).await?;
-    let (shows, names) = futures::join!(load_shows(&tran), load_names(&tran));
-    let db = build_db(shows?, names?);
-    Ok(db)
-}
-
 pub struct ShowDbHolder {
     show_db: Mutex<Option<Arc<ShowDb>>>,
     connector: PgConnector,
+    recent_seasons: Option<u32>,
+    /// Kept across refreshes so that the temporary buffers are only allocated once
+    heap_builder: std::sync::Mutex<HeapBuilder>,
 }
 
 impl ShowDbHolder {
-    pub fn new(connector: &PgConnector) -> Self {
+    /// `recent_seasons` is the number of seasons whose shows are in the recent index
+    ///
+    /// See [ShowDb::recent].
+    pub fn new(connector: &PgConnector, recent_seasons: Option<u32>) -> Self {
         Self {
             show_db: Mutex::new(None),
             connector: connector.clone(),
+            recent_seasons,
+            heap_builder: Default::default(),
         }
     }
 
+    async fn load_db(&self) -> Result<ShowDb> {
+        log::info!("reloading the database");
+        let mut con = self.connector.connect().await?;
+        let tran = pg::transaction(&mut con).await?;
+        let (shows, names, group_rules) = futures::join!(
+            load_shows(&tran, self.recent_seasons),
+            load_names(&tran),
+            load_group_rules(&tran),
+        );
+        let mut builder = self.heap_builder.lock().unwrap();
+        let db = build_db(
+            shows?,
+            names?,
+            group_rules?,
+            self.recent_seasons,
+            &mut builder,
+        );
+        log::info!(
+            "the heap builder holds {} bytes of temporary buffers",
+            builder.heap_size()
+        );
+        Ok(db)
+    }
+
     pub async fn get(&self) -> Result<Arc<ShowDb>> {
         let mut show_db = self.show_db.lock().await;
         if show_db.is_none() {
-            *show_db = Some(Arc::new(load_db(&self.connector).await?));
+            *show_db = Some(Arc::new(self.load_db().await?));
         }
         Ok(show_db.as_ref().unwrap().clone())
     }
 
+    /// Returns the size of the loaded database without loading it
+    pub async fn heap_size(&self) -> usize {
+        match &*self.show_db.lock().await {
+            Some(db) => db.heap_size(),
+            _ => 0,
+        }
+    }
+
     pub async fn refresh(&self) -> Result<()> {
-        let new = Arc::new(load_db(&self.connector).await?);
+        let new = Arc::new(self.load_db().await?);
         *self.show_db.lock().await = Some(new);
         Ok(())
     }
 }
+
+/// Builds a database from `(show_id, names, recent)` tuples for the tests of the matcher
+///
+/// The recent index is only built if at least one show is recent.
+#[cfg(test)]
+pub fn test_db(
+    shows: &[(i64, &[&str], bool)],
+    group_rules: &[(&str, &str, i64)],
+) -> ShowDb {
+    let mut shows_map = HashMap::new();
+    let mut show_vec = vec![];
+    let mut string_lists = StringLists::new();
+    let mut names = vec![];
+    let mut total_names = 0;
+    for &(show_id, show_names, recent) in shows {
+        shows_map.insert(show_id, show_vec.len());
+        show_vec.push(Show {
+            show_id,
+            names: 0,
+            seasons: smallvec![],
+            years: smallvec![],
+            formats: smallvec![Format::Tv],
+            recent,
+        });
+        for name in show_names {
+            string_lists.push_str(name);
+            total_names += 1;
+        }
+        names.push((show_id, string_lists.finish_list()));
+    }
+    let group_rules = group_rules
+        .iter()
+        .map(|&(group, name, show_id)| {
+            (
+                (textnorm::matcher_key(group), textnorm::matcher_key(name)),
+                show_id,
+            )
+        })
+        .collect();
+    let recent_seasons = shows.iter().any(|s| s.2).then(|| 1);
+    build_db(
+        (shows_map, show_vec.into_boxed_slice()),
+        (string_lists, names, total_names),
+        group_rules,
+        recent_seasons,
+        &mut HeapBuilder::default(),
+    )
+}
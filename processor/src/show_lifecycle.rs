@@ -0,0 +1,272 @@
+//! Removal and restoration of shows
+//!
+//! Shows are never deleted from the database because their pages might be linked from
+//! elsewhere. Instead `magnets.show.removal` records why a show is no longer listed.
+//! Removed shows are not loaded into the [ShowDb](crate::show_db::ShowDb), are hidden
+//! from the show lists of the site, and their pages answer with 301 (merged) or 410.
+//!
+//! All removals go through [remove] so that deleting a show by hand, merging two shows,
+//! and dropping a show that AniList no longer lists leave the database in the same
+//! state.
+
+use crate::{db_state::REMATCH_UNMATCHED, show_list};
+use anyhow::{anyhow, Result};
+use tokio_postgres::Transaction;
+
+/// Why a show has been removed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Removal {
+    /// Deleted with `processor show delete`
+    Deleted,
+    /// Merged into another show with `processor show merge`
+    Merged(i64),
+    /// No longer listed by AniList. Such shows are restored if they reappear.
+    RemovedFromAnilist,
+}
+
+impl Removal {
+    pub fn to_db(self) -> (i32, Option<i64>) {
+        match self {
+            Self::Deleted => (1, None),
+            Self::Merged(into) => (2, Some(into)),
+            Self::RemovedFromAnilist => (3, None),
+        }
+    }
+
+    pub fn from_db(n: i32, merged_into: Option<i64>) -> Result<Self> {
+        let v = match (n, merged_into) {
+            (1, _) => Self::Deleted,
+            (2, Some(into)) => Self::Merged(into),
+            (3, _) => Self::RemovedFromAnilist,
+            _ => {
+                return Err(anyhow!(
+                    "invalid removal {} (merged into {:?})",
+                    n,
+                    merged_into
+                ))
+            }
+        };
+        Ok(v)
+    }
+}
+
+/// Removes a show
+///
+/// The matches of the show are moved to the show it is merged into. Otherwise they are
+/// deleted and the torrents that no longer match any show are queued for rematching.
+/// Shows that were merged into the removed show are redirected to its replacement.
+///
+/// The changes become visible when `tran` is committed.
+pub async fn remove(
+    tran: &Transaction<'_>,
+    show_id: i64,
+    removal: Removal,
+) -> Result<()> {
+    lock_listed(tran, show_id).await?;
+    match removal {
+        Removal::Merged(into) => {
+            if into == show_id {
+                return Err(anyhow!("cannot merge show {} into itself", show_id));
+            }
+            lock_listed(tran, into).await?;
+            // language=sql
+            tran.execute(
+                "
+                update magnets.rel_torrent_show rts
+                set show_id = $2
+                where show_id = $1 and not exists (
+                    select *
+                    from magnets.rel_torrent_show o
+                    where o.torrent_id = rts.torrent_id and o.show_id = $2
+                )",
+                &[&show_id, &into],
+            )
+            .await?;
+            // language=sql
+            tran.execute(
+                "delete from magnets.rel_torrent_show where show_id = $1",
+                &[&show_id],
+            )
+            .await?;
+            // Additional names were added by hand and are still valid for the
+            // replacement. Romaji and english names are synced from AniList.
+            // language=sql
+            tran.execute(
+                "
+                update magnets.show_name sn
+                set show_id = $2
+                where show_id = $1 and show_name_type = 3 and not exists (
+                    select *
+                    from magnets.show_name o
+                    where o.show_id = $2 and o.name = sn.name
+                )",
+                &[&show_id, &into],
+            )
+            .await?;
+            // The subscriptions follow the show. Webhooks that the replacement already
+            // has are dropped.
+            // language=sql
+            tran.execute(
+                "
+                update magnets.webhook w
+                set show_id = $2
+                where show_id = $1 and not exists (
+                    select *
+                    from magnets.webhook o
+                    where o.show_id = $2 and o.kind = w.kind and o.target = w.target
+                )",
+                &[&show_id, &into],
+            )
+            .await?;
+            // language=sql
+            tran.execute(
+                "delete from magnets.webhook where show_id = $1",
+                &[&show_id],
+            )
+            .await?;
+            // language=sql
+            tran.execute(
+                "update magnets.show set merged_into = $2 where merged_into = $1",
+                &[&show_id, &into],
+            )
+            .await?;
+        }
+        Removal::Deleted | Removal::RemovedFromAnilist => {
+            // language=sql
+            let rows = tran
+                .query(
+                    "
+                    delete from magnets.rel_torrent_show
+                    where show_id = $1
+                    returning torrent_id",
+                    &[&show_id],
+                )
+                .await?;
+            let torrent_ids: Vec<i64> = rows.iter().map(|r| r.get(0)).collect();
+            // language=sql
+            let unmatched = tran
+                .execute(
+                    "
+                    update magnets.torrent t
+                    set matched = false, batch = false
+                    where torrent_id = any($1) and not exists (
+                        select *
+                        from magnets.rel_torrent_show rts
+                        where rts.torrent_id = t.torrent_id
+                    )",
+                    &[&torrent_ids],
+                )
+                .await?;
+            log::info!(
+                "removing show {} unmatched {} of its {} torrents",
+                show_id,
+                unmatched,
+                torrent_ids.len()
+            );
+        }
+    }
+    // A rematch that is in progress must not restore the matches of the show
+    // language=sql
+    tran.execute(
+        "delete from magnets.rel_torrent_show_next where show_id = $1",
+        &[&show_id],
+    )
+    .await?;
+    // The manual corrections, title overrides, and group rules of a merged show apply
+    // to its replacement
+    if let Removal::Merged(into) = removal {
+        // language=sql
+        tran.execute(
+            "
+            update magnets.match_override mo
+            set show_id = $2
+            where show_id = $1 and not exists (
+                select *
+                from magnets.match_override o
+                where o.torrent_id = mo.torrent_id and o.show_id = $2
+            )",
+            &[&show_id, &into],
+        )
+        .await?;
+        // language=sql
+        tran.execute(
+            "update magnets.title_override set show_id = $2 where show_id = $1",
+            &[&show_id, &into],
+        )
+        .await?;
+        // language=sql
+        tran.execute(
+            "update magnets.group_rule set show_id = $2 where show_id = $1",
+            &[&show_id, &into],
+        )
+        .await?;
+    }
+    // Title overrides and group rules of removed shows are kept but ignored by the
+    // matcher
+    // language=sql
+    tran.execute(
+        "delete from magnets.match_override where show_id = $1",
+        &[&show_id],
+    )
+    .await?;
+    let (removal, merged_into) = removal.to_db();
+    // language=sql
+    tran.execute(
+        "
+        update magnets.show
+        set removal = $2, merged_into = $3, removed_at = now()
+        where show_id = $1",
+        &[&show_id, &removal, &merged_into],
+    )
+    .await?;
+    changed(tran).await
+}
+
+/// Lists a show again that has been removed
+///
+/// Merged shows cannot be restored because their matches and names have been moved.
+pub async fn restore(tran: &Transaction<'_>, show_id: i64) -> Result<()> {
+    // language=sql
+    tran.execute(
+        "
+        update magnets.show
+        set removal = null, removed_at = null
+        where show_id = $1 and merged_into is null",
+        &[&show_id],
+    )
+    .await?;
+    changed(tran).await
+}
+
+/// Locks a show for the rest of the transaction
+///
+/// Returns an error if the show does not exist or has been removed.
+pub async fn lock_listed(tran: &Transaction<'_>, show_id: i64) -> Result<()> {
+    // language=sql
+    let row = tran
+        .query_opt(
+            "select removal is not null from magnets.show where show_id = $1 for update",
+            &[&show_id],
+        )
+        .await?;
+    match row.map(|r| r.get::<_, bool>(0)) {
+        None => Err(anyhow!("there is no show {}", show_id)),
+        Some(true) => Err(anyhow!("show {} has been removed", show_id)),
+        Some(false) => Ok(()),
+    }
+}
+
+/// Propagates a removal or restoration to the show list, the matcher, and the site
+async fn changed(tran: &Transaction<'_>) -> Result<()> {
+    show_list::store(tran).await?;
+    // The matcher reloads the show db before rematching
+    // language=sql
+    tran.execute(
+        "update magnets.state set value = '1' where key = $1 and value = '0'",
+        &[&REMATCH_UNMATCHED],
+    )
+    .await?;
+    // Delivered on commit. The site drops its cached show lists when it receives this.
+    tran.batch_execute("notify show_change").await?;
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use crate::{db_state, db_state::SHOW_LIST};
+use anyhow::Result;
+use tokio_postgres::GenericClient;
+
+/// Stores the names of all shows that have not been removed for the /shows page
+///
+/// Called after syncing the shows and after removing or restoring a show.
+pub async fn store(pg: &impl GenericClient) -> Result<()> {
+    // language=sql
+    let rows = pg
+        .query(
+            "
+            select sn.show_id, sn.name, sn.show_name_type
+            from magnets.show_name sn
+            join magnets.show s using (show_id)
+            where sn.show_name_type in (1, 2) and s.removal is null",
+            &[],
+        )
+        .await?;
+    let names: Vec<(i64, String, i32)> = rows
+        .iter()
+        .map(|r| (r.get(0), r.get(1), r.get(2)))
+        .collect();
+    db_state::set_blob(pg, SHOW_LIST, &names).await
+}
@@ -0,0 +1,181 @@
+use crate::{sleeper::Sleeper, sources::nyaa, state::State};
+use anyhow::{anyhow, Context, Result};
+use common::{pg, Source};
+use scraper::{Html, Selector};
+use std::time::Duration;
+
+/// Torrents whose size could not be determined after this many attempts stay flagged
+const MAX_ATTEMPTS: i32 = 5;
+/// The number of torrents re-parsed per batch
+const BATCH: i64 = 20;
+
+lazy_static::lazy_static! {
+    /// The label and value cells of the details panel, e.g. `File size:` followed by
+    /// `1.4 GiB`
+    static ref DETAILS: Selector = Selector::parse(".panel-body .row > div").unwrap();
+}
+
+/// Takes the sizes of the torrents in `magnets.size_reparse` from their detail pages
+///
+/// The size on the listing is sometimes garbled or implausible. Such torrents are stored
+/// with size 0 and queued by the scraper.
+pub async fn reparse_sizes(state: &State<'_>) {
+    loop {
+        state.sleep(|c| c.nyaa.size_reparse_interval).await;
+        if let Err(e) = reparse_sizes_now(state).await {
+            log::error!("could not re-parse the torrent sizes: {:#}", e);
+        }
+    }
+}
+
+async fn reparse_sizes_now(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select r.torrent_id, t.nyaa_id
+            from magnets.size_reparse r
+            join magnets.torrent t using (torrent_id)
+            where r.attempts < $1
+            order by r.checked nulls first
+            limit $2",
+            &[&MAX_ATTEMPTS, &BATCH],
+        )
+        .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let mut sleeper = Sleeper::new(state.clock.clone());
+    let mut fixed = 0;
+    for row in &rows {
+        sleeper.sleep(Duration::from_secs(1)).await;
+        let torrent_id: i64 = row.get("torrent_id");
+        let nyaa_id: i64 = row.get("nyaa_id");
+        let size = match fetch_size(state, nyaa_id).await {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!(
+                    "could not re-parse the size of torrent {}: {:#}",
+                    torrent_id,
+                    e
+                );
+                None
+            }
+        };
+        state.leader.ensure().await?;
+        let size = match size {
+            Some(s) => s,
+            _ => {
+                // language=sql
+                con.execute(
+                    "
+                    update magnets.size_reparse
+                    set attempts = attempts + 1, checked = now()
+                    where torrent_id = $1",
+                    &[&torrent_id],
+                )
+                .await?;
+                continue;
+            }
+        };
+        log::info!("torrent {} has size {}", torrent_id, size);
+        let tran = pg::transaction(&mut con).await?;
+        // language=sql
+        tran.execute(
+            "update magnets.torrent set size = $2 where torrent_id = $1",
+            &[&torrent_id, &size],
+        )
+        .await?;
+        // language=sql
+        tran.execute(
+            "
+            update magnets.torrent_source
+            set size = $3
+            where source = $1 and source_id = $2",
+            &[&Source::Nyaa.to_db(), &nyaa_id, &size],
+        )
+        .await?;
+        // language=sql
+        tran.execute(
+            "delete from magnets.size_reparse where torrent_id = $1",
+            &[&torrent_id],
+        )
+        .await?;
+        tran.commit().await?;
+        fixed += 1;
+    }
+    log::info!(
+        "re-parsed the sizes of {} of {} torrents",
+        fixed,
+        rows.len()
+    );
+    Ok(())
+}
+
+/// Returns the size from the detail page or `None` if the torrent no longer exists
+async fn fetch_size(state: &State<'_>, nyaa_id: i64) -> Result<Option<i64>> {
+    let url = format!("{}/view/{}", state.config.nyaa.url, nyaa_id);
+    let response = state
+        .web_client
+        .get(&url)
+        .send()
+        .await
+        .context("cannot communicate with nyaa.si")?;
+    match response.status().as_u16() {
+        200 => {}
+        404 => return Ok(None),
+        _ => return Err(anyhow!("nyaa.si status code is {}", response.status())),
+    }
+    let content = response
+        .text()
+        .await
+        .context("cannot read nyaa.si response")?;
+    let size =
+        parse_details(&content).with_context(|| format!("cannot parse {}", url))?;
+    Ok(Some(size))
+}
+
+/// Returns the size in the details panel of a detail page
+fn parse_details(content: &str) -> Result<i64> {
+    let html = Html::parse_document(content);
+    let mut cells = html.select(&DETAILS);
+    while let Some(cell) = cells.next() {
+        if cell.text().collect::<String>().trim() != "File size:" {
+            continue;
+        }
+        let value = cells.next().context("no file size")?;
+        return nyaa::parse_size(value.text().collect::<String>().trim());
+    }
+    Err(anyhow!("no file size"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(size: &str) -> String {
+        format!(
+            r#"<div class="panel-body">
+                <div class="row">
+                    <div class="col-md-1">Submitter:</div>
+                    <div class="col-md-5">uploader</div>
+                </div>
+                <div class="row">
+                    <div class="col-md-1">File size:</div>
+                    <div class="col-md-5">{}</div>
+                </div>
+            </div>"#,
+            size
+        )
+    }
+
+    #[test]
+    fn parses_details() {
+        assert_eq!(parse_details(&page("1.4 GiB")).unwrap(), 1503238553);
+        assert_eq!(parse_details(&page(" 350.2 MiB ")).unwrap(), 367211315);
+        assert!(parse_details(&page("0 Bytes")).is_err());
+        assert!(parse_details(&page("garbled")).is_err());
+        assert!(parse_details("<html></html>").is_err());
+    }
+}
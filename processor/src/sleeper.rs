@@ -1,17 +1,25 @@
-use std::time::{Duration, Instant};
+use common::time::Clock;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-pub struct Sleeper(Instant);
+pub struct Sleeper {
+    clock: Arc<dyn Clock>,
+    last: Instant,
+}
 
 impl Sleeper {
-    pub fn new() -> Self {
-        Self(Instant::now())
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let last = clock.instant();
+        Self { clock, last }
     }
 
     pub async fn sleep(&mut self, duration: Duration) {
-        let end = self.0 + duration;
-        let now = Instant::now();
-        self.0 = if now < end {
-            tokio::time::delay_until(end.into()).await;
+        let end = self.last + duration;
+        let now = self.clock.instant();
+        self.last = if now < end {
+            self.clock.sleep_until(end).await;
             end
         } else {
             now
@@ -19,6 +27,30 @@ impl Sleeper {
     }
 
     pub fn set_now(&mut self) {
-        self.0 = Instant::now();
+        self.last = self.clock.instant();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use common::time::MockClock;
+    use futures::poll;
+
+    #[tokio::test]
+    async fn sleeps_relative_to_last_wakeup() {
+        let clock = Arc::new(MockClock::new(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)));
+        let mut sleeper = Sleeper::new(clock.clone());
+        clock.advance(Duration::from_secs(3));
+        // More than a second has passed since the sleeper was created.
+        assert!(poll!(Box::pin(sleeper.sleep(Duration::from_secs(1)))).is_ready());
+        clock.advance(Duration::from_millis(400));
+        {
+            let mut sleep = Box::pin(sleeper.sleep(Duration::from_secs(1)));
+            assert!(poll!(&mut sleep).is_pending());
+            clock.advance(Duration::from_millis(600));
+            assert!(poll!(&mut sleep).is_ready());
+        }
     }
 }
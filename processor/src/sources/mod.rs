@@ -0,0 +1,250 @@
+//! The sites from which torrents are scraped
+//!
+//! All sources run the nyaa software and use the ids of nyaa.si, so they share the
+//! scraper in [nyaa]. They differ in their urls, the format in which their listing is
+//! read, and the key in `magnets.state` that holds the largest id that has been scraped
+//! from them.
+//!
+//! Torrents are identified by their nyaa.si id in `magnets.torrent`. Sites with their own
+//! ids, e.g. sukebei.nyaa.si, therefore cannot be sources.
+
+use crate::{config, config::ListingFormat};
+use anyhow::{anyhow, Result};
+use common::{pg::PgClient, Source};
+use std::time::SystemTime;
+use tokio_postgres::Transaction;
+
+pub mod nyaa;
+
+/// A site from which torrents are scraped
+pub trait TorrentSource: Send + Sync {
+    /// The name of the source in logs
+    fn name(&self) -> &str;
+
+    /// The source recorded in `magnets.torrent_source`
+    fn source(&self) -> Source;
+
+    /// The key in `magnets.state` that holds the largest id scraped from this source
+    fn max_id_key(&self) -> &str;
+
+    fn format(&self) -> ListingFormat;
+
+    /// Returns the url of a page of the listing. Pages start at 1. The RSS feed has a
+    /// single page.
+    fn page_url(&self, page: u32) -> String;
+}
+
+pub struct NyaaSi {
+    url: String,
+    format: ListingFormat,
+}
+
+impl TorrentSource for NyaaSi {
+    fn name(&self) -> &str {
+        "nyaa.si"
+    }
+
+    fn source(&self) -> Source {
+        Source::Nyaa
+    }
+
+    fn max_id_key(&self) -> &str {
+        crate::db_state::MAX_NYAA_SI_ID
+    }
+
+    fn format(&self) -> ListingFormat {
+        self.format
+    }
+
+    fn page_url(&self, page: u32) -> String {
+        // English-translated anime
+        listing_url(&self.url, "1_2", self.format, page)
+    }
+}
+
+/// A mirror of nyaa.si with the same ids
+///
+/// Mirrors are scraped like nyaa.si so that torrents are still ingested while nyaa.si is
+/// down.
+pub struct Mirror {
+    name: String,
+    url: String,
+    format: ListingFormat,
+    max_id_key: String,
+}
+
+impl TorrentSource for Mirror {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn source(&self) -> Source {
+        Source::Nyaa
+    }
+
+    fn max_id_key(&self) -> &str {
+        &self.max_id_key
+    }
+
+    fn format(&self) -> ListingFormat {
+        self.format
+    }
+
+    fn page_url(&self, page: u32) -> String {
+        listing_url(&self.url, "1_2", self.format, page)
+    }
+}
+
+fn listing_url(base: &str, category: &str, format: ListingFormat, page: u32) -> String {
+    match format {
+        ListingFormat::Html => format!("{}/?f=0&c={}&p={}", base, category, page),
+        ListingFormat::Rss => format!("{}/?page=rss&f=0&c={}", base, category),
+    }
+}
+
+/// Mirror names whose `max_<name>_id` key belongs to another source
+///
+/// `sukebei_nyaa_si` was scraped by earlier versions. Its key can still exist.
+const RESERVED_MIRROR_NAMES: &[&str] = &["nyaa_si", "sukebei_nyaa_si"];
+
+/// Returns the sources in the order in which they are scraped
+pub fn all(config: &config::Nyaa) -> Result<Vec<Box<dyn TorrentSource>>> {
+    let mut sources: Vec<Box<dyn TorrentSource>> = vec![Box::new(NyaaSi {
+        url: config.url.clone(),
+        format: config.format,
+    })];
+    for mirror in &config.mirrors {
+        let valid = !mirror.name.is_empty()
+            && mirror
+                .name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_');
+        if !valid {
+            return Err(anyhow!(
+                "the name of mirror {} must consist of lowercase letters, digits, and \
+                 underscores",
+                mirror.url
+            ));
+        }
+        let taken = RESERVED_MIRROR_NAMES.contains(&&*mirror.name)
+            || sources.iter().any(|s| s.name() == mirror.name);
+        if taken {
+            return Err(anyhow!(
+                "the name {} of mirror {} is already taken",
+                mirror.name,
+                mirror.url
+            ));
+        }
+        sources.push(Box::new(Mirror {
+            name: mirror.name.clone(),
+            url: mirror.url.clone(),
+            format: mirror.format,
+            max_id_key: format!("max_{}_id", mirror.name),
+        }));
+    }
+    Ok(sources)
+}
+
+/// Creates the `magnets.state` keys of sources that have not been scraped before
+pub async fn init(con: &PgClient, sources: &[Box<dyn TorrentSource>]) -> Result<()> {
+    for source in sources {
+        // language=sql
+        con.execute(
+            "
+            insert into magnets.state (key, value) values ($1, '0'::jsonb)
+            on conflict (key) do nothing",
+            &[&source.max_id_key()],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Returns whether a torrent of a source has already been ingested
+pub async fn exists(
+    tran: &Transaction<'_>,
+    source: Source,
+    source_id: i64,
+) -> Result<bool> {
+    // language=sql
+    let row = tran
+        .query_one(
+            "
+            select exists (
+                select *
+                from magnets.torrent_source
+                where source = $1 and source_id = $2
+            )",
+            &[&source.to_db(), &source_id],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Returns the id of the torrent with the same info hash if one exists
+///
+/// Sources that provide the same info hash provide the same torrent, so it must only be
+/// listed once. The sizes reported by the sources should then also agree. If they
+/// don't, one of the sources has misreported the torrent, which we log.
+pub async fn find_duplicate(
+    tran: &Transaction<'_>,
+    source: Source,
+    hash: &[u8],
+    size: i64,
+) -> Result<Option<i64>> {
+    // language=sql
+    let row = tran
+        .query_opt(
+            "select torrent_id, size from magnets.torrent where hash = $1 and hash_type = 1",
+            &[&hash],
+        )
+        .await?;
+    let row = match row {
+        Some(r) => r,
+        _ => return Ok(None),
+    };
+    let torrent_id: i64 = row.get("torrent_id");
+    let existing_size: i64 = row.get("size");
+    if existing_size != size {
+        log::warn!(
+            "{} reports size {} for torrent {} but it is known with size {}",
+            source.as_str(),
+            size,
+            torrent_id,
+            existing_size
+        );
+    }
+    Ok(Some(torrent_id))
+}
+
+/// Records that a source provides a torrent
+///
+/// `scrape_hash` is the hash of the fields scraped from the source. `uploaded_at` is the
+/// upload date reported by the source and is used to measure the ingestion lag.
+pub async fn insert(
+    tran: &Transaction<'_>,
+    torrent_id: i64,
+    source: Source,
+    source_id: i64,
+    size: i64,
+    scrape_hash: &[u8],
+    uploaded_at: SystemTime,
+) -> Result<()> {
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.torrent_source
+        (torrent_id, source, source_id, size, scrape_hash, uploaded_at)
+        values ($1, $2, $3, $4, $5, $6)",
+        &[
+            &torrent_id,
+            &source.to_db(),
+            &source_id,
+            &size,
+            &scrape_hash,
+            &uploaded_at,
+        ],
+    )
+    .await?;
+    Ok(())
+}
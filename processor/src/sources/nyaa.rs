@@ -0,0 +1,998 @@
+//! Scraping of the listings of sites that run the nyaa software
+
+use crate::{
+    config::ListingFormat, db_state, db_state::REMATCH_UNMATCHED, matcher::Overrides,
+    seasons, shadow, sleeper::Sleeper, sources, sources::TorrentSource, state::State,
+    title_analyzer, title_analyzer::Analyzer, webhooks,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use common::{
+    flags::Flag, pg, textnorm, AudioCodec, MediaInfo, Resolution, Script, Source,
+    VideoCodec,
+};
+use futures::future::select_all;
+use rss::{Channel, Item};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use scraper::{ElementRef, Html, Selector};
+use selectors::Element;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, ops::Not, time, time::SystemTime};
+use time::Duration;
+use tokio::time::timeout;
+use tokio_postgres::{GenericClient, Transaction};
+use url::Url;
+
+type LocalName = html5ever::LocalName;
+
+lazy_static::lazy_static! {
+    static ref ROWS: Selector = Selector::parse(".torrent-list > tbody > tr").unwrap();
+    static ref TITLE_LINK: Selector = Selector::parse("td:nth-child(2) > a:not(.comments)").unwrap();
+    static ref MAGNET_LINK: Selector = Selector::parse("a > i.fa-magnet").unwrap();
+    static ref SIZE_FIELD: Selector = Selector::parse("td:nth-child(4)").unwrap();
+    static ref TIMESTAMP_FIELD: Selector = Selector::parse("td:nth-child(5)").unwrap();
+    static ref SEEDERS_FIELD: Selector = Selector::parse("td:nth-child(6)").unwrap();
+    static ref LEECHERS_FIELD: Selector = Selector::parse("td:nth-child(7)").unwrap();
+    static ref COMPLETED_FIELD: Selector = Selector::parse("td:nth-child(8)").unwrap();
+}
+
+fn get_unique_element<'a>(
+    e: &ElementRef<'a>,
+    selector: &Selector,
+) -> Result<ElementRef<'a>> {
+    let mut iter = e.select(selector);
+    let first = match iter.next() {
+        Some(f) => f,
+        _ => return Err(anyhow!("selector matches no element")),
+    };
+    if iter.next().is_some() {
+        return Err(anyhow!("selector matches multiple elements"));
+    }
+    Ok(first)
+}
+
+/// Scrapes the sources one after another
+///
+/// A source that is down does not prevent the others from being scraped. A source whose
+/// `max_id_key` is lowered by hand is scraped again right away.
+pub async fn load_torrents(state: &State<'_>, sources: &[Box<dyn TorrentSource>]) {
+    while let Err(e) = init(state, sources).await {
+        log::error!("could not create the states of the sources: {:#}", e);
+        state.sleep(|c| c.nyaa.scrape_interval).await;
+    }
+    let notifiers: Vec<_> = sources
+        .iter()
+        .map(|s| state.db_watcher.source(s.max_id_key()))
+        .collect();
+    let mut due: Vec<_> = sources.iter().collect();
+    loop {
+        for source in due {
+            log::info!("scraping {}", source.name());
+            match load_torrents_(state, &**source).await {
+                Ok(()) => state.metrics.scrape_succeeded(),
+                Err(e) => {
+                    log::error!("could not load torrents from {}: {:#}", source.name(), e)
+                }
+            }
+        }
+        let interval = state.live_config.current().nyaa.scrape_interval;
+        let changed = select_all(notifiers.iter().map(|n| Box::pin(n.notified())));
+        due = match timeout(interval, changed).await {
+            Ok((_, idx, _)) => vec![&sources[idx]],
+            _ => sources.iter().collect(),
+        };
+    }
+}
+
+async fn init(state: &State<'_>, sources: &[Box<dyn TorrentSource>]) -> Result<()> {
+    let con = state.pg.borrow().await?;
+    sources::init(&con, sources).await
+}
+
+async fn load_torrents_(state: &State<'_>, source: &dyn TorrentSource) -> Result<()> {
+    let con = state.pg.borrow().await?;
+    let max_id: i64 = db_state::get(&**con, source.max_id_key()).await?;
+    let pages = match source.format() {
+        ListingFormat::Html => 100,
+        ListingFormat::Rss => 1,
+    };
+    let mut torrents = vec![];
+    let mut saw_existing = false;
+    let mut sleeper = Sleeper::new(state.clock.clone());
+    for i in 1..=pages {
+        if i > 1 {
+            log::info!("loading page {}", i);
+        }
+        let mut new = vec![];
+        scrape_page(state, source, &mut new, i).await?;
+        saw_existing = new.iter().any(|t| t.source_id.saturating_add(74) <= max_id);
+        torrents.extend(new);
+        if saw_existing {
+            break;
+        }
+        sleeper.sleep(Duration::from_secs(1)).await;
+    }
+    if !saw_existing && max_id > 0 && source.format() == ListingFormat::Rss {
+        log::warn!(
+            "the feed of {} does not reach back to id {}. torrents in between are \
+             missing until the html listing is scraped.",
+            source.name(),
+            max_id
+        );
+    }
+    let newest = torrents.iter().map(|t| t.source_id).max();
+    state.known_nyaa_ids.load(&con).await?;
+    let (existing, mut torrents): (Vec<_>, Vec<_>) = torrents
+        .into_iter()
+        .partition(|t| state.known_nyaa_ids.contains(t.source_id));
+    let edited = find_edited(&**con, source.source(), &existing).await?;
+    state.leader.ensure().await?;
+    update_swarms(&**con, &existing).await?;
+    if torrents.is_empty() && edited.is_empty() {
+        // mirrors usually only see torrents that have been ingested from nyaa.si
+        if let Some(newest) = newest.filter(|&n| n > max_id) {
+            db_state::set(&**con, source.max_id_key(), newest).await?;
+        }
+        return Ok(());
+    }
+    // fetch show_db before opening the transaction so that all shows in the db are
+    // visible to the transaction
+    let show_db = state.show_db.get().await?;
+    let mut con = state.pg_connector.connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    torrents.sort_by_key(|t| t.source_id);
+    for torrent in &mut torrents {
+        insert_torrent(&tran, source, torrent).await?;
+    }
+    update_edited(&tran, source.source(), &edited).await?;
+    // the new_analyzer flag promotes the candidate so that the default analyzer runs
+    // in its shadow
+    let (analyzer, shadow_analyzer) = match state.config.nyaa.shadow_analyzer {
+        Some(c) if state.flags.enabled(Flag::NewAnalyzer) => (c, Some(Analyzer::Default)),
+        c => (Analyzer::Default, c),
+    };
+    let overrides = Overrides::load_patterns(&tran).await?;
+    let mut disagreements = vec![];
+    let mut matched = vec![];
+    for torrent in &torrents {
+        if let Some(torrent_id) = torrent.torrent_id {
+            if let Some(show_id) = overrides.pattern_show(&torrent.title) {
+                insert_new_match(&tran, torrent_id, show_id, &torrent.title).await?;
+                matched.push(show_id);
+                continue;
+            }
+            let show = match analyzer.find_new_show(&show_db, &torrent.title) {
+                Ok(s) => {
+                    insert_new_match(&tran, torrent_id, s.show_id, &torrent.title)
+                        .await?;
+                    matched.push(s.show_id);
+                    Some(s)
+                }
+                Err(e) => {
+                    log::error!("could not match torrent {}: {:#}", torrent.title, e);
+                    None
+                }
+            };
+            if let Some(candidate) = shadow_analyzer {
+                disagreements.extend(shadow::compare(
+                    candidate,
+                    &show_db,
+                    torrent_id,
+                    &torrent.title,
+                    show,
+                ));
+            }
+        }
+    }
+    if let Some(newest) = newest.filter(|&n| n > max_id) {
+        db_state::set(&tran, source.max_id_key(), newest).await?;
+    }
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    state
+        .known_nyaa_ids
+        .insert(torrents.iter().map(|t| t.source_id));
+    if let Some(candidate) = shadow_analyzer {
+        if let Err(e) =
+            shadow::record(&con, &state.leader, candidate, &disagreements).await
+        {
+            log::error!("could not record analyzer disagreements: {:#}", e);
+        }
+    }
+    if !matched.is_empty() {
+        seasons::infer_seasons(&con, &state.leader, Some(&matched)).await?;
+    }
+    Ok(())
+}
+
+async fn scrape_page(
+    state: &State<'_>,
+    source: &dyn TorrentSource,
+    torrents: &mut Vec<Torrent>,
+    page_no: u32,
+) -> Result<()> {
+    let url = source.page_url(page_no);
+    let response = state
+        .web_client
+        .get(&url)
+        .send()
+        .await
+        .with_context(|| format!("cannot communicate with {}", source.name()))?;
+    if response.status().as_u16() != 200 {
+        return Err(anyhow!(
+            "{} status code is {}",
+            source.name(),
+            response.status()
+        ));
+    }
+    // if let Some(cache) = response.headers().get("x-proxy-cache") {
+    //     log::info!("x-proxy-cache: {:?}", cache);
+    // }
+    // if let Some(cache) = response.headers().get("date") {
+    //     log::info!("date: {:?}", cache);
+    // }
+    let content = response
+        .text()
+        .await
+        .with_context(|| format!("cannot read {} response", source.name()))?;
+    match source.format() {
+        ListingFormat::Html => {
+            let html = Html::parse_document(&content);
+            for (i, torrent) in html.select(&ROWS).enumerate() {
+                let torrent = parse_row(&torrent).with_context(|| {
+                    format!("cannot parse torrent number {} on {}", i + 1, url)
+                })?;
+                torrents.push(torrent);
+            }
+        }
+        ListingFormat::Rss => {
+            let parsed =
+                parse_feed(&content).with_context(|| format!("cannot parse {}", url))?;
+            torrents.extend(parsed);
+        }
+    }
+    Ok(())
+}
+
+/// Matches a newly ingested torrent and queues the webhook notifications
+///
+/// Rematches do not notify the webhooks since their torrents are not new.
+async fn insert_new_match(
+    tran: &Transaction<'_>,
+    torrent_id: i64,
+    show_id: i64,
+    title: &str,
+) -> Result<()> {
+    if crate::matcher::insert_match(tran, torrent_id, show_id, title).await? {
+        webhooks::queue(tran, torrent_id, show_id).await?;
+    }
+    Ok(())
+}
+
+async fn insert_torrent(
+    tran: &Transaction<'_>,
+    source: &dyn TorrentSource,
+    torrent: &mut Torrent,
+) -> Result<()> {
+    if sources::exists(tran, source.source(), torrent.source_id).await? {
+        return Ok(());
+    }
+    let duplicate =
+        sources::find_duplicate(tran, source.source(), &torrent.hash, torrent.size)
+            .await?;
+    if let Some(torrent_id) = duplicate {
+        // The torrent has already been matched when it was first ingested
+        log::info!(
+            "torrent {} is a duplicate of torrent {}",
+            torrent.title,
+            torrent_id
+        );
+        sources::insert(
+            tran,
+            torrent_id,
+            source.source(),
+            torrent.source_id,
+            torrent.size,
+            &torrent.scrape_hash(),
+            torrent.timestamp,
+        )
+        .await?;
+        return Ok(());
+    }
+    log::info!("inserting new torrent {}", torrent.title);
+    let media = MediaInfo::detect(&torrent.title);
+    let release_group_id = release_group_id(tran, &torrent.title).await?;
+    // language=sql
+    let row = tran
+        .query_one(
+            "
+                insert into magnets.torrent
+                (nyaa_id, hash, hash_type, uploaded_at, title, size, trusted,
+                 trusted_checked, script, seeders, leechers, completed, swarm_updated,
+                 resolution, video_codec, audio_codec, dual_audio, release_group_id)
+                values ($1, $2, $3, $4, $5, $6, $7, now(), $8, $9, $10, $11,
+                        case when $9::int is null then null else now() end,
+                        $12, $13, $14, $15, $16)
+                returning torrent_id",
+            &[
+                &torrent.source_id,
+                &torrent.hash,
+                &1i32,
+                &torrent.timestamp,
+                &torrent.title,
+                &torrent.size,
+                &torrent.trusted,
+                &Script::detect(&torrent.title).map(Script::to_db),
+                &torrent.swarm.map(|s| s.seeders),
+                &torrent.swarm.map(|s| s.leechers),
+                &torrent.swarm.map(|s| s.completed),
+                &media.resolution.map(Resolution::to_db),
+                &media.video_codec.map(VideoCodec::to_db),
+                &media.audio_codec.map(AudioCodec::to_db),
+                &media.dual_audio,
+                &release_group_id,
+            ],
+        )
+        .await?;
+    let torrent_id = row.get(0);
+    sources::insert(
+        tran,
+        torrent_id,
+        source.source(),
+        torrent.source_id,
+        torrent.size,
+        &torrent.scrape_hash(),
+        torrent.timestamp,
+    )
+    .await?;
+    if let Some(displayed) = &torrent.suspicious_size {
+        // The size is taken from the detail page by `sizes::reparse_sizes`
+        // language=sql
+        tran.execute(
+            "
+            insert into magnets.size_reparse (torrent_id, displayed_size)
+            values ($1, $2)
+            on conflict do nothing",
+            &[&torrent_id, displayed],
+        )
+        .await?;
+    }
+    torrent.torrent_id = Some(torrent_id);
+    Ok(())
+}
+
+/// A torrent whose row on a source has changed since it was last scraped
+struct Edited<'a> {
+    torrent: &'a Torrent,
+    torrent_id: i64,
+    /// The title and trusted status of the torrent if `torrent` is its primary source,
+    /// i.e. they were taken from `torrent` when it was inserted
+    primary: Option<(String, bool)>,
+}
+
+/// Returns the id of the release group of a title and creates the group if necessary
+async fn release_group_id(tran: &Transaction<'_>, title: &str) -> Result<Option<i64>> {
+    let name = match title_analyzer::find_release_group(title) {
+        Some(n) => n,
+        _ => return Ok(None),
+    };
+    // language=sql
+    let row = tran
+        .query_one(
+            "
+            with inserted as (
+                insert into magnets.release_group (name) values ($1)
+                on conflict (name) do nothing
+                returning release_group_id
+            )
+            select release_group_id from inserted
+            union all
+            select release_group_id from magnets.release_group where name = $1",
+            &[&name],
+        )
+        .await?;
+    Ok(Some(row.get(0)))
+}
+
+/// Returns the torrents whose scraped rows differ from the rows that were stored
+async fn find_edited<'a>(
+    con: &impl GenericClient,
+    source: Source,
+    torrents: &'a [Torrent],
+) -> Result<Vec<Edited<'a>>> {
+    let ids: Vec<_> = torrents.iter().map(|t| t.source_id).collect();
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select
+                ts.source_id,
+                ts.torrent_id,
+                ts.scrape_hash,
+                (ts.source = $3 and t.nyaa_id = ts.source_id) as primary,
+                t.title,
+                t.trusted
+            from magnets.torrent_source ts
+            join magnets.torrent t using (torrent_id)
+            where ts.source = $1 and ts.source_id = any($2)",
+            &[&source.to_db(), &ids, &Source::Nyaa.to_db()],
+        )
+        .await?;
+    let torrents: HashMap<_, _> = torrents.iter().map(|t| (t.source_id, t)).collect();
+    let mut res = vec![];
+    for row in rows {
+        let torrent = torrents[&row.get::<_, i64>("source_id")];
+        let scrape_hash: Option<Vec<u8>> = row.get("scrape_hash");
+        if scrape_hash.as_deref() != Some(&torrent.scrape_hash()) {
+            res.push(Edited {
+                torrent,
+                torrent_id: row.get("torrent_id"),
+                primary: match row.get("primary") {
+                    true => Some((row.get("title"), row.get("trusted"))),
+                    false => None,
+                },
+            });
+        }
+    }
+    Ok(res)
+}
+
+/// Stores the new scrape hashes of edited torrents
+///
+/// If the title of a torrent has changed, the title is updated and the torrent is
+/// queued for rematching. If the uploader has gained or lost the trusted status, the
+/// trusted flag is updated. Rows scraped before scrape hashes were introduced have no
+/// hash and only get one.
+async fn update_edited(
+    tran: &Transaction<'_>,
+    source: Source,
+    edited: &[Edited<'_>],
+) -> Result<()> {
+    let mut renamed = vec![];
+    for edited in edited {
+        let torrent = edited.torrent;
+        // language=sql
+        tran.execute(
+            "
+            update magnets.torrent_source
+            set scrape_hash = $3
+            where source = $1 and source_id = $2",
+            &[&source.to_db(), &torrent.source_id, &torrent.scrape_hash()],
+        )
+        .await?;
+        let (title, trusted) = match &edited.primary {
+            Some(p) => p,
+            _ => continue,
+        };
+        if *trusted != torrent.trusted {
+            log::info!(
+                "trusted status of torrent {} changed to {}",
+                edited.torrent_id,
+                torrent.trusted
+            );
+            // language=sql
+            tran.execute(
+                "
+                update magnets.torrent
+                set trusted = $2, trusted_checked = now()
+                where torrent_id = $1",
+                &[&edited.torrent_id, &torrent.trusted],
+            )
+            .await?;
+        }
+        if *title == torrent.title {
+            continue;
+        }
+        log::info!(
+            "torrent {} has been renamed to {}",
+            edited.torrent_id,
+            torrent.title
+        );
+        let media = MediaInfo::detect(&torrent.title);
+        let release_group_id = release_group_id(tran, &torrent.title).await?;
+        // language=sql
+        tran.execute(
+            "
+            update magnets.torrent
+            set title = $2, script = $3, matched = false, batch = false, search_title = null,
+                resolution = $4, video_codec = $5, audio_codec = $6, dual_audio = $7,
+                release_group_id = $8
+            where torrent_id = $1",
+            &[
+                &edited.torrent_id,
+                &torrent.title,
+                &Script::detect(&torrent.title).map(Script::to_db),
+                &media.resolution.map(Resolution::to_db),
+                &media.video_codec.map(VideoCodec::to_db),
+                &media.audio_codec.map(AudioCodec::to_db),
+                &media.dual_audio,
+                &release_group_id,
+            ],
+        )
+        .await?;
+        renamed.push(edited.torrent_id);
+    }
+    if renamed.is_empty() {
+        return Ok(());
+    }
+    // language=sql
+    tran.execute(
+        "delete from magnets.rel_torrent_show where torrent_id = any($1)",
+        &[&renamed],
+    )
+    .await?;
+    // Queue the renamed torrents for rematching unless a rematch is already pending
+    // language=sql
+    tran.execute(
+        "update magnets.state set value = '1' where key = $1 and value = '0'",
+        &[&REMATCH_UNMATCHED],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Stores the swarms of known torrents
+///
+/// The swarm changes all the time, so it is not part of the scrape hash and updated
+/// whenever a torrent is seen on a listing.
+async fn update_swarms(con: &impl GenericClient, torrents: &[Torrent]) -> Result<()> {
+    let mut ids = vec![];
+    let mut seeders = vec![];
+    let mut leechers = vec![];
+    let mut completed = vec![];
+    for torrent in torrents {
+        if let Some(swarm) = torrent.swarm {
+            ids.push(torrent.source_id);
+            seeders.push(swarm.seeders);
+            leechers.push(swarm.leechers);
+            completed.push(swarm.completed);
+        }
+    }
+    if ids.is_empty() {
+        return Ok(());
+    }
+    // language=sql
+    con.execute(
+        "
+        update magnets.torrent t
+        set seeders = s.seeders,
+            leechers = s.leechers,
+            completed = s.completed,
+            swarm_updated = now()
+        from unnest($1::bigint[], $2::int[], $3::int[], $4::int[])
+            as s(nyaa_id, seeders, leechers, completed)
+        where t.nyaa_id = s.nyaa_id",
+        &[&ids, &seeders, &leechers, &completed],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Refreshes the swarms of the torrents on the first pages of the listing
+///
+/// Overlap scraping only sees the first page once no new torrents are uploaded. The
+/// swarms of recent torrents change the most, so the following pages are scraped
+/// periodically. Mirrors are used if nyaa.si is down. Sources that are read via RSS
+/// cannot be paged and are skipped.
+pub async fn refresh_swarms(state: &State<'_>, sources: &[Box<dyn TorrentSource>]) {
+    if state.config.nyaa.swarm_refresh_pages < 2 {
+        return;
+    }
+    loop {
+        state.sleep(|c| c.nyaa.swarm_refresh_interval).await;
+        let sources = sources.iter().filter(|s| s.format() == ListingFormat::Html);
+        for source in sources {
+            match refresh_swarms_(state, &**source).await {
+                Ok(()) => break,
+                Err(e) => log::error!(
+                    "could not refresh the swarms from {}: {:#}",
+                    source.name(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+async fn refresh_swarms_(state: &State<'_>, source: &dyn TorrentSource) -> Result<()> {
+    let mut sleeper = Sleeper::new(state.clock.clone());
+    for i in 2..=state.config.nyaa.swarm_refresh_pages {
+        sleeper.sleep(Duration::from_secs(1)).await;
+        let mut torrents = vec![];
+        scrape_page(state, source, &mut torrents, i).await?;
+        let con = state.pg.borrow().await?;
+        state.leader.ensure().await?;
+        update_swarms(&**con, &torrents).await?;
+    }
+    log::info!(
+        "refreshed the swarms of the first {} pages of {}",
+        state.config.nyaa.swarm_refresh_pages,
+        source.name()
+    );
+    Ok(())
+}
+
+/// The peers of a torrent as displayed on a listing
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Swarm {
+    seeders: i32,
+    leechers: i32,
+    /// The number of completed downloads
+    completed: i32,
+}
+
+#[derive(Debug)]
+struct Torrent {
+    torrent_id: Option<i64>,
+    title: String,
+    hash: Vec<u8>,
+    /// The id of the torrent at the source
+    source_id: i64,
+    trusted: bool,
+    size: i64,
+    /// The size as displayed on nyaa if it could not be parsed or is implausible. `size`
+    /// is 0 in this case.
+    suspicious_size: Option<String>,
+    timestamp: SystemTime,
+    /// `None` if the listing does not contain the swarm
+    swarm: Option<Swarm>,
+}
+
+impl Torrent {
+    /// Returns the hash of the scraped fields
+    fn scrape_hash(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(&(self.title.len() as u64).to_le_bytes());
+        hasher.update(self.title.as_bytes());
+        hasher.update(&(self.hash.len() as u64).to_le_bytes());
+        hasher.update(&self.hash);
+        hasher.update(&[self.trusted as u8]);
+        hasher.update(&self.size.to_le_bytes());
+        let timestamp = self
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        hasher.update(&timestamp.to_le_bytes());
+        hasher.finalize().to_vec()
+    }
+}
+
+fn parse_row(torrent: &ElementRef) -> Result<Torrent> {
+    let title_link =
+        get_unique_element(&torrent, &TITLE_LINK).context("cannot extract title link")?;
+
+    let trusted = torrent
+        .value()
+        .classes
+        .contains(&LocalName::from("success"));
+
+    let title = textnorm::storage(&title_link.text().collect::<String>());
+
+    let source_id = {
+        const URL_PREFIX: &str = "/view/";
+        let nyaa_url = title_link
+            .value()
+            .attr("href")
+            .context("title link does not contain a href attribute")?
+            .to_owned();
+        if nyaa_url.starts_with(URL_PREFIX).not() {
+            return Err(anyhow!(
+                "nyaa link does not start with prefix: {}",
+                nyaa_url
+            ));
+        }
+        nyaa_url[URL_PREFIX.len()..]
+            .parse()
+            .with_context(|| format!("nyaa id is out of bounds: {}", nyaa_url))?
+    };
+
+    let hash = {
+        const TOPIC_PREFIX: &str = "urn:btih:";
+        let magnet_link = get_unique_element(&torrent, &MAGNET_LINK)
+            .context("cannot extract magnet link")?;
+        let magnet_link = magnet_link
+            .parent_element()
+            .unwrap()
+            .value()
+            .attr("href")
+            .context("magnet link does not contain a href attribute")?;
+        let url = Url::parse(magnet_link).with_context(|| {
+            format!("magnet link is not a valid url: {}", magnet_link)
+        })?;
+        let topic = url
+            .query_pairs()
+            .find(|e| e.0 == "xt")
+            .map(|e| e.1)
+            .with_context(|| {
+                format!(
+                    "magnet link does not contain an xt parameter: {}",
+                    magnet_link
+                )
+            })?;
+        if topic.starts_with(TOPIC_PREFIX).not() {
+            return Err(anyhow!(
+                "topic does not start with bittorent prefix: {}",
+                topic
+            ));
+        }
+        let hash = &topic[TOPIC_PREFIX.len()..];
+        hex::decode(hash).with_context(|| format!("hash is not hex: {}", hash))?
+    };
+
+    let (size, suspicious_size) = {
+        let size = get_unique_element(&torrent, &SIZE_FIELD)
+            .context("cannot extract size field")?;
+        let size: String = size.text().collect();
+        listed_size(source_id, &size)
+    };
+
+    let timestamp = {
+        let timestamp = get_unique_element(&torrent, &TIMESTAMP_FIELD)
+            .context("cannot extract timestamp field")?;
+        let timestamp = timestamp
+            .value()
+            .attr("data-timestamp")
+            .context("timestamp field does not contain a data-timestamp attribute")?;
+        let timestamp: u64 = timestamp
+            .parse()
+            .with_context(|| anyhow!("timestamp is invalid: {}", timestamp))?;
+        SystemTime::UNIX_EPOCH
+            .checked_add(Duration::from_secs(timestamp))
+            .context("timestamp is out of bounds")?
+    };
+
+    let swarm = {
+        let field = |selector: &Selector| {
+            get_unique_element(&torrent, selector)
+                .ok()
+                .and_then(|f| f.text().collect::<String>().trim().parse().ok())
+        };
+        match (
+            field(&SEEDERS_FIELD),
+            field(&LEECHERS_FIELD),
+            field(&COMPLETED_FIELD),
+        ) {
+            (Some(seeders), Some(leechers), Some(completed)) => Some(Swarm {
+                seeders,
+                leechers,
+                completed,
+            }),
+            _ => None,
+        }
+    };
+
+    Ok(Torrent {
+        title,
+        hash,
+        source_id,
+        trusted,
+        size,
+        suspicious_size,
+        timestamp,
+        swarm,
+        torrent_id: None,
+    })
+}
+
+/// Parses the items of an RSS feed
+///
+/// The feed contains the same fields as the HTML table, so the torrents have the same
+/// scrape hashes no matter how they were read.
+fn parse_feed(content: &str) -> Result<Vec<Torrent>> {
+    let channel = Channel::read_from(content.as_bytes()).context("invalid feed")?;
+    let mut torrents = vec![];
+    for (i, item) in channel.items().iter().enumerate() {
+        let torrent = parse_item(item)
+            .with_context(|| format!("cannot parse item number {}", i + 1))?;
+        torrents.push(torrent);
+    }
+    Ok(torrents)
+}
+
+fn parse_item(item: &Item) -> Result<Torrent> {
+    let nyaa = |name: &str| {
+        item.extensions()
+            .get("nyaa")
+            .and_then(|e| e.get(name))
+            .and_then(|e| e.first())
+            .and_then(|e| e.value())
+            .with_context(|| format!("item does not contain nyaa:{}", name))
+    };
+
+    let title = textnorm::storage(item.title().context("item does not contain a title")?);
+
+    let source_id = {
+        const URL_PREFIX: &str = "/view/";
+        let guid = item.guid().context("item does not contain a guid")?.value();
+        let pos = guid
+            .rfind(URL_PREFIX)
+            .with_context(|| format!("guid does not contain a view link: {}", guid))?;
+        guid[pos + URL_PREFIX.len()..]
+            .parse()
+            .with_context(|| format!("nyaa id is out of bounds: {}", guid))?
+    };
+
+    let hash = {
+        let hash = nyaa("infoHash")?;
+        hex::decode(hash).with_context(|| format!("hash is not hex: {}", hash))?
+    };
+
+    let trusted = nyaa("trusted")? == "Yes";
+
+    let (size, suspicious_size) = listed_size(source_id, nyaa("size")?);
+
+    let timestamp = {
+        let date = item.pub_date().context("item does not contain a pubDate")?;
+        let date = DateTime::parse_from_rfc2822(date)
+            .with_context(|| format!("pubDate is invalid: {}", date))?;
+        SystemTime::from(date)
+    };
+
+    let swarm = {
+        let field = |name: &str| nyaa(name).ok().and_then(|v| v.trim().parse().ok());
+        match (field("seeders"), field("leechers"), field("downloads")) {
+            (Some(seeders), Some(leechers), Some(completed)) => Some(Swarm {
+                seeders,
+                leechers,
+                completed,
+            }),
+            _ => None,
+        }
+    };
+
+    Ok(Torrent {
+        title,
+        hash,
+        source_id,
+        trusted,
+        size,
+        suspicious_size,
+        timestamp,
+        swarm,
+        torrent_id: None,
+    })
+}
+
+/// Parses the size of a torrent in a listing
+///
+/// Returns size 0 and the displayed size if the size is suspicious.
+fn listed_size(source_id: i64, displayed: &str) -> (i64, Option<String>) {
+    match parse_size(displayed) {
+        Ok(s) => (s, None),
+        Err(e) => {
+            log::warn!("torrent {} has a suspicious size: {:#}", source_id, e);
+            (0, Some(displayed.trim().to_string()))
+        }
+    }
+}
+
+/// Sizes above this are rejected as implausible
+const MAX_SIZE: i64 = 10 * 1024 * 1024 * 1024 * 1024;
+
+/// Parses a size as displayed by nyaa, e.g. `1.4 GiB`
+///
+/// Sizes that are not positive or larger than 10 TiB are rejected.
+pub fn parse_size(s: &str) -> Result<i64> {
+    let (num, unit) = s.split_at(
+        s.find(' ')
+            .with_context(|| format!("missing unit: {}", s))?,
+    );
+    let num: Decimal = num.trim().parse()?;
+    let multiplier: i64 = match &*unit.trim().to_ascii_lowercase() {
+        "" | "b" | "bytes" => 1,
+        "ki" | "kib" => 1024,
+        "mi" | "mib" => 1024 * 1024,
+        "gi" | "gib" => 1024 * 1024 * 1024,
+        "ti" | "tib" => 1024 * 1024 * 1024 * 1024,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        _ => return Err(anyhow!("invalid unit: {}", s)),
+    };
+    let num = num * Decimal::from(multiplier);
+    let size = num
+        .to_i64()
+        .with_context(|| format!("out of bounds: {}", s))?;
+    if size <= 0 || size > MAX_SIZE {
+        return Err(anyhow!("implausible size: {}", s));
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1.4 GiB").unwrap(), 1503238553);
+        assert_eq!(parse_size("512 Bytes").unwrap(), 512);
+        assert_eq!(parse_size("700 MB").unwrap(), 700_000_000);
+        assert_eq!(parse_size("1 TiB").unwrap(), 1024 * 1024 * 1024 * 1024);
+        assert!(parse_size("1.4").is_err());
+        assert!(parse_size("1.4 XiB").is_err());
+        assert!(parse_size("0 B").is_err());
+        assert!(parse_size("-1 GiB").is_err());
+        assert!(parse_size("11 TiB").is_err());
+    }
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss xmlns:atom="http://www.w3.org/2005/Atom" xmlns:nyaa="https://nyaa.si/xmlns/nyaa" version="2.0">
+  <channel>
+    <title>Nyaa - Home - Torrent File RSS</title>
+    <description>RSS Feed for Home</description>
+    <link>https://nyaa.si/</link>
+    <item>
+      <title>[SubsPlease] Sousou no Frieren - 05 (1080p) [8E3F2A1B].mkv</title>
+      <link>https://nyaa.si/download/1202.torrent</link>
+      <guid isPermaLink="true">https://nyaa.si/view/1202</guid>
+      <pubDate>Fri, 20 Oct 2023 16:01:02 -0000</pubDate>
+      <nyaa:seeders>1204</nyaa:seeders>
+      <nyaa:leechers>87</nyaa:leechers>
+      <nyaa:downloads>5321</nyaa:downloads>
+      <nyaa:infoHash>0123456789abcdef0123456789abcdef01234567</nyaa:infoHash>
+      <nyaa:categoryId>1_2</nyaa:categoryId>
+      <nyaa:size>1.4 GiB</nyaa:size>
+      <nyaa:trusted>Yes</nyaa:trusted>
+      <nyaa:remake>No</nyaa:remake>
+    </item>
+    <item>
+      <title>Unknown Show - 01</title>
+      <link>https://nyaa.si/download/1200.torrent</link>
+      <guid isPermaLink="true">https://nyaa.si/view/1200</guid>
+      <pubDate>Fri, 20 Oct 2023 15:00:00 -0000</pubDate>
+      <nyaa:infoHash>89abcdef0123456789abcdef0123456789abcdef</nyaa:infoHash>
+      <nyaa:size>0 Bytes</nyaa:size>
+      <nyaa:trusted>No</nyaa:trusted>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn parses_feed() {
+        let torrents = parse_feed(FEED).unwrap();
+        assert_eq!(torrents.len(), 2);
+        let t = &torrents[0];
+        assert_eq!(
+            t.title,
+            "[SubsPlease] Sousou no Frieren - 05 (1080p) [8E3F2A1B].mkv"
+        );
+        assert_eq!(t.source_id, 1202);
+        assert_eq!(
+            hex::encode(&t.hash),
+            "0123456789abcdef0123456789abcdef01234567"
+        );
+        assert!(t.trusted);
+        assert_eq!(t.size, 1_503_238_553);
+        assert_eq!(t.suspicious_size, None);
+        assert_eq!(
+            t.timestamp,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_697_817_662)
+        );
+        assert_eq!(
+            t.swarm,
+            Some(Swarm {
+                seeders: 1204,
+                leechers: 87,
+                completed: 5321,
+            })
+        );
+        let t = &torrents[1];
+        assert_eq!(t.source_id, 1200);
+        assert!(!t.trusted);
+        assert_eq!(t.size, 0);
+        assert_eq!(t.suspicious_size.as_deref(), Some("0 Bytes"));
+        assert_eq!(t.swarm, None);
+    }
+
+    #[test]
+    fn rejects_items_without_hash() {
+        let feed = FEED.replace(
+            "<nyaa:infoHash>0123456789abcdef0123456789abcdef01234567</nyaa:infoHash>",
+            "",
+        );
+        assert!(parse_feed(&feed).is_err());
+    }
+}
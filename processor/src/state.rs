@@ -1,10 +1,24 @@
 use crate::{
-    anilist::client::AnilistClient,
     config::Config,
     db_state::{DbWatcher, WatchMessageHandler},
+    http::HttpCache,
+    known_ids::KnownIds,
+    leader::Leader,
+    memory::Memory,
+    metadata::client::AnilistClient,
+    metrics::Metrics,
     show_db::ShowDbHolder,
 };
-use common::pg::{Dummy, PgConnector, PgHolder};
+use common::{
+    config::LiveConfig,
+    flags::Flags,
+    pg::{Dummy, PgConnector, PgHolder},
+    time::{Clock, StdDuration},
+};
+use futures::{
+    future::{select, Either},
+    pin_mut,
+};
 use std::sync::Arc;
 use tokio::time::Instant;
 
@@ -12,9 +26,40 @@ pub struct State<'a> {
     pub pg: Arc<PgHolder<Dummy, WatchMessageHandler>>,
     pub show_db: ShowDbHolder,
     pub web_client: &'a reqwest::Client,
+    pub http_cache: HttpCache,
+    pub known_nyaa_ids: KnownIds,
     pub anilist_client: AnilistClient<'a>,
     pub db_watcher: Arc<DbWatcher>,
     pub startup_time: Instant,
     pub pg_connector: PgConnector,
+    /// The config as it was loaded at startup
+    ///
+    /// Settings that are applied on SIGHUP must be read from `live_config` instead.
     pub config: &'a Config,
+    pub live_config: LiveConfig<Config>,
+    pub flags: Flags,
+    pub metrics: Metrics,
+    pub leader: Leader,
+    pub memory: Memory,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl State<'_> {
+    /// Sleeps for an interval that is selected from the live config
+    ///
+    /// If the config is reloaded in the meantime, the sleep ends at the new interval
+    /// after its start, or immediately if that has already passed.
+    pub async fn sleep(&self, interval: fn(&Config) -> StdDuration) {
+        let start = self.clock.instant();
+        loop {
+            let generation = self.live_config.generation();
+            let end = start + interval(&self.live_config.current());
+            let reloaded = self.live_config.reloaded(generation);
+            let sleep = self.clock.sleep_until(end);
+            pin_mut!(reloaded, sleep);
+            if let Either::Right(_) = select(reloaded, sleep).await {
+                return;
+            }
+        }
+    }
 }
@@ -0,0 +1,130 @@
+use crate::{config::Config, db_state};
+use anyhow::{anyhow, Context, Result};
+use common::{
+    pg,
+    pg::{PgClient, PgConnector},
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    fs,
+    future::Future,
+    io,
+    io::{Read, Write},
+};
+use tokio_postgres::types::Json;
+
+const USAGE: &str =
+    "usage: processor state <dump|load> [file] | processor state blob <key>";
+
+/// Saves or restores `magnets.state`
+///
+/// Invoked as
+///
+/// - `processor state dump [file]` to write all keys as a json object to the file or
+///   stdout.
+/// - `processor state load [file]` to restore the keys from a dump read from the file
+///   or stdin.
+///
+/// - `processor state blob <key>` to write the decompressed value of a key of
+///   `magnets.state_blob`, e.g. `show_list`, to stdout. Blobs are not part of dumps since
+///   the processor recreates them.
+///
+/// Loading only updates keys that already exist. Keys that are missing from the dump
+/// are left unchanged. The processor reacts to the changes like it does to changes
+/// made by hand, e.g. restoring `max_nyaa_si_id` makes the scraper continue from the
+/// restored id.
+pub fn state(args: &[String]) -> Result<()> {
+    match args {
+        [command] if command == "dump" => run(dump(None)),
+        [command, file] if command == "dump" => run(dump(Some(file))),
+        [command] if command == "load" => run(load(None)),
+        [command, file] if command == "load" => run(load(Some(file))),
+        [command, key] if command == "blob" => run(blob(key)),
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+fn run(f: impl Future<Output = Result<()>>) -> Result<()> {
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(f)
+}
+
+async fn connect() -> Result<PgClient> {
+    let config: Config = common::config::load()?;
+    PgConnector::new(config.db.connection_string)
+        .connect()
+        .await
+}
+
+async fn dump(file: Option<&String>) -> Result<()> {
+    let con = connect().await?;
+    // language=sql
+    let rows = con
+        .query("select key, value from magnets.state", &[])
+        .await?;
+    let values: BTreeMap<String, Value> = rows
+        .iter()
+        .map(|row| (row.get(0), row.get::<_, Json<Value>>(1).0))
+        .collect();
+    let mut json = serde_json::to_vec_pretty(&values)?;
+    json.push(b'\n');
+    match file {
+        Some(file) => {
+            fs::write(file, json).with_context(|| format!("cannot write {}", file))?
+        }
+        _ => io::stdout().write_all(&json)?,
+    }
+    Ok(())
+}
+
+async fn load(file: Option<&String>) -> Result<()> {
+    let json = match file {
+        Some(file) => fs::read(file).with_context(|| format!("cannot read {}", file))?,
+        _ => {
+            let mut json = vec![];
+            io::stdin().read_to_end(&mut json)?;
+            json
+        }
+    };
+    let values: BTreeMap<String, Value> =
+        serde_json::from_slice(&json).context("the dump is not a json object")?;
+    let mut con = connect().await?;
+    let tran = pg::transaction(&mut con).await?;
+    for (key, value) in &values {
+        // language=sql
+        let updated = tran
+            .execute(
+                "update magnets.state set value = $2 where key = $1 and value <> $2",
+                &[key, &Json(value)],
+            )
+            .await?;
+        // language=sql
+        let exists = tran
+            .query_opt("select 1 from magnets.state where key = $1", &[key])
+            .await?;
+        if exists.is_none() {
+            return Err(anyhow!("unknown state key {}", key));
+        }
+        if updated > 0 {
+            println!("restored {} = {}", key, value);
+        }
+    }
+    tran.commit().await?;
+    Ok(())
+}
+
+async fn blob(key: &str) -> Result<()> {
+    let con = connect().await?;
+    let value: Value = db_state::get_blob(&con, key)
+        .await?
+        .with_context(|| format!("there is no blob {}", key))?;
+    let mut json = serde_json::to_vec_pretty(&value)?;
+    json.push(b'\n');
+    io::stdout().write_all(&json)?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::{
     borrow::Borrow,
     hash::{Hash, Hasher},
+    mem::size_of,
     ops::{Deref, Range},
     sync::Arc,
 };
@@ -41,6 +42,13 @@ impl StringLists {
         pos
     }
 
+    /// Returns the approximate number of bytes allocated by this object
+    pub fn heap_size(&self) -> usize {
+        self.buf.capacity()
+            + (self.strings.capacity() + self.lists.capacity())
+                * size_of::<Range<usize>>()
+    }
+
     pub fn iter(&self, idx: usize) -> impl Iterator<Item = &str> {
         self.strings[self.lists[idx].clone()]
             .iter()
@@ -48,7 +56,7 @@ impl StringLists {
     }
 }
 
-#[derive(Eq)]
+#[derive(Clone, Eq)]
 pub struct ArcString {
     buf: Arc<str>,
     range: Range<usize>,
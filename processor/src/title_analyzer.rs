@@ -1,54 +1,365 @@
-use crate::show_db::{find_format, find_season, find_year, search_name, Show, ShowDb};
-use anyhow::{anyhow, Result};
-use common::Format;
+use crate::show_db::{find_format, find_season, find_year, NameIndex, Show, ShowDb};
+use common::{textnorm, Format, Script};
 use isnt::std_1::vec::IsntVecExt;
 use itertools::Itertools;
 use regex::Regex;
 use serde::export::Formatter;
-use std::{borrow::Cow, fmt, fmt::Display};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, error::Error, fmt, fmt::Display};
 
-pub fn find_show<'a>(db: &'a ShowDb, title: &str) -> Result<&'a Show> {
+/// An implementation of the title analyzer
+///
+/// Candidate implementations can be evaluated against the default one without affecting
+/// the matches by setting `nyaa.shadow_analyzer`.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Analyzer {
+    /// The analyzer used for matching
+    Default,
+    /// Like `Default` but only accepts names that match exactly instead of falling back
+    /// to a prefix search
+    Exact,
+}
+
+impl Analyzer {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Analyzer::Default => "default",
+            Analyzer::Exact => "exact",
+        }
+    }
+
+    /// Matches the title of a new torrent like [find_new_show]
+    pub fn find_new_show<'a>(
+        self,
+        db: &'a ShowDb,
+        title: &str,
+    ) -> Result<&'a Show, MatchError> {
+        find_show_(self, db, title, true)
+    }
+}
+
+/// The reason why a title could not be matched to a show
+#[derive(Debug)]
+pub struct MatchError {
+    pub reason: Reason,
+    /// The non-latin script of the title. Such titles usually contain no romaji name.
+    pub script: Option<Script>,
+}
+
+/// The step of the analysis that failed
+#[derive(Debug, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Reason {
+    /// The title consists only of metadata such as the release group and resolution
+    NoName,
+    /// The title starts with the episode number
+    NoNameBeforeEpisode,
+    /// The name contains no latin letters or digits after normalization
+    NoLatinName { name: String },
+    /// No show has this name
+    ///
+    /// `name` is the lowercased name extracted from the title and `key` its
+    /// [textnorm::matcher_key]. `candidates` are the shows found by the prefix search.
+    /// It is empty if the search found nothing or if only exact matches are accepted.
+    NoMatch {
+        name: String,
+        key: String,
+        candidates: Vec<Candidate>,
+    },
+    /// Multiple shows have this name and neither the season nor the year in the title
+    /// select one of them
+    Ambiguous {
+        name: String,
+        key: String,
+        candidates: Vec<Candidate>,
+    },
+}
+
+/// A show that the name could refer to
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    pub show_id: i64,
+    pub names: Vec<String>,
+}
+
+impl Display for MatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(script) = self.script {
+            write!(f, "the title is written in {}: ", script.as_str())?;
+        }
+        self.reason.fmt(f)
+    }
+}
+
+impl Error for MatchError {}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::NoName => write!(f, "name range is empty"),
+            Reason::NoNameBeforeEpisode => write!(f, "pre episode range is empty"),
+            Reason::NoLatinName { .. } => {
+                write!(f, "the name contains no latin letters or digits")
+            }
+            Reason::NoMatch { candidates, .. } if candidates.is_empty() => {
+                write!(f, "found no perfect match")
+            }
+            Reason::NoMatch { candidates, .. } => write!(
+                f,
+                "found no perfect match. trie search returned {}+ results: {}",
+                candidates.len(),
+                Candidates(candidates),
+            ),
+            Reason::Ambiguous { candidates, .. } => write!(
+                f,
+                "found {} perfect matches: {}",
+                candidates.len(),
+                Candidates(candidates),
+            ),
+        }
+    }
+}
+
+/// Matches a title with a show
+///
+/// Used for rematches and reports. Their torrents can be of any age, so unlike
+/// [find_new_show] the shows of recent seasons are not preferred.
+pub fn find_show<'a>(db: &'a ShowDb, title: &str) -> Result<&'a Show, MatchError> {
+    find_show_(Analyzer::Default, db, title, false)
+}
+
+/// Matches the title of a torrent that has just been uploaded with a show
+///
+/// New torrents are usually of the shows that are airing. Exact matches among the shows
+/// of recent seasons (see [ShowDb::recent]) are therefore preferred.
+pub fn find_new_show<'a>(db: &'a ShowDb, title: &str) -> Result<&'a Show, MatchError> {
+    find_show_(Analyzer::Default, db, title, true)
+}
+
+fn find_show_<'a>(
+    analyzer: Analyzer,
+    db: &'a ShowDb,
+    title: &str,
+    prefer_recent: bool,
+) -> Result<&'a Show, MatchError> {
+    let romanized = textnorm::romanize_kana(title);
+    let title = &*romanized;
+    if let Some(show) = find_group_rule(db, title) {
+        return Ok(show);
+    }
+    // Only exact matches are accepted from the recent shows. Otherwise a prefix search
+    // could match a recent sequel even though the full index contains the show itself.
+    if let Some(recent) = db.recent.as_ref().filter(|_| prefer_recent) {
+        if let Ok(show) = find_show_in(Analyzer::Exact, db, recent, title) {
+            return Ok(show);
+        }
+    }
+    find_show_in(analyzer, db, &db.all, title).map_err(|reason| MatchError {
+        reason,
+        script: Script::detect(title),
+    })
+}
+
+fn find_show_in<'a>(
+    analyzer: Analyzer,
+    db: &'a ShowDb,
+    index: &NameIndex,
+    title: &str,
+) -> Result<&'a Show, Reason> {
     let normalized_title = normalize_title(title, find_separator(title));
     let blocks = parse_blocks(&normalized_title);
     let name_range = find_name_range(&blocks);
     if name_range.is_empty() {
-        return Err(anyhow!("name range is empty"));
+        return Err(Reason::NoName);
     }
     let (ep, season, plain_digits) = find_episode(&name_range);
     let pre_episode_range = truncate_blocks(&name_range, ep);
-    let res = handle_pre_episode_range(db, &normalized_title, &pre_episode_range, season);
+    let res = handle_pre_episode_range(
+        analyzer,
+        db,
+        index,
+        &normalized_title,
+        &pre_episode_range,
+        season,
+    );
     if res.is_err() && plain_digits {
         // e.g. Mob Psycho 100
-        return handle_pre_episode_range(db, &normalized_title, &name_range, season);
+        return handle_pre_episode_range(
+            analyzer,
+            db,
+            index,
+            &normalized_title,
+            &name_range,
+            season,
+        );
     }
     res
 }
 
+/// Returns the show of the group rule for the release group and name of the title
+///
+/// The release group is the bracketed block at the start of the title and the name is
+/// everything between it and the episode number.
+fn find_group_rule<'a>(db: &'a ShowDb, title: &str) -> Option<&'a Show> {
+    if db.group_rules.is_empty() {
+        return None;
+    }
+    let normalized_title = normalize_title(title, find_separator(title));
+    let blocks = parse_blocks(&normalized_title);
+    let group = match blocks.first() {
+        Some(b) if b.delimiter == Some('[') => textnorm::matcher_key(b.val),
+        _ => return None,
+    };
+    let name_range = find_name_range(&blocks);
+    let (ep, _, _) = find_episode(&name_range);
+    let pre_episode_range = truncate_blocks(&name_range, ep);
+    if pre_episode_range.is_empty() {
+        return None;
+    }
+    let name =
+        textnorm::matcher_key(&blocks_to_string(&normalized_title, &pre_episode_range));
+    let &idx = db.group_rules.get(&(group, name))?;
+    Some(&db.shows[idx])
+}
+
+/// Returns the release group of a torrent title, e.g. `SubsPlease` for
+/// `[SubsPlease] Oshi no Ko - 01 (1080p)`
+///
+/// The release group is the bracketed block at the start of the title. Unlike
+/// the lookup of group rules, the name is returned as written and not normalized.
+pub fn find_release_group(title: &str) -> Option<&str> {
+    let rest = title.trim_start().strip_prefix('[')?;
+    let group = rest[..rest.find(']')?].trim();
+    match group.is_empty() || group.len() > MAX_RELEASE_GROUP_LEN {
+        true => None,
+        false => Some(group),
+    }
+}
+
+/// Longer bracketed blocks at the start of a title are not release groups
+const MAX_RELEASE_GROUP_LEN: usize = 100;
+
+/// Extracts the episode number from a torrent title
+///
+/// Returns `None` if the title does not contain an episode number or if it refers to
+/// a range of episodes (batches) or a fractional episode (recaps).
+pub fn find_episode_number(title: &str) -> Option<i32> {
+    lazy_static::lazy_static! {
+        static ref EPISODE: Regex = Regex::new(r"(?x)
+            ^
+            [^a-z0-9]*
+            (ep(\.|isodes?)?\s*)?
+            (s\d+e)?
+            (?P<episode>\d+)
+            \s*(v\d)?
+            \s*(end|final|oad)?
+            [^a-z0-9]*
+            $
+            ").unwrap();
+    }
+    let episode = find_episode_str(title)?;
+    let ca = EPISODE.captures(&episode)?;
+    ca.name("episode").unwrap().as_str().parse().ok()
+}
+
+/// Extracts the first and the last episode from the title of a batch
+///
+/// Returns `None` if the title does not contain a range of episodes, e.g. `01 ~ 12`.
+pub fn find_episode_range(title: &str) -> Option<(i32, i32)> {
+    let episode = find_episode_str(title)?;
+    let ca = EPISODE_RANGE.captures(&episode)?;
+    let first = ca.name("first").unwrap().as_str().parse().ok()?;
+    let last = ca.name("last").unwrap().as_str().parse().ok()?;
+    match first <= last {
+        true => Some((first, last)),
+        false => None,
+    }
+}
+
+/// Extracts the episodes that are stored with a match as `episode` and `episode_end`
+///
+/// Batches store their first and their last episode. Single episodes store their number
+/// and no end.
+pub fn find_episodes(title: &str) -> (Option<i32>, Option<i32>) {
+    match find_episode_range(title) {
+        Some((first, last)) => (Some(first), Some(last)),
+        _ => (find_episode_number(title), None),
+    }
+}
+
+/// Returns whether a torrent title refers to a range of episodes, e.g. a complete season
+///
+/// "Complete" on its own often names an edition of a single release, e.g. "(Complete
+/// Edition)", and only counts if it is a tag of its own or refers to a series or season.
+pub fn is_batch(title: &str) -> bool {
+    lazy_static::lazy_static! {
+        static ref KEYWORD: Regex = Regex::new(r"(?xi)
+            \bbatch\b
+            | [\[(]\s*complete\s*[\])]
+            | \bcomplete\s+(series|seasons?|collection)\b
+            ").unwrap();
+    }
+    if KEYWORD.is_match(title) {
+        return true;
+    }
+    match find_episode_str(title) {
+        Some(s) => EPISODE_RANGE.is_match(&s),
+        _ => false,
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref EPISODE_RANGE: Regex = Regex::new(r"(?x)
+        ^
+        [^a-z0-9]*
+        (ep(\.|isodes?)?\s*)?
+        (?P<first>\d+)\s*(~|-)\s*(?P<last>\d+)
+        \s*(v\d)?
+        \s*(end|final)?
+        [^a-z0-9]*
+        $
+        ").unwrap();
+}
+
+/// Returns the part of the normalized title that starts with the episode number
+fn find_episode_str(title: &str) -> Option<String> {
+    let normalized_title = normalize_title(title, find_separator(title));
+    let blocks = parse_blocks(&normalized_title);
+    let name_range = find_name_range(&blocks);
+    let (block_idx, offset) = find_episode(&name_range).0?;
+    Some(name_range[block_idx].val[offset..].to_string())
+}
+
 fn blocks_to_string(s: &str, blocks: &[Block]) -> String {
     let last = blocks.last().unwrap();
     s[blocks[0].start..last.start + last.val.len()].to_string()
 }
 
 fn handle_pre_episode_range<'a>(
+    analyzer: Analyzer,
     db: &'a ShowDb,
+    index: &NameIndex,
     normalized_title: &str,
     pre_episode_range: &[Block],
     season: Option<u32>,
-) -> Result<&'a Show> {
+) -> Result<&'a Show, Reason> {
     if pre_episode_range.is_empty() {
-        return Err(anyhow!("pre episode range is empty"));
+        return Err(Reason::NoNameBeforeEpisode);
     }
     let mut pre_episode_title = blocks_to_string(normalized_title, pre_episode_range);
     let mut metadata = extract_title_metadata(normalized_title, &mut pre_episode_title);
     if season.is_some() {
         metadata.0 = season;
     }
-    let res = search(db, &pre_episode_title, metadata);
+    let res = search(analyzer, db, index, &pre_episode_title, metadata);
     if let x @ Ok(_) = res {
         return x;
     }
     if let Some(pos) = pre_episode_title.find('|') {
-        if let x @ Ok(_) = search(db, &pre_episode_title[..pos], metadata) {
+        if let x @ Ok(_) =
+            search(analyzer, db, index, &pre_episode_title[..pos], metadata)
+        {
             return x;
         }
     }
@@ -60,7 +371,7 @@ fn handle_pre_episode_range<'a>(
             &pre_episode_range[..pre_episode_range.len() - 1],
         );
         extract_title_metadata(normalized_title, &mut pre_episode_title);
-        return search(db, &pre_episode_title, metadata);
+        return search(analyzer, db, index, &pre_episode_title, metadata);
     }
     res
 }
@@ -68,16 +379,31 @@ fn handle_pre_episode_range<'a>(
 type TitleMetadata = (Option<u32>, Option<u32>, Option<Format>);
 
 fn search<'a>(
+    analyzer: Analyzer,
     db: &'a ShowDb,
+    index: &NameIndex,
     pre_episode_title: &str,
     (season, year, _format): TitleMetadata,
     // ) -> Result<Rc<Show>> {
-) -> Result<&'a Show> {
-    let search_name = search_name(pre_episode_title);
-    let shows = db.map.get(&*search_name);
+) -> Result<&'a Show, Reason> {
+    let search_name = textnorm::matcher_key(pre_episode_title);
+    if search_name.is_empty() {
+        // Searching the heap for the empty string would return arbitrary shows
+        return Err(Reason::NoLatinName {
+            name: pre_episode_title.to_string(),
+        });
+    }
+    let shows = index.map.get(&*search_name);
     if shows.is_none() {
-        let idx = db.heap.find(&search_name);
-        let r: Vec<_> = db
+        if analyzer == Analyzer::Exact {
+            return Err(Reason::NoMatch {
+                name: pre_episode_title.trim().to_string(),
+                key: search_name,
+                candidates: vec![],
+            });
+        }
+        let idx = index.heap.find(&search_name);
+        let r: Vec<_> = index
             .heap
             .iter(idx)
             .copied()
@@ -88,11 +414,11 @@ fn search<'a>(
         if r.len() == 1 {
             return Ok(r[0]);
         }
-        return Err(anyhow!(
-            "found no perfect match. trie search returned {}+ results: {}",
-            r.len(),
-            Shows(db, &r),
-        ));
+        return Err(Reason::NoMatch {
+            name: pre_episode_title.trim().to_string(),
+            key: search_name,
+            candidates: candidates(db, &r),
+        });
     }
     let shows: Vec<_> = shows
         .unwrap()
@@ -144,21 +470,30 @@ fn search<'a>(
     if shows.len() == 1 {
         return Ok(shows[0]);
     }
-    Err(anyhow!(
-        "found {} perfect matches: {}",
-        shows.len(),
-        Shows(db, &**shows)
-    ))
+    Err(Reason::Ambiguous {
+        name: pre_episode_title.trim().to_string(),
+        key: search_name,
+        candidates: candidates(db, shows),
+    })
+}
+
+fn candidates(db: &ShowDb, shows: &[&Show]) -> Vec<Candidate> {
+    shows
+        .iter()
+        .map(|show| Candidate {
+            show_id: show.show_id,
+            names: db.names.iter(show.names).map(|n| n.to_string()).collect(),
+        })
+        .collect()
 }
 
-struct Shows<'a>(&'a ShowDb, &'a [&'a Show]);
+struct Candidates<'a>(&'a [Candidate]);
 
-impl<'a> Display for Shows<'a> {
+impl<'a> Display for Candidates<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        for show in self.1 {
-            write!(f, "{}[", show.show_id)?;
-            for name in self.0.names.iter(show.names) {
-                // for name in &*show.names {
+        for candidate in self.0 {
+            write!(f, "{}[", candidate.show_id)?;
+            for name in &candidate.names {
                 write!(f, "{}, ", name)?;
             }
             write!(f, "], ")?;
@@ -453,3 +788,120 @@ fn find_episode(blocks: &[Block]) -> (Option<(usize, usize)>, Option<u32>, bool)
     }
     (None, None, false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_episodes() {
+        assert_eq!(find_episodes("[Group] Show - 07 [1080p]"), (Some(7), None));
+        assert_eq!(
+            find_episodes("[Group] Show (01-12) [1080p]"),
+            (Some(1), Some(12))
+        );
+        assert_eq!(
+            find_episodes("[Group] Show - 01 ~ 24 [BD]"),
+            (Some(1), Some(24))
+        );
+        assert_eq!(find_episodes("[Group] Show [Batch]"), (None, None));
+    }
+
+    #[test]
+    fn finds_episode_numbers() {
+        assert_eq!(find_episode_number("[Group] Show - 07 [1080p]"), Some(7));
+        assert_eq!(find_episode_number("[Group] Show - 07v2 [1080p]"), Some(7));
+        assert_eq!(
+            find_episode_number("[Group] Show - 12 END [1080p]"),
+            Some(12)
+        );
+        assert_eq!(find_episode_number("[Group] Show S02E05 [1080p]"), Some(5));
+        assert_eq!(
+            find_episode_number("[Group] Show Episode 3 [720p]"),
+            Some(3)
+        );
+        assert_eq!(
+            find_episode_number("[Group] Show 2nd Season - 05 [1080p]"),
+            Some(5)
+        );
+        assert_eq!(find_episode_number("[Group] Show - 01 ~ 12 [1080p]"), None);
+        assert_eq!(find_episode_number("[Group] Show (01-12) [1080p]"), None);
+        assert_eq!(find_episode_number("[Group] Show - 12.5 [1080p]"), None);
+        assert_eq!(find_episode_number("[Group] Show [1080p]"), None);
+    }
+
+    #[test]
+    fn detects_batches() {
+        assert!(is_batch("[Group] Show [Batch]"));
+        assert!(is_batch("[Group] Show (01-12) [1080p]"));
+        assert!(is_batch("[Group] Show - 01 ~ 24 [BD]"));
+        assert!(is_batch("[Group] Show (Complete) [1080p]"));
+        assert!(is_batch("[Group] Show - Complete Series [1080p]"));
+        assert!(!is_batch("[Group] Show - 07 [1080p]"));
+        assert!(!is_batch("[Group] Show - 07v2 [1080p]"));
+        assert!(!is_batch("[Group] Show (Complete Edition) - 01 [1080p]"));
+        assert!(!is_batch("[Group] The Complete Guide to Show - 01 [1080p]"));
+    }
+
+    #[test]
+    fn finds_release_groups() {
+        assert_eq!(
+            find_release_group("[SubsPlease] Oshi no Ko - 01 (1080p)"),
+            Some("SubsPlease")
+        );
+        assert_eq!(
+            find_release_group(" [ Erai-raws ] Show - 01"),
+            Some("Erai-raws")
+        );
+        assert_eq!(find_release_group("[] Show - 01"), None);
+        assert_eq!(find_release_group("Show - 01 [Group]"), None);
+    }
+
+    #[test]
+    fn finds_group_rules() {
+        let db = crate::show_db::test_db(
+            &[(1, &["Oshi no Ko"], false), (2, &["Other Show"], false)],
+            &[("SubsPlease", "Oshi no Ko", 1)],
+        );
+        let rule = |title| find_group_rule(&db, title).map(|s| s.show_id);
+        assert_eq!(rule("[SubsPlease] Oshi no Ko - 01 (1080p)"), Some(1));
+        // Compared by their matcher keys
+        assert_eq!(rule("[subsplease] OSHI NO KO - 02 [720p]"), Some(1));
+        assert_eq!(rule("[Subs-Please] Oshi-no-Ko - 03"), Some(1));
+        assert_eq!(rule("[Erai-raws] Oshi no Ko - 01"), None);
+        assert_eq!(rule("[SubsPlease] Other Show - 01"), None);
+        assert_eq!(rule("Oshi no Ko - 01"), None);
+        assert_eq!(rule("[SubsPlease] - 01"), None);
+    }
+
+    #[test]
+    fn group_rules_take_precedence_over_names() {
+        let db = crate::show_db::test_db(
+            &[(1, &["Oshi no Ko"], false), (2, &["Other Show"], false)],
+            &[("Group", "Other Show", 1)],
+        );
+        let title = "[Group] Other Show - 01";
+        assert_eq!(find_show(&db, title).unwrap().show_id, 1);
+        assert_eq!(
+            find_show(&db, "[Elsewhere] Other Show - 01")
+                .unwrap()
+                .show_id,
+            2
+        );
+    }
+
+    #[test]
+    fn prefers_recent_shows_only_for_new_torrents() {
+        let db =
+            crate::show_db::test_db(&[(1, &["Show"], false), (2, &["Show"], true)], &[]);
+        let title = "[Group] Show - 01 [1080p]";
+        assert_eq!(find_new_show(&db, title).unwrap().show_id, 2);
+        assert!(matches!(
+            find_show(&db, title),
+            Err(MatchError {
+                reason: Reason::Ambiguous { .. },
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{db_state, state::State, title_analyzer};
+use anyhow::Result;
+use common::{pg, time::MINUTE, AudioCodec, MediaInfo, Resolution, VideoCodec};
+use serde::{Deserialize, Serialize};
+
+/// The version of the information that is taken from the titles of torrents
+///
+/// 1. The resolution and codecs (`common::MediaInfo`)
+/// 2. The release group (`title_analyzer::find_release_group`)
+///
+/// Increasing the version runs the backfill again for all torrents.
+const VERSION: i32 = 2;
+
+/// The number of torrents that are analyzed per transaction
+const BATCH: i64 = 1000;
+
+/// The key of the progress of the backfill in `magnets.state_blob`
+const PROGRESS: &str = "title_info_backfill";
+
+#[derive(Serialize, Deserialize, Default)]
+struct Progress {
+    version: i32,
+    /// The torrents up to this id have been analyzed. `i64::MAX` once the backfill of
+    /// `version` has completed.
+    after: i64,
+}
+
+/// Stores the information taken from the titles of the torrents that were inserted
+/// before the information was introduced
+///
+/// New and edited torrents are analyzed when they are stored (see `sources::nyaa`). The
+/// backfill walks the torrents in the order of their ids and records its progress after
+/// every batch so that it resumes after a restart. Returns once all torrents have been
+/// analyzed.
+pub async fn backfill_title_info(state: &State<'_>) {
+    loop {
+        match backfill_now(state).await {
+            Ok(()) => return,
+            Err(e) => {
+                log::error!("could not backfill the title info: {:#}", e);
+                tokio::time::delay_for(MINUTE).await;
+            }
+        }
+    }
+}
+
+async fn backfill_now(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    let mut progress: Progress = db_state::get_blob(&con, PROGRESS)
+        .await?
+        .unwrap_or_default();
+    if progress.version != VERSION {
+        progress = Progress {
+            version: VERSION,
+            after: 0,
+        };
+    }
+    let mut total = 0;
+    while progress.after != i64::MAX {
+        let tran = pg::transaction(&mut con).await?;
+        // language=sql
+        let rows = tran
+            .query(
+                "
+                select torrent_id, title
+                from magnets.torrent
+                where torrent_id > $1
+                order by torrent_id
+                limit $2",
+                &[&progress.after, &BATCH],
+            )
+            .await?;
+        let mut torrent_ids = vec![];
+        let mut titles = vec![];
+        let mut resolutions = vec![];
+        let mut video_codecs = vec![];
+        let mut audio_codecs = vec![];
+        let mut dual_audios = vec![];
+        let mut release_groups = vec![];
+        for row in &rows {
+            let title: String = row.get(1);
+            let media = MediaInfo::detect(&title);
+            torrent_ids.push(row.get::<_, i64>(0));
+            titles.push(title);
+            resolutions.push(media.resolution.map(Resolution::to_db));
+            video_codecs.push(media.video_codec.map(VideoCodec::to_db));
+            audio_codecs.push(media.audio_codec.map(AudioCodec::to_db));
+            dual_audios.push(media.dual_audio);
+            release_groups
+                .push(title_analyzer::find_release_group(&title).map(str::to_string));
+        }
+        // language=sql
+        tran.execute(
+            "
+            insert into magnets.release_group (name)
+            select distinct name
+            from unnest($1::text[]) name
+            where name is not null
+            on conflict (name) do nothing",
+            &[&release_groups],
+        )
+        .await?;
+        // Titles that were edited in the meantime have already been analyzed
+        // language=sql
+        tran.execute(
+            "
+            update magnets.torrent t
+            set resolution = x.resolution, video_codec = x.video_codec,
+                audio_codec = x.audio_codec, dual_audio = x.dual_audio,
+                release_group_id = g.release_group_id
+            from unnest($1::bigint[], $2::text[], $3::int[], $4::int[], $5::int[], $6::bool[],
+                        $7::text[])
+                x (torrent_id, title, resolution, video_codec, audio_codec, dual_audio,
+                   release_group)
+            left join magnets.release_group g on g.name = x.release_group
+            where t.torrent_id = x.torrent_id and t.title = x.title",
+            &[
+                &torrent_ids,
+                &titles,
+                &resolutions,
+                &video_codecs,
+                &audio_codecs,
+                &dual_audios,
+                &release_groups,
+            ],
+        )
+        .await?;
+        progress.after = match torrent_ids.last() {
+            Some(&id) if (rows.len() as i64) == BATCH => id,
+            _ => i64::MAX,
+        };
+        db_state::set_blob(&tran, PROGRESS, &progress).await?;
+        state.leader.ensure().await?;
+        tran.commit().await?;
+        total += rows.len();
+        if total % 100_000 == 0 && !rows.is_empty() {
+            log::info!("backfilled the title info of {} torrents", total);
+        }
+    }
+    if total > 0 {
+        log::info!(
+            "completed the backfill of the title info of {} torrents",
+            total
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::{sleeper::Sleeper, state::State};
+use anyhow::{anyhow, Context, Result};
+use scraper::{Html, Selector};
+use std::time::Duration;
+
+type LocalName = html5ever::LocalName;
+
+lazy_static::lazy_static! {
+    /// The panel at the top of the detail page. Its class is `panel-success` if the
+    /// uploader is trusted.
+    static ref PANEL: Selector = Selector::parse(".container > .panel").unwrap();
+}
+
+/// Re-checks the trusted status of torrents from their detail pages
+///
+/// Overlap scraping only sees the newest torrents. Uploaders can gain or lose the
+/// trusted status later, so this task slowly walks all torrents, starting with the ones
+/// that were checked the longest time ago.
+pub async fn refresh_trusted(state: &State<'_>) {
+    loop {
+        state.sleep(|c| c.nyaa.trusted_refresh_interval).await;
+        if state.memory.shedding() {
+            log::info!(
+                "not refreshing trusted status because the memory budget is exceeded"
+            );
+        } else if let Err(e) = refresh_trusted_now(state).await {
+            log::error!("could not refresh the trusted status: {:#}", e);
+        }
+    }
+}
+
+async fn refresh_trusted_now(state: &State<'_>) -> Result<()> {
+    let con = state.pg_connector.connect().await?;
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select torrent_id, nyaa_id, trusted
+            from magnets.torrent
+            order by trusted_checked nulls first
+            limit $1",
+            &[&state.config.nyaa.trusted_refresh_batch],
+        )
+        .await?;
+    let mut sleeper = Sleeper::new(state.clock.clone());
+    let mut changed = 0;
+    for row in &rows {
+        sleeper.sleep(Duration::from_secs(1)).await;
+        let torrent_id: i64 = row.get("torrent_id");
+        let nyaa_id: i64 = row.get("nyaa_id");
+        let trusted = match fetch_trusted(state, nyaa_id).await {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!(
+                    "could not check the trusted status of torrent {}: {:#}",
+                    torrent_id,
+                    e
+                );
+                None
+            }
+        };
+        let trusted = trusted.unwrap_or_else(|| row.get("trusted"));
+        if trusted != row.get::<_, bool>("trusted") {
+            log::info!(
+                "trusted status of torrent {} changed to {}",
+                torrent_id,
+                trusted
+            );
+            changed += 1;
+        }
+        state.leader.ensure().await?;
+        // language=sql
+        con.execute(
+            "
+            update magnets.torrent
+            set trusted = $2, trusted_checked = now()
+            where torrent_id = $1",
+            &[&torrent_id, &trusted],
+        )
+        .await?;
+    }
+    log::info!(
+        "checked the trusted status of {} torrents, {} changed",
+        rows.len(),
+        changed
+    );
+    Ok(())
+}
+
+/// Returns the trusted status from the detail page or `None` if the torrent no longer
+/// exists
+async fn fetch_trusted(state: &State<'_>, nyaa_id: i64) -> Result<Option<bool>> {
+    let url = format!("{}/view/{}", state.config.nyaa.url, nyaa_id);
+    let response = state
+        .web_client
+        .get(&url)
+        .send()
+        .await
+        .context("cannot communicate with nyaa.si")?;
+    match response.status().as_u16() {
+        200 => {}
+        404 => return Ok(None),
+        _ => return Err(anyhow!("nyaa.si status code is {}", response.status())),
+    }
+    let content = response
+        .text()
+        .await
+        .context("cannot read nyaa.si response")?;
+    let html = Html::parse_document(&content);
+    let panel = html
+        .select(&PANEL)
+        .next()
+        .with_context(|| format!("{} contains no panel", url))?;
+    let classes = &panel.value().classes;
+    Ok(Some(classes.contains(&LocalName::from("panel-success"))))
+}
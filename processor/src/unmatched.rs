@@ -0,0 +1,102 @@
+use crate::{
+    config::Config,
+    show_db::ShowDbHolder,
+    title_analyzer,
+    title_analyzer::{MatchError, Reason},
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use common::pg::PgConnector;
+use serde::Serialize;
+use std::{io, io::Write};
+
+const USAGE: &str = "usage: processor unmatched export [--format jsonl]";
+
+/// Inspects the torrents that could not be matched to a show
+///
+/// Invoked as
+///
+/// - `processor unmatched export [--format jsonl]` to write one json object per
+///   unmatched torrent to stdout. Each object contains the title and the reason why the
+///   title analyzer rejected it (see [Reason]), e.g.
+///
+///   ```json
+///   {"torrent_id":1,"nyaa_id":2,"title":"...","uploaded_at":"...","script":null,"reason":"no_match","name":"...","key":"...","candidates":[]}
+///   ```
+///
+/// The titles are analyzed with the current shows. Torrents that would be matched by
+/// the next rematch are skipped.
+pub fn unmatched(args: &[String]) -> Result<()> {
+    match args {
+        [command] if command == "export" => {}
+        [command, flag, format] if command == "export" && flag == "--format" => {
+            if format != "jsonl" {
+                return Err(anyhow!("unsupported format {}", format));
+            }
+        }
+        _ => return Err(anyhow!(USAGE)),
+    }
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(export())
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    torrent_id: i64,
+    nyaa_id: i64,
+    title: &'a str,
+    uploaded_at: DateTime<Utc>,
+    /// The non-latin script of the title if there is one
+    script: Option<&'static str>,
+    #[serde(flatten)]
+    reason: &'a Reason,
+}
+
+async fn export() -> Result<()> {
+    let config: Config = common::config::load()?;
+    let connector = PgConnector::new(config.db.connection_string);
+    let show_db = ShowDbHolder::new(&connector, config.matcher.recent_seasons)
+        .get()
+        .await?;
+    let con = connector.connect().await?;
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select torrent_id, nyaa_id, title, uploaded_at
+            from magnets.torrent
+            where not matched
+            order by nyaa_id",
+            &[],
+        )
+        .await?;
+    let stdout = io::stdout();
+    let mut stdout = io::BufWriter::new(stdout.lock());
+    let mut exported = 0;
+    for row in &rows {
+        let title: &str = row.get("title");
+        let MatchError { reason, script } =
+            match title_analyzer::find_show(&show_db, title) {
+                Ok(_) => continue,
+                Err(e) => e,
+            };
+        let record = Record {
+            torrent_id: row.get("torrent_id"),
+            nyaa_id: row.get("nyaa_id"),
+            title,
+            uploaded_at: row.get("uploaded_at"),
+            script: script.map(|s| s.as_api_str()),
+            reason: &reason,
+        };
+        serde_json::to_writer(&mut stdout, &record)?;
+        stdout.write_all(b"\n")?;
+        exported += 1;
+    }
+    stdout.flush()?;
+    eprintln!("exported {} of {} unmatched torrents", exported, rows.len());
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use crate::{config::Watchlists, sleeper::Sleeper, state::State};
+use anyhow::{anyhow, Context, Result};
+use chrono::Duration;
+use common::{pg, pg::PgClient, SESSION_DAYS};
+use serde::{Deserialize, Serialize};
+
+/// The number of logins that are processed per query
+const LOGIN_BATCH_SIZE: i64 = 10;
+
+/// Completes the logins on the site
+///
+/// `/login/anilist` of the site stores the authorization code that AniList passes to it
+/// in the session. The code is exchanged for an access token, the session is assigned to
+/// the AniList user, and the watchlist of the user is synced right away. Sessions whose
+/// code cannot be exchanged are marked as failed.
+pub async fn complete_logins(state: &State<'_>) {
+    if state.config.watchlists.anilist_client_secret.is_none() {
+        log::info!("watchlists.anilist_client_secret is not set, not processing logins");
+        return;
+    }
+    loop {
+        if let Err(e) = complete_logins_now(state).await {
+            log::error!("could not complete the logins: {:#}", e);
+        }
+        state.sleep(|c| c.watchlists.login_poll_interval).await;
+    }
+}
+
+async fn complete_logins_now(state: &State<'_>) -> Result<()> {
+    // Polled on the shared connection since there are rarely any logins
+    let rows = {
+        let con = state.pg.borrow().await?;
+        // language=sql
+        con.query(
+            "
+            select token, code
+            from magnets.site_session
+            where code is not null and expires > now()
+            order by created
+            limit $1",
+            &[&LOGIN_BATCH_SIZE],
+        )
+        .await?
+    };
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let config = state.live_config.current();
+    let mut con = state.pg_connector.connect().await?;
+    for row in &rows {
+        let token: &str = row.get("token");
+        let code: &str = row.get("code");
+        state.leader.ensure().await?;
+        let user = match log_in(state, &config.watchlists, &con, token, code).await {
+            Ok(user) => user,
+            Err(e) => {
+                log::warn!("could not complete a login: {:#}", e);
+                // language=sql
+                con.execute(
+                    "
+                    update magnets.site_session
+                    set code = null, failed = true
+                    where token = $1",
+                    &[&token],
+                )
+                .await?;
+                continue;
+            }
+        };
+        if let Err(e) = sync_user(state, &mut con, &user).await {
+            log::error!("could not sync the watchlist of user {}: {:#}", user.id, e);
+        }
+    }
+    Ok(())
+}
+
+struct User {
+    id: i64,
+    anilist_user_id: i64,
+    access_token: String,
+}
+
+/// Exchanges the code of a session and assigns the user to the session
+async fn log_in(
+    state: &State<'_>,
+    config: &Watchlists,
+    con: &PgClient,
+    token: &str,
+    code: &str,
+) -> Result<User> {
+    let access_token = exchange_code(state, config, code).await?;
+    let viewer = viewer(state, &access_token).await?;
+    // language=sql
+    let row = con
+        .query_one(
+            "
+            insert into magnets.site_user (anilist_user_id, anilist_name, access_token)
+            values ($1, $2, $3)
+            on conflict (anilist_user_id) do update
+            set anilist_name = excluded.anilist_name,
+                access_token = excluded.access_token
+            returning site_user_id",
+            &[&viewer.id, &viewer.name, &access_token],
+        )
+        .await?;
+    let user_id: i64 = row.get(0);
+    // The session was created with a short expiry by the site
+    let expires = state.clock.now() + Duration::days(SESSION_DAYS);
+    // language=sql
+    con.execute(
+        "
+        update magnets.site_session
+        set code = null, site_user_id = $2, expires = $3
+        where token = $1",
+        &[&token, &user_id, &expires],
+    )
+    .await?;
+    log::info!("user {} logged in as {}", user_id, viewer.name);
+    Ok(User {
+        id: user_id,
+        anilist_user_id: viewer.id,
+        access_token,
+    })
+}
+
+#[derive(Serialize)]
+struct TokenRequest<'a> {
+    grant_type: &'a str,
+    client_id: i64,
+    client_secret: &'a str,
+    redirect_uri: &'a str,
+    code: &'a str,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+async fn exchange_code(
+    state: &State<'_>,
+    config: &Watchlists,
+    code: &str,
+) -> Result<String> {
+    let (client_id, client_secret) =
+        match (config.anilist_client_id, &config.anilist_client_secret) {
+            (Some(id), Some(secret)) => (id, secret),
+            _ => return Err(anyhow!("the anilist client is not configured")),
+        };
+    let request = TokenRequest {
+        grant_type: "authorization_code",
+        client_id,
+        client_secret,
+        redirect_uri: &config.redirect_url,
+        code,
+    };
+    let response: TokenResponse = state
+        .web_client
+        .post(&format!("{}/token", config.oauth_url.trim_end_matches('/')))
+        .header("Accept", "application/json")
+        .json(&request)
+        .send()
+        .await
+        .context("cannot send the token request")?
+        .error_for_status()?
+        .json()
+        .await
+        .context("cannot parse the token response")?;
+    Ok(response.access_token)
+}
+
+#[derive(Deserialize, Debug)]
+struct Viewer {
+    id: i64,
+    name: String,
+}
+
+async fn viewer(state: &State<'_>, access_token: &str) -> Result<Viewer> {
+    const QUERY: &str = r#"
+query {
+  viewer: Viewer {
+    id
+    name
+  }
+}"#;
+
+    #[derive(Serialize)]
+    struct Variables {}
+
+    #[derive(Deserialize, Debug)]
+    struct Data {
+        viewer: Viewer,
+    }
+
+    let data: Data = state
+        .anilist_client
+        .try_request_as(access_token, QUERY, &Variables {})
+        .await?;
+    Ok(data.viewer)
+}
+
+/// Replaces the watchlists of all users with their "watching" lists on AniList
+///
+/// Also deletes the expired sessions.
+pub async fn sync_watchlists(state: &State<'_>) {
+    if state.config.watchlists.anilist_client_secret.is_none() {
+        return;
+    }
+    loop {
+        log::info!("syncing the watchlists");
+        if let Err(e) = sync_watchlists_now(state).await {
+            log::error!("could not sync the watchlists: {:#}", e);
+        }
+        state.sleep(|c| c.watchlists.sync_interval).await;
+    }
+}
+
+async fn sync_watchlists_now(state: &State<'_>) -> Result<()> {
+    let mut con = state.pg_connector.connect().await?;
+    state.leader.ensure().await?;
+    // language=sql
+    con.execute(
+        "delete from magnets.site_session where expires <= now()",
+        &[],
+    )
+    .await?;
+    // language=sql
+    let rows = con
+        .query(
+            "
+            select site_user_id, anilist_user_id, access_token
+            from magnets.site_user
+            order by site_user_id",
+            &[],
+        )
+        .await?;
+    let mut sleeper = Sleeper::new(state.clock.clone());
+    for row in &rows {
+        let delay = state.live_config.current().watchlists.sync_user_delay;
+        sleeper.sleep(delay).await;
+        let user = User {
+            id: row.get(0),
+            anilist_user_id: row.get(1),
+            access_token: row.get(2),
+        };
+        if let Err(e) = sync_user(state, &mut con, &user).await {
+            log::error!("could not sync the watchlist of user {}: {:#}", user.id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn sync_user(state: &State<'_>, con: &mut PgClient, user: &User) -> Result<()> {
+    let anilist_ids = watching(state, user.anilist_user_id, &user.access_token).await?;
+    let tran = pg::transaction(con).await?;
+    // language=sql
+    tran.execute(
+        "delete from magnets.watchlist where site_user_id = $1",
+        &[&user.id],
+    )
+    .await?;
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.watchlist (site_user_id, show_id)
+        select $1, show_id
+        from magnets.show
+        where anilist_id = any($2::bigint[]) and removal is null",
+        &[&user.id, &anilist_ids],
+    )
+    .await?;
+    // language=sql
+    tran.execute(
+        "update magnets.site_user set synced_at = now() where site_user_id = $1",
+        &[&user.id],
+    )
+    .await?;
+    state.leader.ensure().await?;
+    tran.commit().await?;
+    Ok(())
+}
+
+/// Returns the AniList ids of the shows on the "watching" list of a user
+async fn watching(
+    state: &State<'_>,
+    anilist_user_id: i64,
+    access_token: &str,
+) -> Result<Vec<i64>> {
+    const QUERY: &str = r#"
+query ($user_id: Int) {
+  collection: MediaListCollection(userId: $user_id, type: ANIME, status: CURRENT) {
+    lists {
+      entries {
+        media_id: mediaId
+      }
+    }
+  }
+}"#;
+
+    #[derive(Serialize)]
+    struct Variables {
+        user_id: i64,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Entry {
+        media_id: i64,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct List {
+        entries: Vec<Entry>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Collection {
+        lists: Vec<List>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Data {
+        collection: Collection,
+    }
+
+    let variables = Variables {
+        user_id: anilist_user_id,
+    };
+    // The access token is required to read private lists
+    let data: Data = state
+        .anilist_client
+        .try_request_as(access_token, QUERY, &variables)
+        .await?;
+    let ids = data
+        .collection
+        .lists
+        .into_iter()
+        .flat_map(|l| l.entries)
+        .map(|e| e.media_id)
+        .collect();
+    Ok(ids)
+}
@@ -0,0 +1,345 @@
+use crate::{
+    config::Config,
+    notifiers,
+    notifiers::{Notification, WebhookKind},
+    show_lifecycle,
+    state::State,
+};
+use anyhow::{anyhow, Result};
+use chrono::Duration;
+use common::{pg, pg::PgConnector, MagnetFormatter};
+use tokio_postgres::Transaction;
+
+const USAGE: &str = "usage:
+    processor webhook add <show_id|all> <url>
+    processor webhook discord <show_id|all> <url>
+    processor webhook telegram <show_id|all> <chat_id>
+    processor webhook remove <webhook_id>
+    processor webhook list";
+
+enum Command {
+    Add {
+        /// `None` for all shows
+        show_id: Option<i64>,
+        kind: WebhookKind,
+        target: String,
+    },
+    Remove(i64),
+    List,
+}
+
+/// Manages the webhooks that are notified when a torrent is matched to a show
+///
+/// Invoked as
+///
+/// - `processor webhook add <show_id|all> <url>` to post every torrent that is matched
+///   to the show from now on to the url as json. `all` subscribes to all shows.
+/// - `processor webhook discord <show_id|all> <url>` to post the message in
+///   `webhooks.message` to a Discord webhook instead.
+/// - `processor webhook telegram <show_id|all> <chat_id>` to send the message to a
+///   Telegram chat with the bot in `webhooks.telegram_bot_token`.
+/// - `processor webhook remove <webhook_id>` to remove a webhook. Notifications that
+///   have not been delivered yet are dropped.
+/// - `processor webhook list` to print the webhooks.
+pub fn webhook(args: &[String]) -> Result<()> {
+    let command = parse(args)?;
+    tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async_webhook(&command))
+}
+
+fn parse(args: &[String]) -> Result<Command> {
+    let command = match args {
+        [command, show_id, target] => {
+            let kind = match &**command {
+                "add" => WebhookKind::Json,
+                "discord" => WebhookKind::Discord,
+                "telegram" => WebhookKind::Telegram,
+                _ => return Err(anyhow!(USAGE)),
+            };
+            let is_url = target.starts_with("https://") || target.starts_with("http://");
+            if kind != WebhookKind::Telegram && !is_url {
+                return Err(anyhow!("the url must be an http(s) url"));
+            }
+            Command::Add {
+                show_id: match &**show_id {
+                    "all" => None,
+                    _ => Some(
+                        show_id
+                            .parse()
+                            .map_err(|_| anyhow!("invalid show id {}", show_id))?,
+                    ),
+                },
+                kind,
+                target: target.clone(),
+            }
+        }
+        [command, id] if command == "remove" => Command::Remove(
+            id.parse()
+                .map_err(|_| anyhow!("invalid webhook id {}", id))?,
+        ),
+        [command] if command == "list" => Command::List,
+        _ => return Err(anyhow!(USAGE)),
+    };
+    Ok(command)
+}
+
+async fn async_webhook(command: &Command) -> Result<()> {
+    let config: Config = common::config::load()?;
+    let mut con = PgConnector::new(config.db.connection_string)
+        .connect()
+        .await?;
+    let tran = pg::transaction(&mut con).await?;
+    match *command {
+        Command::Add {
+            show_id,
+            kind,
+            ref target,
+        } => {
+            if let Some(show_id) = show_id {
+                show_lifecycle::lock_listed(&tran, show_id).await?;
+            }
+            // language=sql
+            let row = tran
+                .query_opt(
+                    "
+                    insert into magnets.webhook (show_id, kind, target) values ($1, $2, $3)
+                    on conflict (coalesce(show_id, 0), kind, target) do nothing
+                    returning webhook_id",
+                    &[&show_id, &kind.to_db(), target],
+                )
+                .await?;
+            let id: i64 = match row {
+                Some(row) => row.get(0),
+                _ => return Err(anyhow!("the webhook already exists")),
+            };
+            println!("added webhook {}", id);
+        }
+        Command::Remove(id) => {
+            // language=sql
+            let deleted = tran
+                .execute("delete from magnets.webhook where webhook_id = $1", &[&id])
+                .await?;
+            if deleted == 0 {
+                return Err(anyhow!("there is no webhook {}", id));
+            }
+            println!("removed webhook {}", id);
+        }
+        Command::List => list(&tran).await?,
+    }
+    tran.commit().await?;
+    Ok(())
+}
+
+async fn list(tran: &Transaction<'_>) -> Result<()> {
+    // language=sql
+    let rows = tran
+        .query(
+            "
+            select w.webhook_id, w.show_id, w.kind, w.target,
+                (
+                    select count(*)
+                    from magnets.webhook_delivery d
+                    where d.webhook_id = w.webhook_id
+                ) as pending
+            from magnets.webhook w
+            order by w.webhook_id",
+            &[],
+        )
+        .await?;
+    println!("webhooks:");
+    for row in &rows {
+        let show = match row.get::<_, Option<i64>>("show_id") {
+            Some(show_id) => format!("show {}", show_id),
+            _ => "all shows".to_string(),
+        };
+        println!(
+            "    {}: {}: {} {} ({} pending)",
+            row.get::<_, i64>("webhook_id"),
+            show,
+            WebhookKind::from_db(row.get("kind"))?.as_str(),
+            row.get::<_, String>("target"),
+            row.get::<_, i64>("pending"),
+        );
+    }
+    Ok(())
+}
+
+/// Queues the notifications of the webhooks of a show and of all shows about a new match
+///
+/// Called in the transaction that ingests the torrent so that the notifications are
+/// delivered if and only if the match is committed. Rematches do not queue
+/// notifications. Webhooks are not notified about torrents that were uploaded before
+/// they were added.
+pub async fn queue(tran: &Transaction<'_>, torrent_id: i64, show_id: i64) -> Result<()> {
+    // language=sql
+    tran.execute(
+        "
+        insert into magnets.webhook_delivery (webhook_id, torrent_id, show_id)
+        select w.webhook_id, $2, $1
+        from magnets.webhook w
+        join magnets.torrent t on t.torrent_id = $2
+        where (w.show_id = $1 or w.show_id is null) and t.uploaded_at >= w.created",
+        &[&show_id, &torrent_id],
+    )
+    .await?;
+    Ok(())
+}
+
+/// The number of notifications that are delivered per query
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+/// Posts the queued notifications to the webhooks
+///
+/// See [notifiers] for the formats of the notifications. Deliveries that fail are retried
+/// with exponential backoff and dropped after `webhooks.max_attempts` attempts.
+pub async fn deliver_webhooks(state: &State<'_>) {
+    loop {
+        if let Err(e) = deliver_now(state).await {
+            log::error!("could not deliver the webhook notifications: {:#}", e);
+        }
+        state.sleep(|c| c.webhooks.poll_interval).await;
+    }
+}
+
+async fn deliver_now(state: &State<'_>) -> Result<()> {
+    let config = state.live_config.current();
+    let max_attempts = config.webhooks.max_attempts;
+    let con = state.pg_connector.connect().await?;
+    loop {
+        // language=sql
+        let rows = con
+            .query(
+                "
+                select d.webhook_delivery_id, d.attempts, w.kind, w.target, d.show_id,
+                    s.anilist_id,
+                    coalesce((
+                        select sn.name
+                        from magnets.show_name sn
+                        where sn.show_id = d.show_id and sn.show_name_type = 1
+                        limit 1
+                    ), '') as show_name,
+                    rts.episode, rts.episode_end, t.torrent_id, t.nyaa_id, t.title,
+                    t.uploaded_at, t.hash
+                from magnets.webhook_delivery d
+                join magnets.webhook w using (webhook_id)
+                join magnets.show s on s.show_id = d.show_id
+                join magnets.torrent t on t.torrent_id = d.torrent_id
+                left join magnets.rel_torrent_show rts
+                    on rts.torrent_id = d.torrent_id and rts.show_id = d.show_id
+                where d.next_attempt <= now()
+                order by d.webhook_delivery_id
+                limit $1",
+                &[&DELIVERY_BATCH_SIZE],
+            )
+            .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        for row in &rows {
+            let id: i64 = row.get("webhook_delivery_id");
+            let attempts = row.get::<_, i32>("attempts") + 1;
+            let kind = WebhookKind::from_db(row.get("kind"))?;
+            let target: &str = row.get("target");
+            let show_name: &str = row.get("show_name");
+            let episode: Option<i32> = row.get("episode");
+            let episode_end: Option<i32> = row.get("episode_end");
+            let nyaa_id: i64 = row.get("nyaa_id");
+            let title: &str = row.get("title");
+            let hash: Vec<u8> = row.get("hash");
+            let magnet = MagnetFormatter::new(title, &hash).to_string();
+            let text = notifiers::render_message(
+                &config.webhooks.message,
+                show_name,
+                episode,
+                episode_end,
+                title,
+                &magnet,
+                nyaa_id,
+            );
+            let notification = Notification {
+                show_id: row.get("show_id"),
+                anilist_id: row.get("anilist_id"),
+                show_name,
+                episode,
+                episode_end,
+                torrent_id: row.get("torrent_id"),
+                nyaa_id,
+                title,
+                uploaded_at: row.get("uploaded_at"),
+                magnet,
+                content: text.clone(),
+                text,
+            };
+            state.leader.ensure().await?;
+            let sent =
+                notifiers::send(state, &config.webhooks, kind, target, &notification)
+                    .await;
+            match sent {
+                Ok(()) => {
+                    // language=sql
+                    con.execute(
+                        "delete from magnets.webhook_delivery where webhook_delivery_id = $1",
+                        &[&id],
+                    )
+                    .await?;
+                }
+                Err(e) if attempts >= max_attempts => {
+                    log::error!(
+                        "dropping notification {} after {} attempts: {:#}",
+                        id,
+                        attempts,
+                        e
+                    );
+                    // language=sql
+                    con.execute(
+                        "delete from magnets.webhook_delivery where webhook_delivery_id = $1",
+                        &[&id],
+                    )
+                    .await?;
+                }
+                Err(e) => {
+                    log::warn!(
+                        "could not deliver notification {} (attempt {}): {:#}",
+                        id,
+                        attempts,
+                        e
+                    );
+                    // language=sql
+                    con.execute(
+                        "
+                        update magnets.webhook_delivery
+                        set attempts = $2, next_attempt = $3
+                        where webhook_delivery_id = $1",
+                        &[&id, &attempts, &(state.clock.now() + backoff(attempts))],
+                    )
+                    .await?;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the time until the next attempt after `attempts` failed attempts
+///
+/// One minute after the first failure, doubling up to one day.
+fn backoff(attempts: i32) -> Duration {
+    let minutes = 1i64 << (attempts - 1).max(0).min(11);
+    Duration::minutes(minutes).min(Duration::days(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off() {
+        assert_eq!(backoff(1), Duration::minutes(1));
+        assert_eq!(backoff(2), Duration::minutes(2));
+        assert_eq!(backoff(5), Duration::minutes(16));
+        assert_eq!(backoff(30), Duration::days(1));
+    }
+}
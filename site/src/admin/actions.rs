@@ -0,0 +1,236 @@
+use crate::{
+    admin::{check_same_origin, AdminUser},
+    notify::FLUSH_CACHES,
+    state::State,
+    text::TEXT_HTML,
+};
+use actix_web::{
+    http::header::LOCATION, web, web::Data, HttpRequest, HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use common::Role;
+use serde_json::Value;
+
+/// An action that can be triggered by setting a key in `magnets.state`
+///
+/// The processor listens for changes of these keys and performs the action as soon as
+/// possible. The `maintenance` key is used by the site itself. Flushing the caches is
+/// the exception: it is a notification to all site workers instead of a state change.
+/// The rematch preview and alias suggestion actions are triggered from
+/// /admin/rematch-preview and /admin/alias-suggestions instead of /admin/actions.
+#[derive(Copy, Clone)]
+pub enum Action {
+    RematchUnmatched,
+    RematchAll,
+    SyncShows,
+    SyncSchedule,
+    EnableMaintenance,
+    DisableMaintenance,
+    ComputeRematchPreview,
+    ApplyRematchPreview,
+    ComputeAliasSuggestions,
+    ApplyAliasSuggestions,
+    FlushCaches,
+}
+
+const ACTIONS: &[Action] = &[
+    Action::RematchUnmatched,
+    Action::RematchAll,
+    Action::SyncShows,
+    Action::SyncSchedule,
+    Action::EnableMaintenance,
+    Action::DisableMaintenance,
+    Action::FlushCaches,
+];
+
+/// Setting a `last_*_update` key to a date in the past forces an immediate update
+const LONG_AGO: &str = "2000-01-01T00:00:00Z";
+
+impl Action {
+    pub fn to_url_str(self) -> &'static str {
+        match self {
+            Action::RematchUnmatched => "rematch-unmatched",
+            Action::RematchAll => "rematch-all",
+            Action::SyncShows => "sync-shows",
+            Action::SyncSchedule => "sync-schedule",
+            Action::EnableMaintenance => "enable-maintenance",
+            Action::DisableMaintenance => "disable-maintenance",
+            Action::ComputeRematchPreview => "compute-rematch-preview",
+            Action::ApplyRematchPreview => "apply-rematch-preview",
+            Action::ComputeAliasSuggestions => "compute-alias-suggestions",
+            Action::ApplyAliasSuggestions => "apply-alias-suggestions",
+            Action::FlushCaches => "flush-caches",
+        }
+    }
+
+    pub fn from_url_str(s: &str) -> Option<Self> {
+        ACTIONS.iter().copied().find(|a| a.to_url_str() == s)
+    }
+
+    /// Returns the role required to perform this action
+    pub fn required_role(self) -> Role {
+        match self {
+            Action::RematchUnmatched | Action::FlushCaches => Role::Moderator,
+            Action::RematchAll
+            | Action::SyncShows
+            | Action::SyncSchedule
+            | Action::EnableMaintenance
+            | Action::DisableMaintenance
+            | Action::ComputeRematchPreview
+            | Action::ApplyRematchPreview
+            | Action::ComputeAliasSuggestions
+            | Action::ApplyAliasSuggestions => Role::Admin,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::RematchUnmatched => "Rematch unmatched torrents",
+            Action::RematchAll => "Rematch all torrents",
+            Action::SyncShows => "Synchronize shows with AniList",
+            Action::SyncSchedule => "Synchronize schedule with AniList",
+            Action::EnableMaintenance => "Enable maintenance mode",
+            Action::DisableMaintenance => "Disable maintenance mode",
+            Action::ComputeRematchPreview => "Compute the rematch preview",
+            Action::ApplyRematchPreview => "Apply the approved rematch changes",
+            Action::ComputeAliasSuggestions => "Compute the alias suggestions",
+            Action::ApplyAliasSuggestions => "Add the approved aliases",
+            Action::FlushCaches => "Flush the caches of the site",
+        }
+    }
+
+    /// Returns the `magnets.state` key and value that trigger this action
+    ///
+    /// Returns `None` for actions that are not triggered via `magnets.state`.
+    pub fn state_change(self) -> Option<(&'static str, Value)> {
+        let change = match self {
+            Action::RematchUnmatched => ("rematch_unmatched", Value::from(1)),
+            Action::RematchAll => ("rematch_unmatched", Value::from(2)),
+            Action::SyncShows => ("last_shows_update", Value::from(LONG_AGO)),
+            Action::SyncSchedule => ("last_schedule_update", Value::from(LONG_AGO)),
+            Action::EnableMaintenance => ("maintenance", Value::from(true)),
+            Action::DisableMaintenance => ("maintenance", Value::from(false)),
+            Action::ComputeRematchPreview => ("match_diff", Value::from(1)),
+            Action::ApplyRematchPreview => ("match_diff", Value::from(2)),
+            Action::ComputeAliasSuggestions => ("alias_suggestions", Value::from(1)),
+            Action::ApplyAliasSuggestions => ("alias_suggestions", Value::from(2)),
+            Action::FlushCaches => return None,
+        };
+        Some(change)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin_actions.html")]
+struct Actions<'a> {
+    user: &'a str,
+    actions: &'a [Action],
+    states: Vec<(String, String)>,
+}
+
+#[actix_web::get("/admin/actions")]
+pub async fn get(state: Data<State>, user: AdminUser) -> impl Responder {
+    // every role may view the actions
+    match render(&state, &user).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to render admin actions: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn render(state: &State, user: &AdminUser) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let rows = db.query(&db.t.admin_state.stmt, &[]).await?;
+    let states = rows
+        .iter()
+        .map(|r| (r.get(db.t.admin_state.key), r.get(db.t.admin_state.value)))
+        .collect();
+    let tpl = Actions {
+        user: &user.name,
+        actions: ACTIONS,
+        states,
+    };
+    Ok(tpl.render()?)
+}
+
+#[actix_web::post("/admin/actions/{action}")]
+pub async fn post(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    action: web::Path<(String,)>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let action = match Action::from_url_str(&action.0.0) {
+        Some(a) => a,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    user.require(action.required_role())?;
+    if let Err(e) = perform(&state, &user, action).await {
+        log::error!(
+            "An error occurred while trying to perform admin action {}: {:#}",
+            action.to_url_str(),
+            e
+        );
+        return Ok(HttpResponse::InternalServerError().finish());
+    }
+    log::info!(
+        "{} triggered admin action {}",
+        user.name,
+        action.to_url_str()
+    );
+    Ok(HttpResponse::SeeOther()
+        .header(LOCATION, "/admin/actions")
+        .finish())
+}
+
+pub async fn perform(state: &State, user: &AdminUser, action: Action) -> Result<()> {
+    let db = state.pg.borrow().await?;
+    let (key, value) = match action.state_change() {
+        Some(c) => c,
+        _ => {
+            // language=sql
+            db.execute("select pg_notify($1, '')", &[&FLUSH_CACHES])
+                .await?;
+            // language=sql
+            db.execute(
+                "insert into magnets.audit_log (actor, action) values ($1, $2)",
+                &[&user.name, &action.to_url_str()],
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    // language=sql
+    db.execute(
+        "
+        with
+            old as (
+                select value from magnets.state where key = $2
+            ),
+            new as (
+                update magnets.state set value = $1 where key = $2
+            )
+        insert into magnets.audit_log (actor, action, before, after)
+        select
+            $3,
+            $4,
+            jsonb_build_object('key', $2::text, 'value', old.value),
+            jsonb_build_object('key', $2::text, 'value', $1::jsonb)
+        from old",
+        &[&value, &key, &user.name, &action.to_url_str()],
+    )
+    .await?;
+    match action {
+        Action::EnableMaintenance => state.global.maintenance.set(true),
+        Action::DisableMaintenance => state.global.maintenance.set(false),
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::{
+    admin::{actions, actions::Action, check_same_origin, AdminUser},
+    state::State,
+    text::TEXT_HTML,
+};
+use actix_web::{
+    http::header::LOCATION, web, web::Data, HttpRequest, HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use common::Role;
+
+const PENDING: i32 = 0;
+const APPROVED: i32 = 1;
+const REJECTED: i32 = 2;
+
+#[actix_web::get("/admin/alias-suggestions")]
+pub async fn get(state: Data<State>, _user: AdminUser) -> impl Responder {
+    match render(&state).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to render the alias suggestions: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+struct Suggestion {
+    alias_suggestion_id: i64,
+    alias: String,
+    show_id: i64,
+    name: String,
+    torrents: i32,
+    example_title: String,
+    status: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "admin_alias_suggestions.html")]
+struct Suggestions {
+    suggestions: Vec<Suggestion>,
+}
+
+fn status_str(status: i32) -> &'static str {
+    match status {
+        PENDING => "pending",
+        APPROVED => "approved",
+        REJECTED => "rejected",
+        _ => "unknown",
+    }
+}
+
+async fn render(state: &State) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.alias_suggestions;
+    let rows = db.query(&stmt.stmt, &[]).await?;
+    let suggestions = rows
+        .iter()
+        .map(|row| Suggestion {
+            alias_suggestion_id: row.get(stmt.alias_suggestion_id),
+            alias: row.get(stmt.alias),
+            show_id: row.get(stmt.show_id),
+            name: row.get(stmt.name),
+            torrents: row.get(stmt.torrents),
+            example_title: row.get(stmt.example_title),
+            status: status_str(row.get(stmt.status)),
+        })
+        .collect();
+    Ok(Suggestions { suggestions }.render()?)
+}
+
+/// Computes the suggestions or adds the approved aliases
+#[actix_web::post("/admin/alias-suggestions/{action}")]
+pub async fn post_action(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    action: web::Path<(String,)>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let action = match &*action.0.0 {
+        "compute" => Action::ComputeAliasSuggestions,
+        "apply" => Action::ApplyAliasSuggestions,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    user.require(action.required_role())?;
+    if let Err(e) = actions::perform(&state, &user, action).await {
+        log::error!(
+            "An error occurred while trying to perform admin action {}: {:#}",
+            action.to_url_str(),
+            e
+        );
+        return Ok(HttpResponse::InternalServerError().finish());
+    }
+    log::info!(
+        "{} triggered admin action {}",
+        user.name,
+        action.to_url_str()
+    );
+    Ok(HttpResponse::SeeOther()
+        .header(LOCATION, "/admin/alias-suggestions")
+        .finish())
+}
+
+/// Approves or rejects a suggestion
+#[actix_web::post("/admin/alias-suggestions/{alias_suggestion_id}/{decision}")]
+pub async fn post_review(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    path: web::Path<(i64, String)>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let (alias_suggestion_id, decision) = path.into_inner();
+    let status = match &*decision {
+        "approve" => APPROVED,
+        "reject" => REJECTED,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    user.require(Role::Moderator)?;
+    if let Err(e) = review(&state, &user, alias_suggestion_id, status).await {
+        log::error!(
+            "An error occurred while trying to review alias suggestion {}: {:#}",
+            alias_suggestion_id,
+            e
+        );
+        return Ok(HttpResponse::InternalServerError().finish());
+    }
+    Ok(HttpResponse::SeeOther()
+        .header(LOCATION, "/admin/alias-suggestions")
+        .finish())
+}
+
+async fn review(
+    state: &State,
+    user: &AdminUser,
+    alias_suggestion_id: i64,
+    status: i32,
+) -> Result<()> {
+    let db = state.pg.borrow().await?;
+    let action = match status {
+        APPROVED => "approve-alias",
+        _ => "reject-alias",
+    };
+    // Applied suggestions can no longer be reviewed
+    // language=sql
+    db.execute(
+        "
+        with
+            old as (
+                select alias, show_id, status
+                from magnets.alias_suggestion
+                where alias_suggestion_id = $1 and status < 3
+            ),
+            new as (
+                update magnets.alias_suggestion
+                set status = $2
+                where alias_suggestion_id = $1 and status < 3
+            )
+        insert into magnets.audit_log (actor, action, before, after)
+        select
+            $3,
+            $4,
+            jsonb_build_object('alias', old.alias, 'show_id', old.show_id, 'status', old.status),
+            jsonb_build_object('alias', old.alias, 'show_id', old.show_id, 'status', $2)
+        from old",
+        &[&alias_suggestion_id, &status, &user.name, &action],
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::{admin::AdminUser, state::State, text::TEXT_HTML};
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    #[serde(rename = "a", default = "i64::max_value")]
+    after: i64,
+}
+
+#[actix_web::get("/admin/audit")]
+pub async fn get(
+    state: Data<State>,
+    _user: AdminUser,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    match process(&state, query).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to load the audit log: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+struct Entry {
+    audit_log_id: i64,
+    actor: String,
+    action: String,
+    before: Option<String>,
+    after: Option<String>,
+    created: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_audit.html")]
+struct Audit {
+    entries: Vec<Entry>,
+    last: Option<i64>,
+    first: bool,
+}
+
+mod filters {
+    pub use crate::text::format_full_time;
+}
+
+async fn process(state: &State, query: QueryParams) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let mut rows = db.query(&db.t.audit_log.stmt, &[&query.after]).await?;
+    let last = match rows.len() {
+        101 => {
+            rows.truncate(100);
+            Some(rows.last().unwrap().get(db.t.audit_log.audit_log_id))
+        }
+        _ => None,
+    };
+    let entries = rows
+        .iter()
+        .map(|row| Entry {
+            audit_log_id: row.get(db.t.audit_log.audit_log_id),
+            actor: row.get(db.t.audit_log.actor),
+            action: row.get(db.t.audit_log.action),
+            before: row.get(db.t.audit_log.before),
+            after: row.get(db.t.audit_log.after),
+            created: row.get(db.t.audit_log.created),
+        })
+        .collect();
+    let audit = Audit {
+        entries,
+        last,
+        first: query.after == i64::MAX,
+    };
+    Ok(audit.render()?)
+}
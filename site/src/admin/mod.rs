@@ -0,0 +1,178 @@
+use crate::{client_ip::client_ip, state::State};
+use actix_web::{
+    dev::Payload,
+    error::{ErrorForbidden, ErrorInternalServerError},
+    http::header::{HeaderName, AUTHORIZATION, HOST, ORIGIN, REFERER, WWW_AUTHENTICATE},
+    web::Data,
+    Error, FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use common::Role;
+use futures::future::LocalBoxFuture;
+use std::{fmt, net::IpAddr};
+
+pub mod actions;
+pub mod alias_suggestions;
+pub mod audit;
+pub mod rematch_preview;
+pub mod status;
+pub mod torrent;
+
+/// A user that has been authenticated via http basic authentication
+///
+/// The credentials are configured in the `admin.users` section of the config. The role
+/// of the user is stored in `magnets.admin_user` and can be changed with
+/// `processor grant <user> <role>`. Users without a role cannot access any admin route.
+pub struct AdminUser {
+    pub name: String,
+    pub role: Role,
+}
+
+impl AdminUser {
+    /// Fails with `403 Forbidden` unless the user has at least the `required` role
+    pub fn require(&self, required: Role) -> Result<(), Error> {
+        if self.role.allows(required) {
+            Ok(())
+        } else {
+            Err(ErrorForbidden(format!(
+                "this action requires the {} role",
+                required
+            )))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Unauthorized")
+    }
+}
+
+impl ResponseError for Unauthorized {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::Unauthorized()
+            .header(WWW_AUTHENTICATE, "Basic realm=\"magnets.moe admin\"")
+            .finish()
+    }
+}
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let state = req.app_data::<Data<State>>().cloned();
+        let credentials = basic_credentials(req);
+        let ip = client_ip(req);
+        Box::pin(async move {
+            let state = match state {
+                Some(s) => s,
+                _ => return Err(ErrorInternalServerError("state is not available")),
+            };
+            authenticate(&state, credentials, ip).await
+        })
+    }
+}
+
+async fn authenticate(
+    state: &State,
+    credentials: Option<(String, String)>,
+    ip: Option<IpAddr>,
+) -> Result<AdminUser, Error> {
+    let (name, password) = match credentials {
+        Some(c) => c,
+        _ => return Err(Unauthorized.into()),
+    };
+    match state.global.admin_users.get(&name) {
+        Some(expected) if constant_time_eq(expected.as_bytes(), password.as_bytes()) => {}
+        _ => {
+            log::warn!("failed admin login attempt for user {} from {:?}", name, ip);
+            return Err(Unauthorized.into());
+        }
+    }
+    let role = match load_role(state, &name).await {
+        Ok(Some(role)) => role,
+        Ok(None) => return Err(ErrorForbidden("user has no role")),
+        Err(e) => {
+            log::error!("could not load the role of user {}: {:#}", name, e);
+            return Err(ErrorInternalServerError("could not load role"));
+        }
+    };
+    Ok(AdminUser { name, role })
+}
+
+async fn load_role(state: &State, name: &str) -> anyhow::Result<Option<Role>> {
+    let db = state.pg.borrow().await?;
+    match db.query_opt(&db.t.admin_role.stmt, &[&name]).await? {
+        Some(row) => Ok(Some(Role::from_db(row.get(db.t.admin_role.role))?)),
+        _ => Ok(None),
+    }
+}
+
+fn basic_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64::decode(encoded.trim()).ok()?).ok()?;
+    let pos = decoded.find(':')?;
+    Some((decoded[..pos].to_string(), decoded[pos + 1..].to_string()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Rejects cross-site form submissions
+///
+/// Browsers send basic authentication credentials with cross-site requests, so all
+/// mutating admin routes must check that the request originates from this site. The
+/// `Referer` is checked if the browser sent no `Origin`. Requests without either are
+/// rejected.
+pub fn check_same_origin(req: &HttpRequest) -> Result<(), Error> {
+    let header = |name: HeaderName| req.headers().get(name).and_then(|h| h.to_str().ok());
+    let source = header(ORIGIN).or_else(|| header(REFERER));
+    if !is_same_origin(source, header(HOST).unwrap_or("")) {
+        return Err(ErrorForbidden("cross-origin request"));
+    }
+    Ok(())
+}
+
+/// Returns whether the `Origin` or `Referer` `source` points to `host`
+fn is_same_origin(source: Option<&str>, host: &str) -> bool {
+    let source_host = source
+        .and_then(|s| s.split("://").nth(1))
+        .and_then(|s| s.split('/').next())
+        .unwrap_or("");
+    !host.is_empty() && source_host == host
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_same_origin() {
+        let host = "magnets.moe";
+        assert!(is_same_origin(Some("https://magnets.moe"), host));
+        assert!(is_same_origin(
+            Some("https://magnets.moe/admin/actions"),
+            host
+        ));
+        assert!(!is_same_origin(
+            Some("https://evil.example/magnets.moe"),
+            host
+        ));
+        assert!(!is_same_origin(
+            Some("https://magnets.moe.evil.example"),
+            host
+        ));
+        assert!(!is_same_origin(Some("null"), host));
+        assert!(!is_same_origin(None, host));
+        assert!(!is_same_origin(Some("https://magnets.moe"), ""));
+    }
+}
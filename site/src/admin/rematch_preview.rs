@@ -0,0 +1,210 @@
+use crate::{
+    admin::{actions, actions::Action, check_same_origin, AdminUser},
+    state::State,
+    text::TEXT_HTML,
+};
+use actix_web::{
+    http::header::LOCATION,
+    web,
+    web::{Data, Query},
+    HttpRequest, HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use common::Role;
+use serde::Deserialize;
+
+const PENDING: i32 = 0;
+const APPROVED: i32 = 1;
+const REJECTED: i32 = 2;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    #[serde(rename = "a", default = "i64::max_value")]
+    after: i64,
+}
+
+#[actix_web::get("/admin/rematch-preview")]
+pub async fn get(
+    state: Data<State>,
+    _user: AdminUser,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    match render(&state, query).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to render the rematch preview: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+struct Change {
+    show_id: i64,
+    name: String,
+    added: bool,
+}
+
+struct Entry {
+    torrent_id: i64,
+    nyaa_id: i64,
+    title: String,
+    status: &'static str,
+    changes: Vec<Change>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_rematch_preview.html")]
+struct Preview {
+    entries: Vec<Entry>,
+    last: Option<i64>,
+    first: bool,
+}
+
+fn status_str(status: i32) -> &'static str {
+    match status {
+        PENDING => "pending",
+        APPROVED => "approved",
+        REJECTED => "rejected",
+        _ => "unknown",
+    }
+}
+
+async fn render(state: &State, query: QueryParams) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.match_diff;
+    let rows = db.query(&stmt.stmt, &[&query.after]).await?;
+    let mut entries: Vec<Entry> = vec![];
+    for row in &rows {
+        let torrent_id: i64 = row.get(stmt.torrent_id);
+        if entries.last().map(|e| e.torrent_id) != Some(torrent_id) {
+            entries.push(Entry {
+                torrent_id,
+                nyaa_id: row.get(stmt.nyaa_id),
+                title: row.get(stmt.title),
+                status: status_str(row.get(stmt.status)),
+                changes: vec![],
+            });
+        }
+        entries.last_mut().unwrap().changes.push(Change {
+            show_id: row.get(stmt.show_id),
+            name: row.get(stmt.name),
+            added: row.get(stmt.added),
+        });
+    }
+    let last = match entries.len() {
+        101 => {
+            entries.truncate(100);
+            Some(entries.last().unwrap().torrent_id)
+        }
+        _ => None,
+    };
+    let preview = Preview {
+        entries,
+        last,
+        first: query.after == i64::MAX,
+    };
+    Ok(preview.render()?)
+}
+
+/// Computes the preview or applies the approved changes
+#[actix_web::post("/admin/rematch-preview/{action}")]
+pub async fn post_action(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    action: web::Path<(String,)>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let action = match &*action.0.0 {
+        "compute" => Action::ComputeRematchPreview,
+        "apply" => Action::ApplyRematchPreview,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    user.require(action.required_role())?;
+    if let Err(e) = actions::perform(&state, &user, action).await {
+        log::error!(
+            "An error occurred while trying to perform admin action {}: {:#}",
+            action.to_url_str(),
+            e
+        );
+        return Ok(HttpResponse::InternalServerError().finish());
+    }
+    log::info!(
+        "{} triggered admin action {}",
+        user.name,
+        action.to_url_str()
+    );
+    Ok(HttpResponse::SeeOther()
+        .header(LOCATION, "/admin/rematch-preview")
+        .finish())
+}
+
+/// Approves or rejects the changes of a torrent
+#[actix_web::post("/admin/rematch-preview/{torrent_id}/{decision}")]
+pub async fn post_review(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    path: web::Path<(i64, String)>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let (torrent_id, decision) = path.into_inner();
+    let status = match &*decision {
+        "approve" => APPROVED,
+        "reject" => REJECTED,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    user.require(Role::Moderator)?;
+    if let Err(e) = review(&state, &user, torrent_id, status).await {
+        log::error!(
+            "An error occurred while trying to review the rematch of torrent {}: {:#}",
+            torrent_id,
+            e
+        );
+        return Ok(HttpResponse::InternalServerError().finish());
+    }
+    Ok(HttpResponse::SeeOther()
+        .header(LOCATION, "/admin/rematch-preview")
+        .finish())
+}
+
+async fn review(
+    state: &State,
+    user: &AdminUser,
+    torrent_id: i64,
+    status: i32,
+) -> Result<()> {
+    let db = state.pg.borrow().await?;
+    let action = match status {
+        APPROVED => "approve-rematch",
+        _ => "reject-rematch",
+    };
+    // language=sql
+    db.execute(
+        "
+        with
+            old as (
+                select status
+                from magnets.match_diff
+                where torrent_id = $1
+                limit 1
+            ),
+            new as (
+                update magnets.match_diff set status = $2 where torrent_id = $1
+            )
+        insert into magnets.audit_log (actor, action, before, after)
+        select
+            $3,
+            $4,
+            jsonb_build_object('torrent_id', $1, 'status', old.status),
+            jsonb_build_object('torrent_id', $1, 'status', $2)
+        from old",
+        &[&torrent_id, &status, &user.name, &action],
+    )
+    .await?;
+    Ok(())
+}
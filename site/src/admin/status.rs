@@ -0,0 +1,74 @@
+use crate::{admin::AdminUser, state::State, text::TEXT_HTML};
+use actix_web::{web::Data, HttpResponse, Responder};
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Utc};
+
+#[actix_web::get("/admin/status")]
+pub async fn get(state: Data<State>, _user: AdminUser) -> impl Responder {
+    match render(&state).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to render the admin status: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The torrents ingested in an hour and how many of them have been matched
+struct Hour {
+    hour: DateTime<Utc>,
+    matched: i64,
+    torrents: i64,
+    percent: i64,
+}
+
+#[derive(Template)]
+#[template(path = "admin_status.html")]
+struct Status {
+    matcher_anomaly: bool,
+    maintenance: bool,
+    hours: Vec<Hour>,
+}
+
+mod filters {
+    pub use crate::text::format_full_time;
+}
+
+async fn render(state: &State) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    // Set by the processor, see `matcher.anomaly` in its config
+    let matcher_anomaly = db
+        .query(&db.t.admin_state.stmt, &[])
+        .await?
+        .iter()
+        .any(|r| {
+            r.get::<_, &str>(db.t.admin_state.key) == "matcher_anomaly"
+                && r.get::<_, &str>(db.t.admin_state.value) == "true"
+        });
+    let stmt = &db.t.match_rate;
+    let hours = db
+        .query(&stmt.stmt, &[])
+        .await?
+        .iter()
+        .map(|row| {
+            let matched: i64 = row.get(stmt.matched);
+            let torrents: i64 = row.get(stmt.torrents);
+            Hour {
+                hour: row.get(stmt.hour),
+                matched,
+                torrents,
+                percent: 100 * matched / torrents.max(1),
+            }
+        })
+        .collect();
+    let status = Status {
+        matcher_anomaly,
+        maintenance: state.global.maintenance.enabled(),
+        hours,
+    };
+    Ok(status.render()?)
+}
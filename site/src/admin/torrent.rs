@@ -0,0 +1,289 @@
+use crate::{
+    admin::{check_same_origin, AdminUser},
+    state::State,
+    text::{NotFound, TEXT_HTML},
+};
+use actix_web::{
+    http::header::LOCATION,
+    web,
+    web::{Data, Form, Query},
+    HttpRequest, HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use common::Role;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    torrent_id: i64,
+}
+
+#[actix_web::get("/admin/torrent")]
+pub async fn get(
+    state: Data<State>,
+    user: AdminUser,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    match render(&state, &user, query.torrent_id).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            if e.is::<NotFound>() {
+                HttpResponse::NotFound().finish()
+            } else {
+                log::error!(
+                    "An error occurred while trying to render the matches of torrent {}: {:#}",
+                    query.torrent_id,
+                    e
+                );
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+struct Match {
+    show_id: i64,
+    name: String,
+    matched: bool,
+    /// `Some(true)` if the torrent has been assigned to the show, `Some(false)` if the
+    /// match has been rejected
+    assigned: Option<bool>,
+    actor: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_torrent.html")]
+struct Torrent<'a> {
+    user: &'a str,
+    torrent_id: i64,
+    nyaa_id: i64,
+    title: String,
+    matches: Vec<Match>,
+}
+
+async fn render(state: &State, user: &AdminUser, torrent_id: i64) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.torrent;
+    let torrent = match db.query_opt(&stmt.stmt, &[&torrent_id]).await? {
+        Some(r) => r,
+        _ => return Err(NotFound.into()),
+    };
+    let matches_stmt = &db.t.admin_torrent_matches;
+    let matches = db
+        .query(&matches_stmt.stmt, &[&torrent_id])
+        .await?
+        .iter()
+        .map(|row| Match {
+            show_id: row.get(matches_stmt.show_id),
+            name: row.get(matches_stmt.name),
+            matched: row.get(matches_stmt.matched),
+            assigned: row.get(matches_stmt.assigned),
+            actor: row.get(matches_stmt.actor),
+        })
+        .collect();
+    let tpl = Torrent {
+        user: &user.name,
+        torrent_id,
+        nyaa_id: torrent.get(stmt.nyaa_id),
+        title: torrent.get(stmt.title),
+        matches,
+    };
+    Ok(tpl.render()?)
+}
+
+#[derive(Deserialize)]
+pub struct ShowForm {
+    show_id: i64,
+}
+
+/// Assigns a torrent to a show or removes a match
+///
+/// The correction is recorded in `magnets.match_override` so that rematches keep it.
+/// Assigned matches have no episode until all torrents are rematched.
+#[actix_web::post("/admin/torrent/{torrent_id}/matches/{action}")]
+pub async fn post_correction(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    path: web::Path<(i64, String)>,
+    form: Form<ShowForm>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let (torrent_id, action) = path.into_inner();
+    let assign = match &*action {
+        "assign" => true,
+        "remove" => false,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    user.require(Role::Moderator)?;
+    match correct(&state, &user, torrent_id, form.show_id, assign).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(HttpResponse::BadRequest().body("unknown torrent or show"));
+        }
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to correct the matches of torrent {}: {:#}",
+                torrent_id,
+                e
+            );
+            return Ok(HttpResponse::InternalServerError().finish());
+        }
+    }
+    log::info!(
+        "{} {} torrent {} and show {}",
+        user.name,
+        match assign {
+            true => "assigned",
+            false => "unmatched",
+        },
+        torrent_id,
+        form.show_id
+    );
+    Ok(redirect(torrent_id))
+}
+
+/// Returns false if the torrent or the show does not exist
+async fn correct(
+    state: &State,
+    user: &AdminUser,
+    torrent_id: i64,
+    show_id: i64,
+    assign: bool,
+) -> Result<bool> {
+    let db = state.pg.borrow().await?;
+    // language=sql
+    let exists = db
+        .query_opt(
+            "
+            select 1
+            from magnets.torrent t, magnets.show s
+            where t.torrent_id = $1 and s.show_id = $2 and s.removal is null",
+            &[&torrent_id, &show_id],
+        )
+        .await?
+        .is_some();
+    if !exists {
+        return Ok(false);
+    }
+    let sql = match assign {
+        // language=sql
+        true => {
+            "
+            with
+                correction as (
+                    insert into magnets.match_override (torrent_id, show_id, assigned, actor)
+                    values ($1, $2, true, $3)
+                    on conflict (torrent_id, show_id)
+                    do update set assigned = true, actor = $3, created = now()
+                ),
+                matched as (
+                    insert into magnets.rel_torrent_show (show_id, torrent_id, nyaa_id)
+                    select $2, torrent_id, nyaa_id
+                    from magnets.torrent
+                    where torrent_id = $1
+                    on conflict (torrent_id, show_id) do nothing
+                ),
+                torrent as (
+                    update magnets.torrent set matched = true where torrent_id = $1
+                )
+            insert into magnets.audit_log (actor, action, after)
+            values ($3, 'assign-match', jsonb_build_object('torrent_id', $1, 'show_id', $2))"
+        }
+        // All parts of the statement see the same snapshot. The deleted match must
+        // therefore be excluded explicitly when checking for other matches.
+        // language=sql
+        false => {
+            "
+            with
+                correction as (
+                    insert into magnets.match_override (torrent_id, show_id, assigned, actor)
+                    values ($1, $2, false, $3)
+                    on conflict (torrent_id, show_id)
+                    do update set assigned = false, actor = $3, created = now()
+                ),
+                unmatched as (
+                    delete from magnets.rel_torrent_show
+                    where torrent_id = $1 and show_id = $2
+                ),
+                torrent as (
+                    update magnets.torrent t
+                    set matched = exists (
+                        select *
+                        from magnets.rel_torrent_show rts
+                        where rts.torrent_id = t.torrent_id and rts.show_id <> $2
+                    )
+                    where torrent_id = $1
+                )
+            insert into magnets.audit_log (actor, action, before)
+            values ($3, 'remove-match', jsonb_build_object('torrent_id', $1, 'show_id', $2))"
+        }
+    };
+    db.execute(sql, &[&torrent_id, &show_id, &user.name])
+        .await?;
+    Ok(true)
+}
+
+/// Discards the matches and corrections of a torrent and queues it for rematching
+#[actix_web::post("/admin/torrent/{torrent_id}/rematch")]
+pub async fn post_rematch(
+    req: HttpRequest,
+    state: Data<State>,
+    user: AdminUser,
+    path: web::Path<(i64,)>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    let torrent_id = path.0.0;
+    user.require(Role::Moderator)?;
+    if let Err(e) = rematch(&state, &user, torrent_id).await {
+        log::error!(
+            "An error occurred while trying to rematch torrent {}: {:#}",
+            torrent_id,
+            e
+        );
+        return Ok(HttpResponse::InternalServerError().finish());
+    }
+    log::info!("{} queued torrent {} for rematching", user.name, torrent_id);
+    Ok(redirect(torrent_id))
+}
+
+async fn rematch(state: &State, user: &AdminUser, torrent_id: i64) -> Result<()> {
+    let db = state.pg.borrow().await?;
+    // A rematch of all torrents that is already pending also rematches this torrent
+    // language=sql
+    db.execute(
+        "
+        with
+            overrides as (
+                delete from magnets.match_override where torrent_id = $1
+            ),
+            unmatched as (
+                delete from magnets.rel_torrent_show where torrent_id = $1
+            ),
+            torrent as (
+                update magnets.torrent
+                set matched = false, batch = false
+                where torrent_id = $1
+            ),
+            rematch as (
+                update magnets.state
+                set value = '1'
+                where key = 'rematch_unmatched' and value = '0'
+            )
+        insert into magnets.audit_log (actor, action, after)
+        values ($2, 'rematch-torrent', jsonb_build_object('torrent_id', $1))",
+        &[&torrent_id, &user.name],
+    )
+    .await?;
+    Ok(())
+}
+
+fn redirect(torrent_id: i64) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .header(
+            LOCATION,
+            format!("/admin/torrent?torrent_id={}", torrent_id),
+        )
+        .finish()
+}
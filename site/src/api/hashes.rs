@@ -0,0 +1,112 @@
+use crate::{api::version::ApiVersion, state::State, text::parse_hex};
+use actix_web::{
+    web::{Data, Json},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use common::HexFormatter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The maximum number of hashes that can be looked up in a single request
+const MAX_HASHES: usize = 100;
+
+#[derive(Deserialize)]
+pub struct Request {
+    hashes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Response {
+    results: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    hash: String,
+    torrent: Option<Torrent>,
+}
+
+#[derive(Serialize, Clone)]
+struct Torrent {
+    torrent_id: i64,
+    nyaa_id: i64,
+    title: String,
+    uploaded_at: i64,
+    size: i64,
+    trusted: bool,
+    shows: Vec<Show>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Show {
+    show_id: i64,
+    name: String,
+}
+
+/// Looks up the torrents with the given info hashes
+///
+/// The results are returned in the order of the request. Hashes that are unknown have
+/// `"torrent": null`.
+#[actix_web::post("/api/v1/hashes")]
+pub async fn post(
+    state: Data<State>,
+    version: ApiVersion,
+    Json(request): Json<Request>,
+) -> impl Responder {
+    if request.hashes.len() > MAX_HASHES {
+        return HttpResponse::BadRequest()
+            .body(format!("at most {} hashes can be requested", MAX_HASHES));
+    }
+    let mut hashes = Vec::with_capacity(request.hashes.len());
+    for hash in &request.hashes {
+        match parse_hex(hash) {
+            Some(h) if h.len() == 20 => hashes.push(h),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .body(format!("invalid sha1 info hash: {}", hash));
+            }
+        }
+    }
+    match process(&state, hashes).await {
+        Ok(res) => {
+            let mut builder = HttpResponse::Ok();
+            version.add_headers(&mut builder);
+            builder.json(&res)
+        }
+        Err(e) => {
+            log::error!("An error occurred while trying to look up hashes: {:#}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn process(state: &State, hashes: Vec<Vec<u8>>) -> Result<Response> {
+    let db = state.pg.borrow().await?;
+    let rows = db.query(&db.t.hashes.stmt, &[&hashes]).await?;
+    let mut torrents = HashMap::with_capacity(rows.len());
+    for row in rows {
+        let uploaded_at: DateTime<Utc> = row.get(db.t.hashes.uploaded_at);
+        let shows: tokio_postgres::types::Json<Vec<Show>> = row.get(db.t.hashes.shows);
+        let hash: Vec<u8> = row.get(db.t.hashes.hash);
+        let torrent = Torrent {
+            torrent_id: row.get(db.t.hashes.torrent_id),
+            nyaa_id: row.get(db.t.hashes.nyaa_id),
+            title: row.get(db.t.hashes.title),
+            uploaded_at: uploaded_at.timestamp(),
+            size: row.get(db.t.hashes.size),
+            trusted: row.get(db.t.hashes.trusted),
+            shows: shows.0,
+        };
+        torrents.insert(hash, torrent);
+    }
+    let results = hashes
+        .iter()
+        .map(|hash| Entry {
+            hash: HexFormatter(hash).to_string(),
+            torrent: torrents.get(hash).cloned(),
+        })
+        .collect();
+    Ok(Response { results })
+}
@@ -0,0 +1,59 @@
+use crate::{
+    api::{json, version::ApiVersion},
+    state::State,
+};
+use actix_web::{web::Data, HttpResponse, Responder};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Returns statistics about the index
+///
+/// Mirrors and API consumers can use this to check how stale the data is.
+#[actix_web::get("/api/v1/meta")]
+pub async fn get(state: Data<State>, version: ApiVersion) -> impl Responder {
+    match process(&state).await {
+        Ok(meta) => json(version, &meta),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to retrieve the meta data via the api: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// All times are unix timestamps
+#[derive(Serialize)]
+struct Meta {
+    shows: i64,
+    torrents: i64,
+    unmatched_torrents: i64,
+    max_nyaa_id: Option<i64>,
+    newest_upload: Option<i64>,
+    last_torrent_import: Option<i64>,
+    last_shows_update: Option<i64>,
+    last_schedule_update: Option<i64>,
+    /// Seconds since the last torrent import
+    age: Option<i64>,
+}
+
+async fn process(state: &State) -> Result<Meta> {
+    let db = state.pg.borrow().await?;
+    let row = db.query_one(&db.t.api_meta.stmt, &[]).await?;
+    let stmt = &db.t.api_meta;
+    let time = |idx: usize| row.get::<_, Option<DateTime<Utc>>>(idx);
+    let last_torrent_import = time(stmt.last_torrent_import);
+    Ok(Meta {
+        shows: row.get(stmt.shows),
+        torrents: row.get(stmt.torrents),
+        unmatched_torrents: row.get(stmt.unmatched_torrents),
+        max_nyaa_id: row.get(stmt.max_nyaa_id),
+        newest_upload: time(stmt.newest_upload).map(|t| t.timestamp()),
+        last_torrent_import: last_torrent_import.map(|t| t.timestamp()),
+        last_shows_update: time(stmt.last_shows_update).map(|t| t.timestamp()),
+        last_schedule_update: time(stmt.last_schedule_update).map(|t| t.timestamp()),
+        age: last_torrent_import.map(|t| (Utc::now() - t).num_seconds()),
+    })
+}
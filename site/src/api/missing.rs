@@ -0,0 +1,44 @@
+use crate::{
+    api::{json, version::ApiVersion},
+    missing::missing_episodes,
+    state::State,
+};
+use actix_web::{web, web::Data, HttpResponse, Responder};
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Missing {
+    show_id: i64,
+    missing_episodes: Vec<i32>,
+}
+
+#[actix_web::get("/api/v1/show/{show_id}/missing")]
+pub async fn get(
+    state: Data<State>,
+    version: ApiVersion,
+    id: web::Path<(i64,)>,
+) -> impl Responder {
+    let show_id = id.0.0;
+    match process(&state, show_id).await {
+        Ok(Some(missing)) => json(version, &missing),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to retrieve the missing episodes of show {}: {:#}",
+                show_id,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn process(state: &State, show_id: i64) -> Result<Option<Missing>> {
+    let repo = state.repo().await?;
+    let missing = missing_episodes(&repo, show_id, state.global.clock.now()).await?;
+    Ok(missing.map(|missing_episodes| Missing {
+        show_id,
+        missing_episodes,
+    }))
+}
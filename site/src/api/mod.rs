@@ -0,0 +1,67 @@
+use crate::{api::version::ApiVersion, state::Global};
+use actix_web::{
+    dev::ServiceRequest,
+    http::header::{CacheControl, CacheDirective, CACHE_CONTROL},
+    HttpResponse,
+};
+use common::{flags::Flag, ShowNameType};
+use serde::{Deserialize, Serialize};
+
+pub mod hashes;
+pub mod meta;
+pub mod missing;
+pub mod nyaa;
+pub mod search;
+pub mod season;
+pub mod seasons;
+pub mod torrent;
+pub mod torrents;
+pub mod version;
+pub mod versions;
+
+/// Returns 404 for api requests if the api has been disabled via the `api` flag
+pub fn check_enabled(global: &Global, req: &ServiceRequest) -> Option<HttpResponse> {
+    if req.path().starts_with("/api/") && !global.flags.enabled(Flag::Api) {
+        return Some(HttpResponse::NotFound().finish());
+    }
+    None
+}
+
+/// Creates a successful JSON response that may be cached for 10 minutes
+fn json<T: Serialize>(version: ApiVersion, value: &T) -> HttpResponse {
+    let cc = CacheControl(vec![
+        CacheDirective::MaxAge(10 * 60),
+        CacheDirective::Public,
+    ]);
+    let mut res = HttpResponse::Ok();
+    res.header(CACHE_CONTROL, cc);
+    version.add_headers(&mut res);
+    res.json(value)
+}
+
+/// A show name as returned by the `json_agg` subqueries in [crate::db]
+#[derive(Deserialize)]
+struct DbName {
+    name: String,
+    show_name_type: i32,
+}
+
+#[derive(Serialize, Default)]
+struct Names {
+    romaji: String,
+    english: Option<String>,
+}
+
+impl Names {
+    fn from_db(names: Vec<DbName>) -> Self {
+        let mut res = Names::default();
+        for name in names {
+            if name.show_name_type == ShowNameType::ROMAJI {
+                res.romaji = name.name;
+            } else if name.show_name_type == ShowNameType::ENGLISH {
+                res.english = Some(name.name);
+            }
+        }
+        res
+    }
+}
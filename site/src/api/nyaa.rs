@@ -0,0 +1,46 @@
+use crate::{
+    api::{json, version::ApiVersion},
+    nyaa::resolve,
+    state::State,
+    text::NotFound,
+};
+use actix_web::{web, web::Data, HttpResponse, Responder};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Torrent {
+    torrent_id: i64,
+    nyaa_id: i64,
+    show_ids: Vec<i64>,
+}
+
+#[actix_web::get("/api/v1/nyaa/{nyaa_id}")]
+pub async fn get(
+    state: Data<State>,
+    version: ApiVersion,
+    id: web::Path<(i64,)>,
+) -> impl Responder {
+    let nyaa_id = id.0.0;
+    match resolve(&state, nyaa_id).await {
+        Ok(t) => json(
+            version,
+            &Torrent {
+                torrent_id: t.torrent_id,
+                nyaa_id,
+                show_ids: t.show_ids,
+            },
+        ),
+        Err(e) => {
+            if e.is::<NotFound>() {
+                HttpResponse::NotFound().finish()
+            } else {
+                log::error!(
+                    "An error occurred while trying to resolve nyaa id {} via the api: {:#}",
+                    nyaa_id,
+                    e
+                );
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{
+    api::{json, version::ApiVersion, Names},
+    search::{search_shows, MAX_QUERY_LEN},
+    state::State,
+};
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    q: String,
+}
+
+#[derive(Serialize)]
+struct Show {
+    show_id: i64,
+    names: Names,
+}
+
+/// Returns the shows whose names match a query, best match first
+///
+/// The order is the same as on /search.
+#[actix_web::get("/api/v1/search/shows")]
+pub async fn get(
+    state: Data<State>,
+    version: ApiVersion,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    if query.q.len() > MAX_QUERY_LEN {
+        return HttpResponse::BadRequest().body("the query is too long");
+    }
+    match search_shows(&state, &query.q).await {
+        Ok(shows) => {
+            let shows: Vec<_> = shows
+                .into_iter()
+                .map(|s| Show {
+                    show_id: s.show_id,
+                    names: Names {
+                        romaji: s.romaji,
+                        english: s.english,
+                    },
+                })
+                .collect();
+            json(version, &shows)
+        }
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to search shows via the api: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
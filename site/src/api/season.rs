@@ -0,0 +1,69 @@
+use crate::{
+    api::{json, version::ApiVersion, DbName, Names},
+    state::State,
+};
+use actix_web::{web, web::Data, HttpResponse, Responder};
+use anyhow::Result;
+use common::{Format, YearSeason};
+use serde::Serialize;
+use tokio_postgres::types::Json;
+
+#[actix_web::get("/api/v1/season/{name}")]
+pub async fn get(
+    state: Data<State>,
+    version: ApiVersion,
+    name: web::Path<(String,)>,
+) -> impl Responder {
+    let season = match YearSeason::from_api_str(&name.0.0) {
+        Ok(s) => s,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+    match process(&state, season).await {
+        Ok(season) => json(version, &season),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to retrieve season {} via the api: {:#}",
+                season.display_name(),
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Season {
+    season: String,
+    name: String,
+    shows: Vec<Show>,
+}
+
+#[derive(Serialize)]
+struct Show {
+    show_id: i64,
+    anilist_id: i64,
+    format: &'static str,
+    names: Names,
+    torrents: i64,
+}
+
+async fn process(state: &State, season: YearSeason) -> Result<Season> {
+    let db = state.pg.borrow().await?;
+    let rows = db.query(&db.t.api_season.stmt, &[&season.to_db()]).await?;
+    let mut shows = Vec::with_capacity(rows.len());
+    for row in rows {
+        let names: Option<Json<Vec<DbName>>> = row.get(db.t.api_season.names);
+        shows.push(Show {
+            show_id: row.get(db.t.api_season.show_id),
+            anilist_id: row.get(db.t.api_season.anilist_id),
+            format: Format::from_db(row.get(db.t.api_season.show_format))?.as_api_str(),
+            names: Names::from_db(names.map(|n| n.0).unwrap_or_default()),
+            torrents: row.get(db.t.api_season.torrents),
+        });
+    }
+    Ok(Season {
+        season: season.to_api_str(),
+        name: season.display_name(),
+        shows,
+    })
+}
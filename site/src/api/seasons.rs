@@ -0,0 +1,44 @@
+use crate::{
+    api::{json, version::ApiVersion},
+    state::State,
+};
+use actix_web::{web::Data, HttpResponse, Responder};
+use anyhow::Result;
+use common::YearSeason;
+use serde::Serialize;
+
+#[actix_web::get("/api/v1/seasons")]
+pub async fn get(state: Data<State>, version: ApiVersion) -> impl Responder {
+    match process(&state).await {
+        Ok(seasons) => json(version, &seasons),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to retrieve the seasons via the api: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Season {
+    season: String,
+    name: String,
+    shows: i64,
+}
+
+async fn process(state: &State) -> Result<Vec<Season>> {
+    let db = state.pg.borrow().await?;
+    let rows = db.query(&db.t.api_seasons.stmt, &[]).await?;
+    let mut seasons = Vec::with_capacity(rows.len());
+    for row in rows {
+        let season = YearSeason::from_db(row.get(db.t.api_seasons.season))?;
+        seasons.push(Season {
+            season: season.to_api_str(),
+            name: season.display_name(),
+            shows: row.get(db.t.api_seasons.shows),
+        });
+    }
+    Ok(seasons)
+}
@@ -0,0 +1,161 @@
+use crate::{
+    api::{json, version::ApiVersion, Names},
+    state::State,
+    text::NotFound,
+    torrent::{description, load},
+};
+use actix_web::{
+    web,
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use common::{HexFormatter, MagnetFormatter};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    /// `oembed` to return an oEmbed response instead of the torrent
+    format: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Torrent {
+    torrent_id: i64,
+    nyaa_id: i64,
+    title: String,
+    uploaded_at: i64,
+    size: i64,
+    trusted: bool,
+    /// Null if the torrent has not been seen on a listing that contains the swarm
+    seeders: Option<i32>,
+    leechers: Option<i32>,
+    completed: Option<i32>,
+    hash: String,
+    magnet: String,
+    url: String,
+    nyaa_url: String,
+    shows: Vec<Show>,
+}
+
+#[derive(Serialize)]
+struct Show {
+    show_id: i64,
+    anilist_id: i64,
+    names: Names,
+    url: String,
+    anilist_url: String,
+}
+
+/// A link response as specified by https://oembed.com
+#[derive(Serialize)]
+struct OEmbed {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    version: &'static str,
+    title: String,
+    /// The romaji names of the shows
+    author_name: Option<String>,
+    /// The page of the first show
+    author_url: Option<String>,
+    provider_name: &'static str,
+    provider_url: String,
+    /// The size, upload date and trusted status as on the torrent page
+    description: String,
+}
+
+/// Returns a torrent with the names and links of its shows
+///
+/// With `?format=oembed`, returns an oEmbed link response instead so that chat clients
+/// can unfurl links to /torrent/{torrent_id} with the show.
+#[actix_web::get("/api/v1/torrent/{torrent_id}")]
+pub async fn get(
+    state: Data<State>,
+    version: ApiVersion,
+    id: web::Path<(i64,)>,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    let torrent_id = id.0.0;
+    let res = match query.format.as_deref() {
+        None => process(&state, torrent_id).await.map(|t| json(version, &t)),
+        Some("oembed") => oembed(&state, torrent_id).await.map(|o| json(version, &o)),
+        Some(f) => {
+            return HttpResponse::BadRequest().body(format!("unknown format {}", f));
+        }
+    };
+    match res {
+        Ok(r) => r,
+        Err(e) => {
+            if e.is::<NotFound>() {
+                HttpResponse::NotFound().finish()
+            } else {
+                log::error!(
+                    "An error occurred while trying to retrieve torrent {} via the api: {:#}",
+                    torrent_id,
+                    e
+                );
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+async fn process(state: &State, torrent_id: i64) -> Result<Torrent> {
+    let repo = state.repo().await?;
+    let (details, shows) = load(&repo, &state.global.show_names, torrent_id).await?;
+    let base_url = &state.global.base_url;
+    let torrent = details.torrent;
+    Ok(Torrent {
+        torrent_id,
+        nyaa_id: torrent.nyaa_id,
+        uploaded_at: torrent.uploaded_at.timestamp(),
+        size: details.size,
+        trusted: torrent.trusted,
+        seeders: torrent.swarm.map(|s| s.seeders),
+        leechers: torrent.swarm.map(|s| s.leechers),
+        completed: torrent.swarm.map(|s| s.completed),
+        hash: HexFormatter(&torrent.hash).to_string(),
+        magnet: MagnetFormatter::new(&torrent.title, &torrent.hash)
+            .with_size(details.size)
+            .to_string(),
+        url: format!("{}/torrent/{}", base_url, torrent_id),
+        nyaa_url: format!("https://nyaa.si/view/{}", torrent.nyaa_id),
+        title: torrent.title,
+        shows: shows
+            .into_iter()
+            .map(|s| Show {
+                show_id: s.show_id,
+                anilist_id: s.anilist_id,
+                names: Names {
+                    romaji: s.names.romaji.clone(),
+                    english: s.names.english.clone(),
+                },
+                url: format!("{}/show/{}", base_url, s.show_id),
+                anilist_url: format!("https://anilist.co/anime/{}", s.anilist_id),
+            })
+            .collect(),
+    })
+}
+
+async fn oembed(state: &State, torrent_id: i64) -> Result<OEmbed> {
+    let repo = state.repo().await?;
+    let (details, shows) = load(&repo, &state.global.show_names, torrent_id).await?;
+    let base_url = &state.global.base_url;
+    let torrent = &details.torrent;
+    let names: Vec<_> = shows.iter().map(|s| &*s.names.romaji).collect();
+    Ok(OEmbed {
+        ty: "link",
+        version: "1.0",
+        title: torrent.title.clone(),
+        author_name: match names.is_empty() {
+            true => None,
+            false => Some(names.join(", ")),
+        },
+        author_url: shows
+            .first()
+            .map(|s| format!("{}/show/{}", base_url, s.show_id)),
+        provider_name: "Magnets.moe",
+        provider_url: base_url.clone(),
+        description: description(&details)?,
+    })
+}
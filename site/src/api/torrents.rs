@@ -0,0 +1,241 @@
+use crate::{
+    api::{json, version::ApiVersion},
+    repo::{
+        listing::{TorrentFilter, TorrentQuery},
+        TorrentRepo, PAGE_SIZE,
+    },
+    state::State,
+};
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use common::{
+    AudioCodec, HexFormatter, MagnetFormatter, Resolution, Source, VideoCodec, YearSeason,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    /// Only torrents with a smaller nyaa id. Taken from `next` of the previous page.
+    #[serde(rename = "a", default = "i64::max_value")]
+    after: i64,
+    show_id: Option<i64>,
+    /// Only torrents of shows of a season, e.g. `2021-winter`
+    season: Option<String>,
+    /// Only torrents uploaded at or after this unix timestamp
+    from: Option<i64>,
+    /// Only torrents uploaded before this unix timestamp
+    to: Option<i64>,
+    matched: Option<bool>,
+    batch: Option<bool>,
+    trusted: Option<bool>,
+    /// `480p`, `720p`, `1080p` or `2160p`
+    resolution: Option<String>,
+    /// `h264`, `h265` or `av1`
+    video_codec: Option<String>,
+    /// `aac`, `flac`, `opus` or `ac3`
+    audio_codec: Option<String>,
+    /// The release group without brackets
+    group: Option<String>,
+    episode: Option<i32>,
+    /// The name of a source, e.g. `nyaa.si`
+    source: Option<String>,
+    hidden: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct Torrents {
+    torrents: Vec<Torrent>,
+    /// The value of `a` for the next page. Absent on the last page.
+    next: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct Torrent {
+    torrent_id: i64,
+    nyaa_id: i64,
+    title: String,
+    uploaded_at: i64,
+    trusted: bool,
+    batch: bool,
+    /// Null if the torrent has not been seen on a listing that contains the swarm
+    seeders: Option<i32>,
+    leechers: Option<i32>,
+    completed: Option<i32>,
+    hash: String,
+    magnet: String,
+    url: String,
+}
+
+/// Returns a page of torrents, newest first
+///
+/// The filters are the same as those of the torrent lists on the site. Pages are chained
+/// via `next` so that clients can walk all torrents without scraping the HTML.
+#[actix_web::get("/api/v1/torrents")]
+pub async fn get(
+    state: Data<State>,
+    version: ApiVersion,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    let query = match parse(query) {
+        Ok(q) => q,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match process(&state, &query).await {
+        Ok(t) => json(version, &t),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to list torrents via the api: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+fn parse(query: QueryParams) -> Result<TorrentQuery, String> {
+    let resolution = match query.resolution {
+        Some(r) => match Resolution::parse(&r) {
+            Some(r) => Some(r),
+            _ => return Err(format!("unknown resolution {}", r)),
+        },
+        _ => None,
+    };
+    let video_codec = match query.video_codec {
+        Some(c) => match VideoCodec::parse(&c) {
+            Some(c) => Some(c),
+            _ => return Err(format!("unknown video codec {}", c)),
+        },
+        _ => None,
+    };
+    let audio_codec = match query.audio_codec {
+        Some(c) => match AudioCodec::parse(&c) {
+            Some(c) => Some(c),
+            _ => return Err(format!("unknown audio codec {}", c)),
+        },
+        _ => None,
+    };
+    let source = match query.source {
+        Some(s) => match Source::ALL.iter().find(|source| source.as_str() == s) {
+            Some(source) => Some(source.to_db()),
+            _ => return Err(format!("unknown source {}", s)),
+        },
+        _ => None,
+    };
+    let season = match query.season {
+        Some(s) => match YearSeason::from_api_str(&s) {
+            Ok(season) => Some(season.to_db()),
+            _ => return Err(format!("unknown season {}", s)),
+        },
+        _ => None,
+    };
+    let timestamp = |t: Option<i64>| match t {
+        Some(t) => match Utc.timestamp_opt(t, 0).single() {
+            Some(t) => Ok(Some(t)),
+            _ => Err(format!("invalid timestamp {}", t)),
+        },
+        _ => Ok(None),
+    };
+    if let Some(group) = &query.group {
+        if group.is_empty() || group.len() > 100 {
+            return Err("the group must contain between 1 and 100 bytes".to_string());
+        }
+    }
+    Ok(TorrentQuery {
+        filter: TorrentFilter {
+            show_id: query.show_id,
+            season,
+            uploaded_from: timestamp(query.from)?,
+            uploaded_to: timestamp(query.to)?,
+            matched: query.matched,
+            batch: query.batch,
+            trusted: query.trusted,
+            resolution,
+            video_codec,
+            audio_codec,
+            group: query.group,
+            episode: query.episode,
+            source,
+            hidden: query.hidden,
+        },
+        after: query.after,
+    })
+}
+
+async fn process(state: &State, query: &TorrentQuery) -> Result<Torrents> {
+    let repo = state.repo().await?;
+    let mut torrents = repo.torrents(query).await?;
+    let next = match torrents.len() > PAGE_SIZE {
+        true => {
+            torrents.truncate(PAGE_SIZE);
+            torrents.last().map(|t| t.nyaa_id)
+        }
+        false => None,
+    };
+    let base_url = &state.global.base_url;
+    Ok(Torrents {
+        torrents: torrents
+            .into_iter()
+            .map(|t| Torrent {
+                torrent_id: t.torrent_id,
+                nyaa_id: t.nyaa_id,
+                uploaded_at: t.uploaded_at.timestamp(),
+                trusted: t.trusted,
+                batch: t.batch,
+                seeders: t.swarm.map(|s| s.seeders),
+                leechers: t.swarm.map(|s| s.leechers),
+                completed: t.swarm.map(|s| s.completed),
+                hash: HexFormatter(&t.hash).to_string(),
+                magnet: MagnetFormatter::new(&t.title, &t.hash).to_string(),
+                url: format!("{}/torrent/{}", base_url, t.torrent_id),
+                title: t.title,
+            })
+            .collect(),
+        next,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(params: &str) -> Result<TorrentQuery, String> {
+        parse(Query::from_query(params).unwrap().into_inner())
+    }
+
+    #[test]
+    fn parses_filters() {
+        let q = query("show_id=3&season=2021-winter&from=1609459200&trusted=true&a=100")
+            .unwrap();
+        assert_eq!(q.after, 100);
+        assert_eq!(q.filter.show_id, Some(3));
+        assert_eq!(
+            q.filter.season,
+            Some(YearSeason::from_api_str("2021-winter").unwrap().to_db())
+        );
+        assert_eq!(
+            q.filter.uploaded_from,
+            Some(Utc.timestamp(1_609_459_200, 0))
+        );
+        assert_eq!(q.filter.uploaded_to, None);
+        assert_eq!(q.filter.trusted, Some(true));
+        let q = query("source=nyaa.si&resolution=1080p&video_codec=h265").unwrap();
+        assert_eq!(q.after, i64::MAX);
+        assert_eq!(q.filter.source, Some(Source::Nyaa.to_db()));
+        assert_eq!(q.filter.resolution, Some(Resolution::P1080));
+        assert_eq!(q.filter.video_codec, Some(VideoCodec::H265));
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        assert!(query("season=2021-monsoon").is_err());
+        assert!(query("resolution=1440p").is_err());
+        assert!(query("audio_codec=mp3").is_err());
+        assert!(query("source=example.org").is_err());
+        assert!(query("group=").is_err());
+        assert!(query(&format!("to={}", i64::MAX)).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use actix_web::{
+    dev::{HttpResponseBuilder, Payload},
+    error::ErrorNotFound,
+    http::header::ACCEPT,
+    FromRequest, HttpRequest,
+};
+use futures::future::{ready, Ready};
+
+/// A version of the JSON API
+///
+/// The version of a request is taken from the path prefix (`/api/v1/...`). If the path
+/// does not contain a version, the `Accept` header is consulted
+/// (`application/vnd.magnets.v1+json`). If neither contains a version, the current
+/// version is used.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ApiVersion {
+    V1,
+}
+
+/// Information about the lifecycle of an API version
+pub struct VersionInfo {
+    pub version: ApiVersion,
+    /// Set if the version is deprecated. Contains the date (RFC 7231 format) after which
+    /// the version will be removed.
+    pub sunset: Option<&'static str>,
+    pub changes: &'static [&'static str],
+}
+
+/// All versions of the API in ascending order
+pub const VERSIONS: &[VersionInfo] = &[VersionInfo {
+    version: ApiVersion::V1,
+    sunset: None,
+    changes: &[
+        "Added /api/v1/meta",
+        "Added POST /api/v1/hashes",
+        "Added /api/v1/nyaa/{nyaa_id}",
+        "Added /api/v1/seasons",
+        "Added /api/v1/show/{show_id}/missing",
+        "Added /api/v1/season/{year}-{season}",
+        "Added /api/v1/search/shows",
+        "Added /api/v1/torrent/{torrent_id}",
+        "Added /api/v1/torrents",
+    ],
+}];
+
+const MEDIA_TYPE_PREFIX: &str = "application/vnd.magnets.";
+const MEDIA_TYPE_SUFFIX: &str = "+json";
+
+impl ApiVersion {
+    pub const CURRENT: ApiVersion = ApiVersion::V1;
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "v1" => Some(ApiVersion::V1),
+            _ => None,
+        }
+    }
+
+    pub fn info(self) -> &'static VersionInfo {
+        VERSIONS.iter().find(|v| v.version == self).unwrap()
+    }
+
+    /// Adds the `Deprecation` and `Sunset` headers to a response if this version is
+    /// deprecated
+    pub fn add_headers(self, res: &mut HttpResponseBuilder) {
+        if let Some(sunset) = self.info().sunset {
+            res.header("Deprecation", "true");
+            res.header("Sunset", sunset);
+            res.header("Link", "</api/versions>; rel=\"deprecation\"");
+        }
+    }
+
+    fn from_path(path: &str) -> Result<Option<Self>, ()> {
+        let rest = match path.strip_prefix("/api/") {
+            Some(r) => r,
+            _ => return Ok(None),
+        };
+        let segment = rest.split('/').next().unwrap_or("");
+        let is_version = segment.len() > 1
+            && segment.starts_with('v')
+            && segment[1..].bytes().all(|b| b.is_ascii_digit());
+        if !is_version {
+            return Ok(None);
+        }
+        Self::parse(segment).map(Some).ok_or(())
+    }
+
+    fn from_accept(req: &HttpRequest) -> Result<Option<Self>, ()> {
+        let accept = match req.headers().get(ACCEPT).map(|a| a.to_str()) {
+            Some(Ok(a)) => a,
+            _ => return Ok(None),
+        };
+        for media_type in accept.split(',') {
+            let media_type = media_type.split(';').next().unwrap().trim();
+            let version = media_type
+                .strip_prefix(MEDIA_TYPE_PREFIX)
+                .and_then(|m| m.strip_suffix(MEDIA_TYPE_SUFFIX));
+            if let Some(version) = version {
+                return Self::parse(version).map(Some).ok_or(());
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl FromRequest for ApiVersion {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let version = match Self::from_path(req.path()) {
+            Ok(Some(v)) => Ok(v),
+            Ok(None) => Self::from_accept(req).map(|v| v.unwrap_or(Self::CURRENT)),
+            Err(()) => Err(()),
+        };
+        ready(version.map_err(|_| ErrorNotFound("unknown api version")))
+    }
+}
@@ -0,0 +1,35 @@
+use crate::api::version::{ApiVersion, VERSIONS};
+use actix_web::{HttpResponse, Responder};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Versions {
+    current: &'static str,
+    versions: Vec<Version>,
+}
+
+#[derive(Serialize)]
+struct Version {
+    version: &'static str,
+    deprecated: bool,
+    sunset: Option<&'static str>,
+    changes: &'static [&'static str],
+}
+
+/// Lists all versions of the API together with their changelog
+#[actix_web::get("/api/versions")]
+pub async fn get() -> impl Responder {
+    let versions = Versions {
+        current: ApiVersion::CURRENT.as_str(),
+        versions: VERSIONS
+            .iter()
+            .map(|v| Version {
+                version: v.version.as_str(),
+                deprecated: v.sunset.is_some(),
+                sunset: v.sunset,
+                changes: v.changes,
+            })
+            .collect(),
+    };
+    HttpResponse::Ok().json(&versions)
+}
@@ -0,0 +1,67 @@
+use crate::{
+    repo::{
+        listing::{TorrentFilter, TorrentQuery},
+        TorrentRepo,
+    },
+    state::State,
+    text::TEXT_HTML,
+    torrent_list::{torrent_list, Day},
+};
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use serde::Deserialize;
+
+#[actix_web::get("/batches")]
+pub async fn get(state: Data<State>, Query(query): Query<QueryParams>) -> impl Responder {
+    match process(&state, query).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!("an error occurred while trying to load batches: {:#}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "batches.html")]
+struct Days<'a> {
+    days: &'a [Day<'a>],
+    last: Option<i64>,
+    first: bool,
+}
+
+mod filters {
+    pub use crate::text::{format_day, format_time};
+}
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    #[serde(rename = "a", default = "i64::max_value")]
+    after: i64,
+}
+
+async fn process(state: &State, query: QueryParams) -> Result<String> {
+    render(&state.repo().await?, query).await
+}
+
+async fn render(repo: &impl TorrentRepo, query: QueryParams) -> Result<String> {
+    let query = TorrentQuery {
+        filter: TorrentFilter {
+            batch: Some(true),
+            ..Default::default()
+        },
+        after: query.after,
+    };
+    let torrents = repo.torrents(&query).await?;
+    let (last, days) = torrent_list(&torrents);
+    let days = Days {
+        days: &days,
+        last,
+        first: query.after == i64::MAX,
+    };
+    Ok(days.render()?)
+}
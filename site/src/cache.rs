@@ -1,4 +1,5 @@
 use anyhow::Result;
+use common::time::Clock;
 use std::{
     future::Future,
     ops::Deref,
@@ -9,11 +10,13 @@ use tokio::sync::{Mutex, RwLock};
 
 pub struct Cache<T> {
     lifetime: Duration,
+    clock: Arc<dyn Clock>,
     data: RwLock<Option<Cached<T>>>,
     write_data: Mutex<Option<Cached<T>>>,
 }
 
 pub struct Cached<T> {
+    clock: Arc<dyn Clock>,
     eol: Instant,
     version: u64,
     data: Arc<T>,
@@ -22,6 +25,7 @@ pub struct Cached<T> {
 impl<T> Clone for Cached<T> {
     fn clone(&self) -> Self {
         Self {
+            clock: self.clock.clone(),
             eol: self.eol,
             version: self.version,
             data: self.data.clone(),
@@ -31,7 +35,7 @@ impl<T> Clone for Cached<T> {
 
 impl<T> Cached<T> {
     pub fn max_age(&self) -> u32 {
-        let now = Instant::now();
+        let now = self.clock.instant();
         if self.eol < now {
             0
         } else {
@@ -49,9 +53,10 @@ impl<T> Deref for Cached<T> {
 }
 
 impl<T> Cache<T> {
-    pub fn new(lifetime: Duration) -> Self {
+    pub fn new(lifetime: Duration, clock: Arc<dyn Clock>) -> Self {
         Self {
             lifetime,
+            clock,
             data: RwLock::new(None),
             write_data: Mutex::new(None),
         }
@@ -63,7 +68,7 @@ impl<T> Cache<T> {
         G: Future<Output = Result<T>>,
     {
         let data = self.data.read().await.clone();
-        let now = Instant::now();
+        let now = self.clock.instant();
         match data {
             None => self.reload_data(f, None).await,
             Some(d) if d.eol <= now => self.reload_data(f, Some(d.version)).await,
@@ -71,6 +76,19 @@ impl<T> Cache<T> {
         }
     }
 
+    /// Marks the cached data as expired
+    ///
+    /// The next call of [Cache::get] reloads the data.
+    pub async fn invalidate(&self) {
+        let now = self.clock.instant();
+        if let Some(c) = &mut *self.write_data.lock().await {
+            c.eol = now;
+        }
+        if let Some(c) = &mut *self.data.write().await {
+            c.eol = now;
+        }
+    }
+
     async fn reload_data<G, F>(
         &self,
         f: F,
@@ -86,9 +104,11 @@ impl<T> Cache<T> {
             return Ok(lock.as_ref().unwrap().clone());
         }
         let next_version = actual_version.unwrap_or(0) + 1;
+        let data = Arc::new(f().await?);
         let cached = Cached {
-            data: Arc::new(f().await?),
-            eol: Instant::now() + self.lifetime,
+            clock: self.clock.clone(),
+            data,
+            eol: self.clock.instant() + self.lifetime,
             version: next_version,
         };
         *lock = Some(cached.clone());
@@ -96,3 +116,30 @@ impl<T> Cache<T> {
         Ok(cached)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use common::time::{MockClock, MINUTE};
+    use futures::executor::block_on;
+
+    #[test]
+    fn expires_after_lifetime() {
+        let clock = Arc::new(MockClock::new(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)));
+        let cache = Cache::new(10 * MINUTE, clock.clone());
+        let get = |v| block_on(cache.get(|| async move { Ok(v) })).unwrap();
+
+        assert_eq!(*get(1), 1);
+        clock.advance(4 * MINUTE);
+        let cached = get(2);
+        assert_eq!(*cached, 1);
+        assert_eq!(cached.max_age(), 6 * 60);
+
+        clock.advance(6 * MINUTE);
+        assert_eq!(*get(3), 3);
+
+        block_on(cache.invalidate());
+        assert_eq!(*get(4), 4);
+    }
+}
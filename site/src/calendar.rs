@@ -0,0 +1,248 @@
+use crate::{state::State, text::TEXT_HTML};
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    /// The month to show in the format `YYYY-MM`. Defaults to the current month.
+    month: Option<String>,
+}
+
+#[actix_web::get("/calendar")]
+pub async fn get(state: Data<State>, Query(query): Query<QueryParams>) -> impl Responder {
+    let now = state.global.clock.now();
+    let month = match &query.month {
+        Some(m) => match Month::from_url_str(m) {
+            Some(m) => m,
+            _ => return HttpResponse::NotFound().finish(),
+        },
+        _ => Month::of(now),
+    };
+    match render(&state, month, now).await {
+        Ok(b) => HttpResponse::Ok().content_type(TEXT_HTML).body(b),
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to render the calendar of {}: {:#}",
+                month.to_url_str(),
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// The years that can be requested
+const YEARS: RangeInclusive<i32> = 1900..=9998;
+
+/// A month of the calendar. All dates are UTC dates.
+#[derive(Copy, Clone)]
+struct Month {
+    year: i32,
+    month: u32,
+}
+
+impl Month {
+    fn of(t: DateTime<Utc>) -> Self {
+        Self {
+            year: t.year(),
+            month: t.month(),
+        }
+    }
+
+    /// Returns `None` for invalid months and for years outside of [YEARS] so that the
+    /// neighbouring months can always be constructed
+    fn from_url_str(s: &str) -> Option<Self> {
+        let date = NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d").ok()?;
+        if !YEARS.contains(&date.year()) {
+            return None;
+        }
+        Some(Self {
+            year: date.year(),
+            month: date.month(),
+        })
+    }
+
+    fn to_url_str(self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+
+    fn display_name(self) -> String {
+        self.first_day().format("%B %Y").to_string()
+    }
+
+    fn first_day(self) -> NaiveDate {
+        NaiveDate::from_ymd(self.year, self.month, 1)
+    }
+
+    fn prev(self) -> Self {
+        match self.month {
+            1 => Self {
+                year: self.year - 1,
+                month: 12,
+            },
+            m => Self {
+                year: self.year,
+                month: m - 1,
+            },
+        }
+    }
+
+    fn next(self) -> Self {
+        match self.month {
+            12 => Self {
+                year: self.year + 1,
+                month: 1,
+            },
+            m => Self {
+                year: self.year,
+                month: m + 1,
+            },
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "calendar.html")]
+struct Tpl {
+    weeks: Vec<Vec<Day>>,
+    month_name: String,
+    prev_month_link: String,
+    next_month_link: String,
+    prev_month_name: String,
+    next_month_name: String,
+}
+
+/// A cell of the month grid
+struct Day {
+    /// The day of the month. `None` for the cells before the first and after the last
+    /// day of the month.
+    day: Option<u32>,
+    today: bool,
+    episodes: Vec<Episode>,
+}
+
+impl Day {
+    fn empty() -> Self {
+        Self {
+            day: None,
+            today: false,
+            episodes: vec![],
+        }
+    }
+}
+
+/// An episode in the schedule and the torrents that have been matched to it
+struct Episode {
+    show_id: i64,
+    name: String,
+    episode: i32,
+    air_time: String,
+    aired: bool,
+    /// At most three torrents, trusted torrents first
+    torrents: Vec<i64>,
+    torrent_count: i64,
+}
+
+impl Episode {
+    /// Whether the episode aired but no torrent has been matched to it
+    fn missing(&self) -> bool {
+        self.aired && self.torrent_count == 0
+    }
+
+    fn more_torrents(&self) -> i64 {
+        self.torrent_count - self.torrents.len() as i64
+    }
+}
+
+async fn render(state: &State, month: Month, now: DateTime<Utc>) -> Result<String> {
+    let first = month.first_day();
+    let start = Utc.from_utc_date(&first).and_hms(0, 0, 0);
+    let end = Utc
+        .from_utc_date(&month.next().first_day())
+        .and_hms(0, 0, 0);
+    let num_days = (end - start).num_days() as usize;
+
+    let mut days: Vec<_> = (0..num_days)
+        .map(|d| {
+            let date = first + Duration::days(d as i64);
+            Day {
+                day: Some(date.day()),
+                today: date == now.naive_utc().date(),
+                episodes: vec![],
+            }
+        })
+        .collect();
+
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.calendar;
+    let rows = db.query(&stmt.stmt, &[&start, &end]).await?;
+    for row in &rows {
+        let airs_at: DateTime<Utc> = row.get(stmt.airs_at);
+        let torrents: Option<Vec<i64>> = row.get(stmt.torrents);
+        let day = (airs_at - start).num_days() as usize;
+        days[day].episodes.push(Episode {
+            show_id: row.get(stmt.show_id),
+            name: row.get(stmt.romaji),
+            episode: row.get(stmt.episode),
+            air_time: airs_at.format("%H:%M").to_string(),
+            aired: airs_at <= now,
+            torrents: torrents.unwrap_or_default(),
+            torrent_count: row.get(stmt.torrent_count),
+        });
+    }
+
+    // Weeks start on monday. Pad the first and last week with empty cells.
+    let padding = first.weekday().num_days_from_monday() as usize;
+    let mut cells: Vec<_> = (0..padding).map(|_| Day::empty()).collect();
+    cells.extend(days);
+    while cells.len() % 7 != 0 {
+        cells.push(Day::empty());
+    }
+    let mut weeks = vec![];
+    while !cells.is_empty() {
+        let rest = cells.split_off(7);
+        weeks.push(cells);
+        cells = rest;
+    }
+
+    let tpl = Tpl {
+        weeks,
+        month_name: month.display_name(),
+        prev_month_link: month.prev().to_url_str(),
+        next_month_link: month.next().to_url_str(),
+        prev_month_name: month.prev().display_name(),
+        next_month_name: month.next().display_name(),
+    };
+    Ok(tpl.render()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_months() {
+        let month = Month::from_url_str("2021-04").unwrap();
+        assert_eq!((month.year, month.month), (2021, 4));
+        assert_eq!(month.prev().to_url_str(), "2021-03");
+        assert_eq!(month.next().to_url_str(), "2021-05");
+        assert!(Month::from_url_str("2021-13").is_none());
+        assert!(Month::from_url_str("2021").is_none());
+    }
+
+    #[test]
+    fn rejects_out_of_range_years() {
+        assert!(Month::from_url_str("262143-12").is_none());
+        assert!(Month::from_url_str("-262144-01").is_none());
+        assert!(Month::from_url_str("9999-12").is_none());
+        let month = Month::from_url_str("9998-12").unwrap();
+        assert_eq!(month.next().first_day(), NaiveDate::from_ymd(9999, 1, 1));
+    }
+}
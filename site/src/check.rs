@@ -0,0 +1,31 @@
+use crate::{config::Config, db::Statements, repo::sqlite::Sqlite, stats, trending};
+use anyhow::Result;
+use common::{
+    check::step,
+    pg::{FromClient, PgConnector},
+};
+
+/// Verifies that the site can start and serve requests
+///
+/// Loads the config, connects to postgres, and prepares all statements. In SQLite mode
+/// the SQLite file is opened instead. The outcome of each step is printed and the exit
+/// code is non-zero if a step fails. Meant to run before traffic is switched to a new
+/// deployment.
+///
+/// Usage: `site check`
+pub async fn check() -> Result<()> {
+    let config: Config = step("config", async { common::config::load() }).await?;
+    if let Some(path) = &config.db.sqlite {
+        step("sqlite", async { Sqlite::open(path) }).await?;
+        return Ok(());
+    }
+    let connector = PgConnector::new(config.db.connection_string.clone());
+    let db = step("postgres", connector.connect()).await?;
+    step("statements", async {
+        Statements::from_client(&db).await?;
+        trending::check_statements(&db).await?;
+        stats::check_statements(&db).await
+    })
+    .await?;
+    Ok(())
+}
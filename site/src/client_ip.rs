@@ -0,0 +1,242 @@
+use crate::state::Global;
+use actix_web::{
+    dev::ServiceRequest,
+    http::{
+        header::{HeaderName, FORWARDED},
+        HeaderMap,
+    },
+    HttpMessage, HttpRequest,
+};
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+/// The ip of the client that performed a request
+///
+/// Resolved once per request by [resolve] and stored in the request extensions.
+#[derive(Copy, Clone, Debug)]
+struct ClientIp(Option<IpAddr>);
+
+/// An ip address or a network in CIDR notation
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.find('/') {
+            Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+            _ => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|e| format!("invalid ip address `{}`: {}", addr, e))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => match p.parse() {
+                Ok(p) if p <= max => p,
+                _ => return Err(format!("invalid prefix length `{}`", p)),
+            },
+            _ => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Display for IpNet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The reverse proxies whose forwarding headers are trusted
+///
+/// See the `http.trusted_proxies` setting.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    pub nets: Vec<IpNet>,
+    /// Whether connections via uds sockets come from a trusted proxy
+    pub unix: bool,
+}
+
+impl TrustedProxies {
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|n| n.contains(ip))
+    }
+
+    /// Returns the ip of the client
+    ///
+    /// `peer` is the address of the other end of the connection or `None` for uds
+    /// sockets. If the peer is a trusted proxy, the forwarding headers are walked from
+    /// the nearest to the farthest hop and the first address that is not a trusted
+    /// proxy is the client. Headers sent by other peers are ignored since clients can
+    /// set them to arbitrary values.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer_trusted = match peer {
+            Some(ip) => self.trusts(ip),
+            _ => self.unix,
+        };
+        if !peer_trusted {
+            return peer;
+        }
+        let mut hops = forwarded_for(headers);
+        if hops.is_empty() {
+            hops = x_forwarded_for(headers);
+        }
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(ip) => {
+                    client = Some(ip);
+                    if !self.trusts(ip) {
+                        break;
+                    }
+                }
+                // Obfuscated or unparsable addresses cannot be trusted
+                _ => return None,
+            }
+        }
+        client
+    }
+}
+
+/// Resolves the ip of the client and stores it in the request
+pub fn resolve(global: &Global, req: &ServiceRequest) {
+    let peer = req.peer_addr().map(|a| a.ip());
+    let ip = global.trusted_proxies.client_ip(peer, req.headers());
+    req.extensions_mut().insert(ClientIp(ip));
+}
+
+/// Returns the ip of the client that performed the request
+///
+/// This is `None` if the ip is unknown, e.g. because the request was received via a
+/// uds socket from an untrusted proxy.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    req.extensions().get::<ClientIp>().and_then(|c| c.0)
+}
+
+fn parse_ip(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_matches('"');
+    if let Ok(ip) = s.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    // An ipv6 address without a port, e.g. `[::1]`
+    s.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+}
+
+/// Returns the `for` parameters of the `Forwarded` headers (RFC 7239), nearest hop last
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let mut res = vec![];
+    for value in headers.get_all(FORWARDED) {
+        let value = match value.to_str() {
+            Ok(v) => v,
+            _ => return vec![None],
+        };
+        for element in value.split(',') {
+            for pair in element.split(';') {
+                let mut kv = pair.splitn(2, '=');
+                let key = kv.next().unwrap_or("").trim();
+                if key.eq_ignore_ascii_case("for") {
+                    res.push(kv.next().and_then(parse_ip));
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Returns the addresses in the `X-Forwarded-For` headers, nearest hop last
+fn x_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let name = HeaderName::from_static("x-forwarded-for");
+    let mut res = vec![];
+    for value in headers.get_all(name) {
+        match value.to_str() {
+            Ok(v) => res.extend(v.split(',').map(parse_ip)),
+            _ => return vec![None],
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::HeaderValue;
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies {
+            nets: vec!["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()],
+            unix: true,
+        }
+    }
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        );
+        headers
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn nets() {
+        let net: IpNet = "192.168.0.0/16".parse().unwrap();
+        assert!(net.contains("192.168.1.2".parse().unwrap()));
+        assert!(!net.contains("192.169.1.2".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+        let all: IpNet = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains("1.2.3.4".parse().unwrap()));
+        assert!("1.2.3.4/33".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let h = headers("x-forwarded-for", "1.2.3.4");
+        assert_eq!(proxies().client_ip(ip("5.6.7.8"), &h), ip("5.6.7.8"));
+        assert_eq!(TrustedProxies::default().client_ip(None, &h), None);
+    }
+
+    #[test]
+    fn trusted_peer() {
+        let p = proxies();
+        let h = headers("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.2");
+        assert_eq!(p.client_ip(ip("10.0.0.1"), &h), ip("1.2.3.4"));
+        assert_eq!(p.client_ip(None, &h), ip("1.2.3.4"));
+        assert_eq!(p.client_ip(ip("::1"), &HeaderMap::new()), ip("::1"));
+        let h = headers("forwarded", "for=1.2.3.4, for=\"[2001:db8::1]:4711\"");
+        assert_eq!(p.client_ip(None, &h), ip("2001:db8::1"));
+        let h = headers("x-forwarded-for", "1.2.3.4, unknown");
+        assert_eq!(p.client_ip(None, &h), None);
+    }
+}
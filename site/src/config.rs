@@ -1,5 +1,12 @@
+use crate::{client_ip::TrustedProxies, schedule_model::WeekStart};
+use common::{
+    config::UserAgent,
+    flags::FlagConfig,
+    pg::{ExplainConfig, PoolConfig},
+};
 use serde::{de::Error, Deserialize, Deserializer};
 use std::{
+    collections::HashMap,
     fmt,
     fmt::Display,
     net::{SocketAddr, ToSocketAddrs},
@@ -10,20 +17,161 @@ use std::{
 pub struct Config {
     pub db: Db,
     pub http: Http,
+    pub user_agent: UserAgent,
+    #[serde(default)]
+    pub magnet: Magnet,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub admin: Admin,
+    #[serde(default)]
+    pub covers: Covers,
+    #[serde(default)]
+    pub schedule: Schedule,
+    #[serde(default)]
+    pub flags: FlagConfig,
+    #[serde(default)]
+    pub health: Health,
+    #[serde(default)]
+    pub watchlists: Watchlists,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Db {
+    #[serde(default)]
     pub connection_string: String,
+    pub sqlite: Option<PathBuf>,
+    pub explain: Option<ExplainConfig>,
+    /// Connections per worker thread. A single connection per worker if not set.
+    pub pool: Option<PoolConfig>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Http {
-    #[serde(deserialize_with = "parse_addr_type")]
-    pub listen_addr: Vec<AddrType>,
+    #[serde(deserialize_with = "parse_listeners")]
+    pub listen_addr: Vec<Listener>,
+    #[serde(default, deserialize_with = "parse_trusted_proxies")]
+    pub trusted_proxies: TrustedProxies,
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+}
+
+fn default_base_url() -> String {
+    "https://magnets.moe".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Magnet {
+    #[serde(default = "default_magnet_rate_limit")]
+    pub rate_limit: u32,
+}
+
+impl Default for Magnet {
+    fn default() -> Self {
+        Self {
+            rate_limit: default_magnet_rate_limit(),
+        }
+    }
+}
+
+fn default_magnet_rate_limit() -> u32 {
+    60
+}
+
+/// Requests per minute per client for each route class
+#[derive(Debug, Deserialize)]
+pub struct RateLimit {
+    #[serde(default = "default_html_rate_limit")]
+    pub html: u32,
+    #[serde(default = "default_search_rate_limit")]
+    pub search: u32,
+    #[serde(default = "default_api_rate_limit")]
+    pub api: u32,
+    /// Shared by the html and search classes for requests of crawlers
+    #[serde(default = "default_crawler_rate_limit")]
+    pub crawler: u32,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            html: default_html_rate_limit(),
+            search: default_search_rate_limit(),
+            api: default_api_rate_limit(),
+            crawler: default_crawler_rate_limit(),
+        }
+    }
+}
+
+fn default_html_rate_limit() -> u32 {
+    600
+}
+
+fn default_search_rate_limit() -> u32 {
+    60
+}
+
+fn default_api_rate_limit() -> u32 {
+    120
+}
+
+fn default_crawler_rate_limit() -> u32 {
+    30
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Admin {
+    /// Nobody can log in to /admin if this is empty
+    #[serde(default)]
+    pub users: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Covers {
+    /// Covers are not served if this is not set
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub week_start: WeekStart,
+}
+
+/// The ages of the data above which /readyz fails, see [crate::health]
+#[derive(Copy, Clone, Debug, Deserialize)]
+pub struct Health {
+    #[serde(default = "default_shows_max_age_hours")]
+    pub shows_max_age_hours: i64,
+    #[serde(default = "default_processor_max_age_minutes")]
+    pub processor_max_age_minutes: i64,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            shows_max_age_hours: default_shows_max_age_hours(),
+            processor_max_age_minutes: default_processor_max_age_minutes(),
+        }
+    }
+}
+
+fn default_shows_max_age_hours() -> i64 {
+    72
+}
+
+fn default_processor_max_age_minutes() -> i64 {
+    5
+}
+
+/// See [crate::watchlist]
+#[derive(Debug, Default, Deserialize)]
+pub struct Watchlists {
+    /// The AniList API client with which users log in. Logging in is disabled if not set.
+    pub anilist_client_id: Option<i64>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AddrType {
     Ip(SocketAddr),
     Uds(PathBuf),
@@ -38,19 +186,101 @@ impl Display for AddrType {
     }
 }
 
-fn parse_addr_type<'de, D>(d: D) -> Result<Vec<AddrType>, D::Error>
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listener {
+    pub addr: AddrType,
+    /// Whether connections start with a PROXY header
+    pub proxy_protocol: bool,
+    pub tls: Option<Tls>,
+    pub role: ListenerRole,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Tls {
+    /// PEM file containing the certificate chain
+    pub certificate: PathBuf,
+    /// PEM file containing the private key in PKCS#8 or RSA format
+    pub private_key: PathBuf,
+}
+
+/// Which endpoints a listener serves
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRole {
+    /// Everything except /metrics and /admin if there is an internal listener
+    Public,
+    /// Everything
+    Internal,
+}
+
+impl Default for ListenerRole {
+    fn default() -> Self {
+        Self::Public
+    }
+}
+
+impl Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.addr, f)?;
+        if self.tls.is_some() {
+            f.write_str(" (tls)")?;
+        }
+        if self.proxy_protocol {
+            f.write_str(" (PROXY protocol)")?;
+        }
+        if self.role == ListenerRole::Internal {
+            f.write_str(" (internal)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListenerConfig {
+    Addr(String),
+    Table {
+        addr: String,
+        #[serde(default)]
+        proxy_protocol: bool,
+        tls: Option<Tls>,
+        #[serde(default)]
+        role: ListenerRole,
+    },
+}
+
+fn parse_listeners<'de, D>(d: D) -> Result<Vec<Listener>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let addrs: Vec<String> = Deserialize::deserialize(d)?;
+    let listeners: Vec<ListenerConfig> = Deserialize::deserialize(d)?;
     let mut res = vec![];
-    for addr in addrs {
+    for listener in listeners {
+        let (addr, proxy_protocol, tls, role) = match listener {
+            ListenerConfig::Addr(addr) => (addr, false, None, ListenerRole::Public),
+            ListenerConfig::Table {
+                addr,
+                proxy_protocol,
+                tls,
+                role,
+            } => (addr, proxy_protocol, tls, role),
+        };
         const UNIX: &str = "unix:";
         if let Some(stripped) = addr.strip_prefix(UNIX) {
-            res.push(AddrType::Uds(stripped.to_string().into()));
+            res.push(Listener {
+                addr: AddrType::Uds(stripped.to_string().into()),
+                proxy_protocol,
+                tls,
+                role,
+            });
         } else {
             match addr.to_socket_addrs() {
-                Ok(addrs) => res.extend(addrs.map(AddrType::Ip)),
+                Ok(addrs) => res.extend(addrs.map(|addr| Listener {
+                    addr: AddrType::Ip(addr),
+                    proxy_protocol,
+                    tls: tls.clone(),
+                    role,
+                })),
                 Err(e) => {
                     return Err(D::Error::custom(format!(
                         "cannot parse `{}`: {}",
@@ -62,3 +292,19 @@ where
     }
     Ok(res)
 }
+
+fn parse_trusted_proxies<'de, D>(d: D) -> Result<TrustedProxies, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let proxies: Vec<String> = Deserialize::deserialize(d)?;
+    let mut res = TrustedProxies::default();
+    for proxy in proxies {
+        if proxy == "unix" {
+            res.unix = true;
+        } else {
+            res.nets.push(proxy.parse().map_err(D::Error::custom)?);
+        }
+    }
+    Ok(res)
+}
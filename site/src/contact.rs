@@ -0,0 +1,25 @@
+use crate::{state::State, text::TEXT_HTML};
+use actix_web::{web::Data, HttpResponse, Responder};
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "contact.html")]
+struct Contact<'a> {
+    contact: &'a str,
+    contact_link: String,
+    user_agent: String,
+}
+
+/// Tells operators of upstream services how to reach us about our requests
+#[actix_web::get("/contact")]
+pub async fn get(state: Data<State>) -> impl Responder {
+    let user_agent = &state.global.user_agent;
+    let contact = Contact {
+        contact: &user_agent.contact,
+        contact_link: user_agent.contact_link(),
+        user_agent: user_agent.header(),
+    }
+    .render()
+    .unwrap();
+    HttpResponse::Ok().content_type(TEXT_HTML).body(contact)
+}
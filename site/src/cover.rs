@@ -0,0 +1,40 @@
+use crate::state::State;
+use actix_files::NamedFile;
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, CACHE_CONTROL},
+    web,
+    web::Data,
+    HttpRequest, HttpResponse,
+};
+use common::CoverSize;
+
+/// Serves a cover thumbnail that has been mirrored by the processor
+#[actix_web::get("/img/cover/{show_id}/{size}")]
+pub async fn get(
+    req: HttpRequest,
+    state: Data<State>,
+    path: web::Path<(i64, String)>,
+) -> actix_web::Result<HttpResponse> {
+    let (show_id, size) = path.into_inner();
+    let size = match CoverSize::parse(&size) {
+        Some(s) => s,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let path = match &state.global.cover_dir {
+        Some(dir) => size.path(dir, show_id),
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let file = match NamedFile::open(path) {
+        Ok(f) => f,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let mut res = file.into_response(&req)?;
+    // Covers rarely change
+    let cc = CacheControl(vec![
+        CacheDirective::MaxAge(30 * 24 * 60 * 60),
+        CacheDirective::Public,
+    ]);
+    res.headers_mut()
+        .insert(CACHE_CONTROL, cc.to_string().parse().unwrap());
+    Ok(res)
+}
@@ -0,0 +1,117 @@
+//! Classification of clients by their user agent
+//!
+//! Crawlers and scripts are identified by lowercase substrings of their `User-Agent`.
+//! Requests without a user agent are also counted as crawlers since all browsers send
+//! one. Crawlers share a single, lower rate limit, do not count towards the trending
+//! torrents, and may cache pages for longer.
+
+use actix_web::{
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, HeaderValue},
+    HttpMessage, HttpRequest,
+};
+
+/// Substrings of the user agents of crawlers and http libraries
+const CRAWLERS: &[&str] = &[
+    // Contact urls, e.g. `(compatible; Googlebot/2.1; +http://www.google.com/bot.html)`
+    "+http",
+    "bot/",
+    "crawler",
+    "spider",
+    "ahrefsbot",
+    "applebot",
+    "baiduspider",
+    "bingbot",
+    "bytespider",
+    "facebookexternalhit",
+    "googlebot",
+    "petalbot",
+    "semrushbot",
+    "yandex",
+    "aiohttp",
+    "curl/",
+    "go-http-client",
+    "java/",
+    "libwww-perl",
+    "node-fetch",
+    "okhttp",
+    "python-requests",
+    "python-urllib",
+    "scrapy",
+    "wget/",
+];
+
+/// How long crawlers may cache pages that are cacheable for browsers
+const CRAWLER_MAX_AGE: u32 = 24 * 60 * 60;
+
+/// Marker that is stored in the request if the client is a crawler
+struct Crawler;
+
+/// Returns whether a user agent belongs to a crawler
+pub fn is_crawler_user_agent(user_agent: Option<&str>) -> bool {
+    let ua = match user_agent {
+        Some(ua) if !ua.trim().is_empty() => ua.to_ascii_lowercase(),
+        _ => return true,
+    };
+    CRAWLERS.iter().any(|c| ua.contains(c))
+}
+
+/// Classifies the client and stores the result in the request
+pub fn classify(req: &ServiceRequest) {
+    let ua = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok());
+    if is_crawler_user_agent(ua) {
+        req.extensions_mut().insert(Crawler);
+    }
+}
+
+/// Returns whether the request was performed by a crawler
+pub fn is_crawler(req: &HttpRequest) -> bool {
+    req.extensions().get::<Crawler>().is_some()
+}
+
+/// Extends the cache lifetime of responses to crawlers
+///
+/// Only responses that are cacheable anyway are changed. They are marked private so
+/// that shared caches do not serve the longer-lived copy to browsers.
+pub fn extend_max_age<B>(res: &mut ServiceResponse<B>) {
+    if !is_crawler(res.request()) {
+        return;
+    }
+    let cacheable = res
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|cc| cc.to_str().ok())
+        .map(|cc| cc.contains("max-age") && !cc.contains("no-store"))
+        .unwrap_or(false);
+    if cacheable {
+        let cc = format!("private, max-age={}", CRAWLER_MAX_AGE);
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_str(&cc).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_user_agents() {
+        assert!(is_crawler_user_agent(Some(
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"
+        )));
+        assert!(is_crawler_user_agent(Some("python-requests/2.25.1")));
+        assert!(is_crawler_user_agent(Some("curl/7.74.0")));
+        assert!(is_crawler_user_agent(None));
+        assert!(is_crawler_user_agent(Some(" ")));
+        assert!(!is_crawler_user_agent(Some(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:84.0) Gecko/20100101 Firefox/84.0"
+        )));
+        assert!(!is_crawler_user_agent(Some(
+            "Mozilla/5.0 (Linux; Android 10; Cubot X30) AppleWebKit/537.36 (KHTML, like \
+             Gecko) Chrome/87.0.4280.101 Mobile Safari/537.36"
+        )));
+    }
+}
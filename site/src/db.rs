@@ -4,52 +4,80 @@ use common::pg::FromClient;
 use tokio_postgres::Client;
 
 pub struct Statements {
+    pub calendar: Calendar,
     pub season: Season,
+    pub season_grid: SeasonGrid,
     pub schedule: Schedule,
     pub show_info: ShowInfo,
-    pub show_torrents: ShowTorrents,
-    pub unmatched: Unmatched,
-    pub new: New,
+    pub magnet: Magnet,
+    pub api_season: ApiSeason,
+    pub api_seasons: ApiSeasons,
+    pub nyaa: Nyaa,
+    pub hashes: Hashes,
+    pub episodes: Episodes,
+    pub show_release_groups: ShowReleaseGroups,
+    pub release_group: ReleaseGroup,
+    pub admin_state: AdminState,
+    pub health: Health,
+    pub audit_log: AuditLog,
+    pub admin_role: AdminRole,
+    pub match_diff: MatchDiff,
+    pub admin_torrent_matches: AdminTorrentMatches,
+    pub alias_suggestions: AliasSuggestions,
+    pub match_rate: MatchRate,
+    pub api_meta: ApiMeta,
+    pub torrent: Torrent,
+    pub show_names: ShowNames,
+    pub search_shows: SearchShows,
+    pub search_torrents: SearchTorrents,
+    pub watchlist_session: WatchlistSession,
+    pub watchlist_shows: WatchlistShows,
 }
 
 #[async_trait]
 impl FromClient for Statements {
     async fn from_client(client: &Client) -> Result<Self> {
         Ok(Self {
+            calendar: Calendar::new(client).await?,
             season: Season::new(client).await?,
+            season_grid: SeasonGrid::new(client).await?,
             schedule: Schedule::new(client).await?,
             show_info: ShowInfo::new(client).await?,
-            show_torrents: ShowTorrents::new(client).await?,
-            unmatched: Unmatched::new(client).await?,
-            new: New::new(client).await?,
+            magnet: Magnet::new(client).await?,
+            api_season: ApiSeason::new(client).await?,
+            api_seasons: ApiSeasons::new(client).await?,
+            nyaa: Nyaa::new(client).await?,
+            hashes: Hashes::new(client).await?,
+            episodes: Episodes::new(client).await?,
+            show_release_groups: ShowReleaseGroups::new(client).await?,
+            release_group: ReleaseGroup::new(client).await?,
+            admin_state: AdminState::new(client).await?,
+            health: Health::new(client).await?,
+            audit_log: AuditLog::new(client).await?,
+            admin_role: AdminRole::new(client).await?,
+            match_diff: MatchDiff::new(client).await?,
+            admin_torrent_matches: AdminTorrentMatches::new(client).await?,
+            alias_suggestions: AliasSuggestions::new(client).await?,
+            match_rate: MatchRate::new(client).await?,
+            api_meta: ApiMeta::new(client).await?,
+            torrent: Torrent::new(client).await?,
+            show_names: ShowNames::new(client).await?,
+            search_shows: SearchShows::new(client).await?,
+            search_torrents: SearchTorrents::new(client).await?,
+            watchlist_session: WatchlistSession::new(client).await?,
+            watchlist_shows: WatchlistShows::new(client).await?,
         })
     }
 }
 
 // language=sql
-common::create_statement!(Unmatched, title, trusted, uploaded_at, torrent_id, nyaa_id, hash; "
-    select title, trusted, uploaded_at, torrent_id, nyaa_id, hash
-    from magnets.torrent
-    where not matched and nyaa_id < $1
-    order by nyaa_id desc
-    limit 101;");
-
-// language=sql
-common::create_statement!(ShowTorrents, title, uploaded_at, trusted, torrent_id, hash, nyaa_id; "
-    select t.title, t.uploaded_at, t.trusted, t.torrent_id, t.hash, t.nyaa_id
-    from magnets.rel_torrent_show rts
-    join magnets.torrent t using (torrent_id)
-    where rts.show_id = $1 and rts.nyaa_id < $2
-    order by rts.nyaa_id desc
-    limit 101;");
-
-// language=sql
-common::create_statement!(ShowInfo, show_id, anilist_id, season, show_format, names; "
+common::create_statement!(ShowInfo, show_id, anilist_id, season, show_format, has_cover, names, removed, merged_into, mal_id; "
     select
         s.show_id,
         s.anilist_id,
         s.season,
         s.show_format,
+        s.cover_mirrored_url is not null as has_cover,
         (
             select json_agg(x)
             from (
@@ -57,12 +85,19 @@ common::create_statement!(ShowInfo, show_id, anilist_id, season, show_format, na
                 from magnets.show_name
                 where show_id = s.show_id and show_name_type in (1, 2)
             ) x
-        ) as names
+        ) as names,
+        s.removal is not null as removed,
+        s.merged_into,
+        (
+            select external_id
+            from magnets.show_external_id
+            where show_id = s.show_id and external_site = 1
+        ) as mal_id
     from magnets.show s
     where s.show_id = $1;");
 
 // language=sql
-common::create_statement!(Schedule, schedule_id, show_id, episode, airs_at, names; "
+common::create_statement!(Schedule, schedule_id, show_id, episode, airs_at, names, expected, recommended; "
     select
         s.schedule_id,
         s.show_id,
@@ -75,22 +110,435 @@ common::create_statement!(Schedule, schedule_id, show_id, episode, airs_at, name
                 from magnets.show_name
                 where show_id = s.show_id and show_name_type in (1, 2)
             ) x
-        ) as names
+        ) as names,
+        (
+            select json_agg(x)
+            from (
+                select release_group, delay_seconds
+                from magnets.expected_release
+                where show_id = s.show_id and samples >= 3
+                order by delay_seconds
+            ) x
+        ) as expected,
+        (
+            select rt.torrent_id
+            from magnets.recommended_torrent rt
+            where rt.show_id = s.show_id and rt.episode = s.episode
+        ) as recommended
     from magnets.schedule s
-    where s.airs_at >= $1 and s.airs_at < $2
+    join magnets.show sh using (show_id)
+    where s.airs_at >= $1 and s.airs_at < $2 and sh.removal is null
     order by s.airs_at;");
 
 // language=sql
-common::create_statement!(Season, show_id, name, show_name_type; "
-    select sn.show_id, sn.name, sn.show_name_type
+common::create_statement!(Calendar, schedule_id, show_id, episode, airs_at, romaji, torrents, torrent_count; "
+    select
+        s.schedule_id,
+        s.show_id,
+        s.episode,
+        s.airs_at,
+        coalesce(
+            (
+                select name
+                from magnets.show_name
+                where show_id = s.show_id and show_name_type = 1
+                limit 1
+            ),
+            ''
+        ) as romaji,
+        t.torrents,
+        t.torrent_count
+    from magnets.schedule s
+    join magnets.show sh using (show_id)
+    cross join lateral (
+        select
+            (array_agg(rts.torrent_id order by tor.trusted desc, tor.nyaa_id))[1:3] as torrents,
+            count(rts.torrent_id) as torrent_count
+        from magnets.rel_torrent_show rts
+        join magnets.torrent tor using (torrent_id)
+        where rts.show_id = s.show_id and rts.episode = s.episode and not tor.batch
+    ) t
+    where s.airs_at >= $1 and s.airs_at < $2 and sh.removal is null
+    order by s.airs_at, s.schedule_id");
+
+// language=sql
+common::create_statement!(Season, show_id, name, show_name_type, inferred; "
+    select sn.show_id, sn.name, sn.show_name_type, s.season is null as inferred
     from magnets.show_name sn
     join magnets.show s using (show_id)
-    where sn.show_name_type in (1, 2) and s.season = $1");
+    where sn.show_name_type in (1, 2)
+        and (s.season = $1 or s.season is null and s.inferred_season = $1)
+        and s.removal is null");
+
+// language=sql
+common::create_statement!(SeasonGrid, show_id, romaji, english, show_format, episodes, has_cover, inferred, torrents; "
+    select
+        s.show_id,
+        coalesce(max(sn.name) filter (where sn.show_name_type = 1), '') as romaji,
+        max(sn.name) filter (where sn.show_name_type = 2) as english,
+        s.show_format,
+        s.episodes,
+        s.cover_mirrored_url is not null as has_cover,
+        s.season is null as inferred,
+        (
+            select count(*)
+            from magnets.rel_torrent_show rts
+            where rts.show_id = s.show_id
+        ) as torrents
+    from magnets.show s
+    join magnets.show_name sn on sn.show_id = s.show_id and sn.show_name_type in (1, 2)
+    where (s.season = $1 or s.season is null and s.inferred_season = $1) and s.removal is null
+    group by s.show_id
+    order by torrents desc, s.show_id");
+
+// language=sql
+common::create_statement!(Torrent, nyaa_id, title, trusted, uploaded_at, hash, batch, seeders, leechers, completed, recommended, size, show_ids, anilist_ids; "
+    select
+        t.nyaa_id,
+        t.title,
+        t.trusted,
+        t.uploaded_at,
+        t.hash,
+        t.batch,
+        t.seeders,
+        t.leechers,
+        t.completed,
+        exists (
+            select 1
+            from magnets.recommended_torrent rt
+            where rt.torrent_id = t.torrent_id
+        ) as recommended,
+        t.size,
+        array(
+            select rts.show_id
+            from magnets.rel_torrent_show rts
+            where rts.torrent_id = t.torrent_id
+            order by rts.show_id
+        ) as show_ids,
+        array(
+            select s.anilist_id
+            from magnets.rel_torrent_show rts
+            join magnets.show s on s.show_id = rts.show_id
+            where rts.torrent_id = t.torrent_id
+            order by rts.show_id
+        ) as anilist_ids
+    from magnets.torrent t
+    where t.torrent_id = $1;");
+
+// language=sql
+common::create_statement!(ShowNames, show_id, romaji, english; "
+    select
+        show_id,
+        coalesce(max(name) filter (where show_name_type = 1), '') as romaji,
+        max(name) filter (where show_name_type = 2) as english
+    from magnets.show_name
+    where show_id = any($1) and show_name_type in (1, 2)
+    group by show_id;");
+
+// The conditions on search_names match the indexes in sql/init.sql. $1 is folded with
+// `textnorm::search_words`.
+// language=sql
+common::create_statement!(SearchShows, show_id, romaji, english, similarity, popularity, last_torrent_at; "
+    select
+        s.show_id,
+        coalesce(
+            (
+                select name
+                from magnets.show_name
+                where show_id = s.show_id and show_name_type = 1
+                limit 1
+            ),
+            ''
+        ) as romaji,
+        (
+            select name
+            from magnets.show_name
+            where show_id = s.show_id and show_name_type = 2
+            limit 1
+        ) as english,
+        word_similarity($1, s.search_names) as similarity,
+        s.popularity,
+        s.last_torrent_at
+    from magnets.show s
+    where s.removal is null
+        and (
+            to_tsvector('simple', s.search_names) @@ plainto_tsquery('simple', $1)
+            or $1 <% s.search_names
+        )
+    order by word_similarity($1, s.search_names) desc, s.show_id
+    limit 50;");
 
+// Returns up to PAGE_SIZE + 1 torrents
 // language=sql
-common::create_statement!(New, title, uploaded_at, trusted, torrent_id, hash, nyaa_id; "
-    select title, uploaded_at, trusted, torrent_id, hash, nyaa_id
+common::create_statement!(SearchTorrents, torrent_id, nyaa_id, title, trusted, uploaded_at, hash, batch, seeders, leechers, completed, recommended; "
+    select t.torrent_id, t.nyaa_id, t.title, t.trusted, t.uploaded_at, t.hash, t.batch,
+        t.seeders, t.leechers, t.completed,
+        exists (
+            select 1
+            from magnets.recommended_torrent rt
+            where rt.torrent_id = t.torrent_id
+        ) as recommended
+    from magnets.torrent t
+    where to_tsvector('simple', t.search_title) @@ plainto_tsquery('simple', $1)
+        and t.nyaa_id < $2
+    order by t.nyaa_id desc
+    limit 101;");
+
+// language=sql
+common::create_statement!(Magnet, title, hash; "
+    select title, hash
     from magnets.torrent
-    where nyaa_id < $1
-    order by nyaa_id desc
+    where torrent_id = $1;");
+
+// language=sql
+common::create_statement!(ApiSeason, show_id, anilist_id, show_format, names, torrents; "
+    select
+        s.show_id,
+        s.anilist_id,
+        s.show_format,
+        (
+            select json_agg(x)
+            from (
+                select name, show_name_type
+                from magnets.show_name
+                where show_id = s.show_id and show_name_type in (1, 2)
+            ) x
+        ) as names,
+        (
+            select count(*)
+            from magnets.rel_torrent_show rts
+            where rts.show_id = s.show_id
+        ) as torrents
+    from magnets.show s
+    where s.season = $1 and s.removal is null
+    order by s.show_id;");
+
+// language=sql
+common::create_statement!(ApiSeasons, season, shows; "
+    select season, count(*) as shows
+    from magnets.show
+    where season is not null and removal is null
+    group by season
+    order by season desc;");
+
+// language=sql
+common::create_statement!(Nyaa, torrent_id, show_ids; "
+    select
+        t.torrent_id,
+        array(
+            select rts.show_id
+            from magnets.rel_torrent_show rts
+            where rts.torrent_id = t.torrent_id
+            order by rts.show_id
+        ) as show_ids
+    from magnets.torrent t
+    where t.nyaa_id = $1;");
+
+// language=sql
+common::create_statement!(Hashes, torrent_id, nyaa_id, hash, title, uploaded_at, size, trusted, shows; "
+    select
+        t.torrent_id,
+        t.nyaa_id,
+        t.hash,
+        t.title,
+        t.uploaded_at,
+        t.size,
+        t.trusted,
+        (
+            select coalesce(json_agg(x), '[]'::json)
+            from (
+                select rts.show_id, sn.name
+                from magnets.rel_torrent_show rts
+                join magnets.show_name sn using (show_id)
+                where rts.torrent_id = t.torrent_id and sn.show_name_type = 1
+            ) x
+        ) as shows
+    from magnets.torrent t
+    where t.hash = any($1) and t.hash_type = 1;");
+
+// language=sql
+common::create_statement!(Episodes, episodes, aired, matched; "
+    select
+        s.episodes,
+        (
+            select min(sch.episode) - 1
+            from magnets.schedule sch
+            where sch.show_id = s.show_id and sch.airs_at > $2
+        ) as aired,
+        array(
+            select distinct rts.episode
+            from magnets.rel_torrent_show rts
+            where rts.show_id = s.show_id
+                and rts.episode is not null
+                and rts.episode_end is null
+        ) as matched
+    from magnets.show s
+    where s.show_id = $1;");
+
+// language=sql
+common::create_statement!(ShowReleaseGroups, name; "
+    select g.name
+    from magnets.rel_torrent_show rts
+    join magnets.torrent t using (torrent_id)
+    join magnets.release_group g using (release_group_id)
+    where rts.show_id = $1
+    group by g.name
+    order by count(*) desc, g.name;");
+
+// language=sql
+common::create_statement!(ReleaseGroup, release_group_id; "
+    select release_group_id
+    from magnets.release_group
+    where name = $1;");
+
+// language=sql
+common::create_statement!(AdminState, key, value; "
+    select key, value::text as value
+    from magnets.state
+    order by key;");
+
+// language=sql
+common::create_statement!(Health, last_shows_update, processor_heartbeat; "
+    select
+        max(value::text::timestamptz) filter (where key = 'last_shows_update')
+            as last_shows_update,
+        max(value::text::timestamptz) filter (where key = 'processor_heartbeat')
+            as processor_heartbeat
+    from magnets.state
+    where key in ('last_shows_update', 'processor_heartbeat');");
+
+// language=sql
+common::create_statement!(MatchRate, hour, matched, torrents; "
+    select
+        date_trunc('hour', ts.created) as hour,
+        count(distinct t.torrent_id) filter (where t.matched) as matched,
+        count(distinct t.torrent_id) as torrents
+    from magnets.torrent_source ts
+    join magnets.torrent t using (torrent_id)
+    where ts.created > now() - interval '24 hours'
+    group by hour
+    order by hour desc;");
+
+// language=sql
+common::create_statement!(AuditLog, audit_log_id, actor, action, before, after, created; "
+    select audit_log_id, actor, action, before::text, after::text, created
+    from magnets.audit_log
+    where audit_log_id < $1
+    order by audit_log_id desc
     limit 101;");
+
+// language=sql
+common::create_statement!(AdminRole, role; "
+    select role
+    from magnets.admin_user
+    where name = $1;");
+
+// language=sql
+common::create_statement!(MatchDiff, torrent_id, nyaa_id, title, show_id, name, added, status; "
+    select d.torrent_id, t.nyaa_id, t.title, d.show_id, sn.name, d.added, d.status
+    from magnets.match_diff d
+    join magnets.torrent t on t.torrent_id = d.torrent_id
+    join magnets.show_name sn on sn.show_id = d.show_id and sn.show_name_type = 1
+    where d.torrent_id in (
+        select distinct torrent_id
+        from magnets.match_diff
+        where torrent_id < $1
+        order by torrent_id desc
+        limit 101
+    )
+    order by d.torrent_id desc, d.added;");
+
+// The matches and manual corrections of a torrent
+// language=sql
+common::create_statement!(AdminTorrentMatches, show_id, name, matched, assigned, actor; "
+    select m.show_id, sn.name, m.matched, m.assigned, m.actor
+    from (
+        select show_id, rts.show_id is not null as matched, o.assigned, o.actor
+        from (
+            select show_id
+            from magnets.rel_torrent_show
+            where torrent_id = $1
+        ) rts
+        full join (
+            select show_id, assigned, actor
+            from magnets.match_override
+            where torrent_id = $1
+        ) o using (show_id)
+    ) m
+    join magnets.show_name sn on sn.show_id = m.show_id and sn.show_name_type = 1
+    order by m.show_id;");
+
+// language=sql
+common::create_statement!(AliasSuggestions, alias_suggestion_id, alias, show_id, name, torrents, example_title, status; "
+    select a.alias_suggestion_id, a.alias, a.show_id, sn.name, a.torrents, a.example_title, a.status
+    from magnets.alias_suggestion a
+    join magnets.show s on s.show_id = a.show_id
+    join magnets.show_name sn on sn.show_id = a.show_id and sn.show_name_type = 1
+    where a.status < 3 and s.removal is null
+    order by a.torrents desc, a.alias_key, a.alias_suggestion_id
+    limit 200;");
+
+// language=sql
+common::create_statement!(
+    ApiMeta,
+    shows,
+    torrents,
+    unmatched_torrents,
+    max_nyaa_id,
+    newest_upload,
+    last_torrent_import,
+    last_shows_update,
+    last_schedule_update; "
+    select
+        (select count(*) from magnets.show where removal is null) as shows,
+        (select count(*) from magnets.torrent) as torrents,
+        (select count(*) from magnets.torrent where not matched) as unmatched_torrents,
+        t.nyaa_id as max_nyaa_id,
+        t.uploaded_at as newest_upload,
+        t.created as last_torrent_import,
+        (
+            select (value #>> '{}')::timestamptz
+            from magnets.state
+            where key = 'last_shows_update'
+        ) as last_shows_update,
+        (
+            select (value #>> '{}')::timestamptz
+            from magnets.state
+            where key = 'last_schedule_update'
+        ) as last_schedule_update
+    from (select 1) x
+    left join (
+        select nyaa_id, uploaded_at, created
+        from magnets.torrent
+        order by nyaa_id desc
+        limit 1
+    ) t on true;");
+
+// language=sql
+common::create_statement!(WatchlistSession, code, failed, site_user_id, anilist_name, synced_at; "
+    select s.code, s.failed, s.site_user_id, u.anilist_name, u.synced_at
+    from magnets.site_session s
+    left join magnets.site_user u using (site_user_id)
+    where s.token = $1 and s.expires > now();");
+
+// language=sql
+common::create_statement!(WatchlistShows, show_id, romaji, latest_episode; "
+    select
+        w.show_id,
+        coalesce(
+            (
+                select name
+                from magnets.show_name
+                where show_id = w.show_id and show_name_type = 1
+                limit 1
+            ),
+            ''
+        ) as romaji,
+        (
+            select max(coalesce(rts.episode_end, rts.episode))
+            from magnets.rel_torrent_show rts
+            where rts.show_id = w.show_id
+        ) as latest_episode
+    from magnets.watchlist w
+    where w.site_user_id = $1
+    order by romaji;");
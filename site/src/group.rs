@@ -0,0 +1,138 @@
+use crate::{
+    repo::{
+        listing::{TorrentFilter, TorrentQuery},
+        TorrentRepo,
+    },
+    state::State,
+    text::TEXT_HTML,
+    torrent_list::{torrent_list, Day},
+};
+use actix_web::{
+    web,
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use common::path_encode;
+use serde::Deserialize;
+
+#[actix_web::get("/group/{name}")]
+pub async fn get(
+    state: Data<State>,
+    name: web::Path<(String,)>,
+    Query(query): Query<QueryParams>,
+) -> impl Responder {
+    match process(&state, &name.0.0, query).await {
+        Ok(Some(data)) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to load the torrents of group {}: {:#}",
+                name.0.0,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "group.html")]
+struct Group<'a> {
+    name: &'a str,
+    days: &'a [Day<'a>],
+    last: Option<i64>,
+    first: bool,
+    base: String,
+}
+
+mod filters {
+    pub use crate::text::{format_day, format_time};
+}
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    #[serde(rename = "a", default = "i64::max_value")]
+    after: i64,
+}
+
+async fn process(
+    state: &State,
+    name: &str,
+    query: QueryParams,
+) -> Result<Option<String>> {
+    render(&state.repo().await?, name, query).await
+}
+
+/// Returns `None` if no torrent of the group has been stored
+async fn render(
+    repo: &impl TorrentRepo,
+    name: &str,
+    query: QueryParams,
+) -> Result<Option<String>> {
+    if !repo.release_group_exists(name).await? {
+        return Ok(None);
+    }
+    let query = TorrentQuery {
+        filter: TorrentFilter {
+            group: Some(name.to_string()),
+            ..Default::default()
+        },
+        after: query.after,
+    };
+    let torrents = repo.torrents(&query).await?;
+    let (last, days) = torrent_list(&torrents);
+    let group = Group {
+        name,
+        days: &days,
+        last,
+        first: query.after == i64::MAX,
+        base: format!("/group/{}", path_encode(name)),
+    };
+    Ok(Some(group.render()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{mock::MockRepo, TorrentDetails};
+    use futures::executor::block_on;
+
+    fn repo() -> MockRepo {
+        let mut repo = MockRepo::default();
+        let titles = [
+            "[SubsPlease] Oshi no Ko - 01 (1080p)",
+            "[Erai-raws] Oshi no Ko - 01 [1080p]",
+        ];
+        for (i, title) in titles.iter().enumerate() {
+            repo.torrents.push(TorrentDetails {
+                torrent: MockRepo::torrent_record(i as i64 + 1, title),
+                size: 0,
+                show_ids: vec![],
+                anilist_ids: vec![],
+            });
+        }
+        repo
+    }
+
+    fn params() -> QueryParams {
+        QueryParams { after: i64::MAX }
+    }
+
+    #[test]
+    fn lists_the_torrents_of_a_group() {
+        let html = block_on(render(&repo(), "SubsPlease", params()))
+            .unwrap()
+            .unwrap();
+        assert!(html.contains("[SubsPlease] Oshi no Ko - 01 (1080p)"));
+        assert!(!html.contains("Erai-raws"));
+    }
+
+    #[test]
+    fn unknown_groups_are_not_found() {
+        assert!(block_on(render(&repo(), "Unknown", params()))
+            .unwrap()
+            .is_none());
+    }
+}
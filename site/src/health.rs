@@ -0,0 +1,71 @@
+use crate::state::State;
+use actix_web::{web::Data, HttpResponse, Responder};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::fmt::Write;
+
+const TEXT_PLAIN: &str = "text/plain; charset=utf-8";
+
+/// Answers as long as the site handles requests
+///
+/// Meant for restarting a wedged site. The database is not queried so that an outage
+/// of postgres does not cause restarts.
+#[actix_web::get("/healthz")]
+pub async fn get_healthz() -> impl Responder {
+    HttpResponse::Ok().content_type(TEXT_PLAIN).body("ok\n")
+}
+
+/// Answers with 503 if the site cannot serve current data
+///
+/// This is the case if the database cannot be queried, if the last complete anilist
+/// sync is older than `health.shows_max_age_hours`, or if the heartbeat of the processor
+/// is older than `health.processor_max_age_minutes`. The body lists the problems.
+#[actix_web::get("/readyz")]
+pub async fn get_readyz(state: Data<State>) -> impl Responder {
+    let problems = match problems(&state).await {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("the readiness check failed: {:#}", e);
+            vec!["the database cannot be queried".to_string()]
+        }
+    };
+    if problems.is_empty() {
+        return HttpResponse::Ok().content_type(TEXT_PLAIN).body("ok\n");
+    }
+    let mut body = String::new();
+    for problem in &problems {
+        let _ = writeln!(body, "{}", problem);
+    }
+    HttpResponse::ServiceUnavailable()
+        .content_type(TEXT_PLAIN)
+        .body(body)
+}
+
+async fn problems(state: &State) -> Result<Vec<String>> {
+    // A snapshot in SQLite mode never changes
+    if state.global.sqlite.is_some() {
+        return Ok(vec![]);
+    }
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.health;
+    let row = db.query_one(&stmt.stmt, &[]).await?;
+    let config = &state.global.health;
+    let now = state.global.clock.now();
+    let mut problems = vec![];
+    let mut check = |what: &str, time: DateTime<Utc>, max_age: Duration| {
+        if now - time > max_age {
+            problems.push(format!("{} is from {}", what, time.to_rfc3339()));
+        }
+    };
+    check(
+        "the last anilist sync",
+        row.get(stmt.last_shows_update),
+        Duration::hours(config.shows_max_age_hours),
+    );
+    check(
+        "the heartbeat of the processor",
+        row.get(stmt.processor_heartbeat),
+        Duration::minutes(config.processor_max_age_minutes),
+    );
+    Ok(problems)
+}
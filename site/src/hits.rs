@@ -0,0 +1,74 @@
+use crate::state::Global;
+use actix_web::rt::time::interval;
+use anyhow::Result;
+use common::{pg::PgConnector, time::MINUTE};
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex},
+};
+
+/// Counts magnet redirects per torrent
+///
+/// Hits are buffered in memory and periodically written to `magnets.torrent_hits` so
+/// that the redirect itself never has to wait for the database.
+pub struct HitCounter {
+    pending: Mutex<HashMap<i64, i64>>,
+}
+
+impl HitCounter {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, torrent_id: i64) {
+        *self.pending.lock().unwrap().entry(torrent_id).or_insert(0) += 1;
+    }
+
+    fn take(&self) -> HashMap<i64, i64> {
+        mem::take(&mut *self.pending.lock().unwrap())
+    }
+
+    fn restore(&self, hits: HashMap<i64, i64>) {
+        let mut pending = self.pending.lock().unwrap();
+        for (torrent_id, count) in hits {
+            *pending.entry(torrent_id).or_insert(0) += count;
+        }
+    }
+}
+
+/// Writes the buffered hits to the database once per minute
+pub async fn flush_periodically(global: Arc<Global>) {
+    let mut interval = interval(MINUTE);
+    loop {
+        interval.tick().await;
+        let hits = global.hits.take();
+        if hits.is_empty() {
+            continue;
+        }
+        if let Err(e) = flush(&global.pg_connector, &hits).await {
+            log::error!("could not flush torrent hits: {:#}", e);
+            global.hits.restore(hits);
+        }
+    }
+}
+
+async fn flush(connector: &PgConnector, hits: &HashMap<i64, i64>) -> Result<()> {
+    let (torrent_ids, counts): (Vec<i64>, Vec<i64>) =
+        hits.iter().map(|(&t, &c)| (t, c)).unzip();
+    let db = connector.connect().await?;
+    // language=sql
+    db.execute(
+        "
+        insert into magnets.torrent_hits (torrent_id, day, hits)
+        select torrent_id, (now() at time zone 'utc')::date, hits
+        from unnest($1::bigint[], $2::bigint[]) as x(torrent_id, hits)
+        on conflict (torrent_id, day)
+        do update set hits = magnets.torrent_hits.hits + excluded.hits",
+        &[&torrent_ids, &counts],
+    )
+    .await?;
+    Ok(())
+}
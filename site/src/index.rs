@@ -1,5 +1,6 @@
-use crate::text::TEXT_HTML;
-use actix_web::{HttpResponse, Responder};
+use crate::{state::State, text::TEXT_HTML};
+use actix_web::{web::Data, HttpResponse, Responder};
+use anyhow::Result;
 use askama::Template;
 use common::YearSeason;
 
@@ -8,15 +9,22 @@ use common::YearSeason;
 struct Index {
     season_name: String,
     season_link: String,
+    watchlist: bool,
 }
 
 #[actix_web::get("/")]
-pub async fn get() -> impl Responder {
+pub async fn get(state: Data<State>) -> impl Responder {
+    let index = render(state.global.anilist_client_id.is_some()).unwrap();
+    HttpResponse::Ok().content_type(TEXT_HTML).body(index)
+}
+
+/// Renders the index page. `watchlist` is whether users can log in.
+pub fn render(watchlist: bool) -> Result<String> {
     let season = YearSeason::current();
     let index = Index {
         season_name: season.display_name(),
         season_link: season.to_url_str(),
+        watchlist,
     };
-    let index = index.render().unwrap();
-    HttpResponse::Ok().content_type(TEXT_HTML).body(index)
+    Ok(index.render()?)
 }
@@ -0,0 +1,67 @@
+use crate::{client_ip::client_ip, crawler::is_crawler, state::State, text::NotFound};
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, CACHE_CONTROL, LOCATION, RETRY_AFTER},
+    web,
+    web::Data,
+    HttpRequest, HttpResponse, Responder,
+};
+use anyhow::Result;
+use common::MagnetFormatter;
+
+/// Redirects to the magnet link of a torrent
+///
+/// This gives feeds and API consumers a stable http link for every torrent.
+#[actix_web::get("/magnet/{torrent_id}")]
+pub async fn get(
+    req: HttpRequest,
+    state: Data<State>,
+    id: web::Path<(i64,)>,
+) -> impl Responder {
+    if let Some(ip) = client_ip(&req) {
+        if let Err(retry_after) = state.global.magnet_limiter.check(ip) {
+            return HttpResponse::TooManyRequests()
+                .header(RETRY_AFTER, retry_after.to_string())
+                .finish();
+        }
+    }
+    match process(&state, id.0.0).await {
+        Ok(magnet_link) => {
+            // Crawlers would skew the trending torrents
+            if !is_crawler(&req) {
+                state.global.hits.record(id.0.0);
+            }
+            // The title and hash of a torrent never change
+            let cc = CacheControl(vec![
+                CacheDirective::MaxAge(24 * 60 * 60),
+                CacheDirective::Public,
+            ]);
+            HttpResponse::Found()
+                .header(CACHE_CONTROL, cc)
+                .header(LOCATION, magnet_link)
+                .finish()
+        }
+        Err(e) => {
+            if e.is::<NotFound>() {
+                HttpResponse::NotFound().finish()
+            } else {
+                log::error!(
+                    "An error occurred while trying to retrieve the magnet link of torrent {}: {:#}",
+                    id.0.0,
+                    e
+                );
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+async fn process(state: &State, torrent_id: i64) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let row = match db.query_opt(&db.t.magnet.stmt, &[&torrent_id]).await? {
+        Some(r) => r,
+        _ => return Err(NotFound.into()),
+    };
+    let magnet_link =
+        MagnetFormatter::new(row.get(db.t.magnet.title), row.get(db.t.magnet.hash));
+    Ok(magnet_link.to_string())
+}
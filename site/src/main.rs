@@ -1,61 +1,211 @@
 #![allow(clippy::eval_order_dependence)] // https://github.com/rust-lang/rust-clippy/issues/5684
 
-#[macro_use]
-mod torrent_list;
 #[macro_use]
 mod show_list;
+mod admin;
+mod api;
+mod batches;
 mod cache;
+mod calendar;
+mod check;
+mod client_ip;
 mod config;
+mod contact;
+mod cover;
+mod crawler;
 mod db;
 mod faq;
+mod group;
+mod health;
+mod hits;
 mod index;
+mod magnet;
+mod maintenance;
+mod metrics;
+mod missing;
 mod new;
+mod notify;
+mod nyaa;
+mod og;
+mod precompressed;
+mod proxy_protocol;
+mod rate_limit;
+mod repo;
 mod schedule;
+mod schedule_model;
+mod search;
 mod season;
+mod server;
 mod show;
+mod show_names;
 mod shows;
+mod snapshot;
 mod state;
+mod stats;
 mod text;
 mod torrent;
+mod torrent_list;
+mod trending;
 mod unmatched;
+mod watchlist;
 
 use crate::{
     cache::Cache,
-    config::{AddrType, Config},
+    config::Config,
+    hits::HitCounter,
+    maintenance::Maintenance,
+    precompressed::Precompressed,
+    rate_limit::{RateLimiter, RouteLimiters},
+    repo::sqlite::Sqlite,
+    show_names::ShowNameCache,
     state::{Global, State},
 };
 use actix_files as fs;
 use actix_web::{
+    dev::Service,
     web::{PathConfig, QueryConfig},
-    App, HttpServer,
+    App,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use common::{
-    pg::{PgConnector, PgHolder},
-    time::MINUTE,
+    config::LiveConfig,
+    flags::Flags,
+    pg::{Dummy, PgConnector, PgHolder},
+    time::{Clock, SystemClock, MINUTE},
+};
+use futures::{
+    future::{ok, Either},
+    FutureExt,
 };
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
 #[actix_web::main]
 async fn main() -> Result<()> {
     common::env::configure_logger();
 
-    let config: Config = common::config::load()?;
+    if std::env::args().nth(1).as_deref() == Some("check") {
+        return check::check().await;
+    }
+
+    let live_config = Arc::new(LiveConfig::<Config>::new(common::config::load()?));
+    let config = live_config.current();
 
-    let pg_connector = PgConnector::new(config.db.connection_string.clone());
+    let mut pg_connector = PgConnector::new(config.db.connection_string.clone());
+    if let Some(explain) = &config.db.explain {
+        log::info!(
+            "explaining queries that take longer than {} ms",
+            explain.threshold_ms
+        );
+        pg_connector = pg_connector.with_explain(explain.clone());
+    }
+
+    let sqlite = match &config.db.sqlite {
+        Some(path) => {
+            log::warn!("serving the repositories from {}", path.display());
+            Some(Arc::new(Sqlite::open(path)?))
+        }
+        _ => None,
+    };
+
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+    // The sessions are stored in postgres
+    let anilist_client_id = match sqlite {
+        Some(_) => None,
+        _ => config.watchlists.anilist_client_id,
+    };
 
     let global = Arc::new(Global {
-        shows: Cache::new(10 * MINUTE),
+        shows: Cache::new(10 * MINUTE, clock.clone()),
+        trending: Cache::new(10 * MINUTE, clock.clone()),
+        schedule: Cache::new(10 * MINUTE, clock.clone()),
+        stats: Cache::new(10 * MINUTE, clock.clone()),
+        show_names: ShowNameCache::new(),
         pg_connector: pg_connector.clone(),
+        magnet_limiter: RateLimiter::new(config.magnet.rate_limit, MINUTE),
+        route_limiters: RouteLimiters {
+            html: RateLimiter::new(config.rate_limit.html, MINUTE),
+            search: RateLimiter::new(config.rate_limit.search, MINUTE),
+            api: RateLimiter::new(config.rate_limit.api, MINUTE),
+            crawler: RateLimiter::new(config.rate_limit.crawler, MINUTE),
+        },
+        hits: HitCounter::new(),
+        maintenance: Maintenance::new(),
+        flags: Flags::new(&config.flags),
+        admin_users: config.admin.users.clone(),
+        cover_dir: config.covers.directory.clone(),
+        sqlite,
+        clock,
+        week_start: config.schedule.week_start,
+        health: config.health,
+        trusted_proxies: config.http.trusted_proxies.clone(),
+        base_url: config.http.base_url.trim_end_matches('/').to_string(),
+        user_agent: config.user_agent.clone(),
+        precompressed: Precompressed::generate(Path::new("static"), "/static"),
+        anilist_client_id,
     });
 
-    let mut server = HttpServer::new(move || {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.first().map(|a| &**a) == Some("snapshot") {
+        let out = match &args[1..] {
+            [flag, out] if flag == "--out" => out,
+            _ => return Err(anyhow!("usage: site snapshot --out DIR")),
+        };
         let state = State {
-            global: global.clone(),
+            global,
             pg: PgHolder::new(&pg_connector),
         };
-        App::new()
+        return snapshot::snapshot(&state, Path::new(out)).await;
+    }
+
+    // There is no postgres in SQLite mode
+    let have_pg = global.sqlite.is_none();
+    if have_pg {
+        actix_web::rt::spawn(hits::flush_periodically(global.clone()));
+        actix_web::rt::spawn(maintenance::refresh_periodically(global.clone()));
+    }
+    // Kept alive until the server stops
+    let _listener = have_pg.then(|| {
+        PgHolder::<Dummy, _>::with_message_handler(
+            notify::SiteMessageHandler::new(&global),
+            true,
+            &pg_connector,
+        )
+    });
+
+    let reload_config = live_config.clone();
+    actix_web::rt::spawn(async move { reload_config.reload_on_sighup(|_, _| {}).await });
+
+    let pool = config.db.pool.clone();
+    server::serve_reloading(&live_config, move |internal| {
+        let pg = match &pool {
+            Some(pool) => PgHolder::pooled(&pg_connector, pool),
+            _ => PgHolder::new(&pg_connector),
+        };
+        let state = State {
+            global: global.clone(),
+            pg,
+        };
+        let mw_global = global.clone();
+        let app = App::new()
             .data(state)
+            .wrap_fn(move |req, srv| {
+                client_ip::resolve(&mw_global, &req);
+                crawler::classify(&req);
+                let res = api::check_enabled(&mw_global, &req)
+                    .or_else(|| rate_limit::limit(&mw_global, &req))
+                    .or_else(|| maintenance::check(&mw_global, &req))
+                    .or_else(|| precompressed::serve(&mw_global, &req));
+                match res {
+                    Some(res) => Either::Left(ok(req.into_response(res))),
+                    _ => Either::Right(srv.call(req).map(|res| {
+                        res.map(|mut res| {
+                            crawler::extend_max_age(&mut res);
+                            res
+                        })
+                    })),
+                }
+            })
             .app_data(
                 QueryConfig::default()
                     .error_handler(|_, _| actix_web::error::ErrorNotFound("")),
@@ -66,25 +216,60 @@ async fn main() -> Result<()> {
             )
             .service(fs::Files::new("/static", "static"))
             .service(schedule::get)
+            .service(calendar::get)
             .service(index::get)
             .service(shows::get)
             .service(season::get)
+            .service(season::get_grid)
+            .service(search::get)
             .service(show::get)
+            .service(cover::get)
             .service(unmatched::get)
             .service(torrent::get)
+            .service(contact::get)
             .service(faq::get)
             .service(new::get)
-    });
-    for addr in &config.http.listen_addr {
-        log::info!("binding to {}", addr);
-        server = match addr {
-            AddrType::Ip(addr) => server.bind(addr)?,
-            #[cfg(unix)]
-            AddrType::Uds(addr) => server.bind_uds(addr)?,
-            #[cfg(not(unix))]
-            AddrType::Uds(_) => log::warn!("skipping uds address"),
-        };
-    }
-    server.run().await?;
+            .service(batches::get)
+            .service(group::get)
+            .service(magnet::get)
+            .service(trending::get)
+            .service(stats::get)
+            .service(nyaa::get)
+            .service(watchlist::get)
+            .service(watchlist::login)
+            .service(watchlist::callback)
+            .service(watchlist::logout)
+            .service(health::get_healthz)
+            .service(health::get_readyz)
+            .service(api::hashes::post)
+            .service(api::meta::get)
+            .service(api::missing::get)
+            .service(api::nyaa::get)
+            .service(api::season::get)
+            .service(api::search::get)
+            .service(api::seasons::get)
+            .service(api::torrent::get)
+            .service(api::torrents::get)
+            .service(api::versions::get);
+        if internal {
+            app.service(metrics::get)
+                .service(admin::actions::get)
+                .service(admin::actions::post)
+                .service(admin::alias_suggestions::get)
+                .service(admin::alias_suggestions::post_action)
+                .service(admin::alias_suggestions::post_review)
+                .service(admin::audit::get)
+                .service(admin::rematch_preview::get)
+                .service(admin::rematch_preview::post_action)
+                .service(admin::rematch_preview::post_review)
+                .service(admin::status::get)
+                .service(admin::torrent::get)
+                .service(admin::torrent::post_correction)
+                .service(admin::torrent::post_rematch)
+        } else {
+            app
+        }
+    })
+    .await?;
     Ok(())
 }
@@ -0,0 +1,98 @@
+use crate::{rate_limit::RouteClass, state::Global, text::TEXT_HTML};
+use actix_web::{
+    dev::ServiceRequest,
+    http::{header::RETRY_AFTER, Method},
+    rt::time::interval,
+    HttpResponse,
+};
+use anyhow::Result;
+use askama::Template;
+use common::{pg::PgConnector, time::MINUTE};
+use std::sync::{
+    atomic::{AtomicBool, Ordering::Relaxed},
+    Arc,
+};
+
+/// Whether the site is in maintenance mode
+///
+/// The flag mirrors the `maintenance` key in `magnets.state`. While it is set, only
+/// cheap pages, static files and the admin interface are served so that schema
+/// migrations and large rematches are not slowed down by other queries.
+pub struct Maintenance {
+    enabled: AtomicBool,
+}
+
+impl Maintenance {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Relaxed);
+    }
+}
+
+#[derive(Template)]
+#[template(path = "maintenance.html")]
+struct MaintenancePage;
+
+/// Returns the maintenance page if the request must not be processed right now
+pub fn check(global: &Global, req: &ServiceRequest) -> Option<HttpResponse> {
+    if !global.maintenance.enabled() || req.path().starts_with("/admin/") {
+        return None;
+    }
+    let expensive = req.method() != Method::GET
+        || match RouteClass::of(req.path()) {
+            Some(RouteClass::Html) | None => false,
+            Some(RouteClass::Search) | Some(RouteClass::Api) => true,
+        };
+    if !expensive {
+        return None;
+    }
+    let page = MaintenancePage.render().unwrap();
+    Some(
+        HttpResponse::ServiceUnavailable()
+            .header(RETRY_AFTER, "600")
+            .content_type(TEXT_HTML)
+            .body(page),
+    )
+}
+
+/// Reloads the maintenance flag from the database once per minute
+///
+/// Changes made via the admin interface take effect immediately. This only picks up
+/// changes made directly in the database.
+pub async fn refresh_periodically(global: Arc<Global>) {
+    let mut interval = interval(MINUTE);
+    loop {
+        interval.tick().await;
+        match load(&global.pg_connector).await {
+            Ok(enabled) => {
+                if enabled != global.maintenance.enabled() {
+                    log::info!("maintenance mode changed to {}", enabled);
+                }
+                global.maintenance.set(enabled);
+            }
+            Err(e) => log::error!("could not load the maintenance flag: {:#}", e),
+        }
+    }
+}
+
+async fn load(connector: &PgConnector) -> Result<bool> {
+    let db = connector.connect().await?;
+    // language=sql
+    let row = db
+        .query_opt(
+            "select value from magnets.state where key = 'maintenance'",
+            &[],
+        )
+        .await?;
+    let value: Option<serde_json::Value> = row.map(|r| r.get(0));
+    Ok(value.and_then(|v| v.as_bool()).unwrap_or(false))
+}
@@ -0,0 +1,36 @@
+use crate::{
+    rate_limit::{RateLimiter, ROUTE_CLASSES},
+    state::State,
+};
+use actix_web::{web::Data, HttpResponse, Responder};
+use std::fmt::Write;
+
+const TEXT_PLAIN: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Exports internal counters in the prometheus text format
+#[actix_web::get("/metrics")]
+pub async fn get(state: Data<State>) -> impl Responder {
+    let mut body = String::new();
+    body.push_str(concat!(
+        "# HELP magnets_rate_limit_requests_total Requests seen by the rate limiters\n",
+        "# TYPE magnets_rate_limit_requests_total counter\n",
+    ));
+    let global = &state.global;
+    for &class in ROUTE_CLASSES {
+        write_limiter(&mut body, class.as_str(), global.route_limiters.get(class));
+    }
+    write_limiter(&mut body, "crawler", &global.route_limiters.crawler);
+    write_limiter(&mut body, "magnet", &global.magnet_limiter);
+    HttpResponse::Ok().content_type(TEXT_PLAIN).body(body)
+}
+
+fn write_limiter(body: &mut String, class: &str, limiter: &RateLimiter) {
+    let (allowed, limited) = limiter.counters();
+    for (result, count) in &[("allowed", allowed), ("limited", limited)] {
+        let _ = writeln!(
+            body,
+            "magnets_rate_limit_requests_total{{class=\"{}\",result=\"{}\"}} {}",
+            class, result, count
+        );
+    }
+}
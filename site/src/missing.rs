@@ -0,0 +1,34 @@
+use crate::repo::{EpisodeCounts, ShowRepo};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+/// Returns the episodes of a show that have aired before `now` but have no matched torrent
+///
+/// The number of aired episodes is derived from the schedule if the show is currently
+/// airing and from the episode count on AniList otherwise. Returns `None` if the show
+/// does not exist or if the number of episodes is unknown. Shows with a single episode
+/// (e.g. movies) are skipped because their torrents usually don't carry an episode
+/// number.
+pub async fn missing_episodes(
+    repo: &impl ShowRepo,
+    show_id: i64,
+    now: DateTime<Utc>,
+) -> Result<Option<Vec<i32>>> {
+    Ok(repo
+        .episode_counts(show_id, now)
+        .await?
+        .and_then(|counts| find_missing(&counts)))
+}
+
+/// Returns the missing episodes of a show whose episode counts have already been loaded
+///
+/// See [missing_episodes].
+pub fn find_missing(counts: &EpisodeCounts) -> Option<Vec<i32>> {
+    let expected = match counts.aired.or(counts.episodes) {
+        Some(n) if n > 1 => n,
+        _ => return None,
+    };
+    let matched: HashSet<i32> = counts.matched.iter().copied().collect();
+    Some((1..=expected).filter(|e| !matched.contains(e)).collect())
+}
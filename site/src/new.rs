@@ -1,7 +1,11 @@
 use crate::{
+    repo::{
+        listing::{TorrentFilter, TorrentQuery},
+        TorrentRepo,
+    },
     state::State,
     text::TEXT_HTML,
-    torrent_list::{torrent_list_from_rows, Day},
+    torrent_list::{torrent_list, Day},
 };
 use actix_web::{
     web::{Data, Query},
@@ -41,9 +45,16 @@ pub struct QueryParams {
 }
 
 async fn process(state: &State, query: QueryParams) -> Result<String> {
-    let db = state.pg.borrow().await?;
-    let rows = db.query(&db.t.new.stmt, &[&query.after]).await?;
-    let (last, days) = torrent_list_from_rows!(db.t.new, &rows);
+    render(&state.repo().await?, query).await
+}
+
+async fn render(repo: &impl TorrentRepo, query: QueryParams) -> Result<String> {
+    let query = TorrentQuery {
+        filter: TorrentFilter::default(),
+        after: query.after,
+    };
+    let torrents = repo.torrents(&query).await?;
+    let (last, days) = torrent_list(&torrents);
     let days = Days {
         days: &days,
         last,
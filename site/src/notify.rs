@@ -0,0 +1,112 @@
+use crate::state::Global;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use common::{
+    flags::FLAGS_STATE_KEY,
+    pg::{MessageHandler, PgClient},
+};
+use std::sync::{Arc, Weak};
+
+/// Channel on which the processor announces that the schedule has changed
+const SCHEDULE_CHANGE: &str = "schedule_change";
+/// Channel on which the processor announces that names of shows have changed or that
+/// shows have been removed
+const SHOW_CHANGE: &str = "show_change";
+/// Channel on which changes of `magnets.state` are announced
+const STATE_CHANGE: &str = "state_change";
+/// Channel on which all caches are dropped. Used after editing the database by hand
+/// (`notify flush_caches`) and by the admin action.
+pub const FLUSH_CACHES: &str = "flush_caches";
+
+/// Reacts to changes made by the processor and via the database
+///
+/// Without this, changes only become visible once the cached pages expire.
+#[derive(Clone)]
+pub struct SiteMessageHandler {
+    global: Weak<Global>,
+}
+
+impl SiteMessageHandler {
+    pub fn new(global: &Arc<Global>) -> Self {
+        Self {
+            global: Arc::downgrade(global),
+        }
+    }
+
+    fn invalidate_schedule(&self) {
+        if let Some(global) = self.global.upgrade() {
+            tokio::spawn(async move { global.schedule.invalidate().await });
+        }
+    }
+
+    fn invalidate_shows(&self) {
+        if let Some(global) = self.global.upgrade() {
+            global.show_names.invalidate();
+            tokio::spawn(async move {
+                global.shows.invalidate().await;
+                global.schedule.invalidate().await;
+            });
+        }
+    }
+
+    fn flush_caches(&self) {
+        if let Some(global) = self.global.upgrade() {
+            tokio::spawn(async move { global.flush_caches().await });
+        }
+    }
+
+    fn refresh_flags(&self) {
+        if let Some(global) = self.global.upgrade() {
+            tokio::spawn(async move {
+                if let Err(e) = refresh_flags(&global).await {
+                    log::error!("could not refresh the flags: {:#}", e);
+                }
+            });
+        }
+    }
+}
+
+async fn refresh_flags(global: &Global) -> Result<()> {
+    let db = global.pg_connector.connect().await?;
+    global.flags.refresh(&db).await
+}
+
+#[async_trait]
+impl MessageHandler for SiteMessageHandler {
+    async fn listen(&self, client: &PgClient) -> Result<()> {
+        client
+            .simple_query(
+                "listen schedule_change; listen show_change; listen state_change; listen flush_caches",
+            )
+            .await
+            .context("could not execute `listen`")?;
+        // We might have missed notifications while we were not connected
+        self.invalidate_schedule();
+        self.invalidate_shows();
+        self.refresh_flags();
+        Ok(())
+    }
+
+    fn handle(&self, channel: &str, payload: &str) {
+        match channel {
+            SCHEDULE_CHANGE => {
+                log::info!("received schedule change");
+                self.invalidate_schedule();
+            }
+            SHOW_CHANGE => {
+                log::info!("received show change");
+                self.invalidate_shows();
+            }
+            STATE_CHANGE => {
+                if payload == FLAGS_STATE_KEY {
+                    self.refresh_flags();
+                }
+            }
+            FLUSH_CACHES => {
+                log::info!("received request to flush the caches");
+                self.flush_caches();
+            }
+            _ => log::warn!("received notification on unknown channel {}", channel),
+        }
+    }
+}
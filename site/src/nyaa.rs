@@ -0,0 +1,43 @@
+use crate::{state::State, text::NotFound};
+use actix_web::{http::header::LOCATION, web, web::Data, HttpResponse, Responder};
+use anyhow::Result;
+
+/// Redirects from a nyaa.si id to the corresponding torrent page
+#[actix_web::get("/nyaa/{nyaa_id}")]
+pub async fn get(state: Data<State>, id: web::Path<(i64,)>) -> impl Responder {
+    match resolve(&state, id.0.0).await {
+        Ok(torrent) => HttpResponse::Found()
+            .header(LOCATION, format!("/torrent/{}", torrent.torrent_id))
+            .finish(),
+        Err(e) => {
+            if e.is::<NotFound>() {
+                HttpResponse::NotFound().finish()
+            } else {
+                log::error!(
+                    "An error occurred while trying to resolve nyaa id {}: {:#}",
+                    id.0.0,
+                    e
+                );
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+pub struct Torrent {
+    pub torrent_id: i64,
+    pub show_ids: Vec<i64>,
+}
+
+/// Looks up the torrent with the given nyaa.si id
+pub async fn resolve(state: &State, nyaa_id: i64) -> Result<Torrent> {
+    let db = state.pg.borrow().await?;
+    let row = match db.query_opt(&db.t.nyaa.stmt, &[&nyaa_id]).await? {
+        Some(r) => r,
+        _ => return Err(NotFound.into()),
+    };
+    Ok(Torrent {
+        torrent_id: row.get(db.t.nyaa.torrent_id),
+        show_ids: row.get(db.t.nyaa.show_ids),
+    })
+}
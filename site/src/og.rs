@@ -0,0 +1,20 @@
+//! Canonical links, OpenGraph and Twitter card meta tags
+//!
+//! Chat clients such as Discord use these tags to render a preview of shared links.
+//! Since the tags require absolute URLs, they are built from `http.base_url` instead of
+//! the `Host` header, which is not reliable behind proxies.
+
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "og.html")]
+pub struct OpenGraph<'a> {
+    /// The public URL of the site without a trailing slash
+    pub base_url: &'a str,
+    /// The path of the page
+    pub path: String,
+    pub title: &'a str,
+    pub description: String,
+    /// The path of the preview image
+    pub image: Option<String>,
+}
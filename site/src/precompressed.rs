@@ -0,0 +1,172 @@
+//! Precompressed static assets
+//!
+//! At startup, a `.br` and a `.gz` sibling is written for every compressible file under
+//! `static/`. Requests for such files are answered with the sibling if the client
+//! accepts the encoding. All other requests fall through to `fs::Files`.
+
+use crate::state::Global;
+use actix_files::NamedFile;
+use actix_web::{
+    dev::ServiceRequest,
+    http::{header, HeaderValue},
+    HttpResponse,
+};
+use anyhow::{Context, Result};
+use brotli2::write::BrotliEncoder;
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    collections::HashSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The extensions of files that are worth compressing
+const COMPRESSIBLE: &[&str] = &["css", "html", "js", "json", "svg", "txt"];
+
+/// The supported encodings in order of preference
+const ENCODINGS: &[(&str, &str)] = &[("br", ".br"), ("gzip", ".gz")];
+
+pub struct Precompressed {
+    dir: PathBuf,
+    prefix: &'static str,
+    /// The paths relative to `dir` that have compressed siblings
+    files: HashSet<String>,
+}
+
+impl Precompressed {
+    /// Compresses all files under `dir` that are served under `prefix`
+    ///
+    /// Siblings that are newer than their file are reused. If compressing fails, the
+    /// files that have not been compressed yet are served uncompressed.
+    pub fn generate(dir: &Path, prefix: &'static str) -> Self {
+        let mut files = HashSet::new();
+        if let Err(e) = generate_dir(dir, dir, &mut files) {
+            log::error!("cannot compress the files in {}: {:#}", dir.display(), e);
+        }
+        log::info!("serving {} precompressed files", files.len());
+        Self {
+            dir: dir.to_path_buf(),
+            prefix,
+            files,
+        }
+    }
+}
+
+fn generate_dir(root: &Path, dir: &Path, files: &mut HashSet<String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            generate_dir(root, &path, files)?;
+            continue;
+        }
+        let compressible = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| COMPRESSIBLE.contains(&e))
+            .unwrap_or(false);
+        if !compressible {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        let mut data = None;
+        for &(encoding, suffix) in ENCODINGS {
+            let sibling = sibling(&path, suffix);
+            let fresh = match fs::metadata(&sibling) {
+                Ok(m) => m.modified()? >= modified,
+                _ => false,
+            };
+            if fresh {
+                continue;
+            }
+            if data.is_none() {
+                data = Some(fs::read(&path)?);
+            }
+            let compressed = compress(encoding, data.as_ref().unwrap())?;
+            fs::write(&sibling, compressed)
+                .with_context(|| format!("cannot write {}", sibling.display()))?;
+        }
+        let relative = path.strip_prefix(root)?;
+        if let Some(relative) = relative.to_str() {
+            files.insert(relative.replace('\\', "/"));
+        }
+    }
+    Ok(())
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+fn compress(encoding: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if encoding == "br" {
+        let mut encoder = BrotliEncoder::new(vec![], 11);
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    } else {
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+/// Returns whether the `Accept-Encoding` header allows `encoding`
+fn accepts(header: &str, encoding: &str) -> bool {
+    header.split(',').any(|part| {
+        let mut params = part.split(';');
+        let name = params.next().unwrap_or("").trim();
+        if !name.eq_ignore_ascii_case(encoding) {
+            return false;
+        }
+        params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .all(|q| q.parse::<f32>().map(|q| q > 0.0).unwrap_or(false))
+    })
+}
+
+/// Serves the compressed sibling of the requested file if there is one
+pub fn serve(global: &Global, req: &ServiceRequest) -> Option<HttpResponse> {
+    let pc = &global.precompressed;
+    let relative = req.path().strip_prefix(pc.prefix)?.strip_prefix('/')?;
+    if !pc.files.contains(relative) {
+        return None;
+    }
+    let accept = req.headers().get(header::ACCEPT_ENCODING)?.to_str().ok()?;
+    let &(encoding, suffix) = ENCODINGS.iter().find(|(e, _)| accepts(accept, e))?;
+    let path = pc.dir.join(relative);
+    let ext = path.extension()?.to_str()?;
+    let file = match NamedFile::open(sibling(&path, suffix)) {
+        Ok(f) => f,
+        Err(e) => {
+            log::warn!("cannot open the compressed sibling of {}: {}", relative, e);
+            return None;
+        }
+    };
+    let mut res = file
+        .set_content_type(actix_files::file_extension_to_mime(ext))
+        .disable_content_disposition()
+        .into_response(req.request())
+        .ok()?;
+    let headers = res.headers_mut();
+    headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_encoding() {
+        assert!(accepts("gzip, deflate, br", "br"));
+        assert!(accepts("gzip, deflate, br", "gzip"));
+        assert!(accepts("BR;q=0.5", "br"));
+        assert!(!accepts("br;q=0, gzip", "br"));
+        assert!(!accepts("gzip", "br"));
+        assert!(!accepts("", "gzip"));
+    }
+}
@@ -0,0 +1,252 @@
+//! The PROXY protocol of HAProxy (v1 and v2)
+//!
+//! See https://www.haproxy.org/download/2.3/doc/proxy-protocol.txt
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    str,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+
+/// A parsed PROXY header
+#[derive(Debug, Eq, PartialEq)]
+pub struct Header {
+    /// The address of the client or `None` if the proxy does not know it (e.g. for
+    /// health checks of the proxy itself)
+    pub source: Option<SocketAddr>,
+    /// The length of the header in bytes
+    pub len: usize,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PROXY header: {}", msg))
+}
+
+/// Parses a PROXY header at the start of `buf`
+///
+/// Returns `None` if more data is needed.
+pub fn parse(buf: &[u8]) -> io::Result<Option<Header>> {
+    let n = buf.len().min(V2_SIGNATURE.len());
+    if buf[..n] == V2_SIGNATURE[..n] {
+        if n < V2_SIGNATURE.len() {
+            return Ok(None);
+        }
+        return parse_v2(buf);
+    }
+    let n = buf.len().min(V1_PREFIX.len());
+    if buf[..n] == V1_PREFIX[..n] {
+        if n < V1_PREFIX.len() {
+            return Ok(None);
+        }
+        return parse_v1(buf);
+    }
+    Err(invalid("missing"))
+}
+
+fn parse_v1(buf: &[u8]) -> io::Result<Option<Header>> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(e) => e,
+        _ if buf.len() >= V1_MAX_LEN => return Err(invalid("v1 header is too long")),
+        _ => return Ok(None),
+    };
+    let line =
+        str::from_utf8(&buf[..end]).map_err(|_| invalid("v1 header is not ascii"))?;
+    let len = end + 2;
+    let parts: Vec<_> = line.split(' ').collect();
+    match parts.get(1).copied() {
+        Some("UNKNOWN") => return Ok(Some(Header { source: None, len })),
+        Some("TCP4") | Some("TCP6") if parts.len() == 6 => {}
+        _ => return Err(invalid("invalid v1 header")),
+    }
+    let ip: IpAddr = parts[2]
+        .parse()
+        .map_err(|_| invalid("invalid v1 source address"))?;
+    let port: u16 = parts[4]
+        .parse()
+        .map_err(|_| invalid("invalid v1 source port"))?;
+    Ok(Some(Header {
+        source: Some(SocketAddr::new(ip, port)),
+        len,
+    }))
+}
+
+fn parse_v2(buf: &[u8]) -> io::Result<Option<Header>> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let version_command = buf[12];
+    let family = buf[13];
+    let len = V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let addrs = &buf[V2_HEADER_LEN..len];
+    let source = match (version_command & 0xf, family >> 4) {
+        // LOCAL: the connection was established by the proxy itself
+        (0, _) => None,
+        // PROXY over AF_INET
+        (1, 1) if addrs.len() >= 12 => {
+            let ip = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            let port = u16::from_be_bytes([addrs[8], addrs[9]]);
+            Some(SocketAddr::new(ip.into(), port))
+        }
+        // PROXY over AF_INET6
+        (1, 2) if addrs.len() >= 36 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&addrs[..16]);
+            let port = u16::from_be_bytes([addrs[32], addrs[33]]);
+            Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port))
+        }
+        // PROXY over AF_UNSPEC or AF_UNIX
+        (1, _) => None,
+        _ => return Err(invalid("unsupported command")),
+    };
+    Ok(Some(Header { source, len }))
+}
+
+/// Reads the PROXY header from the start of a connection
+///
+/// Returns the header and the stream with any bytes that were read past the header.
+pub async fn read_header<T: AsyncRead + Unpin>(
+    mut io: T,
+) -> io::Result<(Header, Prefixed<T>)> {
+    let mut buf = vec![];
+    let mut chunk = [0; 512];
+    loop {
+        let n = io.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(header) = parse(&buf)? {
+            buf.drain(..header.len);
+            return Ok((header, Prefixed::new(buf, io)));
+        }
+    }
+}
+
+/// A stream whose first bytes have already been read
+pub struct Prefixed<T> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: T,
+}
+
+impl<T> Prefixed<T> {
+    pub fn new(prefix: Vec<u8>, inner: T) -> Self {
+        Self {
+            prefix,
+            pos: 0,
+            inner,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Prefixed<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.pos < this.prefix.len() {
+            let n = buf.len().min(this.prefix.len() - this.pos);
+            buf[..n].copy_from_slice(&this.prefix[this.pos..this.pos + n]);
+            this.pos += n;
+            if this.pos == this.prefix.len() {
+                this.prefix = vec![];
+                this.pos = 0;
+            }
+            return Poll::Ready(Ok(n));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Prefixed<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v1() {
+        let buf = b"PROXY TCP4 1.2.3.4 5.6.7.8 4711 80\r\nGET / HTTP/1.1\r\n";
+        let header = parse(buf).unwrap().unwrap();
+        assert_eq!(header.source, Some("1.2.3.4:4711".parse().unwrap()));
+        assert_eq!(&buf[header.len..], b"GET / HTTP/1.1\r\n");
+        let header = parse(b"PROXY TCP6 ::1 ::2 4711 80\r\n").unwrap().unwrap();
+        assert_eq!(header.source, Some("[::1]:4711".parse().unwrap()));
+        let header = parse(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!(header.source, None);
+        assert!(parse(b"PROXY TCP4 1.2.3.4").unwrap().is_none());
+        assert!(parse(b"PRO").unwrap().is_none());
+        assert!(parse(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(parse(b"PROXY TCP4 x 5.6.7.8 4711 80\r\n").is_err());
+    }
+
+    #[test]
+    fn v2() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        buf.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 0x12, 0x67, 0, 80]);
+        assert!(parse(&buf[..20]).unwrap().is_none());
+        buf.extend_from_slice(b"GET");
+        let header = parse(&buf).unwrap().unwrap();
+        assert_eq!(header.source, Some("1.2.3.4:4711".parse().unwrap()));
+        assert_eq!(&buf[header.len..], b"GET");
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        let header = parse(&local).unwrap().unwrap();
+        assert_eq!(
+            header,
+            Header {
+                source: None,
+                len: 16
+            }
+        );
+    }
+
+    #[test]
+    fn prefixed() {
+        let stream: &[u8] = b" world";
+        let mut io = Prefixed::new(b"hello".to_vec(), stream);
+        let mut s = String::new();
+        futures::executor::block_on(io.read_to_string(&mut s)).unwrap();
+        assert_eq!(s, "hello world");
+    }
+}
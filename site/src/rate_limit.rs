@@ -0,0 +1,215 @@
+use crate::{client_ip::client_ip, crawler::is_crawler, state::Global};
+use actix_web::{dev::ServiceRequest, http::header::RETRY_AFTER, HttpResponse};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// A leaky-bucket rate limiter keyed by client ip
+///
+/// Each client has a bucket that can hold `limit` requests and drains at a rate of
+/// `limit` requests per window. A client can therefore burst up to `limit` requests
+/// and afterwards perform requests at the drain rate. Empty buckets are removed
+/// periodically to keep memory usage bounded.
+pub struct RateLimiter {
+    capacity: f64,
+    /// Requests per second
+    rate: f64,
+    window: Duration,
+    inner: Mutex<Inner>,
+    allowed: AtomicU64,
+    limited: AtomicU64,
+}
+
+struct Inner {
+    last_sweep: Instant,
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+struct Bucket {
+    level: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn drain(&mut self, now: Instant, rate: f64) {
+        let elapsed = (now - self.updated).as_secs_f64();
+        self.level = (self.level - elapsed * rate).max(0.0);
+        self.updated = now;
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            capacity: limit as f64,
+            rate: limit as f64 / window.as_secs_f64(),
+            window,
+            inner: Mutex::new(Inner {
+                last_sweep: Instant::now(),
+                buckets: HashMap::new(),
+            }),
+            allowed: AtomicU64::new(0),
+            limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a request of `ip`
+    ///
+    /// Returns `Err` with the number of seconds until the client may perform the next
+    /// request if the client has exhausted its budget.
+    pub fn check(&self, ip: IpAddr) -> Result<(), u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        if now - inner.last_sweep >= self.window {
+            let rate = self.rate;
+            inner.buckets.retain(|_, b| {
+                b.drain(now, rate);
+                b.level > 0.0
+            });
+            inner.last_sweep = now;
+        }
+        let bucket = inner.buckets.entry(ip).or_insert(Bucket {
+            level: 0.0,
+            updated: now,
+        });
+        bucket.drain(now, self.rate);
+        if bucket.level + 1.0 > self.capacity {
+            self.limited.fetch_add(1, Relaxed);
+            let retry_after = (bucket.level + 1.0 - self.capacity) / self.rate;
+            return Err(retry_after.ceil() as u64);
+        }
+        bucket.level += 1.0;
+        self.allowed.fetch_add(1, Relaxed);
+        Ok(())
+    }
+
+    /// Returns the number of allowed and rejected requests since startup
+    pub fn counters(&self) -> (u64, u64) {
+        (self.allowed.load(Relaxed), self.limited.load(Relaxed))
+    }
+}
+
+/// The class of a route for the purpose of rate limiting
+///
+/// Each class has its own budget since the cost of the routes differs widely.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RouteClass {
+    /// Pages that are served from a cache or with a single cheap query
+    Html,
+    /// Torrent listings that require paginated queries
+    Search,
+    /// The JSON API
+    Api,
+}
+
+pub const ROUTE_CLASSES: &[RouteClass] =
+    &[RouteClass::Html, RouteClass::Search, RouteClass::Api];
+
+impl RouteClass {
+    /// Returns the class of a request or `None` if the request is not rate limited
+    ///
+    /// The class only depends on the path so that a query string cannot move a request
+    /// into a different budget.
+    pub fn of(path: &str) -> Option<Self> {
+        if path.starts_with("/static/")
+            || path.starts_with("/admin/")
+            || path == "/healthz"
+            || path == "/readyz"
+        {
+            None
+        } else if path.starts_with("/api/") {
+            Some(RouteClass::Api)
+        } else if path == "/search"
+            || path == "/new"
+            || path == "/batches"
+            || path == "/unmatched"
+            || path.starts_with("/group/")
+        {
+            Some(RouteClass::Search)
+        } else {
+            Some(RouteClass::Html)
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteClass::Html => "html",
+            RouteClass::Search => "search",
+            RouteClass::Api => "api",
+        }
+    }
+}
+
+/// The rate limiters of all route classes
+pub struct RouteLimiters {
+    pub html: RateLimiter,
+    pub search: RateLimiter,
+    pub api: RateLimiter,
+    /// The budget of crawlers for the html and search classes
+    ///
+    /// Most clients of the api are scripts. They keep the budget of the api.
+    pub crawler: RateLimiter,
+}
+
+impl RouteLimiters {
+    pub fn get(&self, class: RouteClass) -> &RateLimiter {
+        match class {
+            RouteClass::Html => &self.html,
+            RouteClass::Search => &self.search,
+            RouteClass::Api => &self.api,
+        }
+    }
+}
+
+/// Applies the route limiters to a request
+///
+/// Returns the response that should be sent instead of processing the request if the
+/// client has exhausted the budget of the route class.
+pub fn limit(global: &Global, req: &ServiceRequest) -> Option<HttpResponse> {
+    let class = RouteClass::of(req.path())?;
+    let ip = client_ip(req.request())?;
+    let limiter = match class {
+        RouteClass::Html | RouteClass::Search if is_crawler(req.request()) => {
+            &global.route_limiters.crawler
+        }
+        _ => global.route_limiters.get(class),
+    };
+    match limiter.check(ip) {
+        Ok(()) => None,
+        Err(retry_after) => Some(
+            HttpResponse::TooManyRequests()
+                .header(RETRY_AFTER, retry_after.to_string())
+                .finish(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_routes_by_path() {
+        assert_eq!(RouteClass::of("/static/main.css"), None);
+        assert_eq!(RouteClass::of("/healthz"), None);
+        assert_eq!(RouteClass::of("/api/v1/torrents"), Some(RouteClass::Api));
+        assert_eq!(RouteClass::of("/search"), Some(RouteClass::Search));
+        assert_eq!(RouteClass::of("/new"), Some(RouteClass::Search));
+        assert_eq!(
+            RouteClass::of("/group/SubsPlease"),
+            Some(RouteClass::Search)
+        );
+        assert_eq!(RouteClass::of("/"), Some(RouteClass::Html));
+        assert_eq!(RouteClass::of("/show/1"), Some(RouteClass::Html));
+        assert_eq!(
+            RouteClass::of("/season/2021-winter"),
+            Some(RouteClass::Html)
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Filters and keyset pagination of torrent lists
+//!
+//! /new, /unmatched, /batches, /show/{show_id} and /api/v1/torrents list torrents newest
+//! first, [PAGE_SIZE] per page. Instead of one statement per list, [TorrentQuery::sql]
+//! composes the filters of a list into a single query for postgres or SQLite.
+
+use crate::repo::PAGE_SIZE;
+use chrono::{DateTime, Utc};
+use common::{AudioCodec, Resolution, VideoCodec};
+
+/// The torrents of a list. `None` does not filter.
+#[derive(Clone, Debug, Default)]
+pub struct TorrentFilter {
+    /// Only torrents matched to this show
+    pub show_id: Option<i64>,
+    /// Only torrents matched to a show of this season (see `common::YearSeason::to_db`)
+    pub season: Option<i32>,
+    /// Only torrents uploaded at or after this time
+    pub uploaded_from: Option<DateTime<Utc>>,
+    /// Only torrents uploaded before this time
+    pub uploaded_to: Option<DateTime<Utc>>,
+    pub matched: Option<bool>,
+    pub batch: Option<bool>,
+    pub trusted: Option<bool>,
+    /// Only torrents whose title advertises the resolution (see `common::MediaInfo`)
+    pub resolution: Option<Resolution>,
+    pub video_codec: Option<VideoCodec>,
+    pub audio_codec: Option<AudioCodec>,
+    /// Only torrents of this release group (see `magnets.release_group`)
+    pub group: Option<String>,
+    /// Only torrents matched to this episode, including the batches that contain it
+    pub episode: Option<i32>,
+    /// Only torrents provided by this source (see `common::Source::to_db`)
+    pub source: Option<i32>,
+    /// Whether the torrent is hidden, i.e. only matched to removed shows
+    pub hidden: Option<bool>,
+}
+
+/// A page of a torrent list
+#[derive(Clone, Debug)]
+pub struct TorrentQuery {
+    pub filter: TorrentFilter,
+    /// Only torrents with `nyaa_id < after`
+    pub after: i64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dialect {
+    Postgres,
+    Sqlite,
+}
+
+impl Dialect {
+    fn table(self, name: &str) -> String {
+        match self {
+            Dialect::Postgres => format!("magnets.{}", name),
+            Dialect::Sqlite => name.to_string(),
+        }
+    }
+
+    fn param(self, n: usize) -> String {
+        match self {
+            Dialect::Postgres => format!("${}", n),
+            Dialect::Sqlite => format!("?{}", n),
+        }
+    }
+}
+
+/// A query parameter. Its position is its index in the parameters plus one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Param {
+    Int(i32),
+    BigInt(i64),
+    Text(String),
+    Timestamp(DateTime<Utc>),
+}
+
+impl TorrentQuery {
+    /// The first page
+    pub fn first(filter: TorrentFilter) -> Self {
+        Self {
+            filter,
+            after: i64::MAX,
+        }
+    }
+
+    /// Returns the query of up to `PAGE_SIZE + 1` torrents and its parameters
+    ///
+    /// Boolean filters are inlined so that postgres can use the partial indexes of
+    /// `magnets.torrent`. All other values are passed as parameters.
+    pub fn sql(&self, dialect: Dialect) -> (String, Vec<Param>) {
+        let f = &self.filter;
+        let table = |name: &str| dialect.table(name);
+        let mut params = vec![];
+        let mut param = |p: Param| {
+            params.push(p);
+            dialect.param(params.len())
+        };
+        let mut conditions = vec![];
+        // the torrents of a show are paginated by rel_torrent_show (show_id, nyaa_id)
+        let (from, key) = match f.show_id {
+            Some(show_id) => {
+                conditions
+                    .push(format!("rts.show_id = {}", param(Param::BigInt(show_id))));
+                let from = format!(
+                    "{} rts join {} t using (torrent_id)",
+                    table("rel_torrent_show"),
+                    table("torrent")
+                );
+                (from, "rts.nyaa_id")
+            }
+            _ => (format!("{} t", table("torrent")), "t.nyaa_id"),
+        };
+        conditions.push(format!("{} < {}", key, param(Param::BigInt(self.after))));
+        let hidden = format!(
+            "(t.matched and not exists (
+                select 1
+                from {} r
+                join {} s on s.show_id = r.show_id
+                where r.torrent_id = t.torrent_id and s.removal is null
+            ))",
+            table("rel_torrent_show"),
+            table("show")
+        );
+        let flags = [
+            (f.matched, "t.matched"),
+            (f.batch, "t.batch"),
+            (f.trusted, "t.trusted"),
+            (f.hidden, &*hidden),
+        ];
+        for &(value, expr) in &flags {
+            match value {
+                Some(true) => conditions.push(expr.to_string()),
+                Some(false) => conditions.push(format!("not {}", expr)),
+                _ => {}
+            }
+        }
+        let formats = [
+            (f.resolution.map(Resolution::to_db), "t.resolution"),
+            (f.video_codec.map(VideoCodec::to_db), "t.video_codec"),
+            (f.audio_codec.map(AudioCodec::to_db), "t.audio_codec"),
+        ];
+        for &(value, column) in &formats {
+            if let Some(value) = value {
+                conditions.push(format!("{} = {}", column, param(Param::Int(value))));
+            }
+        }
+        if let Some(group) = &f.group {
+            let p = param(Param::Text(group.clone()));
+            conditions.push(format!(
+                "t.release_group_id = (
+                select release_group_id
+                from {}
+                where name = {}
+            )",
+                table("release_group"),
+                p
+            ));
+        }
+        if let Some(episode) = f.episode {
+            let p = param(Param::Int(episode));
+            // batches contain the episodes from `episode` to `episode_end`
+            let contains = |r: &str| {
+                format!(
+                    "{0}.episode <= {1} and coalesce({0}.episode_end, {0}.episode) >= {1}",
+                    r, p
+                )
+            };
+            conditions.push(match f.show_id {
+                Some(_) => contains("rts"),
+                _ => format!(
+                    "exists (
+                select 1
+                from {} r
+                where r.torrent_id = t.torrent_id and {}
+            )",
+                    table("rel_torrent_show"),
+                    contains("r")
+                ),
+            });
+        }
+        if let Some(season) = f.season {
+            let p = param(Param::Int(season));
+            conditions.push(format!(
+                "exists (
+                select 1
+                from {} r
+                join {} s on s.show_id = r.show_id
+                where r.torrent_id = t.torrent_id
+                    and (s.season = {p} or s.season is null and s.inferred_season = {p})
+            )",
+                table("rel_torrent_show"),
+                table("show"),
+                p = p
+            ));
+        }
+        if let Some(from) = f.uploaded_from {
+            let p = param(Param::Timestamp(from));
+            conditions.push(format!("t.uploaded_at >= {}", p));
+        }
+        if let Some(to) = f.uploaded_to {
+            let p = param(Param::Timestamp(to));
+            conditions.push(format!("t.uploaded_at < {}", p));
+        }
+        if let Some(source) = f.source {
+            let p = param(Param::Int(source));
+            conditions.push(format!(
+                "exists (
+                select 1
+                from {} ts
+                where ts.torrent_id = t.torrent_id and ts.source = {}
+            )",
+                table("torrent_source"),
+                p
+            ));
+        }
+        // language=sql
+        let sql = format!(
+            "
+            select t.torrent_id, t.nyaa_id, t.title, t.trusted, t.uploaded_at, t.hash, t.batch,
+                t.seeders, t.leechers, t.completed,
+                exists (
+                    select 1
+                    from {} rt
+                    where rt.torrent_id = t.torrent_id
+                ) as recommended
+            from {}
+            where {}
+            order by {} desc
+            limit {}",
+            table("recommended_torrent"),
+            from,
+            conditions.join("\n                and "),
+            key,
+            PAGE_SIZE + 1
+        );
+        (sql, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn normalize(sql: &str) -> String {
+        sql.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn lists_new_torrents() {
+        let query = TorrentQuery::first(TorrentFilter::default());
+        let (sql, params) = query.sql(Dialect::Postgres);
+        assert_eq!(
+            normalize(&sql),
+            "select t.torrent_id, t.nyaa_id, t.title, t.trusted, t.uploaded_at, t.hash, \
+             t.batch, t.seeders, t.leechers, t.completed, exists ( select 1 \
+             from magnets.recommended_torrent rt where rt.torrent_id = t.torrent_id ) \
+             as recommended from magnets.torrent t \
+             where t.nyaa_id < $1 \
+             order by t.nyaa_id desc limit 101"
+        );
+        assert_eq!(params, vec![Param::BigInt(i64::MAX)]);
+    }
+
+    #[test]
+    fn paginates_shows_by_their_matches() {
+        let query = TorrentQuery {
+            filter: TorrentFilter {
+                show_id: Some(7),
+                batch: Some(true),
+                episode: Some(3),
+                ..Default::default()
+            },
+            after: 100,
+        };
+        let (sql, params) = query.sql(Dialect::Sqlite);
+        let sql = normalize(&sql);
+        assert!(sql.contains(
+            "from rel_torrent_show rts join torrent t using (torrent_id) \
+             where rts.show_id = ?1 and rts.nyaa_id < ?2 and t.batch \
+             and rts.episode <= ?3 and coalesce(rts.episode_end, rts.episode) >= ?3 \
+             order by rts.nyaa_id desc"
+        ));
+        assert_eq!(
+            params,
+            vec![Param::BigInt(7), Param::BigInt(100), Param::Int(3)]
+        );
+    }
+
+    #[test]
+    fn numbers_parameters_in_order() {
+        let query = TorrentQuery::first(TorrentFilter {
+            matched: Some(false),
+            trusted: Some(true),
+            resolution: Some(Resolution::P1080),
+            group: Some("SubsPlease".to_string()),
+            episode: Some(5),
+            source: Some(1),
+            hidden: Some(false),
+            uploaded_to: Some(Utc.timestamp(1_600_000_000, 0)),
+            ..Default::default()
+        });
+        let (sql, params) = query.sql(Dialect::Postgres);
+        let sql = normalize(&sql);
+        assert!(sql.contains("and not t.matched and t.trusted and not (t.matched and"));
+        assert!(sql.contains(
+            "t.resolution = $2 and t.release_group_id = ( \
+             select release_group_id from magnets.release_group where name = $3 )"
+        ));
+        assert!(
+            sql.contains("r.episode <= $4 and coalesce(r.episode_end, r.episode) >= $4")
+        );
+        assert!(sql.contains("t.uploaded_at < $5"));
+        assert!(sql.contains("from magnets.torrent_source ts"));
+        assert!(sql.contains("ts.source = $6"));
+        assert_eq!(
+            params,
+            vec![
+                Param::BigInt(i64::MAX),
+                Param::Int(1080),
+                Param::Text("SubsPlease".to_string()),
+                Param::Int(5),
+                Param::Timestamp(Utc.timestamp(1_600_000_000, 0)),
+                Param::Int(1),
+            ]
+        );
+    }
+}
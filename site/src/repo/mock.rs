@@ -0,0 +1,203 @@
+use crate::repo::{
+    listing::TorrentQuery, EpisodeCounts, ScheduleRecord, ScheduleRepo, ShowNames,
+    ShowRecord, ShowRepo, TorrentDetails, TorrentRecord, TorrentRepo, PAGE_SIZE,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use common::{MediaInfo, ShowNameType};
+use std::collections::HashMap;
+
+/// An in-memory implementation of the repositories
+///
+/// Queries behave like their SQL counterparts, including the page size.
+#[derive(Default)]
+pub struct MockRepo {
+    pub shows: Vec<ShowRecord>,
+    pub show_torrents: HashMap<i64, Vec<TorrentRecord>>,
+    pub episode_counts: HashMap<i64, EpisodeCounts>,
+    pub torrents: Vec<TorrentDetails>,
+    pub unmatched: Vec<i64>,
+    /// The episodes of matched torrents by torrent id
+    pub episodes: HashMap<i64, i32>,
+    /// The last episodes of matched batches by torrent id
+    pub episode_ends: HashMap<i64, i32>,
+    /// The sources of torrents by torrent id
+    pub sources: HashMap<i64, Vec<i32>>,
+    /// The torrents that are only matched to removed shows
+    pub hidden: Vec<i64>,
+    pub schedule: Vec<ScheduleRecord>,
+}
+
+impl MockRepo {
+    /// Creates a torrent with the given nyaa id and title
+    pub fn torrent_record(nyaa_id: i64, title: &str) -> TorrentRecord {
+        TorrentRecord {
+            torrent_id: nyaa_id,
+            nyaa_id,
+            title: title.to_string(),
+            trusted: false,
+            uploaded_at: Utc.timestamp(nyaa_id * 60, 0),
+            hash: vec![0; 20],
+            batch: false,
+            swarm: None,
+            recommended: false,
+        }
+    }
+}
+
+impl MockRepo {
+    fn matches(&self, query: &TorrentQuery, t: &TorrentRecord) -> bool {
+        let f = &query.filter;
+        let flag =
+            |filter: Option<bool>, value: bool| filter.map_or(true, |f| f == value);
+        let id = t.torrent_id;
+        let media = MediaInfo::detect(&t.title);
+        t.nyaa_id < query.after
+            && flag(f.matched, !self.unmatched.contains(&id))
+            && flag(f.batch, t.batch)
+            && flag(f.trusted, t.trusted)
+            && flag(f.hidden, self.hidden.contains(&id))
+            && f.season.map_or(true, |season| {
+                self.shows.iter().any(|s| {
+                    s.season == Some(season)
+                        && self
+                            .show_torrents
+                            .get(&s.show_id)
+                            .map_or(false, |t| t.iter().any(|t| t.torrent_id == id))
+                })
+            })
+            && f.uploaded_from.map_or(true, |from| t.uploaded_at >= from)
+            && f.uploaded_to.map_or(true, |to| t.uploaded_at < to)
+            && matches_format(f.resolution, media.resolution)
+            && matches_format(f.video_codec, media.video_codec)
+            && matches_format(f.audio_codec, media.audio_codec)
+            && f.group
+                .as_ref()
+                .map_or(true, |g| release_group(&t.title) == Some(g.as_str()))
+            && f.episode.map_or(true, |e| match self.episodes.get(&id) {
+                Some(&first) => {
+                    let last = self.episode_ends.get(&id).copied().unwrap_or(first);
+                    first <= e && e <= last
+                }
+                _ => false,
+            })
+            && f.source.map_or(true, |s| {
+                self.sources.get(&id).map_or(false, |v| v.contains(&s))
+            })
+    }
+}
+
+fn matches_format<T: PartialEq>(filter: Option<T>, value: Option<T>) -> bool {
+    filter.map_or(true, |f| Some(f) == value)
+}
+
+/// Returns the release group like the processor does when it stores a torrent
+fn release_group(title: &str) -> Option<&str> {
+    let rest = title.trim_start().strip_prefix('[')?;
+    Some(rest[..rest.find(']')?].trim()).filter(|g| !g.is_empty())
+}
+
+#[async_trait]
+impl ShowRepo for MockRepo {
+    async fn show(&self, show_id: i64) -> Result<Option<ShowRecord>> {
+        Ok(self.shows.iter().find(|s| s.show_id == show_id).cloned())
+    }
+
+    async fn episode_counts(
+        &self,
+        show_id: i64,
+        _now: DateTime<Utc>,
+    ) -> Result<Option<EpisodeCounts>> {
+        Ok(self.episode_counts.get(&show_id).cloned())
+    }
+
+    async fn release_groups(&self, show_id: i64) -> Result<Vec<String>> {
+        let mut counts = HashMap::<_, usize>::new();
+        for t in self.show_torrents.get(&show_id).into_iter().flatten() {
+            if let Some(group) = release_group(&t.title) {
+                *counts.entry(group.to_string()).or_default() += 1;
+            }
+        }
+        let mut groups: Vec<_> = counts.into_iter().collect();
+        groups.sort_by(|(a, n), (b, m)| m.cmp(n).then_with(|| a.cmp(b)));
+        Ok(groups.into_iter().map(|(g, _)| g).collect())
+    }
+
+    async fn show_names(&self, show_ids: &[i64]) -> Result<Vec<(i64, ShowNames)>> {
+        let name = |s: &ShowRecord, ty| {
+            s.names
+                .iter()
+                .find(|n| n.show_name_type == ty)
+                .map(|n| n.name.clone())
+        };
+        Ok(self
+            .shows
+            .iter()
+            .filter(|s| show_ids.contains(&s.show_id))
+            .map(|s| {
+                let names = ShowNames {
+                    romaji: name(s, ShowNameType::ROMAJI).unwrap_or_default(),
+                    english: name(s, ShowNameType::ENGLISH),
+                };
+                (s.show_id, names)
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TorrentRepo for MockRepo {
+    async fn torrent(&self, torrent_id: i64) -> Result<Option<TorrentDetails>> {
+        Ok(self
+            .torrents
+            .iter()
+            .find(|t| t.torrent.torrent_id == torrent_id)
+            .cloned())
+    }
+
+    async fn torrents(&self, query: &TorrentQuery) -> Result<Vec<TorrentRecord>> {
+        let torrents: Vec<_> = match query.filter.show_id {
+            Some(show_id) => match self.show_torrents.get(&show_id) {
+                Some(t) => t.iter().collect(),
+                _ => vec![],
+            },
+            _ => self.torrents.iter().map(|t| &t.torrent).collect(),
+        };
+        let mut res: Vec<_> = torrents
+            .into_iter()
+            .filter(|t| self.matches(query, t))
+            .cloned()
+            .collect();
+        res.sort_by_key(|t| -t.nyaa_id);
+        res.truncate(PAGE_SIZE + 1);
+        Ok(res)
+    }
+
+    async fn release_group_exists(&self, name: &str) -> Result<bool> {
+        Ok(self
+            .torrents
+            .iter()
+            .map(|t| &t.torrent)
+            .chain(self.show_torrents.values().flatten())
+            .any(|t| release_group(&t.title) == Some(name)))
+    }
+}
+
+#[async_trait]
+impl ScheduleRepo for MockRepo {
+    async fn schedule(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ScheduleRecord>> {
+        let mut res: Vec<_> = self
+            .schedule
+            .iter()
+            .filter(|s| s.airs_at >= from && s.airs_at < to)
+            .cloned()
+            .collect();
+        res.sort_by_key(|s| s.airs_at);
+        Ok(res)
+    }
+}
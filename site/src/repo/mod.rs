@@ -0,0 +1,225 @@
+//! Data access of the handlers
+//!
+//! Handlers don't use the prepared statements directly but go through these traits. They
+//! are implemented for `Pg<Statements>` in production and by [mock::MockRepo] in tests
+//! so that the logic of the handlers can be tested without a running postgres. During
+//! development they can also be backed by a SQLite file, see [sqlite::Sqlite].
+
+pub mod listing;
+#[cfg(test)]
+pub mod mock;
+mod pg;
+pub mod sqlite;
+
+use crate::repo::listing::TorrentQuery;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// The number of torrents on a page of a torrent list
+///
+/// Queries return one additional torrent so that we know if there is another page.
+pub const PAGE_SIZE: usize = 100;
+
+#[derive(Clone, Deserialize)]
+pub struct ShowName {
+    pub name: String,
+    pub show_name_type: i32,
+}
+
+#[derive(Clone)]
+pub struct ShowRecord {
+    pub show_id: i64,
+    pub anilist_id: i64,
+    pub season: Option<i32>,
+    pub show_format: i32,
+    pub has_cover: bool,
+    /// The romaji and english names
+    pub names: Vec<ShowName>,
+    /// Whether the show is no longer listed
+    pub removed: bool,
+    /// The show that replaced this show
+    pub merged_into: Option<i64>,
+    /// The MyAnimeList id
+    pub mal_id: Option<i64>,
+}
+
+#[derive(Clone)]
+pub struct ShowNames {
+    pub romaji: String,
+    pub english: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct EpisodeCounts {
+    /// The number of episodes according to AniList
+    pub episodes: Option<i32>,
+    /// The number of aired episodes if the show is in the schedule
+    pub aired: Option<i32>,
+    /// The episodes that have a matched torrent. Batches are not included.
+    pub matched: Vec<i32>,
+}
+
+#[derive(Clone)]
+pub struct TorrentRecord {
+    pub torrent_id: i64,
+    pub nyaa_id: i64,
+    pub title: String,
+    pub trusted: bool,
+    pub uploaded_at: DateTime<Utc>,
+    pub hash: Vec<u8>,
+    /// Whether the torrent contains multiple episodes
+    pub batch: bool,
+    /// `None` if the torrent has not been seen on a listing that contains the swarm
+    pub swarm: Option<Swarm>,
+    /// Whether the torrent is the recommended torrent of an episode
+    pub recommended: bool,
+}
+
+/// The peers of a torrent as last seen on the nyaa.si listing
+#[derive(Copy, Clone)]
+pub struct Swarm {
+    pub seeders: i32,
+    pub leechers: i32,
+    /// The number of completed downloads
+    pub completed: i32,
+}
+
+impl Swarm {
+    /// Combines the columns of `magnets.torrent`, which are either all null or all set
+    pub fn from_columns(
+        seeders: Option<i32>,
+        leechers: Option<i32>,
+        completed: Option<i32>,
+    ) -> Option<Self> {
+        Some(Self {
+            seeders: seeders?,
+            leechers: leechers?,
+            completed: completed?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct TorrentDetails {
+    pub torrent: TorrentRecord,
+    pub size: i64,
+    /// The shows the torrent has been matched to
+    pub show_ids: Vec<i64>,
+    /// The AniList ids of the shows in the order of `show_ids`
+    pub anilist_ids: Vec<i64>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ExpectedRelease {
+    pub release_group: String,
+    pub delay_seconds: i32,
+}
+
+#[derive(Clone)]
+pub struct ScheduleRecord {
+    pub schedule_id: i64,
+    pub show_id: i64,
+    pub episode: i32,
+    pub airs_at: DateTime<Utc>,
+    pub names: Vec<ShowName>,
+    /// The release groups that usually release the show, fastest first
+    pub expected: Vec<ExpectedRelease>,
+    /// The id of the recommended torrent of the episode once it has been released
+    pub recommended: Option<i64>,
+}
+
+#[async_trait]
+pub trait ShowRepo: Sync {
+    async fn show(&self, show_id: i64) -> Result<Option<ShowRecord>>;
+
+    /// Returns the episode counts of a show, counting the episodes aired before `now`
+    async fn episode_counts(
+        &self,
+        show_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<EpisodeCounts>>;
+
+    /// Returns the release groups of the torrents of a show, most torrents first
+    async fn release_groups(&self, show_id: i64) -> Result<Vec<String>>;
+
+    /// Returns the names of the shows. Unknown shows are omitted.
+    async fn show_names(&self, show_ids: &[i64]) -> Result<Vec<(i64, ShowNames)>>;
+}
+
+#[async_trait]
+pub trait TorrentRepo: Sync {
+    async fn torrent(&self, torrent_id: i64) -> Result<Option<TorrentDetails>>;
+
+    /// Returns up to `PAGE_SIZE + 1` torrents of a list, newest first
+    async fn torrents(&self, query: &TorrentQuery) -> Result<Vec<TorrentRecord>>;
+
+    /// Returns whether a torrent of the release group has been stored
+    async fn release_group_exists(&self, name: &str) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait ScheduleRepo: Sync {
+    /// Returns the episodes airing in `[from, to)` ordered by airing time
+    async fn schedule(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ScheduleRecord>>;
+}
+
+/// All repositories of a storage backend
+pub trait Repo: ShowRepo + TorrentRepo + ScheduleRepo + Send {}
+
+impl<T: ShowRepo + TorrentRepo + ScheduleRepo + Send> Repo for T {}
+
+#[async_trait]
+impl ShowRepo for Arc<dyn Repo> {
+    async fn show(&self, show_id: i64) -> Result<Option<ShowRecord>> {
+        (**self).show(show_id).await
+    }
+
+    async fn episode_counts(
+        &self,
+        show_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<EpisodeCounts>> {
+        (**self).episode_counts(show_id, now).await
+    }
+
+    async fn release_groups(&self, show_id: i64) -> Result<Vec<String>> {
+        (**self).release_groups(show_id).await
+    }
+
+    async fn show_names(&self, show_ids: &[i64]) -> Result<Vec<(i64, ShowNames)>> {
+        (**self).show_names(show_ids).await
+    }
+}
+
+#[async_trait]
+impl TorrentRepo for Arc<dyn Repo> {
+    async fn torrent(&self, torrent_id: i64) -> Result<Option<TorrentDetails>> {
+        (**self).torrent(torrent_id).await
+    }
+
+    async fn torrents(&self, query: &TorrentQuery) -> Result<Vec<TorrentRecord>> {
+        (**self).torrents(query).await
+    }
+
+    async fn release_group_exists(&self, name: &str) -> Result<bool> {
+        (**self).release_group_exists(name).await
+    }
+}
+
+#[async_trait]
+impl ScheduleRepo for Arc<dyn Repo> {
+    async fn schedule(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ScheduleRecord>> {
+        (**self).schedule(from, to).await
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    db::Statements,
+    repo::{
+        listing::{Dialect, Param, TorrentQuery},
+        EpisodeCounts, ExpectedRelease, ScheduleRecord, ScheduleRepo, ShowName,
+        ShowNames, ShowRecord, ShowRepo, Swarm, TorrentDetails, TorrentRecord,
+        TorrentRepo,
+    },
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use common::pg::Pg;
+use tokio_postgres::types::{Json, ToSql};
+
+#[async_trait]
+impl ShowRepo for Pg<Statements> {
+    async fn show(&self, show_id: i64) -> Result<Option<ShowRecord>> {
+        let stmt = &self.t.show_info;
+        let row = match self.query_opt(&stmt.stmt, &[&show_id]).await? {
+            Some(r) => r,
+            _ => return Ok(None),
+        };
+        let names: Json<Vec<ShowName>> = row.get(stmt.names);
+        Ok(Some(ShowRecord {
+            show_id: row.get(stmt.show_id),
+            anilist_id: row.get(stmt.anilist_id),
+            season: row.get(stmt.season),
+            show_format: row.get(stmt.show_format),
+            has_cover: row.get(stmt.has_cover),
+            names: names.0,
+            removed: row.get(stmt.removed),
+            merged_into: row.get(stmt.merged_into),
+            mal_id: row.get(stmt.mal_id),
+        }))
+    }
+
+    async fn episode_counts(
+        &self,
+        show_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<EpisodeCounts>> {
+        let stmt = &self.t.episodes;
+        let row = match self.query_opt(&stmt.stmt, &[&show_id, &now]).await? {
+            Some(r) => r,
+            _ => return Ok(None),
+        };
+        Ok(Some(EpisodeCounts {
+            episodes: row.get(stmt.episodes),
+            aired: row.get(stmt.aired),
+            matched: row.get(stmt.matched),
+        }))
+    }
+
+    async fn release_groups(&self, show_id: i64) -> Result<Vec<String>> {
+        let stmt = &self.t.show_release_groups;
+        let rows = self.query(&stmt.stmt, &[&show_id]).await?;
+        Ok(rows.iter().map(|row| row.get(stmt.name)).collect())
+    }
+
+    async fn show_names(&self, show_ids: &[i64]) -> Result<Vec<(i64, ShowNames)>> {
+        let stmt = &self.t.show_names;
+        let rows = self.query(&stmt.stmt, &[&show_ids]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let names = ShowNames {
+                    romaji: row.get(stmt.romaji),
+                    english: row.get(stmt.english),
+                };
+                (row.get(stmt.show_id), names)
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TorrentRepo for Pg<Statements> {
+    async fn torrent(&self, torrent_id: i64) -> Result<Option<TorrentDetails>> {
+        let stmt = &self.t.torrent;
+        let row = match self.query_opt(&stmt.stmt, &[&torrent_id]).await? {
+            Some(r) => r,
+            _ => return Ok(None),
+        };
+        Ok(Some(TorrentDetails {
+            torrent: TorrentRecord {
+                torrent_id,
+                nyaa_id: row.get(stmt.nyaa_id),
+                title: row.get(stmt.title),
+                trusted: row.get(stmt.trusted),
+                uploaded_at: row.get(stmt.uploaded_at),
+                hash: row.get(stmt.hash),
+                batch: row.get(stmt.batch),
+                swarm: Swarm::from_columns(
+                    row.get(stmt.seeders),
+                    row.get(stmt.leechers),
+                    row.get(stmt.completed),
+                ),
+                recommended: row.get(stmt.recommended),
+            },
+            size: row.get(stmt.size),
+            show_ids: row.get(stmt.show_ids),
+            anilist_ids: row.get(stmt.anilist_ids),
+        }))
+    }
+
+    async fn torrents(&self, query: &TorrentQuery) -> Result<Vec<TorrentRecord>> {
+        let (sql, params) = query.sql(Dialect::Postgres);
+        let params: Vec<&(dyn ToSql + Sync)> = params
+            .iter()
+            .map(|p| match p {
+                Param::Int(v) => v as &(dyn ToSql + Sync),
+                Param::BigInt(v) => v,
+                Param::Text(v) => v,
+                Param::Timestamp(v) => v,
+            })
+            .collect();
+        // The sql only depends on which filters are set. Their values are parameters.
+        let stmt = self.prepare_cached(&sql).await?;
+        let rows = self.query(&stmt, &params).await?;
+        Ok(rows
+            .iter()
+            .map(|row| TorrentRecord {
+                torrent_id: row.get("torrent_id"),
+                nyaa_id: row.get("nyaa_id"),
+                title: row.get("title"),
+                trusted: row.get("trusted"),
+                uploaded_at: row.get("uploaded_at"),
+                hash: row.get("hash"),
+                batch: row.get("batch"),
+                swarm: Swarm::from_columns(
+                    row.get("seeders"),
+                    row.get("leechers"),
+                    row.get("completed"),
+                ),
+                recommended: row.get("recommended"),
+            })
+            .collect())
+    }
+
+    async fn release_group_exists(&self, name: &str) -> Result<bool> {
+        let stmt = &self.t.release_group;
+        Ok(self.query_opt(&stmt.stmt, &[&name]).await?.is_some())
+    }
+}
+
+#[async_trait]
+impl ScheduleRepo for Pg<Statements> {
+    async fn schedule(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ScheduleRecord>> {
+        let stmt = &self.t.schedule;
+        let rows = self.query(&stmt.stmt, &[&from, &to]).await?;
+        let mut res = vec![];
+        for row in rows {
+            let names: Json<Vec<ShowName>> = row.get(stmt.names);
+            let expected: Option<Json<Vec<ExpectedRelease>>> = row.get(stmt.expected);
+            res.push(ScheduleRecord {
+                schedule_id: row.get(stmt.schedule_id),
+                show_id: row.get(stmt.show_id),
+                episode: row.get(stmt.episode),
+                airs_at: row.get(stmt.airs_at),
+                names: names.0,
+                expected: expected.map(|e| e.0).unwrap_or_default(),
+                recommended: row.get(stmt.recommended),
+            });
+        }
+        Ok(res)
+    }
+}
@@ -0,0 +1,340 @@
+use crate::repo::{
+    listing::{Dialect, Param, TorrentQuery},
+    EpisodeCounts, ScheduleRecord, ScheduleRepo, ShowNames, ShowRecord, ShowRepo, Swarm,
+    TorrentDetails, TorrentRecord, TorrentRepo,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{
+    params,
+    types::{Type, Value},
+    Connection, OpenFlags, OptionalExtension, Row,
+};
+use serde::de::DeserializeOwned;
+use std::{path::Path, sync::Mutex};
+
+/// The repositories backed by a SQLite file created with `dump export --format sqlite`
+///
+/// This allows running the site without postgres during development. The file is
+/// opened read-only. Queries block the worker thread which is fine for a single
+/// developer but not for production.
+pub struct Sqlite {
+    con: Mutex<Connection>,
+}
+
+impl Sqlite {
+    pub fn open(path: &Path) -> Result<Self> {
+        let con = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("cannot open {}", path.display()))?;
+        Ok(Self {
+            con: Mutex::new(con),
+        })
+    }
+}
+
+fn timestamp(row: &Row, col: &str) -> rusqlite::Result<DateTime<Utc>> {
+    Ok(Utc.timestamp(row.get(col)?, 0))
+}
+
+fn json<T: DeserializeOwned>(row: &Row, col: &str) -> rusqlite::Result<T> {
+    let idx = row.column_index(col)?;
+    let s: String = row.get(idx)?;
+    serde_json::from_str(&s).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e))
+    })
+}
+
+fn torrent_record(row: &Row) -> rusqlite::Result<TorrentRecord> {
+    Ok(TorrentRecord {
+        torrent_id: row.get("torrent_id")?,
+        nyaa_id: row.get("nyaa_id")?,
+        title: row.get("title")?,
+        trusted: row.get("trusted")?,
+        uploaded_at: timestamp(row, "uploaded_at")?,
+        hash: row.get("hash")?,
+        batch: row.get("batch")?,
+        swarm: Swarm::from_columns(
+            row.get("seeders")?,
+            row.get("leechers")?,
+            row.get("completed")?,
+        ),
+        recommended: row.get("recommended")?,
+    })
+}
+
+// language=sql
+const NAMES: &str = "
+    (
+        select json_group_array(json_object('name', name, 'show_name_type', show_name_type))
+        from show_name
+        where show_id = s.show_id and show_name_type in (1, 2)
+    ) as names";
+
+#[async_trait]
+impl ShowRepo for Sqlite {
+    async fn show(&self, show_id: i64) -> Result<Option<ShowRecord>> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let sql = format!(
+            "
+            select
+                s.show_id,
+                s.anilist_id,
+                s.season,
+                s.show_format,
+                s.cover_mirrored_url is not null as has_cover,
+                s.removal is not null as removed,
+                s.merged_into,
+                (
+                    select external_id
+                    from show_external_id
+                    where show_id = s.show_id and external_site = 1
+                ) as mal_id,
+                {}
+            from show s
+            where s.show_id = ?",
+            NAMES
+        );
+        let show = con
+            .query_row(&sql, params![show_id], |row| {
+                Ok(ShowRecord {
+                    show_id: row.get("show_id")?,
+                    anilist_id: row.get("anilist_id")?,
+                    season: row.get("season")?,
+                    show_format: row.get("show_format")?,
+                    has_cover: row.get("has_cover")?,
+                    names: json(row, "names")?,
+                    removed: row.get("removed")?,
+                    merged_into: row.get("merged_into")?,
+                    mal_id: row.get("mal_id")?,
+                })
+            })
+            .optional()?;
+        Ok(show)
+    }
+
+    async fn episode_counts(
+        &self,
+        show_id: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Option<EpisodeCounts>> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let counts = con
+            .query_row(
+                "
+                select
+                    s.episodes,
+                    (
+                        select min(sch.episode) - 1
+                        from schedule sch
+                        where sch.show_id = s.show_id and sch.airs_at > ?
+                    ) as aired,
+                    (
+                        select json_group_array(distinct rts.episode)
+                        from rel_torrent_show rts
+                        where rts.show_id = s.show_id
+                            and rts.episode is not null
+                            and rts.episode_end is null
+                    ) as matched
+                from show s
+                where s.show_id = ?",
+                params![now.timestamp(), show_id],
+                |row| {
+                    Ok(EpisodeCounts {
+                        episodes: row.get("episodes")?,
+                        aired: row.get("aired")?,
+                        matched: json(row, "matched")?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(counts)
+    }
+
+    async fn release_groups(&self, show_id: i64) -> Result<Vec<String>> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let mut stmt = con.prepare_cached(
+            "
+            select g.name
+            from rel_torrent_show rts
+            join torrent t using (torrent_id)
+            join release_group g using (release_group_id)
+            where rts.show_id = ?
+            group by g.name
+            order by count(*) desc, g.name",
+        )?;
+        let groups = stmt
+            .query_map(params![show_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(groups)
+    }
+
+    async fn show_names(&self, show_ids: &[i64]) -> Result<Vec<(i64, ShowNames)>> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let mut stmt = con.prepare_cached(
+            "
+            select
+                coalesce(max(name) filter (where show_name_type = 1), '') as romaji,
+                max(name) filter (where show_name_type = 2) as english
+            from show_name
+            where show_id = ? and show_name_type in (1, 2)
+            having count(*) > 0",
+        )?;
+        let mut res = vec![];
+        for &show_id in show_ids {
+            let names = stmt
+                .query_row(params![show_id], |row| {
+                    Ok(ShowNames {
+                        romaji: row.get("romaji")?,
+                        english: row.get("english")?,
+                    })
+                })
+                .optional()?;
+            if let Some(names) = names {
+                res.push((show_id, names));
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl TorrentRepo for Sqlite {
+    async fn torrent(&self, torrent_id: i64) -> Result<Option<TorrentDetails>> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let details = con
+            .query_row(
+                "
+                select
+                    t.*,
+                    exists (
+                        select 1
+                        from recommended_torrent rt
+                        where rt.torrent_id = t.torrent_id
+                    ) as recommended,
+                    (
+                        select json_group_array(show_id)
+                        from (
+                            select show_id
+                            from rel_torrent_show
+                            where torrent_id = t.torrent_id
+                            order by show_id
+                        )
+                    ) as show_ids,
+                    (
+                        select json_group_array(anilist_id)
+                        from (
+                            select s.anilist_id
+                            from rel_torrent_show r
+                            join show s on s.show_id = r.show_id
+                            where r.torrent_id = t.torrent_id
+                            order by r.show_id
+                        )
+                    ) as anilist_ids
+                from torrent t
+                where t.torrent_id = ?",
+                params![torrent_id],
+                |row| {
+                    Ok(TorrentDetails {
+                        torrent: torrent_record(row)?,
+                        size: row.get("size")?,
+                        show_ids: json(row, "show_ids")?,
+                        anilist_ids: json(row, "anilist_ids")?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(details)
+    }
+
+    async fn torrents(&self, query: &TorrentQuery) -> Result<Vec<TorrentRecord>> {
+        let (sql, params) = query.sql(Dialect::Sqlite);
+        // timestamps are stored as unix timestamps
+        let params: Vec<Value> = params
+            .into_iter()
+            .map(|p| match p {
+                Param::Int(v) => Value::Integer(v.into()),
+                Param::BigInt(v) => Value::Integer(v),
+                Param::Text(v) => Value::Text(v),
+                Param::Timestamp(v) => Value::Integer(v.timestamp()),
+            })
+            .collect();
+        let con = self.con.lock().unwrap();
+        let mut stmt = con.prepare_cached(&sql)?;
+        let rows = stmt.query_map(&params, torrent_record)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    async fn release_group_exists(&self, name: &str) -> Result<bool> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let exists = con
+            .query_row(
+                "select 1 from release_group where name = ?",
+                params![name],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(exists.is_some())
+    }
+}
+
+#[async_trait]
+impl ScheduleRepo for Sqlite {
+    async fn schedule(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ScheduleRecord>> {
+        let con = self.con.lock().unwrap();
+        // language=sql
+        let sql = format!(
+            "
+            select
+                s.schedule_id,
+                s.show_id,
+                s.episode,
+                s.airs_at,
+                {},
+                (
+                    select json_group_array(
+                        json_object('release_group', release_group, 'delay_seconds', delay_seconds)
+                    )
+                    from (
+                        select release_group, delay_seconds
+                        from expected_release
+                        where show_id = s.show_id and samples >= 3
+                        order by delay_seconds
+                    )
+                ) as expected,
+                (
+                    select rt.torrent_id
+                    from recommended_torrent rt
+                    where rt.show_id = s.show_id and rt.episode = s.episode
+                ) as recommended
+            from schedule s
+            join show sh using (show_id)
+            where s.airs_at >= ? and s.airs_at < ? and sh.removal is null
+            order by s.airs_at",
+            NAMES
+        );
+        let mut stmt = con.prepare_cached(&sql)?;
+        let rows = stmt.query_map(params![from.timestamp(), to.timestamp()], |row| {
+            Ok(ScheduleRecord {
+                schedule_id: row.get("schedule_id")?,
+                show_id: row.get("show_id")?,
+                episode: row.get("episode")?,
+                airs_at: timestamp(row, "airs_at")?,
+                names: json(row, "names")?,
+                expected: json(row, "expected")?,
+                recommended: row.get("recommended")?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
@@ -1,25 +1,21 @@
 use crate::{
-    db::Statements,
+    cache::Cached,
+    repo::{ExpectedRelease, ScheduleRepo},
+    schedule_model::Week,
     state::State,
-    text::{searchable_text, TEXT_HTML},
+    text::TEXT_HTML,
 };
 use actix_web::{
     http::header::{CacheControl, CacheDirective, CACHE_CONTROL},
-    web::Data,
+    web::{Bytes, Data},
     HttpResponse, Responder,
 };
 use anyhow::Result;
 use askama::Template;
-use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
-use common::pg::Pg;
-use serde::{Deserialize, Serialize};
-use tokio_postgres::types::Json;
-
-#[derive(Deserialize)]
-struct Name {
-    name: String,
-    show_name_type: i32,
-}
+use chrono::{DateTime, Timelike, Utc};
+use common::textnorm;
+use itertools::Itertools;
+use serde::Serialize;
 
 #[derive(Serialize)]
 struct ScheduleItem {
@@ -27,6 +23,10 @@ struct ScheduleItem {
     show_id: i64,
     episode: i32,
     name: String,
+    /// E.g. "usually released by SubsPlease within 2 hours"
+    expected: Option<String>,
+    /// The torrent id of the recommended release
+    recommended: Option<i64>,
 }
 
 #[derive(Serialize)]
@@ -59,53 +59,19 @@ impl<T> Day<T> {
     }
 }
 
-struct TimeRange {
-    now: DateTime<Utc>,
-    now_ts: i64,
-    num_days_from_monday: usize,
-    yesterday: DateTime<Utc>,
-    end_of_week: DateTime<Utc>,
-}
-
-impl TimeRange {
-    pub fn new() -> Self {
-        let now = Utc::now();
-        let now_ts = now.timestamp();
-        let today = now.date().and_hms(0, 0, 0);
-        let yesterday = today - Duration::days(1);
-        let end_of_week = today + Duration::days(6);
-        Self {
-            now,
-            now_ts,
-            num_days_from_monday: now.date().weekday().num_days_from_monday() as usize,
-            yesterday,
-            end_of_week,
-        }
-    }
-}
-
-const WEEKDAYS: &[&str] = &[
-    "Monday",
-    "Tuesday",
-    "Wednesday",
-    "Thursday",
-    "Friday",
-    "Saturday",
-    "Sunday",
-];
-
 #[actix_web::get("/schedule")]
 pub async fn get(state: Data<State>) -> impl Responder {
-    match get_(state).await {
+    match schedule_(&state).await {
         Ok(b) => {
+            let bytes: Bytes = (*b).clone();
             let cc = CacheControl(vec![
-                CacheDirective::MaxAge(10 * 60),
+                CacheDirective::MaxAge(b.max_age()),
                 CacheDirective::Public,
             ]);
             HttpResponse::Ok()
                 .header(CACHE_CONTROL, cc)
                 .content_type(TEXT_HTML)
-                .body(b)
+                .body(bytes)
         }
         Err(e) => {
             log::error!(
@@ -120,79 +86,96 @@ pub async fn get(state: Data<State>) -> impl Responder {
 #[derive(Template)]
 #[template(path = "schedule.html")]
 struct Tpl<'a> {
-    days: &'a [&'a Day<HtmlEntry>],
+    days: &'a [Day<HtmlEntry>],
     json: &'a str,
 }
 
-async fn get_(state: Data<State>) -> anyhow::Result<String> {
-    let client = state.pg.borrow().await?;
+/// The schedule is cached for 10 minutes or until the processor notifies us of a change.
+/// See [crate::notify].
+async fn schedule_(state: &State) -> Result<Cached<Bytes>> {
+    state.global.schedule.get(|| render(state)).await
+}
+
+pub async fn render(state: &State) -> Result<Bytes> {
+    let repo = state.repo().await?;
 
-    let time_range = TimeRange::new();
+    let week = Week::new(state.global.clock.now(), state.global.week_start);
 
-    let (mut html_days, json_days) = collect_shedules(&client, &time_range).await?;
+    let (mut html_days, json_days) = collect_shedules(&repo, &week).await?;
 
-    insert_current_time(&mut html_days, &time_range);
-    let arranged_days = arrange_days(&html_days, &time_range);
+    insert_current_time(&mut html_days, &week);
 
     let json = serde_json::to_string(&json_days)?;
 
     let tpl = Tpl {
-        days: &arranged_days,
+        days: &html_days,
         json: &json,
     };
 
-    Ok(tpl.render()?)
+    Ok(tpl.render()?.into())
 }
 
 async fn collect_shedules(
-    pg: &Pg<Statements>,
-    times: &TimeRange,
+    repo: &impl ScheduleRepo,
+    week: &Week,
 ) -> Result<(Vec<Day<HtmlEntry>>, Vec<Day<ShowingJson>>)> {
-    let mut html_days: Vec<_> = WEEKDAYS.iter().copied().map(Day::new).collect();
-    let mut json_days: Vec<_> = WEEKDAYS.iter().copied().map(Day::new).collect();
-
-    json_days[times.num_days_from_monday].always_visible = true;
-
-    let rows = pg
-        .query(&pg.t.schedule.stmt, &[&times.yesterday, &times.end_of_week])
-        .await?;
-    for row in rows {
-        let names: Json<Vec<Name>> = row.get(pg.t.schedule.names);
-        let time: DateTime<Utc> = row.get(pg.t.schedule.airs_at);
-        let schedule_id = row.get(pg.t.schedule.schedule_id);
+    let names = week.day_names();
+    let mut html_days: Vec<_> = names.iter().copied().map(Day::new).collect();
+    let mut json_days: Vec<_> = names.iter().copied().map(Day::new).collect();
+
+    json_days[week.today()].always_visible = true;
+
+    let records = repo.schedule(week.start, week.end()).await?;
+    for record in records {
+        let day = match week.day_of(record.airs_at) {
+            Some(d) => d,
+            _ => continue,
+        };
+        let names = &record.names;
+        let expected = &record.expected;
+        let time = record.airs_at;
+        let schedule_id = record.schedule_id;
         let item = HtmlEntry {
             timestamp: time.timestamp(),
             air_time: format_time(&time),
             showing_data: Some(ScheduleItem {
                 schedule_id,
-                show_id: row.get(pg.t.schedule.show_id),
-                episode: row.get(pg.t.schedule.episode),
+                show_id: record.show_id,
+                episode: record.episode,
                 name: names
-                    .0
                     .iter()
                     .find(|n| n.show_name_type == 1)
                     .unwrap()
                     .name
                     .clone(),
+                expected: if expected.is_empty() {
+                    None
+                } else {
+                    Some(format_expected(expected))
+                },
+                recommended: record.recommended,
             }),
         };
         let json_item = ShowingJson {
             element_id: schedule_id,
-            names: names.0.iter().map(|n| searchable_text(&n.name)).collect(),
+            names: names
+                .iter()
+                .map(|n| textnorm::search_fold(&n.name))
+                .collect(),
         };
-        let date = time.date().weekday().num_days_from_monday() as usize;
-        html_days[date].elements.push(item);
-        json_days[date].elements.push(json_item);
+        html_days[day].elements.push(item);
+        json_days[day].elements.push(json_item);
     }
 
     Ok((html_days, json_days))
 }
 
-fn insert_current_time(html: &mut Vec<Day<HtmlEntry>>, times: &TimeRange) {
-    let today_el = &mut html[times.num_days_from_monday];
+fn insert_current_time(html: &mut Vec<Day<HtmlEntry>>, week: &Week) {
+    let now_ts = week.now.timestamp();
+    let today_el = &mut html[week.today()];
     let mut idx = today_el.elements.len();
     for (i, entry) in today_el.elements.iter().enumerate() {
-        if entry.timestamp >= times.now_ts {
+        if entry.timestamp >= now_ts {
             idx = i;
             break;
         }
@@ -200,22 +183,82 @@ fn insert_current_time(html: &mut Vec<Day<HtmlEntry>>, times: &TimeRange) {
     today_el.elements.insert(
         idx,
         HtmlEntry {
-            timestamp: times.now_ts,
-            air_time: format_time(&times.now),
+            timestamp: now_ts,
+            air_time: format_time(&week.now),
             showing_data: None,
         },
     )
 }
 
-fn arrange_days<'a>(
-    html: &'a [Day<HtmlEntry>],
-    times: &TimeRange,
-) -> Vec<&'a Day<HtmlEntry>> {
-    let start_idx = times.now.date().weekday().pred().num_days_from_monday() as usize;
-    html[start_idx..].iter().chain(&html[..start_idx]).collect()
+fn format_expected(expected: &[ExpectedRelease]) -> String {
+    let groups = expected.iter().map(|e| {
+        let hours = (e.delay_seconds.max(0) + 3599) / 3600;
+        let unit = if hours == 1 { "hour" } else { "hours" };
+        format!("{} within {} {}", e.release_group, hours, unit)
+    });
+    format!("usually released by {}", groups.join(" or "))
 }
 
 fn format_time(t: &DateTime<Utc>) -> String {
     let time = t.time();
     format!("{:02}:{:02}", time.hour(), time.minute())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        repo::{mock::MockRepo, ScheduleRecord, ShowName},
+        schedule_model::WeekStart,
+    };
+    use chrono::TimeZone;
+    use common::ShowNameType;
+    use futures::executor::block_on;
+
+    fn record(schedule_id: i64, airs_at: DateTime<Utc>) -> ScheduleRecord {
+        ScheduleRecord {
+            schedule_id,
+            show_id: schedule_id,
+            episode: 1,
+            airs_at,
+            names: vec![ShowName {
+                name: format!("Show {}", schedule_id),
+                show_name_type: ShowNameType::ROMAJI,
+            }],
+            expected: vec![],
+            recommended: None,
+        }
+    }
+
+    #[test]
+    fn buckets_by_day() {
+        // A Wednesday
+        let week = Week::new(Utc.ymd(2021, 1, 6).and_hms(12, 0, 0), WeekStart::Yesterday);
+        let repo = MockRepo {
+            schedule: vec![
+                record(1, Utc.ymd(2021, 1, 4).and_hms(23, 0, 0)),
+                record(2, Utc.ymd(2021, 1, 5).and_hms(23, 0, 0)),
+                record(3, Utc.ymd(2021, 1, 6).and_hms(10, 0, 0)),
+                record(4, Utc.ymd(2021, 1, 10).and_hms(9, 0, 0)),
+                record(5, Utc.ymd(2021, 1, 12).and_hms(1, 0, 0)),
+            ],
+            ..Default::default()
+        };
+        let (mut html, json) = block_on(collect_shedules(&repo, &week)).unwrap();
+        let ids = |d: &Day<HtmlEntry>| -> Vec<_> {
+            d.elements
+                .iter()
+                .map(|e| e.showing_data.as_ref().map(|s| s.schedule_id))
+                .collect()
+        };
+        assert_eq!(html[0].name, "Tuesday");
+        assert_eq!(ids(&html[0]), vec![Some(2)]);
+        assert_eq!(ids(&html[1]), vec![Some(3)]);
+        assert_eq!(ids(&html[5]), vec![Some(4)]);
+        assert_eq!(ids(&html[6]), vec![]);
+        assert!(json[1].always_visible);
+
+        insert_current_time(&mut html, &week);
+        assert_eq!(ids(&html[1]), vec![Some(3), None]);
+    }
+}
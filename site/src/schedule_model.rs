@@ -0,0 +1,148 @@
+//! The arrangement of the schedule into days
+//!
+//! All dates are UTC dates.
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc, Weekday};
+use serde::Deserialize;
+
+/// The first day shown on the schedule page
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WeekStart {
+    /// The schedule starts yesterday and shows the following six days
+    Yesterday,
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Default for WeekStart {
+    fn default() -> Self {
+        WeekStart::Yesterday
+    }
+}
+
+impl WeekStart {
+    fn weekday(self) -> Option<Weekday> {
+        let day = match self {
+            WeekStart::Yesterday => return None,
+            WeekStart::Monday => Weekday::Mon,
+            WeekStart::Tuesday => Weekday::Tue,
+            WeekStart::Wednesday => Weekday::Wed,
+            WeekStart::Thursday => Weekday::Thu,
+            WeekStart::Friday => Weekday::Fri,
+            WeekStart::Saturday => Weekday::Sat,
+            WeekStart::Sunday => Weekday::Sun,
+        };
+        Some(day)
+    }
+}
+
+/// The seven days shown on the schedule page
+pub struct Week {
+    pub now: DateTime<Utc>,
+    /// Midnight of the first day
+    pub start: DateTime<Utc>,
+}
+
+impl Week {
+    pub fn new(now: DateTime<Utc>, week_start: WeekStart) -> Self {
+        let today = Utc.ymd(now.year(), now.month(), now.day()).and_hms(0, 0, 0);
+        let start = match week_start.weekday() {
+            None => today - Duration::days(1),
+            Some(first) => {
+                let days = (7 + now.weekday().num_days_from_monday()
+                    - first.num_days_from_monday())
+                    % 7;
+                today - Duration::days(days as i64)
+            }
+        };
+        Self { now, start }
+    }
+
+    /// Midnight after the last day
+    pub fn end(&self) -> DateTime<Utc> {
+        self.start + Duration::days(7)
+    }
+
+    /// Returns the index of the day containing `t` or `None` if `t` is not in this week
+    pub fn day_of(&self, t: DateTime<Utc>) -> Option<usize> {
+        if t < self.start || t >= self.end() {
+            return None;
+        }
+        Some(((t - self.start).num_seconds() / (24 * 60 * 60)) as usize)
+    }
+
+    /// Returns the index of the day containing `now`
+    pub fn today(&self) -> usize {
+        self.day_of(self.now).unwrap()
+    }
+
+    /// Returns the names of the days in order
+    pub fn day_names(&self) -> Vec<&'static str> {
+        (0..7)
+            .map(|i| weekday_name((self.start + Duration::days(i)).weekday()))
+            .collect()
+    }
+}
+
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(d: u32, h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        // 2021-01-04 is a Monday
+        Utc.ymd(2021, 1, d).and_hms(h, m, s)
+    }
+
+    #[test]
+    fn rolling_week() {
+        let week = Week::new(at(6, 12, 0, 0), WeekStart::Yesterday);
+        assert_eq!(week.start, at(5, 0, 0, 0));
+        assert_eq!(week.end(), at(12, 0, 0, 0));
+        assert_eq!(week.today(), 1);
+        assert_eq!(week.day_names()[0], "Tuesday");
+        assert_eq!(week.day_names()[6], "Monday");
+    }
+
+    #[test]
+    fn calendar_week() {
+        let week = Week::new(at(6, 12, 0, 0), WeekStart::Monday);
+        assert_eq!(week.start, at(4, 0, 0, 0));
+        assert_eq!(week.today(), 2);
+        let week = Week::new(at(10, 12, 0, 0), WeekStart::Monday);
+        assert_eq!(week.start, at(4, 0, 0, 0));
+        assert_eq!(week.today(), 6);
+        let week = Week::new(at(10, 12, 0, 0), WeekStart::Sunday);
+        assert_eq!(week.start, at(10, 0, 0, 0));
+        assert_eq!(week.day_names()[0], "Sunday");
+    }
+
+    #[test]
+    fn midnight() {
+        let week = Week::new(at(6, 0, 0, 0), WeekStart::Yesterday);
+        assert_eq!(week.today(), 1);
+        assert_eq!(week.day_of(at(4, 23, 59, 59)), None);
+        assert_eq!(week.day_of(at(5, 0, 0, 0)), Some(0));
+        assert_eq!(week.day_of(at(5, 23, 59, 59)), Some(0));
+        assert_eq!(week.day_of(at(6, 0, 0, 0)), Some(1));
+        assert_eq!(week.day_of(at(11, 23, 59, 59)), Some(6));
+        assert_eq!(week.day_of(at(12, 0, 0, 0)), None);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{
+    repo::{Swarm, TorrentRecord},
+    state::State,
+    text::TEXT_HTML,
+    torrent_list::{torrent_list, Day},
+};
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Duration, Utc};
+use common::{query_encode, textnorm};
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// The maximum length of a query in bytes
+pub const MAX_QUERY_LEN: usize = 200;
+
+/// Shows whose names match the query within this similarity are ranked by their activity
+const SIMILARITY_STEP: f32 = 0.1;
+
+/// Shows with a torrent in this many days are active
+const ACTIVE_DAYS: i64 = 90;
+
+#[actix_web::get("/search")]
+pub async fn get(state: Data<State>, Query(query): Query<QueryParams>) -> impl Responder {
+    if query.q.len() > MAX_QUERY_LEN {
+        return HttpResponse::BadRequest().body("the query is too long");
+    }
+    match render(&state, &query).await {
+        Ok(data) => HttpResponse::Ok().content_type(TEXT_HTML).body(data),
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to search for {:?}: {:#}",
+                query.q,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    q: String,
+    #[serde(rename = "a", default = "i64::max_value")]
+    after: i64,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+struct Search<'a> {
+    query: &'a str,
+    searched: bool,
+    shows: &'a [Show],
+    days: &'a [Day<'a>],
+    last: Option<i64>,
+    first: bool,
+    base: String,
+}
+
+/// A show found by [search_shows]
+pub struct Show {
+    pub show_id: i64,
+    pub romaji: String,
+    pub english: Option<String>,
+    /// The word similarity of the query and the names of the show
+    similarity: f32,
+    popularity: Option<i32>,
+    last_torrent_at: Option<DateTime<Utc>>,
+}
+
+/// Searches the names of the listed shows and returns them best match first
+///
+/// Used by /search and /api/v1/search/shows. `query` is folded with
+/// `textnorm::search_words`. See [rank] for the order.
+pub async fn search_shows(state: &State, query: &str) -> Result<Vec<Show>> {
+    let folded = textnorm::search_words(query);
+    if folded.is_empty() {
+        return Ok(vec![]);
+    }
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.search_shows;
+    let mut shows = vec![];
+    for row in db.query(&stmt.stmt, &[&folded]).await? {
+        shows.push(Show {
+            show_id: row.get(stmt.show_id),
+            romaji: row.get(stmt.romaji),
+            english: row.get(stmt.english),
+            similarity: row.get(stmt.similarity),
+            popularity: row.get(stmt.popularity),
+            last_torrent_at: row.get(stmt.last_torrent_at),
+        });
+    }
+    rank(&mut shows, state.global.clock.now());
+    Ok(shows)
+}
+
+/// Sorts shows by how well their names match the query and then by their activity
+///
+/// Many shows share most of their names, e.g. the seasons of a series. The similarity
+/// is therefore compared in steps of [SIMILARITY_STEP]. Within a step, shows with
+/// recent torrents come first, then the more popular shows, then the shows with the
+/// newer torrents.
+fn rank(shows: &mut [Show], now: DateTime<Utc>) {
+    let active_since = now - Duration::days(ACTIVE_DAYS);
+    shows.sort_by_key(|s| {
+        (
+            Reverse((s.similarity / SIMILARITY_STEP).floor() as i32),
+            Reverse(s.last_torrent_at.map_or(false, |t| t >= active_since)),
+            Reverse(s.popularity),
+            Reverse(s.last_torrent_at),
+            s.show_id,
+        )
+    });
+}
+
+mod filters {
+    pub use crate::text::{format_day, format_time};
+}
+
+/// Searches the names of shows and the titles of torrents
+///
+/// The query is folded like the columns that the processor maintains, e.g. `ＦＲＩＥＲＥＮ`
+/// and `frieren` are the same query. Shows are only listed on the first page. Torrents
+/// are paginated like the other torrent lists.
+async fn render(state: &State, query: &QueryParams) -> Result<String> {
+    let folded = textnorm::search_words(&query.q);
+    let mut shows = vec![];
+    let mut torrents = vec![];
+    if !folded.is_empty() {
+        if query.after == i64::MAX {
+            shows = search_shows(state, &query.q).await?;
+        }
+        let db = state.pg.borrow().await?;
+        let stmt = &db.t.search_torrents;
+        for row in db.query(&stmt.stmt, &[&folded, &query.after]).await? {
+            torrents.push(TorrentRecord {
+                torrent_id: row.get(stmt.torrent_id),
+                nyaa_id: row.get(stmt.nyaa_id),
+                title: row.get(stmt.title),
+                trusted: row.get(stmt.trusted),
+                uploaded_at: row.get(stmt.uploaded_at),
+                hash: row.get(stmt.hash),
+                batch: row.get(stmt.batch),
+                swarm: Swarm::from_columns(
+                    row.get(stmt.seeders),
+                    row.get(stmt.leechers),
+                    row.get(stmt.completed),
+                ),
+                recommended: row.get(stmt.recommended),
+            });
+        }
+    }
+    let (last, days) = torrent_list(&torrents);
+    let search = Search {
+        query: &query.q,
+        searched: !folded.is_empty(),
+        shows: &shows,
+        days: &days,
+        last,
+        first: query.after == i64::MAX,
+        base: format!("/search?q={}", query_encode(&query.q)),
+    };
+    Ok(search.render()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn show(
+        show_id: i64,
+        similarity: f32,
+        popularity: Option<i32>,
+        last_torrent_at: Option<DateTime<Utc>>,
+    ) -> Show {
+        Show {
+            show_id,
+            romaji: String::new(),
+            english: None,
+            similarity,
+            popularity,
+            last_torrent_at,
+        }
+    }
+
+    #[test]
+    fn ranks_active_and_popular_shows_first() {
+        let now = Utc.ymd(2021, 6, 1).and_hms(0, 0, 0);
+        let recent = Some(now - Duration::days(7));
+        let old = Some(now - Duration::days(400));
+        let mut shows = vec![
+            show(1, 0.95, Some(100_000), old),
+            show(2, 0.92, Some(1_000), recent),
+            show(3, 0.91, Some(5_000), recent),
+            show(4, 0.99, None, None),
+            show(5, 0.5, Some(500_000), recent),
+            show(6, 0.93, Some(5_000), Some(now - Duration::days(1))),
+        ];
+        rank(&mut shows, now);
+        let ids: Vec<_> = shows.iter().map(|s| s.show_id).collect();
+        assert_eq!(ids, [6, 3, 2, 1, 4, 5]);
+    }
+}
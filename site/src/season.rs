@@ -1,11 +1,12 @@
 use crate::{
+    og::OpenGraph,
     show_list::{show_list_from_rows, Letter},
     state::State,
 };
 use actix_web::{web, web::Data, HttpResponse, Responder};
 use anyhow::Result;
 use askama::Template;
-use common::YearSeason;
+use common::{Format, YearSeason};
 
 #[actix_web::get("/season/{name}")]
 pub async fn get(state: Data<State>, name: web::Path<(String,)>) -> impl Responder {
@@ -13,7 +14,7 @@ pub async fn get(state: Data<State>, name: web::Path<(String,)>) -> impl Respond
         Ok(s) => s,
         _ => return HttpResponse::NotFound().finish(),
     };
-    match season_(state, season).await {
+    match render(&state, season).await {
         Ok(b) => HttpResponse::Ok().content_type("text/html").body(b),
         Err(e) => {
             log::error!(
@@ -32,24 +33,118 @@ struct Tpl<'a> {
     letters: &'a [Letter],
     json: &'a str,
     season_name: String,
+    season_link: String,
     prev_season_link: String,
     next_season_link: String,
     prev_season_name: String,
     next_season_name: String,
+    og: OpenGraph<'a>,
 }
 
-async fn season_(state: Data<State>, season: YearSeason) -> Result<String> {
+pub async fn render(state: &State, season: YearSeason) -> Result<String> {
     let db = state.pg.borrow().await?;
     let rows = db.query(&db.t.season.stmt, &[&season.to_db()]).await?;
-    let show_list = show_list_from_rows!(db.t.season, &rows);
+    let show_list = show_list_from_rows!(db.t.season, &rows, inferred);
+    let season_name = season.display_name();
+    let og = OpenGraph {
+        base_url: &state.global.base_url,
+        path: format!("/season/{}", season.to_url_str()),
+        title: &season_name,
+        description: format!("Anime torrents of the {} season", season_name),
+        image: None,
+    };
     let tpl = Tpl {
         letters: &show_list.letters,
         json: &show_list.json,
-        season_name: season.display_name(),
+        season_name: season_name.clone(),
+        season_link: season.to_url_str(),
+        prev_season_link: season.prev().to_url_str(),
+        next_season_link: season.next().to_url_str(),
+        prev_season_name: season.prev().display_name(),
+        next_season_name: season.next().display_name(),
+        og,
+    };
+    Ok(tpl.render()?)
+}
+
+/// The shows of a season as a grid of covers, ordered by their number of torrents
+#[actix_web::get("/season/{name}/grid")]
+pub async fn get_grid(state: Data<State>, name: web::Path<(String,)>) -> impl Responder {
+    let season = match YearSeason::from_url_str(&name.0.0) {
+        Ok(s) => s,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+    match render_grid(&state, season).await {
+        Ok(b) => HttpResponse::Ok().content_type("text/html").body(b),
+        Err(e) => {
+            log::error!(
+                "An error occurred while trying to retrieve the grid of season {}: {:#}",
+                season.display_name(),
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "season_grid.html")]
+struct GridTpl<'a> {
+    shows: Vec<GridShow>,
+    season_name: String,
+    season_link: String,
+    prev_season_link: String,
+    next_season_link: String,
+    prev_season_name: String,
+    next_season_name: String,
+    og: OpenGraph<'a>,
+}
+
+struct GridShow {
+    show_id: i64,
+    romaji: String,
+    english: Option<String>,
+    format: Format,
+    episodes: Option<i32>,
+    has_cover: bool,
+    inferred: bool,
+    torrents: i64,
+}
+
+async fn render_grid(state: &State, season: YearSeason) -> Result<String> {
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.season_grid;
+    let rows = db.query(&stmt.stmt, &[&season.to_db()]).await?;
+    let mut shows = Vec::with_capacity(rows.len());
+    for row in &rows {
+        shows.push(GridShow {
+            show_id: row.get(stmt.show_id),
+            romaji: row.get(stmt.romaji),
+            english: row.get(stmt.english),
+            format: Format::from_db(row.get(stmt.show_format))?,
+            episodes: row.get(stmt.episodes),
+            has_cover: row.get(stmt.has_cover),
+            inferred: row.get(stmt.inferred),
+            torrents: row.get(stmt.torrents),
+        });
+    }
+    let season_name = season.display_name();
+    let og = OpenGraph {
+        base_url: &state.global.base_url,
+        path: format!("/season/{}/grid", season.to_url_str()),
+        title: &season_name,
+        description: format!("Chart of the {} anime season", season_name),
+        image: None,
+    };
+    let tpl = GridTpl {
+        shows,
+        season_name: season_name.clone(),
+        season_link: season.to_url_str(),
         prev_season_link: season.prev().to_url_str(),
         next_season_link: season.next().to_url_str(),
         prev_season_name: season.prev().display_name(),
         next_season_name: season.next().display_name(),
+        og,
     };
     Ok(tpl.render()?)
 }
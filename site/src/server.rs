@@ -0,0 +1,233 @@
+//! The http listeners
+//!
+//! `HttpServer` of actix-web hands connections directly to the http dispatcher. This
+//! module builds the equivalent server by hand so that the PROXY header can be read
+//! and TLS can be terminated per listener before the first request.
+
+use crate::{
+    config::{AddrType, Config, Listener, ListenerRole, Tls},
+    proxy_protocol,
+    proxy_protocol::Prefixed,
+};
+use actix_http::{
+    body::MessageBody, error::DispatchError, HttpService, Protocol, Request, Response,
+};
+use actix_server::Server;
+use actix_service::{
+    fn_service, map_config, pipeline_factory, IntoServiceFactory, Service, ServiceFactory,
+};
+use actix_web::{dev::AppConfig, Error};
+use common::config::LiveConfig;
+use futures::{
+    future::{select, Either},
+    pin_mut,
+};
+use std::{
+    fmt::Debug, fs::File, io, io::BufReader, net::SocketAddr, path::Path, sync::Arc,
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{
+    rustls::{
+        internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys},
+        NoClientAuth, ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+/// How long a client may take to send the PROXY header and to complete the TLS
+/// handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+trait Io: AsyncRead + AsyncWrite + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Io for T {}
+
+/// How connections of a listener are set up before they are handed to the http service
+#[derive(Clone)]
+struct Setup {
+    proxy_protocol: bool,
+    tls: Option<TlsAcceptor>,
+}
+
+/// Binds to all listeners and starts the server
+///
+/// The argument of `factory` is whether the app should serve the internal endpoints
+/// (/metrics and /admin). This is true for internal listeners and, if there are no
+/// internal listeners, for all listeners.
+pub fn serve<F, I, S, B>(listeners: &[Listener], factory: F) -> io::Result<Server>
+where
+    F: Fn(bool) -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S>,
+    S: ServiceFactory<Config = AppConfig, Request = Request>,
+    S::Error: Into<Error> + 'static,
+    S::InitError: Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let have_internal = listeners.iter().any(|l| l.role == ListenerRole::Internal);
+    let mut builder = Server::build();
+    for listener in listeners {
+        log::info!("binding to {}", listener);
+        let name = format!("magnets-{}", listener.addr);
+        let internal = listener.role == ListenerRole::Internal || !have_internal;
+        let setup = Setup {
+            proxy_protocol: listener.proxy_protocol,
+            tls: match &listener.tls {
+                Some(tls) => Some(load_tls(tls)?),
+                _ => None,
+            },
+        };
+        let factory = factory.clone();
+        builder = match &listener.addr {
+            &AddrType::Ip(addr) => builder.bind(name, addr, move || {
+                let setup = setup.clone();
+                pipeline_factory(fn_service(move |io: actix_rt::net::TcpStream| {
+                    let peer = io.peer_addr().ok();
+                    accept(io, peer, setup.clone())
+                }))
+                .and_then(
+                    HttpService::build()
+                        .local_addr(addr)
+                        .finish(map_config(factory(internal), |_| AppConfig::default())),
+                )
+            })?,
+            #[cfg(unix)]
+            AddrType::Uds(path) => builder.bind_uds(name, path, move || {
+                let setup = setup.clone();
+                pipeline_factory(fn_service(move |io: actix_rt::net::UnixStream| {
+                    accept(io, None, setup.clone())
+                }))
+                .and_then(
+                    HttpService::build()
+                        .finish(map_config(factory(internal), |_| AppConfig::default())),
+                )
+            })?,
+            #[cfg(not(unix))]
+            AddrType::Uds(_) => {
+                log::warn!("skipping uds address");
+                builder
+            }
+        };
+    }
+    Ok(builder.run())
+}
+
+/// Serves on `http.listen_addr` and restarts the server when it changes on reload
+///
+/// The old server is stopped gracefully before the new listeners are bound, so
+/// connections are refused for a moment. If the new listeners cannot be bound, the
+/// previous ones are bound again.
+pub async fn serve_reloading<F, I, S, B>(
+    live_config: &LiveConfig<Config>,
+    factory: F,
+) -> io::Result<()>
+where
+    F: Fn(bool) -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S>,
+    S: ServiceFactory<Config = AppConfig, Request = Request>,
+    S::Error: Into<Error> + 'static,
+    S::InitError: Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let mut listeners = live_config.current().http.listen_addr.clone();
+    let mut server = serve(&listeners, factory.clone())?;
+    loop {
+        let changed = listen_addr_changed(live_config, &listeners);
+        pin_mut!(changed);
+        let new = match select(server.clone(), changed).await {
+            Either::Left((res, _)) => return res,
+            Either::Right((new, _)) => new,
+        };
+        log::info!("http.listen_addr changed, restarting the server");
+        server.stop(true).await;
+        server = match serve(&new, factory.clone()) {
+            Ok(server) => {
+                listeners = new;
+                server
+            }
+            Err(e) => {
+                log::error!("cannot bind to the new listen_addr: {}", e);
+                serve(&listeners, factory.clone())?
+            }
+        };
+    }
+}
+
+/// Returns the listeners once a reload has changed them
+async fn listen_addr_changed(
+    live_config: &LiveConfig<Config>,
+    listeners: &[Listener],
+) -> Vec<Listener> {
+    loop {
+        let generation = live_config.generation();
+        let config = live_config.current();
+        if config.http.listen_addr != listeners {
+            return config.http.listen_addr.clone();
+        }
+        live_config.reloaded(generation).await;
+    }
+}
+
+fn load_tls(tls: &Tls) -> io::Result<TlsAcceptor> {
+    fn open(path: &Path) -> io::Result<BufReader<File>> {
+        File::open(path).map(BufReader::new).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot open {}: {}", path.display(), e))
+        })
+    }
+    fn invalid(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} contains no valid PEM data", path.display()),
+        )
+    }
+    let chain =
+        certs(&mut open(&tls.certificate)?).map_err(|_| invalid(&tls.certificate))?;
+    let mut keys = pkcs8_private_keys(&mut open(&tls.private_key)?)
+        .map_err(|_| invalid(&tls.private_key))?;
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut open(&tls.private_key)?)
+            .map_err(|_| invalid(&tls.private_key))?;
+    }
+    let key = keys.pop().ok_or_else(|| invalid(&tls.private_key))?;
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config
+        .set_single_cert(chain, key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Performs the setup of the listener and returns the connection for the http service
+///
+/// If the PROXY protocol is enabled, `peer` is replaced by the source address in the
+/// header.
+async fn accept<T: Io + 'static>(
+    io: T,
+    peer: Option<SocketAddr>,
+    setup: Setup,
+) -> Result<(Box<dyn Io>, Protocol, Option<SocketAddr>), DispatchError> {
+    let handshake = async {
+        let (io, peer) = if setup.proxy_protocol {
+            let (header, io) = proxy_protocol::read_header(io).await?;
+            (io, header.source)
+        } else {
+            (Prefixed::new(vec![], io), peer)
+        };
+        let io: Box<dyn Io> = match &setup.tls {
+            Some(acceptor) => Box::new(acceptor.accept(io).await?),
+            _ => Box::new(io),
+        };
+        Ok::<_, io::Error>((io, Protocol::Http1, peer))
+    };
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(res)) => Ok(res),
+        Ok(Err(e)) => {
+            log::debug!("could not set up a connection: {}", e);
+            Err(DispatchError::Io(e))
+        }
+        Err(_) => Err(DispatchError::Io(io::ErrorKind::TimedOut.into())),
+    }
+}
@@ -1,19 +1,30 @@
 use crate::{
+    missing::find_missing,
+    og::OpenGraph,
+    repo::{
+        listing::{TorrentFilter, TorrentQuery},
+        ShowName, ShowRepo, TorrentRepo,
+    },
     state::State,
-    text::{NotFound, SqlParams, TEXT_HTML},
-    torrent_list::{torrent_list_from_rows, Day},
+    text::{Gone, Moved, NotFound, TEXT_HTML},
+    torrent_list::{torrent_list, Day},
 };
 use actix_web::{
+    http::header::LOCATION,
     web,
     web::{Data, Query},
     HttpResponse, Responder,
 };
 use anyhow::Result;
 use askama::Template;
-use common::{Format, ShowNameType, YearSeason};
+use chrono::{DateTime, Utc};
+use common::{
+    path_encode, query_encode, AudioCodec, Format, Resolution, ShowNameType, VideoCodec,
+    YearSeason, AUDIO_CODECS, RESOLUTIONS, VIDEO_CODECS,
+};
+use itertools::Itertools;
 use serde::Deserialize;
 use std::ops::Deref;
-use tokio_postgres::types::Json;
 
 #[actix_web::get("/show/{show_id}")]
 pub async fn get(
@@ -26,6 +37,12 @@ pub async fn get(
         Err(e) => {
             if e.is::<NotFound>() {
                 HttpResponse::NotFound().finish()
+            } else if e.is::<Gone>() {
+                HttpResponse::Gone().finish()
+            } else if let Some(Moved(location)) = e.downcast_ref() {
+                HttpResponse::MovedPermanently()
+                    .header(LOCATION, location.as_str())
+                    .finish()
             } else {
                 log::error!(
                     "An error occurred while trying to retrieve show {}: {:#}",
@@ -38,17 +55,13 @@ pub async fn get(
     }
 }
 
-#[derive(Deserialize)]
-struct Name {
-    name: String,
-    show_name_type: i32,
-}
-
 #[derive(Template)]
 #[template(path = "show.html")]
 struct Show<'a> {
     show_id: i64,
     anilist_id: i64,
+    mal_id: Option<i64>,
+    has_cover: bool,
     romaji: &'a str,
     english: Option<&'a str>,
     format: &'static str,
@@ -56,6 +69,40 @@ struct Show<'a> {
     days: &'a [Day<'a>],
     last: Option<i64>,
     first: bool,
+    missing_episodes: Option<String>,
+    /// The episodes that have a matched torrent in ascending order
+    episodes: Vec<EpisodeLink>,
+    latest_episode: Option<i32>,
+    batches_only: bool,
+    episode: Option<i32>,
+    format_filters: Vec<FormatFilter>,
+    /// The name and the url of the page of the selected release group
+    group: Option<(String, String)>,
+    /// Whether any filter is applied to the torrent list
+    filtered: bool,
+    base: String,
+    og: OpenGraph<'a>,
+}
+
+struct EpisodeLink {
+    number: i32,
+    /// Whether the list is filtered by this episode
+    selected: bool,
+}
+
+/// A select of the form that filters the torrents by their release group or by the
+/// formats in their titles
+struct FormatFilter {
+    name: &'static str,
+    /// The label of the empty option
+    any: &'static str,
+    options: Vec<FormatOption>,
+}
+
+struct FormatOption {
+    value: String,
+    label: String,
+    selected: bool,
 }
 
 mod filters {
@@ -66,6 +113,34 @@ mod filters {
 pub struct QueryParams {
     #[serde(rename = "a", default = "i64::max_value")]
     after: i64,
+    /// Only list batches
+    #[serde(default)]
+    batches: bool,
+    /// Only list the torrents of this episode, including the batches that contain it
+    episode: Option<i32>,
+    /// Only list torrents whose title advertises this resolution, e.g. `1080p`
+    resolution: Option<String>,
+    /// e.g. `h265`
+    video_codec: Option<String>,
+    /// e.g. `flac`
+    audio_codec: Option<String>,
+    /// Only list the torrents of this release group
+    group: Option<String>,
+}
+
+impl Default for QueryParams {
+    /// The first page with all torrents
+    fn default() -> Self {
+        Self {
+            after: i64::MAX,
+            batches: false,
+            episode: None,
+            resolution: None,
+            video_codec: None,
+            audio_codec: None,
+            group: None,
+        }
+    }
 }
 
 async fn process(state: &State, id: &str, query: QueryParams) -> Result<String> {
@@ -73,48 +148,467 @@ async fn process(state: &State, id: &str, query: QueryParams) -> Result<String>
         Ok(i) => i,
         _ => return Err(NotFound.into()),
     };
-    let db = state.pg.borrow().await?;
-    let (show_info_row, show_torrents_rows) = {
-        let p1: SqlParams = &[&show_id];
-        let a = db.query_opt(&db.t.show_info.stmt, p1);
-        let p2: SqlParams = &[&show_id, &query.after];
-        let b = db.query(&db.t.show_torrents.stmt, p2);
-        futures::join!(a, b)
-    };
-    let show_info_row = match show_info_row? {
-        Some(r) => r,
-        _ => return Err(NotFound.into()),
-    };
-    let show_torrents_rows = show_torrents_rows?;
-    let (last, days) = torrent_list_from_rows!(db.t.show_torrents, &show_torrents_rows);
-    let names: Json<Vec<Name>> = show_info_row.get(db.t.show_info.names);
+    let repo = state.repo().await?;
+    let now = state.global.clock.now();
+    render(&repo, &state.global.base_url, now, show_id, query).await
+}
+
+/// Returns the romaji and the english name of a show
+fn select_names(names: &[ShowName]) -> (&str, Option<&str>) {
     let mut romaji = "";
     let mut english = None;
-    for name in &names.0 {
+    for name in names {
         if name.show_name_type == ShowNameType::ROMAJI {
             romaji = &name.name;
         } else if name.show_name_type == ShowNameType::ENGLISH {
             english = Some(name.name.deref());
         }
     }
+    (romaji, english)
+}
+
+/// Returns the description of the show in the link preview
+fn describe(format: &str, season: Option<&str>, latest: Option<&str>) -> String {
+    let mut description = format.to_string();
+    if let Some(season) = season {
+        description.push_str(" · ");
+        description.push_str(season);
+    }
+    if let Some(latest) = latest {
+        description.push_str(" · Latest: ");
+        description.push_str(latest);
+    }
+    description
+}
+
+/// Parses a format filter. An empty value does not filter.
+fn parse_format<T>(
+    value: &Option<String>,
+    parse: fn(&str) -> Option<T>,
+) -> Result<Option<T>> {
+    match value.as_deref() {
+        None | Some("") => Ok(None),
+        Some(s) => match parse(s) {
+            Some(v) => Ok(Some(v)),
+            _ => Err(NotFound.into()),
+        },
+    }
+}
+
+/// Returns the selects of the form. `groups` are the release groups of the show.
+fn format_filters(filter: &TorrentFilter, groups: &[String]) -> Vec<FormatFilter> {
+    let mut group_options: Vec<_> = groups
+        .iter()
+        .map(|g| FormatOption {
+            value: g.clone(),
+            label: g.clone(),
+            selected: filter.group.as_ref() == Some(g),
+        })
+        .collect();
+    if let Some(group) = &filter.group {
+        if !groups.contains(group) {
+            group_options.push(FormatOption {
+                value: group.clone(),
+                label: group.clone(),
+                selected: true,
+            });
+        }
+    }
+    vec![
+        FormatFilter {
+            name: "group",
+            any: "Any release group",
+            options: group_options,
+        },
+        FormatFilter {
+            name: "resolution",
+            any: "Any resolution",
+            options: RESOLUTIONS
+                .iter()
+                .map(|&r| FormatOption {
+                    value: r.as_str().to_string(),
+                    label: r.as_str().to_string(),
+                    selected: filter.resolution == Some(r),
+                })
+                .collect(),
+        },
+        FormatFilter {
+            name: "video_codec",
+            any: "Any video codec",
+            options: VIDEO_CODECS
+                .iter()
+                .map(|&c| FormatOption {
+                    value: c.as_api_str().to_string(),
+                    label: c.as_str().to_string(),
+                    selected: filter.video_codec == Some(c),
+                })
+                .collect(),
+        },
+        FormatFilter {
+            name: "audio_codec",
+            any: "Any audio codec",
+            options: AUDIO_CODECS
+                .iter()
+                .map(|&c| FormatOption {
+                    value: c.as_api_str().to_string(),
+                    label: c.as_str().to_string(),
+                    selected: filter.audio_codec == Some(c),
+                })
+                .collect(),
+        },
+    ]
+}
+
+/// Returns the url of the first page of the torrent list
+fn base_url_of(show_id: i64, filter: &TorrentFilter) -> String {
+    let mut params = vec![];
+    if filter.batch == Some(true) {
+        params.push("batches=true".to_string());
+    }
+    if let Some(episode) = filter.episode {
+        params.push(format!("episode={}", episode));
+    }
+    if let Some(resolution) = filter.resolution {
+        params.push(format!("resolution={}", resolution.as_str()));
+    }
+    if let Some(codec) = filter.video_codec {
+        params.push(format!("video_codec={}", codec.as_api_str()));
+    }
+    if let Some(codec) = filter.audio_codec {
+        params.push(format!("audio_codec={}", codec.as_api_str()));
+    }
+    if let Some(group) = &filter.group {
+        params.push(format!("group={}", query_encode(group)));
+    }
+    match params.is_empty() {
+        true => format!("/show/{}", show_id),
+        false => format!("/show/{}?{}", show_id, params.join("&")),
+    }
+}
+
+pub async fn render(
+    repo: &(impl ShowRepo + TorrentRepo),
+    base_url: &str,
+    now: DateTime<Utc>,
+    show_id: i64,
+    query: QueryParams,
+) -> Result<String> {
+    let torrent_query = TorrentQuery {
+        filter: TorrentFilter {
+            show_id: Some(show_id),
+            batch: match query.batches {
+                true => Some(true),
+                false => None,
+            },
+            episode: query.episode,
+            resolution: parse_format(&query.resolution, Resolution::parse)?,
+            video_codec: parse_format(&query.video_codec, VideoCodec::parse)?,
+            audio_codec: parse_format(&query.audio_codec, AudioCodec::parse)?,
+            group: query.group.filter(|g| !g.is_empty()),
+            ..Default::default()
+        },
+        after: query.after,
+    };
+    let (show, torrents, counts, groups) = futures::join!(
+        repo.show(show_id),
+        repo.torrents(&torrent_query),
+        repo.episode_counts(show_id, now),
+        repo.release_groups(show_id),
+    );
+    let show = match show? {
+        Some(s) => s,
+        _ => return Err(NotFound.into()),
+    };
+    if show.removed {
+        return Err(match show.merged_into {
+            Some(into) => Moved(format!("/show/{}", into)).into(),
+            _ => Gone.into(),
+        });
+    }
+    let torrents = torrents?;
+    let counts = counts?;
+    let groups = groups?;
+    let mut matched = counts
+        .as_ref()
+        .map(|c| c.matched.clone())
+        .unwrap_or_default();
+    matched.sort_unstable();
+    matched.dedup();
+    let (last, days) = torrent_list(&torrents);
+    let (romaji, english) = select_names(&show.names);
+    let format = Format::from_db(show.show_format)?.as_str();
+    let season = match show.season {
+        None => None,
+        Some(ys) => {
+            let season = YearSeason::from_db(ys)?;
+            Some((season.display_name(), season.to_url_str()))
+        }
+    };
+    let og = OpenGraph {
+        base_url,
+        path: format!("/show/{}", show_id),
+        title: romaji,
+        description: describe(
+            format,
+            season.as_ref().map(|s| &*s.0),
+            torrents.first().map(|t| &*t.title),
+        ),
+        image: match show.has_cover {
+            true => Some(format!("/img/cover/{}/medium", show_id)),
+            false => None,
+        },
+    };
+    let base = base_url_of(show_id, &torrent_query.filter);
     let show = Show {
-        show_id,
-        anilist_id: show_info_row.get(db.t.show_info.anilist_id),
+        show_id: show.show_id,
+        anilist_id: show.anilist_id,
+        mal_id: show.mal_id,
+        has_cover: show.has_cover,
         romaji,
         english,
-        format: Format::from_db(show_info_row.get(db.t.show_info.show_format))?.as_str(),
-        season: {
-            match show_info_row.get(db.t.show_info.season) {
-                None => None,
-                Some(ys) => {
-                    let season = YearSeason::from_db(ys)?;
-                    Some((season.display_name(), season.to_url_str()))
-                }
-            }
-        },
+        format,
+        season,
         days: &days,
         last,
         first: query.after == i64::MAX,
+        missing_episodes: match counts.as_ref().and_then(find_missing) {
+            Some(m) if !m.is_empty() => Some(m.iter().join(", ")),
+            _ => None,
+        },
+        latest_episode: matched.last().copied(),
+        episodes: matched
+            .iter()
+            .map(|&number| EpisodeLink {
+                number,
+                selected: query.episode == Some(number),
+            })
+            .collect(),
+        batches_only: query.batches,
+        episode: query.episode,
+        format_filters: format_filters(&torrent_query.filter, &groups),
+        group: torrent_query
+            .filter
+            .group
+            .as_ref()
+            .map(|g| (g.clone(), format!("/group/{}", path_encode(g)))),
+        filtered: base != format!("/show/{}", show_id),
+        base,
+        og,
     };
     Ok(show.render()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{mock::MockRepo, EpisodeCounts, ShowRecord};
+    use chrono::TimeZone;
+    use futures::executor::block_on;
+
+    const URL: &str = "https://magnets.moe";
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+    }
+
+    fn repo() -> MockRepo {
+        let mut repo = MockRepo::default();
+        repo.shows.push(ShowRecord {
+            show_id: 1,
+            anilist_id: 101,
+            season: None,
+            show_format: 1,
+            has_cover: false,
+            removed: false,
+            merged_into: None,
+            mal_id: None,
+            names: vec![
+                ShowName {
+                    name: "Attack on Titan".to_string(),
+                    show_name_type: ShowNameType::ENGLISH,
+                },
+                ShowName {
+                    name: "Shingeki no Kyojin".to_string(),
+                    show_name_type: ShowNameType::ROMAJI,
+                },
+            ],
+        });
+        let torrents = (1..=150)
+            .map(|i| MockRepo::torrent_record(i, &format!("[Group] Show - {:02}", i)))
+            .collect();
+        repo.show_torrents.insert(1, torrents);
+        repo.episode_counts.insert(
+            1,
+            EpisodeCounts {
+                episodes: Some(3),
+                aired: None,
+                matched: vec![2],
+            },
+        );
+        repo
+    }
+
+    fn query(after: i64) -> QueryParams {
+        QueryParams {
+            after,
+            ..QueryParams::default()
+        }
+    }
+
+    #[test]
+    fn unknown_show_is_not_found() {
+        let err = block_on(render(&repo(), URL, now(), 2, query(i64::MAX))).unwrap_err();
+        assert!(err.is::<NotFound>());
+    }
+
+    #[test]
+    fn removed_show_is_gone_or_moved() {
+        let mut repo = repo();
+        repo.shows[0].removed = true;
+        let err = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap_err();
+        assert!(err.is::<Gone>());
+        repo.shows[0].merged_into = Some(2);
+        let err = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap_err();
+        assert_eq!(err.downcast_ref::<Moved>().unwrap().0, "/show/2");
+    }
+
+    #[test]
+    fn selects_names_by_type() {
+        let repo = repo();
+        let (romaji, english) = select_names(&repo.shows[0].names);
+        assert_eq!(romaji, "Shingeki no Kyojin");
+        assert_eq!(english, Some("Attack on Titan"));
+    }
+
+    #[test]
+    fn paginates_torrents() {
+        let first = block_on(render(&repo(), URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(first.contains("?a=51"));
+        assert!(first.contains("Show - 150"));
+        assert!(!first.contains("Show - 50"));
+        let second = block_on(render(&repo(), URL, now(), 1, query(51))).unwrap();
+        assert!(!second.contains("?a="));
+        assert!(second.contains("Show - 50"));
+    }
+
+    #[test]
+    fn filters_batches() {
+        let mut repo = repo();
+        let torrents = repo.show_torrents.get_mut(&1).unwrap();
+        torrents[0].title = "[Group] Show (01-12)".to_string();
+        torrents[0].batch = true;
+        let query = QueryParams {
+            batches: true,
+            ..QueryParams::default()
+        };
+        let page = block_on(render(&repo, URL, now(), 1, query)).unwrap();
+        assert!(page.contains("Show (01-12)"));
+        assert!(!page.contains("Show - 150"));
+        assert!(!page.contains("?batches=true&a="));
+    }
+
+    #[test]
+    fn links_to_myanimelist() {
+        let mut repo = repo();
+        let page = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(!page.contains("myanimelist.net"));
+        repo.shows[0].mal_id = Some(16498);
+        let page = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(page.contains(r#"<a href="https://myanimelist.net/anime/16498">"#));
+    }
+
+    #[test]
+    fn has_link_preview() {
+        let page = block_on(render(&repo(), URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(
+            page.contains(r#"<meta property="og:title" content="Shingeki no Kyojin">"#)
+        );
+        assert!(page.contains("Latest: [Group] Show - 150"));
+        assert!(!page.contains("og:image"));
+    }
+
+    #[test]
+    fn lists_missing_episodes() {
+        let page = block_on(render(&repo(), URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(page.contains("1, 3"));
+    }
+
+    #[test]
+    fn filters_episodes() {
+        let mut repo = repo();
+        repo.episodes = (1..=150).map(|i| (i, i as i32)).collect();
+        let torrents = repo.show_torrents.get_mut(&1).unwrap();
+        torrents[0].title = "[Group] Show (01-12)".to_string();
+        torrents[0].batch = true;
+        repo.episode_ends.insert(1, 12);
+        let query = QueryParams {
+            episode: Some(7),
+            ..QueryParams::default()
+        };
+        let page = block_on(render(&repo, URL, now(), 1, query)).unwrap();
+        assert!(page.contains("Show - 07"));
+        assert!(page.contains("Show (01-12)"));
+        assert!(!page.contains("Show - 08"));
+    }
+
+    #[test]
+    fn links_to_episodes() {
+        let mut repo = repo();
+        repo.episode_counts.get_mut(&1).unwrap().matched = vec![3, 2];
+        let page = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(page.contains(r#"<a href="/show/1?episode=2">2</a>"#));
+        assert!(page.contains(r#"<a href="/show/1?episode=3">Latest episode</a>"#));
+    }
+
+    #[test]
+    fn filters_formats() {
+        let mut repo = repo();
+        let torrents = repo.show_torrents.get_mut(&1).unwrap();
+        torrents[0].title = "[Group] Show - 01 [1080p HEVC]".to_string();
+        torrents[1].title = "[Group] Show - 02 [720p HEVC]".to_string();
+        let query = QueryParams {
+            resolution: Some("1080p".to_string()),
+            video_codec: Some("h265".to_string()),
+            audio_codec: Some("".to_string()),
+            ..QueryParams::default()
+        };
+        let page = block_on(render(&repo, URL, now(), 1, query)).unwrap();
+        assert!(page.contains("Show - 01 [1080p HEVC]"));
+        assert!(!page.contains("Show - 02"));
+        assert!(page.contains(r#"<option value="h265" selected>"#));
+        let query = QueryParams {
+            resolution: Some("1440p".to_string()),
+            ..QueryParams::default()
+        };
+        let err = block_on(render(&repo, URL, now(), 1, query)).unwrap_err();
+        assert!(err.is::<NotFound>());
+    }
+
+    #[test]
+    fn filters_groups() {
+        let mut repo = repo();
+        let torrents = repo.show_torrents.get_mut(&1).unwrap();
+        torrents[0].title = "[Other Group] Show - 01".to_string();
+        let page = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(page.contains(r#"<option value="Group">Group</option>"#));
+        assert!(page.contains(r#"<option value="Other Group">Other Group</option>"#));
+        let query = QueryParams {
+            group: Some("Other Group".to_string()),
+            ..QueryParams::default()
+        };
+        let page = block_on(render(&repo, URL, now(), 1, query)).unwrap();
+        assert!(page.contains("[Other Group] Show - 01"));
+        assert!(!page.contains("Show - 150"));
+        assert!(page.contains("Other%20Group\">All torrents of Other Group</a>"));
+    }
+
+    #[test]
+    fn marks_recommended_torrents() {
+        let mut repo = repo();
+        let page = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(!page.contains("Recommended"));
+        repo.show_torrents.get_mut(&1).unwrap()[149].recommended = true;
+        let page = block_on(render(&repo, URL, now(), 1, query(i64::MAX))).unwrap();
+        assert!(page.contains(">Recommended</span>"));
+    }
+}
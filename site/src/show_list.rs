@@ -1,10 +1,8 @@
-use crate::text::searchable_text;
-use common::ShowNameType;
+use common::{textnorm, ShowNameType};
 use isnt::std_1::string::IsntStringExt;
 use serde::Serialize;
-use std::{borrow::Cow, collections::HashMap, mem};
+use std::{collections::HashMap, mem};
 use tokio_postgres::Row;
-use unicode_normalization::UnicodeNormalization;
 
 #[derive(Serialize)]
 pub struct Show {
@@ -13,6 +11,8 @@ pub struct Show {
     pub display_name: String,
     pub display_name_is_romaji: bool,
     pub add_name: Option<String>,
+    /// Whether the season of the show has been inferred by us
+    pub inferred: bool,
 }
 
 #[derive(Serialize)]
@@ -38,23 +38,30 @@ pub struct ShowList {
     pub json: String,
 }
 
-fn map_rows(
-    rows: &[Row],
-    show_id_idx: usize,
-    name_idx: usize,
-    show_name_type_idx: usize,
-) -> HashMap<i64, Show> {
+/// A name of a show in a show list
+pub struct ShowListName {
+    pub show_id: i64,
+    pub name: String,
+    pub show_name_type: i32,
+    pub inferred: bool,
+}
+
+fn map_names(names: impl Iterator<Item = ShowListName>) -> HashMap<i64, Show> {
     let mut shows = HashMap::new();
-    for row in rows {
-        let show_id: i64 = row.get(show_id_idx);
-        let name: String = row.get(name_idx);
-        let ty: i32 = row.get(show_name_type_idx);
+    for ShowListName {
+        show_id,
+        name,
+        show_name_type: ty,
+        inferred,
+    } in names
+    {
         let show = shows.entry(show_id).or_insert(Show {
             show_id,
             display_name: String::new(),
             display_name_is_romaji: false,
             add_name: None,
             letter: ' ',
+            inferred,
         });
         if !show.display_name_is_romaji {
             let add_name = mem::replace(&mut show.display_name, name);
@@ -71,7 +78,16 @@ fn map_rows(
 
 macro_rules! show_list_from_rows {
     ($stmt:expr, $rows:expr) => {
-        show_list_from_rows($rows, $stmt.show_id, $stmt.name, $stmt.show_name_type)
+        show_list_from_rows($rows, $stmt.show_id, $stmt.name, $stmt.show_name_type, None)
+    };
+    ($stmt:expr, $rows:expr, inferred) => {
+        show_list_from_rows(
+            $rows,
+            $stmt.show_id,
+            $stmt.name,
+            $stmt.show_name_type,
+            Some($stmt.inferred),
+        )
     };
 }
 
@@ -80,17 +96,21 @@ pub fn show_list_from_rows(
     show_id_idx: usize,
     name_idx: usize,
     show_name_type_idx: usize,
+    inferred_idx: Option<usize>,
 ) -> ShowList {
-    let shows = map_rows(rows, show_id_idx, name_idx, show_name_type_idx);
+    show_list(rows.iter().map(|row| ShowListName {
+        show_id: row.get(show_id_idx),
+        name: row.get(name_idx),
+        show_name_type: row.get(show_name_type_idx),
+        inferred: inferred_idx.map(|i| row.get(i)).unwrap_or(false),
+    }))
+}
+
+pub fn show_list(names: impl Iterator<Item = ShowListName>) -> ShowList {
+    let shows = map_names(names);
     let mut letters = HashMap::new();
     for (_, mut show) in shows {
-        let display_name = &show.display_name;
-        let display_name = if unicode_normalization::is_nfkd(display_name) {
-            Cow::Borrowed(display_name)
-        } else {
-            Cow::Owned(display_name.nfkd().collect())
-        };
-        let letter = display_name.chars().next().unwrap().to_ascii_lowercase();
+        let letter = textnorm::display_letter(&show.display_name).unwrap();
         show.letter = letter;
         letters.entry(letter).or_insert(vec![]).push(show);
     }
@@ -117,9 +137,9 @@ pub fn show_list_from_rows(
                 .map(|s| JsonShow {
                     element_id: s.show_id,
                     names: {
-                        let mut res = vec![searchable_text(&s.display_name)];
+                        let mut res = vec![textnorm::search_fold(&s.display_name)];
                         if let Some(ref n) = s.add_name {
-                            res.push(searchable_text(n));
+                            res.push(textnorm::search_fold(n));
                         }
                         res
                     },
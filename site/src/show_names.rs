@@ -0,0 +1,119 @@
+use crate::repo::{ShowNames, ShowRepo};
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The cache is cleared once it would contain more than this many shows
+const CAPACITY: usize = 10_000;
+
+/// A read-through cache of the romaji and english names of shows
+///
+/// Names rarely change. The processor notifies us when they do, see [crate::notify].
+pub struct ShowNameCache {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    names: HashMap<i64, Arc<ShowNames>>,
+    /// Incremented whenever the cache is invalidated
+    generation: u64,
+}
+
+impl ShowNameCache {
+    pub fn new() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+
+    /// Returns the names of the shows, loading the missing ones from `repo`
+    pub async fn get(
+        &self,
+        repo: &impl ShowRepo,
+        show_ids: &[i64],
+    ) -> Result<HashMap<i64, Arc<ShowNames>>> {
+        let mut res = HashMap::new();
+        let mut missing = vec![];
+        let generation = {
+            let inner = self.inner.lock().unwrap();
+            for &show_id in show_ids {
+                match inner.names.get(&show_id) {
+                    Some(names) => {
+                        res.insert(show_id, names.clone());
+                    }
+                    _ => missing.push(show_id),
+                }
+            }
+            inner.generation
+        };
+        if missing.is_empty() {
+            return Ok(res);
+        }
+        let loaded = repo.show_names(&missing).await?;
+        let mut inner = self.inner.lock().unwrap();
+        // Don't cache names that might have been loaded before an invalidation
+        let cache = inner.generation == generation;
+        if cache && inner.names.len() + loaded.len() > CAPACITY {
+            inner.names.clear();
+        }
+        for (show_id, names) in loaded {
+            let names = Arc::new(names);
+            if cache {
+                inner.names.insert(show_id, names.clone());
+            }
+            res.insert(show_id, names);
+        }
+        Ok(res)
+    }
+
+    pub fn invalidate(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.names.clear();
+        inner.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{mock::MockRepo, ShowName, ShowRecord};
+    use common::ShowNameType;
+    use futures::executor::block_on;
+
+    fn repo(name: &str) -> MockRepo {
+        let mut repo = MockRepo::default();
+        repo.shows.push(ShowRecord {
+            show_id: 1,
+            anilist_id: 101,
+            season: None,
+            show_format: 1,
+            has_cover: false,
+            removed: false,
+            merged_into: None,
+            mal_id: None,
+            names: vec![ShowName {
+                name: name.to_string(),
+                show_name_type: ShowNameType::ROMAJI,
+            }],
+        });
+        repo
+    }
+
+    #[test]
+    fn reads_through_until_invalidated() {
+        let cache = ShowNameCache::new();
+        let names = block_on(cache.get(&repo("Old"), &[1, 2])).unwrap();
+        assert_eq!(names[&1].romaji, "Old");
+        assert!(!names.contains_key(&2));
+
+        let names = block_on(cache.get(&repo("New"), &[1])).unwrap();
+        assert_eq!(names[&1].romaji, "Old");
+
+        cache.invalidate();
+        let names = block_on(cache.get(&repo("New"), &[1])).unwrap();
+        assert_eq!(names[&1].romaji, "New");
+    }
+}
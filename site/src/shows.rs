@@ -1,6 +1,6 @@
 use crate::{
     cache::Cached,
-    show_list::{show_list_from_rows, Letter},
+    show_list::{show_list, Letter, ShowListName},
     state::State,
     text::TEXT_HTML,
 };
@@ -11,7 +11,7 @@ use actix_web::{
 };
 use anyhow::Result;
 use askama::Template;
-use common::pg::PgConnector;
+use common::{pg, pg::PgConnector};
 
 #[actix_web::get("/shows")]
 pub async fn get(state: Data<State>) -> impl Responder {
@@ -41,7 +41,7 @@ async fn shows_(state: Data<State>) -> Result<Cached<Bytes>> {
     state
         .global
         .shows
-        .get(|| load_shows(&state.global.pg_connector))
+        .get(|| render(&state.global.pg_connector))
         .await
 }
 
@@ -52,17 +52,29 @@ struct Shows<'a> {
     json: &'a str,
 }
 
-// language=sql
-common::create_statement!(ShowsStmt, show_id, name, show_name_type; "
-    select show_id, name, show_name_type
-    from magnets.show_name
-    where show_name_type in (1, 2)");
-
-async fn load_shows(connector: &PgConnector) -> Result<Bytes> {
+/// Loads the names from the `show_list` blob which the processor stores after syncing
+/// the shows
+pub async fn render(connector: &PgConnector) -> Result<Bytes> {
     let db = connector.connect().await?;
-    let stmt = ShowsStmt::new(&db).await?;
-    let rows = db.query(&stmt.stmt, &[]).await?;
-    let show_list = show_list_from_rows!(stmt, &rows);
+    // language=sql
+    let row = db
+        .query_opt(
+            "select value from magnets.state_blob where key = 'show_list'",
+            &[],
+        )
+        .await?;
+    // The blob does not exist before the first sync
+    let names: Vec<(i64, String, i32)> = match row {
+        Some(row) => pg::decompress_json(row.get(0))?,
+        _ => vec![],
+    };
+    let show_list =
+        show_list(names.into_iter().map(|(show_id, name, ty)| ShowListName {
+            show_id,
+            name,
+            show_name_type: ty,
+            inferred: false,
+        }));
     let show = Shows {
         letters: &show_list.letters,
         json: &show_list.json,
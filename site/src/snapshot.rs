@@ -0,0 +1,97 @@
+use crate::{index, schedule, season, show, show::QueryParams, shows, state::State};
+use anyhow::{Context, Result};
+use common::YearSeason;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Writes a static snapshot of the site to `out`
+///
+/// The snapshot contains the index, the list of shows, all seasons, the first page of
+/// every show, and the schedule. Every page is written to `{route}/index.html` so that
+/// the links between the pages work when the directory is served by any static file
+/// server. The static assets are copied to `{out}/static`. Covers, torrent pages and
+/// further pages of the torrent lists are not included.
+///
+/// Usage: `site snapshot --out DIR`
+pub async fn snapshot(state: &State, out: &Path) -> Result<()> {
+    write(out, "", index::render(false)?.as_bytes())?;
+    write(
+        out,
+        "shows",
+        &shows::render(&state.global.pg_connector).await?,
+    )?;
+    write(out, "schedule", &schedule::render(state).await?)?;
+
+    let db = state.pg.borrow().await?;
+    // language=sql
+    let stmt = db
+        .prepare_cached(
+            "
+            select show_id, season
+            from magnets.show
+            where removal is null
+            order by show_id",
+        )
+        .await?;
+    let rows = db.query(&stmt, &[]).await?;
+    let mut seasons = BTreeSet::new();
+    for row in &rows {
+        if let Some(season) = row.get::<_, Option<i32>>("season") {
+            seasons.insert(season);
+        }
+    }
+    log::info!("writing {} seasons", seasons.len());
+    for season in seasons {
+        let season = YearSeason::from_db(season)?;
+        let html = season::render(state, season).await?;
+        write(
+            out,
+            &format!("season/{}", season.to_url_str()),
+            html.as_bytes(),
+        )?;
+    }
+    log::info!("writing {} shows", rows.len());
+    let repo = state.repo().await?;
+    for row in &rows {
+        let show_id: i64 = row.get("show_id");
+        let html = show::render(
+            &repo,
+            &state.global.base_url,
+            state.global.clock.now(),
+            show_id,
+            QueryParams::default(),
+        )
+        .await
+        .with_context(|| format!("cannot render show {}", show_id))?;
+        write(out, &format!("show/{}", show_id), html.as_bytes())?;
+    }
+
+    copy_dir(Path::new("static"), &out.join("static"))
+        .context("cannot copy the assets")?;
+    log::info!("wrote the snapshot to {}", out.display());
+    Ok(())
+}
+
+fn write(out: &Path, route: &str, data: &[u8]) -> Result<()> {
+    let dir = out.join(route);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("index.html");
+    fs::write(&path, data).with_context(|| format!("cannot write {}", path.display()))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let to: PathBuf = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            fs::copy(entry.path(), &to)?;
+        }
+    }
+    Ok(())
+}
@@ -1,14 +1,79 @@
-use crate::{cache::Cache, db::Statements};
+use crate::{
+    cache::Cache,
+    client_ip::TrustedProxies,
+    config,
+    db::Statements,
+    hits::HitCounter,
+    maintenance::Maintenance,
+    precompressed::Precompressed,
+    rate_limit::{RateLimiter, RouteLimiters},
+    repo::{sqlite::Sqlite, Repo},
+    schedule_model::WeekStart,
+    show_names::ShowNameCache,
+};
 use actix_web::web::Bytes;
-use common::pg::{PgConnector, PgHolder};
-use std::sync::Arc;
+use anyhow::Result;
+use common::{
+    config::UserAgent,
+    flags::Flags,
+    pg::{PgConnector, PgHolder},
+    time::Clock,
+};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 pub struct Global {
     pub shows: Cache<Bytes>,
+    pub trending: Cache<Bytes>,
+    pub schedule: Cache<Bytes>,
+    pub stats: Cache<Bytes>,
+    pub show_names: ShowNameCache,
     pub pg_connector: PgConnector,
+    pub magnet_limiter: RateLimiter,
+    pub route_limiters: RouteLimiters,
+    pub hits: HitCounter,
+    pub maintenance: Maintenance,
+    pub flags: Flags,
+    pub admin_users: HashMap<String, String>,
+    pub cover_dir: Option<PathBuf>,
+    /// Set if the site serves its repositories from a SQLite file instead of postgres
+    pub sqlite: Option<Arc<Sqlite>>,
+    pub clock: Arc<dyn Clock>,
+    pub week_start: WeekStart,
+    pub health: config::Health,
+    pub trusted_proxies: TrustedProxies,
+    /// The URL under which the site is reachable, without a trailing slash
+    pub base_url: String,
+    /// The user agent of the processor, shown on /contact
+    pub user_agent: UserAgent,
+    pub precompressed: Precompressed,
+    /// Set if users can log in with AniList, see [crate::watchlist]
+    pub anilist_client_id: Option<i64>,
+}
+
+impl Global {
+    /// Drops all cached pages and show names
+    pub async fn flush_caches(&self) {
+        self.show_names.invalidate();
+        self.shows.invalidate().await;
+        self.trending.invalidate().await;
+        self.schedule.invalidate().await;
+        self.stats.invalidate().await;
+    }
 }
 
 pub struct State {
     pub global: Arc<Global>,
     pub pg: Arc<PgHolder<Statements>>,
 }
+
+impl State {
+    /// Returns the repositories backed by the connection of this thread
+    ///
+    /// See [crate::repo].
+    pub async fn repo(&self) -> Result<Arc<dyn Repo>> {
+        if let Some(sqlite) = &self.global.sqlite {
+            return Ok(sqlite.clone());
+        }
+        Ok(self.pg.borrow().await?)
+    }
+}
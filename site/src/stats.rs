@@ -0,0 +1,109 @@
+use crate::{cache::Cached, state::State, text::TEXT_HTML};
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, CACHE_CONTROL},
+    web::{Bytes, Data},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use common::{pg::PgConnector, Source};
+use tokio_postgres::Client;
+
+#[actix_web::get("/stats")]
+pub async fn get(state: Data<State>) -> impl Responder {
+    match stats_(state).await {
+        Ok(b) => {
+            let bytes: Bytes = (*b).clone();
+            let cc = CacheControl(vec![
+                CacheDirective::MaxAge(b.max_age()),
+                CacheDirective::Public,
+            ]);
+            HttpResponse::Ok()
+                .header(CACHE_CONTROL, cc)
+                .content_type(TEXT_HTML)
+                .body(bytes)
+        }
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to retrieve the stats: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn stats_(state: Data<State>) -> Result<Cached<Bytes>> {
+    state
+        .global
+        .stats
+        .get(|| load_stats(&state.global.pg_connector))
+        .await
+}
+
+#[derive(Template)]
+#[template(path = "stats.html")]
+struct Stats {
+    hours: Vec<Hour>,
+}
+
+/// The torrents a source delivered in an hour
+struct Hour {
+    hour: DateTime<Utc>,
+    source: &'static str,
+    torrents: i64,
+    median_lag: String,
+    max_lag: String,
+}
+
+mod filters {
+    pub use crate::text::format_full_time;
+}
+
+// language=sql
+common::create_statement!(IngestionLag, source, hour, torrents, median, max; "
+    select
+        source,
+        date_trunc('hour', created) as hour,
+        count(*) as torrents,
+        percentile_cont(0.5) within group (
+            order by extract(epoch from created - uploaded_at)
+        )::float8 as median,
+        max(extract(epoch from created - uploaded_at))::float8 as max
+    from magnets.torrent_source
+    where created > now() - interval '48 hours' and uploaded_at is not null
+    group by source, hour
+    order by hour desc, source");
+
+/// Prepares the statements of this module
+pub async fn check_statements(db: &Client) -> Result<()> {
+    IngestionLag::new(db).await?;
+    Ok(())
+}
+
+/// Formats a number of seconds as a short duration, e.g. `2m 5s`
+fn format_lag(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as i64;
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+async fn load_stats(connector: &PgConnector) -> Result<Bytes> {
+    let db = connector.connect().await?;
+    let stmt = IngestionLag::new(&db).await?;
+    let mut hours = vec![];
+    for row in db.query(&stmt.stmt, &[]).await? {
+        hours.push(Hour {
+            hour: row.get(stmt.hour),
+            source: Source::from_db(row.get(stmt.source))?.as_str(),
+            torrents: row.get(stmt.torrents),
+            median_lag: format_lag(row.get(stmt.median)),
+            max_lag: format_lag(row.get(stmt.max)),
+        });
+    }
+    Ok(Stats { hours }.render()?.into())
+}
@@ -1,13 +1,16 @@
 use crate::{
+    og::OpenGraph,
+    repo::{ShowNames, ShowRepo, Swarm, TorrentDetails, TorrentRepo},
+    show_names::ShowNameCache,
     state::State,
-    text::{HexFormatter, MagnetFormatter, NotFound, TEXT_HTML},
+    text::{format_full_time, format_size, NotFound, TEXT_HTML},
 };
 use actix_web::{web, web::Data, HttpResponse, Responder};
 use anyhow::Result;
 use askama::Template;
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
-use tokio_postgres::types::Json;
+use common::{HexFormatter, MagnetFormatter};
+use std::sync::Arc;
 
 #[actix_web::get("/torrent/{torrent_id}")]
 pub async fn get(state: Data<State>, id: web::Path<(i64,)>) -> impl Responder {
@@ -38,56 +41,117 @@ struct Torrent<'a> {
     date: DateTime<Utc>,
     magnet_link: MagnetFormatter<'a>,
     hash: HexFormatter<'a>,
-    shows: Vec<Show>,
+    shows: &'a [Show<'a>],
     size: i64,
+    swarm: Option<Swarm>,
+    og: OpenGraph<'a>,
+}
+
+struct Show<'a> {
+    show_id: i64,
+    anilist_id: i64,
+    name: &'a str,
+}
+
+/// A show that a torrent has been matched to
+pub struct TorrentShow {
+    pub show_id: i64,
+    pub anilist_id: i64,
+    pub names: Arc<ShowNames>,
+}
+
+/// Loads a torrent and the names of its shows
+///
+/// Shows whose names are unknown are omitted.
+pub async fn load(
+    repo: &(impl TorrentRepo + ShowRepo),
+    show_names: &ShowNameCache,
+    torrent_id: i64,
+) -> Result<(TorrentDetails, Vec<TorrentShow>)> {
+    let details = match repo.torrent(torrent_id).await? {
+        Some(d) => d,
+        _ => return Err(NotFound.into()),
+    };
+    let names = show_names.get(repo, &details.show_ids).await?;
+    let shows = details
+        .show_ids
+        .iter()
+        .zip(details.anilist_ids.iter())
+        .filter_map(|(show_id, anilist_id)| {
+            names.get(show_id).map(|n| TorrentShow {
+                show_id: *show_id,
+                anilist_id: *anilist_id,
+                names: n.clone(),
+            })
+        })
+        .collect();
+    Ok((details, shows))
 }
 
 mod filters {
     pub use crate::text::{format_full_time, format_size};
 }
 
-#[derive(Deserialize)]
-struct Show {
-    show_id: i64,
-    name: String,
+/// Returns the size, upload date and trusted status for link previews
+pub fn description(details: &TorrentDetails) -> Result<String> {
+    let mut description = format!(
+        "{} · {}",
+        format_size(&details.size)?,
+        format_full_time(&details.torrent.uploaded_at)?
+    );
+    if details.torrent.trusted {
+        description.push_str(" · Trusted");
+    }
+    Ok(description)
 }
 
 async fn process(state: &State, torrent_id: i64) -> Result<String> {
-    // language=sql
-    const QUERY: &str = r"
-        select *,
-               (
-                   select coalesce(json_agg(x), '[]'::json)
-                   from (
-                       select rts.show_id, sn.name
-                       from magnets.rel_torrent_show rts
-                       join magnets.show_name sn using (show_id)
-                       where rts.torrent_id = $1 and sn.show_name_type = 1
-                   ) x
-               ) as shows
-        from magnets.torrent
-        where torrent_id = $1;
-    ";
+    render(
+        &state.repo().await?,
+        &state.global.show_names,
+        &state.global.base_url,
+        torrent_id,
+    )
+    .await
+}
 
-    let db = state.pg.borrow().await?;
-    let row = db.query_opt(QUERY, &[&torrent_id]).await?;
-    let row = match row {
-        Some(r) => r,
-        _ => return Err(NotFound.into()),
+async fn render(
+    repo: &(impl TorrentRepo + ShowRepo),
+    show_names: &ShowNameCache,
+    base_url: &str,
+    torrent_id: i64,
+) -> Result<String> {
+    let (details, shows) = load(repo, show_names, torrent_id).await?;
+    let shows: Vec<_> = shows
+        .iter()
+        .map(|s| Show {
+            show_id: s.show_id,
+            anilist_id: s.anilist_id,
+            name: &s.names.romaji,
+        })
+        .collect();
+    let torrent = &details.torrent;
+    let description = description(&details)?;
+    let og = OpenGraph {
+        base_url,
+        path: format!("/torrent/{}", torrent_id),
+        title: &torrent.title,
+        description,
+        image: None,
     };
-    let shows: Json<Vec<Show>> = row.get("shows");
-    let title = row.get("title");
-    let hash = row.get("hash");
-    let torrent = Torrent {
+    let page = Torrent {
         torrent_id,
-        title,
-        nyaa_id: row.get("nyaa_id"),
-        trusted: row.get("trusted"),
-        date: row.get("uploaded_at"),
-        magnet_link: MagnetFormatter(title, hash),
-        hash: HexFormatter(hash),
-        shows: shows.0,
-        size: row.get("size"),
+        title: &torrent.title,
+        nyaa_id: torrent.nyaa_id,
+        trusted: torrent.trusted,
+        date: torrent.uploaded_at,
+        magnet_link: MagnetFormatter::new(&torrent.title, &torrent.hash)
+            .with_size(details.size),
+        hash: HexFormatter(&torrent.hash),
+        shows: &shows,
+        size: details.size,
+        swarm: torrent.swarm,
+        og,
     };
-    Ok(torrent.render()?)
+    Ok(page.render()?)
 }
@@ -1,8 +1,8 @@
-use crate::text::MagnetFormatter;
+use crate::repo::{Swarm, TorrentRecord, PAGE_SIZE};
 use chrono::{DateTime, Utc};
+use common::{MagnetFormatter, Script};
 use itertools::Itertools;
 use std::collections::HashMap;
-use tokio_postgres::Row;
 
 pub struct Day<'a> {
     pub date: DateTime<Utc>,
@@ -13,54 +13,44 @@ pub struct Torrent<'a> {
     pub torrent_id: i64,
     pub title: &'a str,
     pub trusted: bool,
+    pub batch: bool,
+    pub swarm: Option<Swarm>,
+    /// Whether the torrent is the recommended release of an episode
+    pub recommended: bool,
     pub date: DateTime<Utc>,
     pub magnet_link: MagnetFormatter<'a>,
+    /// The non-latin script of the title if there is one
+    pub script: Option<Script>,
 }
 
-macro_rules! torrent_list_from_rows {
-    ($stmt:expr, $rows:expr) => {
-        torrent_list_from_rows(
-            $rows,
-            $stmt.title,
-            $stmt.nyaa_id,
-            $stmt.torrent_id,
-            $stmt.trusted,
-            $stmt.hash,
-            $stmt.uploaded_at,
-        )
-    };
-}
-
-pub fn torrent_list_from_rows(
-    mut rows: &[Row],
-    title_idx: usize,
-    nyaa_id_idx: usize,
-    torrent_id_idx: usize,
-    trusted_idx: usize,
-    hash_idx: usize,
-    uploaded_at_idx: usize,
-) -> (Option<i64>, Vec<Day>) {
-    let last = match rows.len() {
-        101 => {
-            rows = &rows[..100];
-            Some(rows.last().unwrap().get(nyaa_id_idx))
+/// Groups a page of torrents by day
+///
+/// Returns the nyaa id from which the next page starts if there is one.
+pub fn torrent_list(mut torrents: &[TorrentRecord]) -> (Option<i64>, Vec<Day>) {
+    let last = match torrents.len() {
+        n if n > PAGE_SIZE => {
+            torrents = &torrents[..PAGE_SIZE];
+            Some(torrents.last().unwrap().nyaa_id)
         }
         _ => None,
     };
     let mut days = HashMap::new();
-    for row in rows {
-        let title = row.get(title_idx);
-        let uploaded_at: DateTime<Utc> = row.get(uploaded_at_idx);
+    for torrent in torrents {
+        let uploaded_at = torrent.uploaded_at;
         let day = days.entry(uploaded_at.date()).or_insert_with(|| Day {
             date: uploaded_at,
             torrents: vec![],
         });
         day.torrents.push(Torrent {
-            torrent_id: row.get(torrent_id_idx),
-            title,
-            trusted: row.get(trusted_idx),
+            torrent_id: torrent.torrent_id,
+            title: &torrent.title,
+            trusted: torrent.trusted,
+            batch: torrent.batch,
+            swarm: torrent.swarm,
+            recommended: torrent.recommended,
             date: uploaded_at,
-            magnet_link: MagnetFormatter(title, row.get(hash_idx)),
+            magnet_link: MagnetFormatter::new(&torrent.title, &torrent.hash),
+            script: Script::detect(&torrent.title),
         });
     }
     let days: Vec<_> = days
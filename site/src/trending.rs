@@ -0,0 +1,120 @@
+use crate::{cache::Cached, state::State, text::TEXT_HTML};
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, CACHE_CONTROL},
+    web::{Bytes, Data},
+    HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use common::pg::PgConnector;
+use tokio_postgres::Client;
+
+#[actix_web::get("/trending")]
+pub async fn get(state: Data<State>) -> impl Responder {
+    match trending_(state).await {
+        Ok(b) => {
+            let bytes: Bytes = (*b).clone();
+            let cc = CacheControl(vec![
+                CacheDirective::MaxAge(b.max_age()),
+                CacheDirective::Public,
+            ]);
+            HttpResponse::Ok()
+                .header(CACHE_CONTROL, cc)
+                .content_type(TEXT_HTML)
+                .body(bytes)
+        }
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to retrieve trending torrents: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn trending_(state: Data<State>) -> Result<Cached<Bytes>> {
+    state
+        .global
+        .trending
+        .get(|| load_trending(&state.global.pg_connector))
+        .await
+}
+
+#[derive(Template)]
+#[template(path = "trending.html")]
+struct Trending {
+    torrents: Vec<Torrent>,
+    shows: Vec<Show>,
+}
+
+struct Torrent {
+    torrent_id: i64,
+    title: String,
+    trusted: bool,
+    hits: i64,
+}
+
+struct Show {
+    show_id: i64,
+    name: String,
+    hits: i64,
+}
+
+// language=sql
+common::create_statement!(TrendingTorrents, torrent_id, title, trusted, hits; "
+    select t.torrent_id, t.title, t.trusted, sum(h.hits)::bigint as hits
+    from magnets.torrent_hits h
+    join magnets.torrent t using (torrent_id)
+    where h.day > (now() at time zone 'utc')::date - 7
+    group by t.torrent_id
+    order by hits desc, t.torrent_id desc
+    limit 50");
+
+// language=sql
+common::create_statement!(TrendingShows, show_id, name, hits; "
+    select rts.show_id, sn.name, sum(h.hits)::bigint as hits
+    from magnets.torrent_hits h
+    join magnets.rel_torrent_show rts using (torrent_id)
+    join magnets.show_name sn on sn.show_id = rts.show_id and sn.show_name_type = 1
+    where h.day > (now() at time zone 'utc')::date - 7
+    group by rts.show_id, sn.name
+    order by hits desc, rts.show_id desc
+    limit 50");
+
+/// Prepares the statements of this module
+pub async fn check_statements(db: &Client) -> Result<()> {
+    TrendingTorrents::new(db).await?;
+    TrendingShows::new(db).await?;
+    Ok(())
+}
+
+async fn load_trending(connector: &PgConnector) -> Result<Bytes> {
+    let db = connector.connect().await?;
+    let torrents_stmt = TrendingTorrents::new(&db).await?;
+    let shows_stmt = TrendingShows::new(&db).await?;
+    let (torrents, shows) = futures::join!(
+        db.query(&torrents_stmt.stmt, &[]),
+        db.query(&shows_stmt.stmt, &[]),
+    );
+    let trending = Trending {
+        torrents: torrents?
+            .iter()
+            .map(|row| Torrent {
+                torrent_id: row.get(torrents_stmt.torrent_id),
+                title: row.get(torrents_stmt.title),
+                trusted: row.get(torrents_stmt.trusted),
+                hits: row.get(torrents_stmt.hits),
+            })
+            .collect(),
+        shows: shows?
+            .iter()
+            .map(|row| Show {
+                show_id: row.get(shows_stmt.show_id),
+                name: row.get(shows_stmt.name),
+                hits: row.get(shows_stmt.hits),
+            })
+            .collect(),
+    };
+    Ok(trending.render()?.into())
+}
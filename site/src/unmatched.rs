@@ -1,7 +1,11 @@
 use crate::{
+    repo::{
+        listing::{TorrentFilter, TorrentQuery},
+        TorrentRepo,
+    },
     state::State,
     text::TEXT_HTML,
-    torrent_list::{torrent_list_from_rows, Day},
+    torrent_list::{torrent_list, Day},
 };
 use actix_web::{
     web::{Data, Query},
@@ -47,9 +51,19 @@ mod filters {
 }
 
 async fn get_(a: i64, state: Data<State>) -> Result<String> {
-    let db = state.pg.borrow().await?;
-    let rows = db.query(&db.t.unmatched.stmt, &[&a]).await?;
-    let (last, days) = torrent_list_from_rows!(db.t.unmatched, &rows);
+    render(&state.repo().await?, a).await
+}
+
+async fn render(repo: &impl TorrentRepo, a: i64) -> Result<String> {
+    let query = TorrentQuery {
+        filter: TorrentFilter {
+            matched: Some(false),
+            ..Default::default()
+        },
+        after: a,
+    };
+    let torrents = repo.torrents(&query).await?;
+    let (last, days) = torrent_list(&torrents);
     let template = Days {
         last,
         days: &days,
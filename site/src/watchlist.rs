@@ -0,0 +1,287 @@
+//! The watchlists of users who log in with AniList
+//!
+//! `/login` creates a session and redirects to AniList. AniList redirects back to
+//! `/login/anilist` with an authorization code, which is stored in the session. The site
+//! does not talk to AniList itself: the processor exchanges the code, assigns the user
+//! to the session, and replaces the watchlist with the "watching" list of the user on
+//! AniList (see `processor::watchlists`). /watchlist reloads itself until then.
+//!
+//! Sessions expire after [LOGIN_MINUTES] unless the login is completed. The processor
+//! then extends them to [SESSION_DAYS].
+
+use crate::{admin::check_same_origin, state::State, text::TEXT_HTML};
+use actix_web::{
+    http::header::{CacheControl, CacheDirective, CACHE_CONTROL, LOCATION, SET_COOKIE},
+    web::{Data, Query},
+    HttpMessage, HttpRequest, HttpResponse, Responder,
+};
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Duration, Utc};
+use common::{query_encode, SESSION_DAYS};
+use serde::Deserialize;
+use std::{fs::File, io::Read};
+
+const ANILIST_AUTHORIZE_URL: &str = "https://anilist.co/api/v2/oauth/authorize";
+
+const SESSION_COOKIE: &str = "session";
+
+/// The time after which a session expires if the login has not been completed
+const LOGIN_MINUTES: i64 = 10;
+
+#[derive(Template)]
+#[template(path = "watchlist.html")]
+struct Watchlist {
+    /// Whether AniList has redirected back but the processor has not completed the login
+    pending: bool,
+    /// Whether the processor could not complete the login
+    failed: bool,
+    user: Option<User>,
+}
+
+struct User {
+    name: String,
+    synced_at: Option<DateTime<Utc>>,
+    shows: Vec<Show>,
+}
+
+struct Show {
+    show_id: i64,
+    name: String,
+    /// The last episode for which there are torrents
+    latest_episode: Option<i32>,
+}
+
+mod filters {
+    pub use crate::text::format_full_time;
+}
+
+#[actix_web::get("/watchlist")]
+pub async fn get(req: HttpRequest, state: Data<State>) -> impl Responder {
+    if state.global.anilist_client_id.is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    let token = req.cookie(SESSION_COOKIE);
+    match render(&state, token.as_ref().map(|c| c.value())).await {
+        Ok(data) => HttpResponse::Ok()
+            .header(CACHE_CONTROL, no_store())
+            .content_type(TEXT_HTML)
+            .body(data),
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to render the watchlist: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+async fn render(state: &State, token: Option<&str>) -> Result<String> {
+    let mut watchlist = Watchlist {
+        pending: false,
+        failed: false,
+        user: None,
+    };
+    let token = match token {
+        Some(t) => t,
+        _ => return Ok(watchlist.render()?),
+    };
+    let db = state.pg.borrow().await?;
+    let stmt = &db.t.watchlist_session;
+    let session = match db.query_opt(&stmt.stmt, &[&token]).await? {
+        Some(row) => row,
+        _ => return Ok(watchlist.render()?),
+    };
+    watchlist.pending = session.get::<_, Option<&str>>(stmt.code).is_some();
+    watchlist.failed = session.get(stmt.failed);
+    if let Some(user_id) = session.get::<_, Option<i64>>(stmt.site_user_id) {
+        let shows = &db.t.watchlist_shows;
+        let shows = db
+            .query(&shows.stmt, &[&user_id])
+            .await?
+            .iter()
+            .map(|row| Show {
+                show_id: row.get(shows.show_id),
+                name: row.get(shows.romaji),
+                latest_episode: row.get(shows.latest_episode),
+            })
+            .collect();
+        watchlist.user = Some(User {
+            name: session.get(stmt.anilist_name),
+            synced_at: session.get(stmt.synced_at),
+            shows,
+        });
+    }
+    Ok(watchlist.render()?)
+}
+
+/// Creates a session and redirects to the authorization page of AniList
+#[actix_web::get("/login")]
+pub async fn login(state: Data<State>) -> impl Responder {
+    let client_id = match state.global.anilist_client_id {
+        Some(id) => id,
+        _ => return HttpResponse::NotFound().finish(),
+    };
+    match create_session(&state).await {
+        Ok((token, oauth_state)) => {
+            let url = format!(
+                "{}?client_id={}&redirect_uri={}&response_type=code&state={}",
+                ANILIST_AUTHORIZE_URL,
+                client_id,
+                query_encode(&format!("{}/login/anilist", state.global.base_url)),
+                oauth_state,
+            );
+            HttpResponse::Found()
+                .header(CACHE_CONTROL, no_store())
+                .header(SET_COOKIE, session_cookie(&state, &token, SESSION_DAYS))
+                .header(LOCATION, url)
+                .finish()
+        }
+        Err(e) => {
+            log::error!(
+                "an error occurred while trying to create a session: {:#}",
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Returns the token and the oauth state of the new session
+async fn create_session(state: &State) -> Result<(String, String)> {
+    let token = random_token()?;
+    let oauth_state = random_token()?;
+    let expires = state.global.clock.now() + Duration::minutes(LOGIN_MINUTES);
+    let db = state.pg.borrow().await?;
+    // language=sql
+    db.execute(
+        "
+        insert into magnets.site_session (token, oauth_state, expires)
+        values ($1, $2, $3)",
+        &[&token, &oauth_state, &expires],
+    )
+    .await?;
+    Ok((token, oauth_state))
+}
+
+#[derive(Deserialize)]
+pub struct CallbackParams {
+    /// Not set if the user has denied the authorization
+    code: Option<String>,
+    state: Option<String>,
+}
+
+/// Stores the authorization code in the session for the processor
+///
+/// The code is only accepted from the browser that started the login, i.e. if the
+/// state matches the session in the cookie.
+#[actix_web::get("/login/anilist")]
+pub async fn callback(
+    req: HttpRequest,
+    state: Data<State>,
+    Query(params): Query<CallbackParams>,
+) -> impl Responder {
+    if state.global.anilist_client_id.is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    if let (Some(cookie), Some(code), Some(oauth_state)) =
+        (req.cookie(SESSION_COOKIE), &params.code, &params.state)
+    {
+        if let Err(e) = store_code(&state, cookie.value(), oauth_state, code).await {
+            log::error!(
+                "an error occurred while trying to store an authorization code: {:#}",
+                e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    HttpResponse::SeeOther()
+        .header(CACHE_CONTROL, no_store())
+        .header(LOCATION, "/watchlist")
+        .finish()
+}
+
+async fn store_code(
+    state: &State,
+    token: &str,
+    oauth_state: &str,
+    code: &str,
+) -> Result<()> {
+    let db = state.pg.borrow().await?;
+    // language=sql
+    let updated = db
+        .execute(
+            "
+            update magnets.site_session
+            set oauth_state = null, code = $3
+            where token = $1 and oauth_state = $2 and expires > now()",
+            &[&token, &oauth_state, &code],
+        )
+        .await?;
+    if updated == 0 {
+        log::info!("ignoring an authorization code that does not match the session");
+    }
+    Ok(())
+}
+
+#[actix_web::post("/logout")]
+pub async fn logout(
+    req: HttpRequest,
+    state: Data<State>,
+) -> actix_web::Result<HttpResponse> {
+    check_same_origin(&req)?;
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        if let Err(e) = delete_session(&state, cookie.value()).await {
+            log::error!(
+                "an error occurred while trying to delete a session: {:#}",
+                e
+            );
+            return Ok(HttpResponse::InternalServerError().finish());
+        }
+    }
+    Ok(HttpResponse::SeeOther()
+        .header(SET_COOKIE, session_cookie(&state, "", 0))
+        .header(LOCATION, "/watchlist")
+        .finish())
+}
+
+async fn delete_session(state: &State, token: &str) -> Result<()> {
+    let db = state.pg.borrow().await?;
+    // language=sql
+    db.execute(
+        "delete from magnets.site_session where token = $1",
+        &[&token],
+    )
+    .await?;
+    Ok(())
+}
+
+fn no_store() -> CacheControl {
+    CacheControl(vec![CacheDirective::NoStore])
+}
+
+/// Formats the Set-Cookie header of the session
+///
+/// The cookie is sent with the redirect from AniList since that is a top-level
+/// navigation but not with cross-site form submissions.
+fn session_cookie(state: &State, token: &str, days: i64) -> String {
+    let secure = match state.global.base_url.starts_with("https://") {
+        true => "; Secure",
+        false => "",
+    };
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        SESSION_COOKIE,
+        token,
+        days * 24 * 60 * 60,
+        secure
+    )
+}
+
+/// Returns 32 random bytes as url-safe base64
+fn random_token() -> Result<String> {
+    let mut bytes = [0; 32];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))
+}
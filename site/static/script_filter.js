@@ -0,0 +1,27 @@
+// Hides the torrents whose titles are written in the scripts checked by the user.
+// The choice is stored in localStorage and applies to all torrent lists.
+const KEY = "hidden-scripts";
+
+let filter = document.getElementById("script-filter");
+let hidden = new Set(JSON.parse(localStorage.getItem(KEY) || "[]"));
+
+let update_torrents = () => {
+    for (let torrent of document.querySelectorAll("[data-script]")) {
+        torrent.hidden = hidden.has(torrent.dataset.script);
+    }
+};
+
+for (let checkbox of filter.querySelectorAll("input")) {
+    checkbox.checked = hidden.has(checkbox.value);
+    checkbox.addEventListener("change", () => {
+        if (checkbox.checked) {
+            hidden.add(checkbox.value);
+        } else {
+            hidden.delete(checkbox.value);
+        }
+        localStorage.setItem(KEY, JSON.stringify([...hidden]));
+        update_torrents();
+    });
+}
+filter.hidden = false;
+update_torrents();
@@ -11,17 +11,78 @@ for (let group of json) {
 let input_value = "";
 let input = document.getElementById("showname");
 
-let update_shows = () => {
-    let new_input = "";
-    for (let char of input.value) {
-        char = char.codePointAt(0);
-        if (char >= 65 && char <= 90) {
-            char += 32;
+// Must be kept in sync with common::textnorm
+const KANA = [
+    "a", "a", "i", "i", "u", "u", "e", "e", "o", "o",
+    "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go",
+    "sa", "za", "shi", "ji", "su", "zu", "se", "ze", "so", "zo",
+    "ta", "da", "chi", "ji", "", "tsu", "zu", "te", "de", "to", "do",
+    "na", "ni", "nu", "ne", "no",
+    "ha", "ba", "pa", "hi", "bi", "pi", "fu", "bu", "pu",
+    "he", "be", "pe", "ho", "bo", "po",
+    "ma", "mi", "mu", "me", "mo",
+    "ya", "ya", "yu", "yu", "yo", "yo",
+    "ra", "ri", "ru", "re", "ro",
+    "wa", "wa", "i", "e", "o", "n", "vu", "ka", "ke",
+];
+const HIRAGANA_START = 0x3041;
+const HIRAGANA_END = 0x3096;
+const KATAKANA_OFFSET = 0x60;
+const SMALL_TSU = 0x3063;
+const SMALL_Y = [0x3083, 0x3085, 0x3087];
+const LONG_VOWEL = 0x30fc;
+
+let search_fold = (s) => {
+    let res = "";
+    let double_next = false;
+    let last_was_kana = false;
+    for (let char of s.normalize("NFKC")) {
+        let n = char.codePointAt(0);
+        if (n === LONG_VOWEL) {
+            continue;
         }
-        if (char > 127 || (char >= 48 && char <= 57) || (char >= 97 && char <= 122)) {
-            new_input += String.fromCodePoint(char);
+        if (n >= HIRAGANA_START + KATAKANA_OFFSET && n <= HIRAGANA_END + KATAKANA_OFFSET) {
+            n -= KATAKANA_OFFSET;
         }
+        if (n < HIRAGANA_START || n > HIRAGANA_END) {
+            double_next = false;
+            last_was_kana = false;
+            for (let l of char.toLowerCase().normalize("NFKD")) {
+                if (/^[\p{L}\p{N}]$/u.test(l)) {
+                    res += l;
+                }
+            }
+            continue;
+        }
+        if (n === SMALL_TSU) {
+            double_next = true;
+            continue;
+        }
+        let romaji = KANA[n - HIRAGANA_START];
+        if (SMALL_Y.includes(n) && last_was_kana && res.endsWith("i")) {
+            res = res.slice(0, -1);
+            if (!(res.endsWith("sh") || res.endsWith("ch") || res.endsWith("j"))) {
+                res += "y";
+            }
+            res += romaji.slice(1);
+            continue;
+        }
+        if (double_next) {
+            double_next = false;
+            if (romaji.startsWith("c")) {
+                res += "t";
+            } else if (!"aiueon".includes(romaji[0])) {
+                res += romaji[0];
+            }
+        }
+        res += romaji;
+        last_was_kana = true;
     }
+    return res;
+};
+
+let update_shows = () => {
+    let new_input = search_fold(input.value);
     if (new_input === input_value) {
         return;
     }
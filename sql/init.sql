@@ -4,6 +4,9 @@ begin;
 
 create schema magnets;
 
+-- trigram indexes of the show search (see site/src/search.rs)
+create extension if not exists pg_trgm;
+
 create table magnets.show_format (
     show_format int primary key,
     description text not null,
@@ -25,14 +28,46 @@ create table magnets.show (
     show_id bigserial primary key,
     anilist_id bigint not null unique,
     season int,
+    -- the season derived from the earliest airing or torrent date if anilist does not
+    -- know the season
+    inferred_season int,
     show_format int not null references magnets.show_format(show_format),
+    episodes int,
+    cover_url text,
+    -- the cover_url whose thumbnails have been written to the cover directory
+    cover_mirrored_url text,
+    -- whether the season or the format has been set with `processor show set`. the
+    -- anilist sync does not change it until it is released with `processor show unset`.
+    season_curated boolean not null default false,
+    format_curated boolean not null default false,
+    -- why the show was removed (see processor/src/show_lifecycle.rs). null if the show
+    -- is listed. removed shows are kept so that links to them keep working.
+    removal int,
+    -- the show that replaced this show if it has been merged
+    merged_into bigint references magnets.show,
+    removed_at timestamptz,
+    -- the names of the show folded with `common::textnorm::search_words`, one per line.
+    -- maintained by the processor (see processor/src/search.rs). null until indexed.
+    search_names text,
+    -- the number of anilist users who have the show on their list. null for shows
+    -- that anilist does not rank.
+    popularity int,
+    -- the upload time of the newest torrent matched to the show. maintained by the
+    -- processor. ranks the results of the show search.
+    last_torrent_at timestamptz,
     created timestamptz not null default now()
 );
 
 create index on magnets.show(anilist_id);
 
+create index on magnets.show using gin (to_tsvector('simple', search_names));
+
+create index on magnets.show using gin (search_names gin_trgm_ops);
+
 create index on magnets.show(season);
 
+create index on magnets.show(inferred_season);
+
 -- drop table if exists magnets.show_name_type cascade;
 
 create table magnets.show_name_type (
@@ -50,21 +85,45 @@ create table magnets.show_name (
     show_id bigint not null references magnets.show,
     show_name_type int not null references magnets.show_name_type,
     name text not null,
-    created timestamptz not null default now()
+    created timestamptz not null default now(),
+    -- the processor inserts with `on conflict do nothing` so that a sync that is retried
+    -- after its commit failed with an unknown outcome cannot duplicate names
+    unique (show_id, show_name_type, name)
 );
 
 create index on magnets.show_name(show_id);
 
+create table magnets.external_site (
+    external_site int primary key,
+    description text not null,
+    created timestamptz not null default now()
+);
+
+insert into magnets.external_site values (1, 'myanimelist');
+
+-- the ids of shows on other sites as known by anilist. synced with the shows.
+create table magnets.show_external_id (
+    show_id bigint not null references magnets.show,
+    external_site int not null references magnets.external_site,
+    external_id bigint not null,
+    created timestamptz not null default now(),
+    primary key (show_id, external_site)
+);
+
 -- truncate magnets.show cascade;
 
 -- drop table if exists magnets.schedule;
 
+-- the airing schedule from anilist. only the upcoming week is synced. past entries are
+-- kept for /calendar.
 create table magnets.schedule (
     schedule_id bigserial primary key,
     show_id bigint not null references magnets.show,
     episode int not null,
     airs_at timestamptz not null,
-    created timestamptz not null default now()
+    created timestamptz not null default now(),
+    -- see magnets.show_name
+    unique (show_id, episode, airs_at)
 );
 
 -- drop table if exists magnets.hash_type;
@@ -77,6 +136,16 @@ create table magnets.hash_type (
 
 insert into magnets.hash_type (hash_type, description) values (1, 'sha1');
 
+-- drop table if exists magnets.release_group cascade;
+
+-- the release groups (subgroups) of torrents. taken from the `[group]` block at the
+-- start of the titles.
+create table magnets.release_group (
+    release_group_id bigserial primary key,
+    name text not null unique,
+    created timestamptz not null default now()
+);
+
 -- drop table if exists magnets.torrent cascade;
 
 create table magnets.torrent (
@@ -88,15 +157,95 @@ create table magnets.torrent (
     title text not null,
     size bigint not null,
     matched bool not null default false,
+    -- whether the torrent contains multiple episodes, e.g. a complete season. only set
+    -- for matched torrents.
+    batch bool not null default false,
     trusted bool not null,
+    -- when `trusted` was last compared with nyaa. null if it never was.
+    trusted_checked timestamptz,
+    -- the non-latin script of the title (see common::Script). a hint for the language of
+    -- the torrent. null if the title is latin.
+    script int,
+    -- the formats advertised in the title (see common::MediaInfo). null if the title
+    -- does not mention them.
+    resolution int,
+    video_codec int,
+    audio_codec int,
+    dual_audio bool not null default false,
+    -- null if the title does not start with a release group
+    release_group_id bigint references magnets.release_group,
+    -- the title folded with `common::textnorm::search_words`. maintained by the
+    -- processor. null until indexed and after the title has been edited.
+    search_title text,
+    -- the swarm as last seen on a nyaa.si listing. null if the torrent has not been seen
+    -- on a listing that contains the swarm.
+    seeders int,
+    leechers int,
+    -- the number of completed downloads
+    completed int,
+    swarm_updated timestamptz,
     created timestamptz not null default now(),
+    -- also serves as the index for lookups by hash (see /api/v1/hashes)
     unique (hash, hash_type)
 );
 
+create index on magnets.torrent (trusted_checked nulls first);
+
 create index on magnets.torrent (nyaa_id desc) where matched;
 
 create index on magnets.torrent (nyaa_id desc) where not matched;
 
+create index on magnets.torrent (nyaa_id desc) where batch;
+
+create index on magnets.torrent (release_group_id, nyaa_id desc);
+
+create index on magnets.torrent using gin (to_tsvector('simple', search_title));
+
+create index on magnets.torrent (torrent_id) where search_title is null;
+
+create table magnets.source (
+    source int primary key,
+    description text not null,
+    created timestamptz not null default now()
+);
+
+insert into magnets.source values (1, 'nyaa.si'), (2, 'anidex.info'), (3, 'animetosho.org');
+
+-- the sources that provide a torrent. torrents with the same info hash are stored once in
+-- magnets.torrent no matter how many sources provide them.
+create table magnets.torrent_source (
+    torrent_source_id bigserial primary key,
+    torrent_id bigint not null references magnets.torrent,
+    source int not null references magnets.source,
+    -- the id of the torrent at the source, e.g. the nyaa id
+    source_id bigint not null,
+    -- the size reported by the source
+    size bigint not null,
+    -- hash of the fields scraped from the source. used to detect edits at the source.
+    scrape_hash bytea,
+    -- the upload date reported by the source. `created - uploaded_at` is the time it
+    -- took us to ingest the torrent.
+    uploaded_at timestamptz,
+    created timestamptz not null default now(),
+    unique (source, source_id)
+);
+
+create index on magnets.torrent_source (torrent_id);
+create index on magnets.torrent_source (created);
+
+-- torrents whose size on the nyaa listing could not be parsed or was implausible (zero or
+-- above 10 TiB). they are stored with size 0 until the size has been taken from their
+-- detail page. rows whose attempts are exhausted stay as a flag for manual review.
+create table magnets.size_reparse (
+    torrent_id bigint primary key references magnets.torrent,
+    -- the size as displayed on the listing
+    displayed_size text not null,
+    attempts int not null default 0,
+    -- when the detail page was last fetched. null if it never was.
+    checked timestamptz,
+    created timestamptz not null default now()
+);
+
 -- drop table if exists magnets.rel_torrent_show cascade;
 
 create table magnets.rel_torrent_show (
@@ -104,12 +253,232 @@ create table magnets.rel_torrent_show (
     show_id bigint not null references magnets.show,
     torrent_id bigint not null references magnets.torrent,
     nyaa_id bigint not null references magnets.torrent(nyaa_id),
+    -- the episode number taken from the title. the first episode of a batch.
+    episode int,
+    -- the last episode of a batch. null for single episodes.
+    episode_end int,
     created timestamptz not null default now(),
     unique (torrent_id, show_id)
 );
 
 create index on magnets.rel_torrent_show (show_id, nyaa_id desc);
 
+-- manual corrections of matches made on /admin/torrent on the site or with
+-- `processor override torrent`. the matcher never matches a torrent to a show whose
+-- match has been rejected, and rematching all torrents keeps the assigned shows.
+create table magnets.match_override (
+    torrent_id bigint not null references magnets.torrent,
+    show_id bigint not null references magnets.show,
+    -- true if the torrent has been assigned to the show, false if the match has been
+    -- rejected
+    assigned bool not null,
+    -- the admin user who made the correction
+    actor text not null,
+    created timestamptz not null default now(),
+    primary key (torrent_id, show_id)
+);
+
+-- titles that are matched to a show no matter what the title analyzer finds. added with
+-- `processor override title`. the pattern is a regular expression that is matched
+-- case-insensitively anywhere in the title. the first pattern that matches wins.
+create table magnets.title_override (
+    title_override_id bigserial primary key,
+    pattern text not null unique,
+    show_id bigint not null references magnets.show,
+    created timestamptz not null default now()
+);
+
+-- rules for release groups whose titles the title analyzer cannot match on its own,
+-- e.g. every `[SubsPlease] <name> - 01` belongs to the show. added with
+-- `processor override group`. the name is the part of the title before the episode
+-- number. titles are compared by the matcher keys (`common::textnorm::matcher_key`) of
+-- the release group and the name, which are stored next to them as written.
+create table magnets.group_rule (
+    group_rule_id bigserial primary key,
+    release_group text not null,
+    name text not null,
+    release_group_key text not null,
+    name_key text not null,
+    show_id bigint not null references magnets.show,
+    created timestamptz not null default now(),
+    unique (release_group_key, name_key)
+);
+
+-- the matches collected while rematching all torrents. they replace the contents of
+-- rel_torrent_show once all torrents have been rematched.
+create unlogged table magnets.rel_torrent_show_next (
+    show_id bigint not null,
+    torrent_id bigint not null,
+    nyaa_id bigint not null,
+    episode int,
+    episode_end int,
+    batch bool not null
+);
+
+create index on magnets.rel_torrent_show_next (torrent_id);
+
+create table magnets.torrent_hits (
+    torrent_id bigint not null references magnets.torrent,
+    day date not null,
+    hits bigint not null,
+    primary key (torrent_id, day)
+);
+
+create index on magnets.torrent_hits (day);
+
+create table magnets.release_observation (
+    show_id bigint not null references magnets.show,
+    release_group text not null,
+    episode int not null,
+    -- seconds between the scheduled airing time and the release
+    delay_seconds int not null,
+    created timestamptz not null default now(),
+    primary key (show_id, release_group, episode)
+);
+
+-- torrents that a candidate analyzer (`nyaa.shadow_analyzer`) matched differently than
+-- the analyzer used for matching. null means that the analyzer found no show.
+create table magnets.analyzer_shadow (
+    torrent_id bigint not null references magnets.torrent on delete cascade,
+    analyzer text not null,
+    show_id bigint,
+    candidate_show_id bigint,
+    created timestamptz not null default now(),
+    primary key (torrent_id, analyzer)
+);
+
+create table magnets.expected_release (
+    show_id bigint not null references magnets.show,
+    release_group text not null,
+    -- 90th percentile of the observed delays
+    delay_seconds int not null,
+    samples int not null,
+    created timestamptz not null default now(),
+    primary key (show_id, release_group)
+);
+
+-- the torrent that is recommended for every episode. among the torrents that contain
+-- only the episode, the one with the highest score under the weights in the
+-- `recommend` section of the processor config. recomputed periodically.
+create table magnets.recommended_torrent (
+    show_id bigint not null references magnets.show,
+    episode int not null,
+    torrent_id bigint not null references magnets.torrent,
+    score float8 not null,
+    primary key (show_id, episode)
+);
+
+create index on magnets.recommended_torrent (torrent_id);
+
+-- the targets to which the torrents that are matched to a show are posted. managed
+-- with `processor webhook`.
+create table magnets.webhook (
+    webhook_id bigserial primary key,
+    -- null for all shows
+    show_id bigint references magnets.show,
+    -- 1: json, 2: discord, 3: telegram (see `processor::notifiers::WebhookKind`)
+    kind int not null default 1,
+    -- the url of the webhook. the chat id for telegram.
+    target text not null,
+    created timestamptz not null default now()
+);
+
+create unique index on magnets.webhook (coalesce(show_id, 0), kind, target);
+
+-- the notifications that have not been delivered yet. queued in the transaction that
+-- matches the torrent.
+create table magnets.webhook_delivery (
+    webhook_delivery_id bigserial primary key,
+    webhook_id bigint not null references magnets.webhook on delete cascade,
+    torrent_id bigint not null references magnets.torrent,
+    -- the show to which the torrent has been matched
+    show_id bigint not null references magnets.show,
+    -- the number of failed attempts
+    attempts int not null default 0,
+    next_attempt timestamptz not null default now(),
+    created timestamptz not null default now()
+);
+
+create index on magnets.webhook_delivery (next_attempt);
+
+-- the users of the site. created when someone logs in with anilist for the first time.
+create table magnets.site_user (
+    site_user_id bigserial primary key,
+    anilist_user_id bigint not null unique,
+    anilist_name text not null,
+    -- the token with which the list of the user is read. anilist tokens are valid for
+    -- one year and are replaced on every login.
+    access_token text not null,
+    created timestamptz not null default now(),
+    -- the last time the watchlist was replaced with the list on anilist
+    synced_at timestamptz
+);
+
+-- the login sessions of the site. created by /login. the processor exchanges the code
+-- that anilist passes to /login/anilist for an access token and assigns the user.
+create table magnets.site_session (
+    -- the value of the session cookie
+    token text primary key,
+    -- the state parameter of the authorization request. null once anilist has
+    -- redirected back.
+    oauth_state text,
+    -- the authorization code. null once it has been exchanged.
+    code text,
+    site_user_id bigint references magnets.site_user on delete cascade,
+    -- whether the code could not be exchanged
+    failed bool not null default false,
+    created timestamptz not null default now(),
+    -- minutes after the creation until the login has been completed, then
+    -- `common::SESSION_DAYS` days
+    expires timestamptz not null
+);
+
+create index on magnets.site_session (site_user_id);
+
+create index on magnets.site_session (created) where code is not null;
+
+-- the shows that users are watching. replaced with the shows on the "watching" list of
+-- the user on anilist on every sync.
+create table magnets.watchlist (
+    site_user_id bigint not null references magnets.site_user on delete cascade,
+    show_id bigint not null references magnets.show,
+    primary key (site_user_id, show_id)
+);
+
+-- the changes that a rematch of all torrents would make to rel_torrent_show. computed
+-- by the processor when `match_diff` is set to 1 and reviewed on
+-- /admin/rematch-preview. the approved changes are applied when `match_diff` is set
+-- to 2.
+create table magnets.match_diff (
+    torrent_id bigint not null references magnets.torrent,
+    show_id bigint not null references magnets.show,
+    -- true if the torrent would be added to the show, false if it would be removed
+    added bool not null,
+    -- 0 = pending, 1 = approved, 2 = rejected
+    status int not null default 0,
+    created timestamptz not null default now(),
+    primary key (torrent_id, show_id)
+);
+
+-- names of shows suggested for clusters of unmatched torrents. computed by the processor
+-- when `alias_suggestions` is set to 1 and reviewed on /admin/alias-suggestions. the
+-- approved aliases are added as additional names when `alias_suggestions` is set to 2.
+create table magnets.alias_suggestion (
+    alias_suggestion_id bigserial primary key,
+    -- the name extracted from the unmatched titles
+    alias text not null,
+    -- the matcher key of the alias. the unmatched titles are clustered by it.
+    alias_key text not null,
+    show_id bigint not null references magnets.show,
+    -- the number of unmatched torrents in the cluster
+    torrents int not null,
+    example_title text not null,
+    -- 0 = pending, 1 = approved, 2 = rejected, 3 = applied
+    status int not null default 0,
+    created timestamptz not null default now(),
+    unique (alias_key, show_id)
+);
+
 create table magnets.state (
     key text primary key,
     value jsonb not null,
@@ -118,10 +487,52 @@ create table magnets.state (
 
 insert into magnets.state values
     ('max_nyaa_si_id', '0'::jsonb),
+    -- mirrors of nyaa.si have their own `max_<name>_id` keys that the processor creates
     ('last_schedule_update', '"2000-01-01T00:00:00Z"'::jsonb),
     ('last_shows_update', '"2000-01-01T00:00:00Z"'::jsonb),
     ('rematch_unmatched', '0'::jsonb),
-    ('initial_setup', 'true'::jsonb);
+    ('match_diff', '0'::jsonb),
+    ('alias_suggestions', '0'::jsonb),
+    ('initial_setup', 'true'::jsonb),
+    ('maintenance', 'false'::jsonb),
+    ('flags', '{}'::jsonb),
+    ('matcher_anomaly', 'false'::jsonb),
+    -- updated by the processor every `heartbeat.interval`, see /readyz on the site
+    ('processor_heartbeat', '"2000-01-01T00:00:00Z"'::jsonb);
+
+-- large cached artifacts, e.g. the names of all shows for the /shows page (`show_list`).
+-- kept out of magnets.state, whose values are read in full on every reconnect. the
+-- values are JSON with a small header and usually zstd compressed, see
+-- `common::pg::compress_json`.
+create table magnets.state_blob (
+    key text primary key,
+    value bytea not null,
+    updated timestamptz not null default now()
+);
+
+create table magnets.role (
+    role int primary key,
+    description text not null,
+    created timestamptz not null default now()
+);
+
+insert into magnets.role values (1, 'admin'), (2, 'moderator'), (3, 'readonly');
+
+create table magnets.admin_user (
+    name text primary key,
+    role int not null references magnets.role,
+    created timestamptz not null default now()
+);
+
+create table magnets.audit_log (
+    audit_log_id bigserial primary key,
+    -- the name of the admin user. `processor` for changes made with the processor cli.
+    actor text not null,
+    action text not null,
+    before jsonb,
+    after jsonb,
+    created timestamptz not null default now()
+);
 
 create or replace procedure magnets.notify_state_change(key text) as $$
 begin
@@ -132,11 +543,13 @@ $$ language plpgsql;
 
 create or replace function magnets.handle_state_update () returns trigger as $$
 begin
-    if NEW.key = 'max_nyaa_si_id' then
+    -- the largest ids scraped from nyaa.si and its mirrors. lowered by hand to scrape
+    -- a source again.
+    if NEW.key like 'max\_%\_id' then
         if NEW.value::bigint < OLD.value::bigint then
             call magnets.notify_state_change(NEW.key);
         end if;
-    elsif NEW.key = 'rematch_unmatched' then
+    elsif NEW.key in ('rematch_unmatched', 'match_diff', 'alias_suggestions') then
         if NEW.value::int > 0 then
             call magnets.notify_state_change(NEW.key);
         end if;
@@ -144,6 +557,8 @@ begin
         if NEW.value::text::timestamptz < OLD.value::text::timestamptz then
             call magnets.notify_state_change(NEW.key);
         end if;
+    elsif NEW.key = 'flags' then
+        call magnets.notify_state_change(NEW.key);
     end if;
     return null;
 end;
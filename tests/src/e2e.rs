@@ -0,0 +1,485 @@
+//! Runs the processor and the site against a fresh database
+//!
+//! nyaa.si and AniList are replaced by mock servers that answer with the recorded
+//! responses in `tests/fixtures`. The test builds and starts the real binaries and
+//! requires docker. Run it with
+//!
+//! ```text
+//! cargo test -p tests -- --ignored
+//! ```
+
+use crate::{
+    mock_http::{MockServer, Response},
+    Testdb,
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use testcontainers::clients::Cli;
+use tokio::time::delay_for;
+
+const NYAA_PAGE: &str = include_str!("../fixtures/nyaa.html");
+const ANILIST_SHOWS: &str = include_str!("../fixtures/anilist_shows.json");
+
+/// The AniList id of Sousou no Frieren in `anilist_shows.json`
+const FRIEREN: i64 = 154587;
+
+/// The AniList id of Shingeki no Kyojin in `anilist_shows.json`
+const SHINGEKI: i64 = 16498;
+
+/// The time the processor and the site have to start and to ingest the fixtures
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A child process that is killed when it goes out of scope
+struct Process(Child);
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn end_to_end() -> Result<()> {
+    common::env::configure_logger();
+
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    build(workspace)?;
+
+    let docker = Cli::default();
+    let testdb = Testdb::new(&docker).await?;
+    {
+        // Nyaa ids below this have been scraped before. Stops the scraper after the
+        // first page.
+        let con = testdb.connector.connect().await?;
+        // language=sql
+        con.execute(
+            "update magnets.state set value = '1100' where key = 'max_nyaa_si_id'",
+            &[],
+        )
+        .await?;
+    }
+
+    let nyaa = MockServer::start(|req| match req.path.starts_with("/?") {
+        true => Response::ok("text/html; charset=utf-8", NYAA_PAGE),
+        false => Response::not_found(),
+    })
+    .await?;
+    let anilist = MockServer::start(|req| {
+        if req.method != "POST" {
+            Response::not_found()
+        } else if req.path == "/token" {
+            Response::ok("application/json", r#"{"access_token": "token"}"#)
+        } else if req.body.contains("Viewer") {
+            Response::ok("application/json", viewer())
+        } else if req.body.contains("MediaListCollection") {
+            Response::ok("application/json", watching())
+        } else if req.body.contains("airingSchedules") {
+            Response::ok("application/json", schedule())
+        } else {
+            Response::ok("application/json", ANILIST_SHOWS)
+        }
+    })
+    .await?;
+
+    let dir = temp_dir()?;
+    let processor_dir = dir.join("processor");
+    let site_dir = dir.join("site");
+    std::fs::create_dir_all(&processor_dir)?;
+    std::fs::create_dir_all(&site_dir)?;
+    std::fs::create_dir_all(dir.join("covers"))?;
+    std::os::unix::fs::symlink(workspace.join("site/static"), site_dir.join("static"))?;
+    let site_addr = format!("127.0.0.1:{}", free_port()?);
+    std::fs::write(
+        processor_dir.join("config.toml"),
+        processor_config(&testdb.connection_string, &nyaa.url, &anilist.url, &dir),
+    )?;
+    std::fs::write(
+        site_dir.join("config.toml"),
+        site_config(&testdb.connection_string, &site_addr, &dir),
+    )?;
+
+    let bin = target_dir(workspace).join("debug");
+    let _processor = spawn(&bin.join("processor"), &processor_dir)?;
+    wait_for_ingestion(&testdb).await?;
+    let _site = spawn(&bin.join("site"), &site_dir)?;
+
+    let show_id: i64 = {
+        let con = testdb.connector.connect().await?;
+        // language=sql
+        con.query_one(
+            "select show_id from magnets.show where anilist_id = $1",
+            &[&FRIEREN],
+        )
+        .await?
+        .get(0)
+    };
+
+    let base = format!("http://{}", site_addr);
+    let client = reqwest::Client::new();
+    let index = get(&client, &format!("{}/", base)).await?;
+    assert!(index.contains("Sousou no Frieren"));
+    let new = get(&client, &format!("{}/new", base)).await?;
+    assert!(new.contains("[SubsPlease] Sousou no Frieren - 05 (1080p) [8E3F2A1B].mkv"));
+    assert!(new.contains("[Erai-raws] Shingeki no Kyojin - 05 [720p].mkv"));
+    assert!(new.contains("1204/87/5321"));
+    let show = get(&client, &format!("{}/show/{}", base, show_id)).await?;
+    assert!(show.contains("Sousou no Frieren"));
+    assert!(show.contains("[SubsPlease] Sousou no Frieren - 05 (1080p) [8E3F2A1B].mkv"));
+    assert!(show.contains("[SubsPlease] Sousou no Frieren - 01 (1080p) [C0FFEE00].mkv"));
+    assert!(!show.contains("Shingeki no Kyojin - 05"));
+    let unmatched = get(&client, &format!("{}/unmatched", base)).await?;
+    assert!(
+        unmatched.contains("[SomeGroup] Unknown Show Nobody Heard Of - 01 [1080p].mkv")
+    );
+    assert!(!unmatched.contains("Sousou no Frieren - 05"));
+    let schedule = get(&client, &format!("{}/schedule", base)).await?;
+    assert!(schedule.contains("Sousou no Frieren"));
+    let search = get(&client, &format!("{}/search?q=FRIEREN", base)).await?;
+    assert!(search.contains(&format!("/show/{}", show_id)));
+    assert!(search.contains("[SubsPlease] Sousou no Frieren - 01 (1080p) [C0FFEE00].mkv"));
+    assert!(!search.contains("Shingeki no Kyojin - 05"));
+    // The anilist sync and the heartbeat of the processor are fresh
+    let ready = get(&client, &format!("{}/readyz", base)).await?;
+    assert_eq!(ready, "ok\n");
+    check_watchlist(&testdb, &base, show_id).await?;
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+fn build(workspace: &Path) -> Result<()> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .current_dir(workspace)
+        .args(&["build", "-p", "processor", "-p", "site"])
+        .status()
+        .context("cannot run cargo")?;
+    if !status.success() {
+        return Err(anyhow!("cannot build the processor and the site"));
+    }
+    Ok(())
+}
+
+fn target_dir(workspace: &Path) -> PathBuf {
+    match std::env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => workspace.join(dir),
+        _ => workspace.join("target"),
+    }
+}
+
+fn spawn(bin: &Path, dir: &Path) -> Result<Process> {
+    let child = Command::new(bin)
+        .current_dir(dir)
+        .spawn()
+        .with_context(|| format!("cannot spawn {}", bin.display()))?;
+    Ok(Process(child))
+}
+
+fn temp_dir() -> Result<PathBuf> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let dir = std::env::temp_dir().join(format!("magnets-e2e-{}", nanos));
+    std::fs::create_dir(&dir)?;
+    Ok(dir)
+}
+
+fn free_port() -> Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+/// Waits until the torrents have been matched and indexed and the schedule has been
+/// loaded
+async fn wait_for_ingestion(testdb: &Testdb<'_>) -> Result<()> {
+    let con = testdb.connector.connect().await?;
+    let start = Instant::now();
+    loop {
+        // language=sql
+        let row = con
+            .query_one(
+                "
+                select
+                    (select count(*) from magnets.rel_torrent_show),
+                    (select count(*) from magnets.schedule),
+                    (select count(*) from magnets.torrent where search_title is null)",
+                &[],
+            )
+            .await?;
+        let (matched, scheduled, unindexed): (i64, i64, i64) =
+            (row.get(0), row.get(1), row.get(2));
+        if matched >= 3 && scheduled >= 1 && unindexed == 0 {
+            return Ok(());
+        }
+        if start.elapsed() > TIMEOUT {
+            return Err(anyhow!(
+                "the processor matched {} torrents, loaded {} schedule entries and has \
+                 not indexed {} torrents",
+                matched,
+                scheduled,
+                unindexed
+            ));
+        }
+        delay_for(Duration::from_millis(500)).await;
+    }
+}
+
+/// Performs a GET request, retrying while the site is starting
+async fn get(client: &reqwest::Client, url: &str) -> Result<String> {
+    let start = Instant::now();
+    loop {
+        match client.get(url).send().await {
+            Ok(r) if r.status().is_success() => return Ok(r.text().await?),
+            Ok(r) => return Err(anyhow!("{} returned {}", url, r.status())),
+            Err(e) if start.elapsed() > TIMEOUT => {
+                return Err(e).with_context(|| format!("cannot get {}", url))
+            }
+            Err(_) => delay_for(Duration::from_millis(500)).await,
+        }
+    }
+}
+
+/// Logs in with AniList and checks that the "watching" list becomes the watchlist
+async fn check_watchlist(testdb: &Testdb<'_>, base: &str, show_id: i64) -> Result<()> {
+    let con = testdb.connector.connect().await?;
+    // Removed shows are left off the watchlist
+    // language=sql
+    con.execute(
+        "update magnets.show set removal = 1 where anilist_id = $1",
+        &[&SHINGEKI],
+    )
+    .await?;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let login = client.get(&format!("{}/login", base)).send().await?;
+    assert_eq!(login.status().as_u16(), 302);
+    let cookie = login.headers()["set-cookie"].to_str()?;
+    let cookie = cookie.split(';').next().unwrap().to_string();
+    let location = login.headers()["location"].to_str()?;
+    assert!(location.contains("client_id=1&"));
+    let oauth_state = location.rsplit("state=").next().unwrap().to_string();
+    // language=sql
+    let session = "
+        select s.code, s.site_user_id, s.failed, s.expires < now() + interval '1 hour',
+            u.synced_at is not null
+        from magnets.site_session s
+        left join magnets.site_user u using (site_user_id)";
+    let row = con.query_one(session, &[]).await?;
+    assert!(
+        row.get::<_, bool>(3),
+        "sessions must expire soon until the login has been completed"
+    );
+
+    let callback = |state: &str| {
+        client
+            .get(&format!("{}/login/anilist?code=code&state={}", base, state))
+            .header("Cookie", &cookie)
+            .send()
+    };
+    let response = callback("forged").await?;
+    assert_eq!(response.status().as_u16(), 303);
+    let row = con.query_one(session, &[]).await?;
+    assert_eq!(row.get::<_, Option<&str>>(0), None);
+    let response = callback(&oauth_state).await?;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let start = Instant::now();
+    loop {
+        let row = con.query_one(session, &[]).await?;
+        assert!(!row.get::<_, bool>(2), "the processor could not log in");
+        if row.get::<_, Option<i64>>(1).is_some() && row.get::<_, bool>(4) {
+            assert!(
+                !row.get::<_, bool>(3),
+                "the session must be extended after the login"
+            );
+            break;
+        }
+        if start.elapsed() > TIMEOUT {
+            return Err(anyhow!("the processor did not complete the login"));
+        }
+        delay_for(Duration::from_millis(500)).await;
+    }
+
+    let watchlist = client
+        .get(&format!("{}/watchlist", base))
+        .header("Cookie", &cookie)
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(watchlist.contains("Logged in as Tester"));
+    assert!(watchlist.contains(&format!("/show/{}", show_id)));
+    assert!(!watchlist.contains("Shingeki no Kyojin"));
+    Ok(())
+}
+
+fn viewer() -> String {
+    serde_json::json!({
+        "data": {
+            "viewer": {
+                "id": 7,
+                "name": "Tester",
+            },
+        },
+    })
+    .to_string()
+}
+
+/// Frieren and Shingeki no Kyojin are on the "watching" list
+fn watching() -> String {
+    serde_json::json!({
+        "data": {
+            "collection": {
+                "lists": [
+                    {
+                        "entries": [
+                            { "media_id": FRIEREN },
+                            { "media_id": SHINGEKI },
+                        ],
+                    },
+                ],
+            },
+        },
+    })
+    .to_string()
+}
+
+/// The next episode of Frieren airs in one day
+fn schedule() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    serde_json::json!({
+        "data": {
+            "page": {
+                "page_info": {
+                    "total": 1,
+                    "per_page": 50,
+                    "current_page": 1,
+                    "last_page": 1,
+                    "has_next_page": false,
+                },
+                "airing_schedule": [
+                    {
+                        "airing_at": now + 24 * 60 * 60,
+                        "episode": 6,
+                        "media_id": FRIEREN,
+                    },
+                ],
+            },
+        },
+    })
+    .to_string()
+}
+
+fn processor_config(
+    connection_string: &str,
+    nyaa_url: &str,
+    anilist_url: &str,
+    dir: &Path,
+) -> String {
+    format!(
+        r#"
+[db]
+connection_string = "{connection_string}"
+
+[http]
+cache_ttl = "1 day"
+
+[user_agent]
+product = "magnets-e2e"
+contact = "https://magnets.moe/contact"
+
+[anilist]
+url = "{anilist_url}"
+startup_grace_period = "0 seconds"
+shows_poll_interval = "1 day"
+schedule_poll_interval = "1 day"
+
+[releases]
+enabled = false
+poll_interval = "1 day"
+
+[nyaa]
+url = "{nyaa_url}"
+scrape_interval = "1 second"
+trusted_refresh_interval = "1 day"
+trusted_refresh_batch = 50
+size_reparse_interval = "1 day"
+
+[covers]
+directory = "{covers}"
+poll_interval = "1 day"
+
+[standby]
+enabled = false
+poll_interval = "10 seconds"
+
+[memory]
+check_interval = "1 minute"
+
+[export]
+poll_interval = "1 day"
+
+[search]
+poll_interval = "1 second"
+
+[watchlists]
+anilist_client_id = 1
+anilist_client_secret = "secret"
+oauth_url = "{anilist_url}"
+login_poll_interval = "1 second"
+sync_user_delay = "0 seconds"
+
+[metrics]
+"#,
+        connection_string = connection_string,
+        anilist_url = anilist_url,
+        nyaa_url = nyaa_url,
+        covers = dir.join("covers").display(),
+    )
+}
+
+fn site_config(connection_string: &str, listen_addr: &str, dir: &Path) -> String {
+    format!(
+        r#"
+[db]
+connection_string = "{connection_string}"
+
+[http]
+listen_addr = ["{listen_addr}"]
+
+[user_agent]
+product = "magnets-e2e"
+contact = "https://magnets.moe/contact"
+
+[magnet]
+rate_limit = 60
+
+[rate_limit]
+html = 600
+search = 60
+api = 120
+
+[admin.users]
+
+[covers]
+directory = "{covers}"
+
+[schedule]
+
+[watchlists]
+anilist_client_id = 1
+"#,
+        connection_string = connection_string,
+        listen_addr = listen_addr,
+        covers = dir.join("covers").display(),
+    )
+}
@@ -6,6 +6,11 @@ use testcontainers::{
     clients::Cli, core::Port, Container, Docker, Image, WaitForMessage,
 };
 
+pub mod mock_http;
+
+#[cfg(test)]
+mod e2e;
+
 #[derive(Debug)]
 struct Postgres {
     arguments: PostgresArgs,
@@ -82,6 +87,7 @@ impl Image for Postgres {
 
 pub struct Testdb<'a> {
     _container: Container<'a, Cli, Postgres>,
+    pub connection_string: String,
     pub connector: PgConnector,
 }
 
@@ -94,7 +100,8 @@ impl<'a> Testdb<'a> {
         );
         let res = Testdb {
             _container: container,
-            connector: PgConnector::new(connection_string),
+            connector: PgConnector::new(connection_string.clone()),
+            connection_string,
         };
         let client = res.connector.connect().await?;
         client
@@ -0,0 +1,135 @@
+//! A minimal http server that answers requests with canned responses
+//!
+//! It stands in for nyaa.si and AniList in the end-to-end test. Every connection
+//! carries a single request and is closed after the response.
+
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+pub struct Request {
+    pub method: String,
+    /// The path including the query string
+    pub path: String,
+    pub body: String,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            status: 404,
+            content_type: "text/plain",
+            body: String::new(),
+        }
+    }
+}
+
+pub struct MockServer {
+    /// The base url of the server without a trailing slash
+    pub url: String,
+}
+
+impl MockServer {
+    /// Starts serving on a random port of the loopback interface
+    ///
+    /// Must be called from within a tokio runtime. The server runs until the runtime
+    /// shuts down.
+    pub async fn start<F>(handler: F) -> Result<Self>
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((s, _)) => s,
+                    Err(e) => {
+                        log::error!("cannot accept mock connection: {}", e);
+                        continue;
+                    }
+                };
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &*handler).await {
+                        log::error!("cannot answer mock request: {:#}", e);
+                    }
+                });
+            }
+        });
+        Ok(Self { url })
+    }
+}
+
+async fn respond<F>(mut stream: TcpStream, handler: &F) -> Result<()>
+where
+    F: Fn(&Request) -> Response,
+{
+    let mut buf = vec![];
+    let mut chunk = [0; 4096];
+    let head_len = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().context("empty request")?.split(' ');
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().unwrap_or("").to_string();
+    let content_length = lines
+        .filter_map(|l| {
+            let (name, value) = l.split_at(l.find(':')?);
+            match name.eq_ignore_ascii_case("content-length") {
+                true => value[1..].trim().parse::<usize>().ok(),
+                false => None,
+            }
+        })
+        .next()
+        .unwrap_or(0);
+    while buf.len() < head_len + content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = String::from_utf8_lossy(&buf[head_len..]).into_owned();
+    let response = handler(&Request { method, path, body });
+    let head = format!(
+        "HTTP/1.1 {} Mock\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n",
+        response.status,
+        response.content_type,
+        response.body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    Ok(())
+}